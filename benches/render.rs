@@ -0,0 +1,119 @@
+//! Benchmarks of getting dots onto the canvas, run with `cargo bench`:
+//!
+//! - `upload`: turning dots into instance buffers, in dots per second.
+//! - `frame`: encoding and submitting a whole frame of the canvas and its effects, without waiting
+//!   for the GPU.
+//! - `draw`: drawing dots until the GPU is done, in dots per second, per path. `instanced` is the
+//!   dot pipeline, `cpu` the software rasterizer for comparison. Other paths, like drawing with
+//!   compute shaders, go in next to them.
+//!
+//! Without an adapter only the CPU rasterizer is measured.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use hellopaint_wgpu::raster::CpuCanvas;
+use hellopaint_wgpu::surface::CanvasColorSpace;
+use hellopaint_wgpu::{Dot, HpSurface, SurfaceRenderResources};
+
+const CANVAS_SIZE: u32 = 1024;
+
+const DOT_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn random_dots(count: usize) -> Vec<Dot> {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    (0..count).map(|_| Dot::random(&mut rng)).collect()
+}
+
+fn headless() -> Option<HpSurface> {
+    match HpSurface::headless(CANVAS_SIZE) {
+        Ok(surface) => Some(surface),
+        Err(err) => {
+            eprintln!("Only benchmarking the CPU: {err}");
+            None
+        }
+    }
+}
+
+fn upload(c: &mut Criterion) {
+    let Some(mut surface) = headless() else {
+        return;
+    };
+    let mut group = c.benchmark_group("upload");
+    for count in DOT_COUNTS {
+        let dots = random_dots(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_batched(|| dots.clone(), |dots| surface.set_dots(dots), BatchSize::LargeInput);
+        });
+    }
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    let Some(mut surface) = headless() else {
+        return;
+    };
+    surface.set_dots(random_dots(10_000));
+    let device = surface.global.device.clone();
+    let queue = surface.global.queue.clone();
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Benchmark Target"),
+        size: wgpu::Extent3d {
+            width: 1280,
+            height: 720,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let mut resources = SurfaceRenderResources::new(&device, surface, format);
+
+    c.bench_function("frame", |b| {
+        b.iter_custom(|iterations| {
+            let mut encoding = Duration::ZERO;
+            for _ in 0..iterations {
+                let start = Instant::now();
+                resources.render_to_texture(&device, &queue, &target).unwrap();
+                encoding += start.elapsed();
+                // Not measured, but keeps frames from piling up
+                device.poll(wgpu::Maintain::Wait);
+            }
+            encoding
+        });
+    });
+}
+
+fn draw(c: &mut Criterion) {
+    let mut surface = headless();
+    let mut group = c.benchmark_group("draw");
+    group.sample_size(10);
+    for count in DOT_COUNTS {
+        let dots = random_dots(count);
+        group.throughput(Throughput::Elements(count as u64));
+        if let Some(surface) = &mut surface {
+            surface.set_dots(dots.clone());
+            group.bench_function(BenchmarkId::new("instanced", count), |b| {
+                b.iter(|| {
+                    surface.render().unwrap();
+                    surface.global.device.poll(wgpu::Maintain::Wait);
+                });
+            });
+        }
+        let mut canvas = CpuCanvas::new(CANVAS_SIZE, CanvasColorSpace::Srgb);
+        canvas.set_dots(dots);
+        group.bench_function(BenchmarkId::new("cpu", count), |b| b.iter(|| canvas.render()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, upload, frame, draw);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+//! Saved documents: any input is parsed or rejected, and what's accepted saves and loads again.
+
+#![no_main]
+
+use hellopaint_wgpu::document::Document;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|json: &str| {
+    let Ok(document) = Document::from_json(json) else {
+        return;
+    };
+    let saved = document.to_json().expect("a loaded document can be saved");
+    let reloaded = Document::from_json(&saved).expect("a saved document loads again");
+    assert_eq!(reloaded.dot_count(), document.dot_count());
+});
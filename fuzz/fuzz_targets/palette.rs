@@ -0,0 +1,22 @@
+//! Palette files: any input is parsed or rejected as either format, and accepted colors are
+//! numbers.
+
+#![no_main]
+
+use hellopaint_wgpu::palette::Palette;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for file_name in ["fuzz.gpl", "fuzz.ase"] {
+        let Ok(palette) = Palette::load(file_name, data) else {
+            continue;
+        };
+        for swatch in palette.all_swatches() {
+            let color = swatch.color;
+            assert!(
+                [color.r, color.g, color.b, color.a].iter().all(|value| value.is_finite()),
+                "{file_name} has a swatch that isn't a color: {swatch:?}"
+            );
+        }
+    }
+});
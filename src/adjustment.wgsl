@@ -0,0 +1,61 @@
+// Brightness, contrast, hue and saturation adjustments of the canvas, see `Adjustment` in filter.rs.
+//
+// `Adjustment` mirrors `AdjustmentUniforms` in filter.rs, keep them in sync when changing either side.
+
+struct Adjustment {
+    origin: vec2<u32>,
+    size: vec2<u32>,
+    brightness: f32,
+    contrast: f32,
+    // In radians
+    hue: f32,
+    saturation: f32,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> adjustment: Adjustment;
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= adjustment.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(adjustment.origin + id.xy);
+    let color = textureLoad(input, pixel, 0);
+    if (color.a <= 0.0) {
+        textureStore(output, pixel, color);
+        return;
+    }
+
+    // Adjusted unpremultiplied and sRGB encoded, so the values feel even across the range
+    var rgb = linear_to_srgb(color.rgb / color.a);
+    rgb += adjustment.brightness;
+    rgb = (rgb - 0.5) * (1.0 + adjustment.contrast) + 0.5;
+
+    // Rotates around the gray axis
+    let axis = vec3<f32>(0.57735);
+    let cos_hue = cos(adjustment.hue);
+    rgb = rgb * cos_hue + cross(axis, rgb) * sin(adjustment.hue) + axis * dot(axis, rgb) * (1.0 - cos_hue);
+
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, 1.0 + adjustment.saturation);
+
+    rgb = srgb_to_linear(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    textureStore(output, pixel, vec4<f32>(rgb * color.a, color.a));
+}
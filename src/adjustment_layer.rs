@@ -0,0 +1,230 @@
+//! Adjustment layers: curves, hue/saturation/lightness, levels and gradient maps, stacked on top of
+//! the canvas.
+//!
+//! They never touch the canvas. [`PostProcess`](crate::post_process::PostProcess) applies the
+//! stack while compositing, before the effects, by baking the visible layers into a 3D lookup
+//! table whenever the stack changes. Like `.cube` LUTs, they work on display (sRGB encoded) colors.
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::gradient::{self, Gradient};
+use crate::lut::Lut;
+
+/// Entries along each axis of the baked table, as common for grading LUTs.
+const BAKED_SIZE: u32 = 33;
+
+/// What an adjustment layer does. Serialized with its name in `kind`, like
+/// `{ "kind": "levels", "input_black": 0.1, "gamma": 1.2 }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AdjustmentKind {
+    /// Remaps channels along curves through `[input, output]` points, the combined `rgb` curve
+    /// after the per-channel ones. A curve without points leaves its channels as they are.
+    Curves {
+        #[serde(default)]
+        rgb: Vec<[f32; 2]>,
+        #[serde(default)]
+        red: Vec<[f32; 2]>,
+        #[serde(default)]
+        green: Vec<[f32; 2]>,
+        #[serde(default)]
+        blue: Vec<[f32; 2]>,
+    },
+    /// Shifts hue by degrees, and saturation and lightness by -1 to 1.
+    Hsl {
+        #[serde(default)]
+        hue: f32,
+        #[serde(default)]
+        saturation: f32,
+        #[serde(default)]
+        lightness: f32,
+    },
+    /// Stretches the input range to the output range, bending the midtones with `gamma`.
+    Levels(Levels),
+    /// Replaces colors by the gradient's color at their luminance, see [`gradient::map_position`].
+    /// Transparent stops let the original color through.
+    GradientMap(Gradient),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Levels {
+    pub input_black: f32,
+    pub input_white: f32,
+    /// Above 1 brightens the midtones, below darkens them.
+    pub gamma: f32,
+    pub output_black: f32,
+    pub output_white: f32,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            input_black: 0.0,
+            input_white: 1.0,
+            gamma: 1.0,
+            output_black: 0.0,
+            output_white: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdjustmentLayer {
+    #[serde(flatten)]
+    pub kind: AdjustmentKind,
+    /// How much of the adjustment shows, 0..1.
+    #[serde(default = "full_opacity")]
+    pub opacity: f32,
+    /// Hidden layers stay in the stack without doing anything.
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+fn full_opacity() -> f32 {
+    1.0
+}
+
+impl AdjustmentLayer {
+    /// A visible layer at full opacity.
+    pub fn new(kind: AdjustmentKind) -> Self {
+        Self {
+            kind,
+            opacity: 1.0,
+            hidden: false,
+        }
+    }
+
+    /// `rgb` adjusted by this layer.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        if self.hidden {
+            return rgb;
+        }
+        let adjusted = match &self.kind {
+            AdjustmentKind::Curves { rgb: all, red, green, blue } => {
+                let [r, g, b] = rgb;
+                [curve(red, r), curve(green, g), curve(blue, b)].map(|channel| curve(all, channel))
+            }
+            AdjustmentKind::Hsl {
+                hue,
+                saturation,
+                lightness,
+            } => hsl(rgb, *hue, *saturation, *lightness),
+            AdjustmentKind::Levels(levels) => rgb.map(|channel| levels.apply(channel)),
+            AdjustmentKind::GradientMap(gradient) => {
+                let [r, g, b] = rgb;
+                let position = gradient::map_position(Color::from_srgb([r, g, b, 1.0]));
+                let mapped = gradient.sample(position).to_srgb();
+                [0, 1, 2].map(|index| rgb[index] + (mapped[index] - rgb[index]) * mapped[3])
+            }
+        };
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        [0, 1, 2].map(|index| rgb[index] + (adjusted[index] - rgb[index]) * opacity)
+    }
+}
+
+impl Levels {
+    fn apply(&self, value: f32) -> f32 {
+        let range = (self.input_white - self.input_black).max(f32::EPSILON);
+        let normalized = ((value - self.input_black) / range).clamp(0.0, 1.0);
+        let bent = normalized.powf(1.0 / self.gamma.max(0.01));
+        self.output_black + bent * (self.output_white - self.output_black)
+    }
+}
+
+/// The stack baked into a lookup table, or `None` if no layer is visible.
+pub fn bake(layers: &[AdjustmentLayer]) -> Option<Lut> {
+    if layers.iter().all(|layer| layer.hidden) {
+        return None;
+    }
+    let step = 1.0 / (BAKED_SIZE - 1) as f32;
+    let mut table = Vec::with_capacity(BAKED_SIZE.pow(3) as usize);
+    for blue in 0..BAKED_SIZE {
+        for green in 0..BAKED_SIZE {
+            for red in 0..BAKED_SIZE {
+                let rgb = [red, green, blue].map(|index| index as f32 * step);
+                table.push(layers.iter().fold(rgb, |rgb, layer| layer.apply(rgb)));
+            }
+        }
+    }
+    Some(Lut {
+        size: BAKED_SIZE,
+        domain_min: [0.0; 3],
+        domain_max: [1.0; 3],
+        table,
+    })
+}
+
+/// Evaluates a smooth curve through `points`, which don't overshoot between them (monotone cubic
+/// interpolation). Flat beyond the first and last point, the identity without points.
+fn curve(points: &[[f32; 2]], x: f32) -> f32 {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    points.dedup_by(|a, b| a[0] == b[0]);
+    match points.as_slice() {
+        [] => return x,
+        [only] => return only[1],
+        _ => {}
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if x <= first[0] {
+        return first[1];
+    }
+    if x >= last[0] {
+        return last[1];
+    }
+
+    let slopes: Vec<f32> = points
+        .windows(2)
+        .map(|pair| (pair[1][1] - pair[0][1]) / (pair[1][0] - pair[0][0]))
+        .collect();
+    // Fritsch-Carlson tangents: zero at extrema, averaged elsewhere
+    let tangent = |index: usize| -> f32 {
+        if index == 0 {
+            return slopes[0];
+        }
+        if index == points.len() - 1 {
+            return slopes[index - 1];
+        }
+        let (before, after) = (slopes[index - 1], slopes[index]);
+        if before * after <= 0.0 {
+            0.0
+        } else {
+            2.0 / (1.0 / before + 1.0 / after)
+        }
+    };
+
+    let index = points.windows(2).position(|pair| x < pair[1][0]).unwrap();
+    let ([x0, y0], [x1, y1]) = (points[index], points[index + 1]);
+    let width = x1 - x0;
+    let t = (x - x0) / width;
+    let (t2, t3) = (t * t, t * t * t);
+    (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+        + (t3 - 2.0 * t2 + t) * width * tangent(index)
+        + (-2.0 * t3 + 3.0 * t2) * y1
+        + (t3 - t2) * width * tangent(index + 1)
+}
+
+/// Shifts `rgb` in HSL space, with `saturation` and `lightness` moving towards gray and towards
+/// black or white like the hue/saturation dialogs of image editors.
+fn hsl(rgb: [f32; 3], hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|channel| channel.clamp(0.0, 1.0));
+    let mut hsl = Color::from_srgb([r, g, b, 1.0]).to_hsl();
+    hsl.h = (hsl.h + hue).rem_euclid(360.0);
+    hsl.s = if saturation < 0.0 {
+        hsl.s * (1.0 + saturation)
+    } else {
+        hsl.s + (1.0 - hsl.s) * saturation
+    }
+    .clamp(0.0, 1.0);
+
+    let [r, g, b, _] = Color::from_hsl(hsl, 1.0).to_srgb();
+    [r, g, b].map(|channel| {
+        if lightness < 0.0 {
+            channel * (1.0 + lightness)
+        } else {
+            channel + (1.0 - channel) * lightness
+        }
+    })
+}
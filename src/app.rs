@@ -0,0 +1,1453 @@
+//! The painting app: the window and its event loop. Input is turned into actions and commands
+//! for the [`CommandBus`] in `input.rs`, and the GPU side lives in `renderer.rs`.
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+use futures_channel::oneshot;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use winit::{
+    dpi::PhysicalPosition,
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget},
+    window::Window,
+};
+
+use crate::adjustment_layer::AdjustmentLayer;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+use crate::audio::{self, AudioInput};
+use crate::clock::Clock;
+use crate::color::Color;
+use crate::command::{Command, CommandBus, Painting};
+use crate::config::Config;
+use crate::document::{Document, StrokeMetadata};
+use crate::dump;
+use crate::error::Error;
+use crate::export::{self, ExportError, ExportOptions, TextureReadback};
+use crate::filter::{Filter, FilterKind, FilterProgress, Kernel};
+use crate::framing::Framing;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::framing::Watermark;
+use crate::generators::Generator;
+use crate::guides::Guides;
+use crate::histogram::Histogram;
+use crate::input::{Action, Input};
+use crate::lsystem::LSystem;
+use crate::lut::Lut;
+use crate::new_document::{CanvasSetup, DialogAnswer, NewDocumentDialog};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::new_document::RecentDocuments;
+use crate::palette::Palette;
+use crate::particles::ParticleSettings;
+use crate::post_process::ColorBlindness;
+use crate::recent_colors::RecentColors;
+use crate::replay::Replay;
+use crate::renderer::Renderer;
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+use crate::script::{self, ScriptContext};
+use crate::selection::MagicWand;
+use crate::session::{Session, ViewState};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::session::WindowGeometry;
+use crate::shaders::ShaderError;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::ShaderWatcher;
+use crate::spatial::CanvasRect;
+#[cfg(target_arch = "wasm32")]
+use crate::storage::IndexedDbStorage;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stipple::{self, StippleImage, StippleOptions};
+use crate::stress::{StressStep, StressTest};
+use crate::stroke::{Brush, Stroke};
+use crate::surface::{Dot, HpSurface, StrokeOverlap};
+use crate::surface_view::{Comparison, SurfaceRenderResources, ViewFlip};
+use crate::tasks::{TaskEvent, Tasks};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::replay::Recording;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::video::{self, VideoExporter, VideoFormat, VideoFrame, VideoOptions};
+use crate::timeline::OnionSkin;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::timeline::Timeline;
+
+/// Called with the dots of every finished stroke.
+#[cfg(not(target_arch = "wasm32"))]
+pub type StrokeListener = Box<dyn FnMut(&[Dot]) + Send>;
+/// Called with the dots of every finished stroke.
+#[cfg(target_arch = "wasm32")]
+pub type StrokeListener = Box<dyn FnMut(&[Dot])>;
+
+/// Events sent into the app's event loop from outside of it.
+pub enum UserEvent {
+    /// The browser tab was hidden, the last moment we can reliably persist the document.
+    #[cfg(target_arch = "wasm32")]
+    PageHidden,
+    /// Applies a command as if the app's own input had published it.
+    Command(Command),
+    /// Grades the image with a LUT, or stops grading with `None`.
+    SetLut(Option<Lut>),
+    /// Skips the LUT while set, to compare against the ungraded image.
+    SetLutBypass(bool),
+    /// Simulates a kind of color blindness on the display, or stops with `None`.
+    SetColorBlindness(Option<ColorBlindness>),
+    /// Replaces the adjustment layers shown on top of the canvas.
+    SetAdjustmentLayers(Vec<AdjustmentLayer>),
+    /// Replaces the swatches the number keys pick the brush color from.
+    SetPalette(Palette),
+    /// Shows other guides, or none with `None`. Strokes snapping to guides snap to these.
+    SetGuides(Option<Guides>),
+    /// Runs a filter over the canvas after the existing ones.
+    AddFilter(Filter),
+    /// Removes all filters.
+    ClearFilters,
+    /// Shows a filter on top of the others until committed, or cancels the preview with `None`.
+    PreviewFilter(Option<Filter>),
+    /// Adds the previewed filter after the others.
+    CommitPreview,
+    /// Asks which document to start next, like Ctrl+N.
+    NewDocument,
+    /// Makes a kernel available to convolution filters, see [`GlobalSurface::register_kernel`](crate::surface::GlobalSurface::register_kernel).
+    RegisterKernel(String, Kernel),
+    /// Selects with the magic wand, limiting filters to the selection, or selects all with `None`.
+    Select(Option<MagicWand>),
+    /// Sends the recently painted colors, most recent first.
+    RecentColors(oneshot::Sender<Vec<Color>>),
+    /// Sends the indices of the dots touching a rectangle, in drawing order, e.g. to select them.
+    DotsIn(CanvasRect, oneshot::Sender<Vec<usize>>),
+    /// Replaces the look of dots with a WGSL snippet, see [`GlobalSurface::set_dot_snippet`](crate::surface::GlobalSurface::set_dot_snippet).
+    SetDotSnippet(Option<String>, oneshot::Sender<Result<(), ShaderError>>),
+    /// Counts the canvas into histograms and sends them, `None` if the device can't.
+    Histogram(oneshot::Sender<Option<Histogram>>),
+    /// Reads back the canvas and sends it encoded as PNG.
+    ExportPng(oneshot::Sender<Result<Vec<u8>, ExportError>>),
+    SetStrokeListener(StrokeListener),
+    /// The device replacing a lost one, sent by the app to itself once the old one reports it was lost.
+    DeviceRecreated(Result<GpuDevice, Error>),
+    /// A background task finished, sent by the app to itself.
+    Task(TaskEvent),
+}
+
+/// An adapter with a device and queue on it.
+pub type GpuDevice = (wgpu::Adapter, Arc<wgpu::Device>, Arc<wgpu::Queue>);
+
+#[cfg(target_arch = "wasm32")]
+const AUTOSAVE_DOCUMENT: &str = "autosave";
+/// Where Ctrl+D saves documents that weren't opened from a file.
+#[cfg(not(target_arch = "wasm32"))]
+const DOCUMENT_FILE_NAME: &str = "hellopaint.json";
+
+/// Sets up the GPU for `window` and runs the app on `event_loop`.
+///
+/// Natively this never returns unless setting up fails. On the web it returns once the event
+/// loop has been handed over to the browser.
+pub async fn run(event_loop: EventLoop<UserEvent>, window: Rc<Window>, mut config: Config) -> Result<(), Error> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::WindowExtWebSys;
+        // Let touches paint instead of scrolling or zooming the page
+        window
+            .canvas()
+            .style()
+            .set_property("touch-action", "none")
+            .expect("couldn't set canvas touch-action");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let chrome_trace = config.chrome_trace.as_deref().and_then(record_chrome_trace);
+    #[cfg(target_arch = "wasm32")]
+    if let Some(path) = &config.chrome_trace {
+        tracing::warn!("Ignoring --chrome-trace {path}, on the web record a profile in the browser's dev tools instead");
+    }
+    #[cfg(target_arch = "wasm32")]
+    if let Some(path) = &config.open {
+        tracing::warn!("Ignoring --open {path}, on the web the autosaved document opens instead");
+    }
+
+    let session = match config.restore_session {
+        true => Session::load().unwrap_or_default(),
+        false => Session::default(),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(geometry) = &session.window {
+        geometry.apply(&window);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let storage = match IndexedDbStorage::open().await {
+        Ok(storage) => Some(Rc::new(storage)),
+        Err(err) => {
+            tracing::error!("Documents won't be saved: {err}");
+            None
+        }
+    };
+    #[cfg(target_arch = "wasm32")]
+    let document = load_autosave(storage.as_deref()).await;
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut recent_documents = RecentDocuments::load();
+    #[cfg(not(target_arch = "wasm32"))]
+    let document = config.open.as_deref().and_then(|path| match open_document(path) {
+        Ok(document) => {
+            recent_documents.push(path.as_ref());
+            recent_documents.save();
+            Some(document)
+        }
+        Err(err) => {
+            tracing::error!("Couldn't open {path}, starting a new document: {err}");
+            None
+        }
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    let opened = document.is_some();
+    let ask_document = config.ask_document && !config.readonly && document.is_none();
+    // Documents open on the canvas they were painted on
+    if let Some(canvas) = document.as_ref().and_then(|document| document.canvas) {
+        config.set_canvas_setup(canvas);
+    }
+
+    let mut renderer = Renderer::new(&window, &config).await?;
+    if let Some(document) = document {
+        renderer.resources.surface_mut().set_document(document);
+    }
+    let export_options = ExportOptions {
+        gamut: renderer.gamut(),
+        dither: config.dither,
+    };
+
+    let watermark = match &config.watermark {
+        #[cfg(not(target_arch = "wasm32"))]
+        Some(path) => match load_watermark(path) {
+            Ok(mut watermark) => {
+                watermark.corner = config.watermark_corner;
+                watermark.opacity = config.watermark_opacity;
+                Some(watermark)
+            }
+            Err(err) => {
+                tracing::error!("Exports won't be watermarked, couldn't load {path}: {err}");
+                None
+            }
+        },
+        #[cfg(target_arch = "wasm32")]
+        Some(path) => {
+            tracing::warn!("Ignoring the watermark {path}, exports can only be watermarked natively");
+            None
+        }
+        None => None,
+    };
+    let framing = Framing {
+        crop: None,
+        border: config.export_border,
+        border_color: config.border_color,
+        watermark,
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let video_options = VideoOptions {
+        format: VideoFormat::for_path(config.video.as_ref()),
+        size: config.video_size,
+        fps: config.video_fps,
+        export: export_options,
+        ..VideoOptions::default()
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    notify_when_page_hidden(event_loop.create_proxy());
+
+    let palette = match &config.palette {
+        #[cfg(not(target_arch = "wasm32"))]
+        Some(path) => load_palette(path).unwrap_or_else(|err| {
+            tracing::error!("Couldn't load the palette {path}: {err}");
+            Palette::default()
+        }),
+        #[cfg(target_arch = "wasm32")]
+        Some(path) => {
+            tracing::warn!("Ignoring the palette {path}, on the web pass it to importPalette instead");
+            Palette::default()
+        }
+        None => Palette::default(),
+    };
+
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    if config.initial_dots > 0 || config.stress.is_some() {
+        tracing::info!("Generating dots from seed {seed}, pass --seed {seed} to get them again");
+    }
+    if config.initial_dots > 0 {
+        let dots: Vec<Dot> = (0..config.initial_dots).map(|_| Dot::random(&mut rng)).collect();
+        renderer.resources.surface_mut().add_dots(&dots);
+    }
+    match &config.stipple {
+        #[cfg(not(target_arch = "wasm32"))]
+        Some(path) => match load_stipples(path) {
+            Ok(dots) => {
+                tracing::info!("Drawing {path} with {} stipples", dots.len());
+                renderer.resources.surface_mut().add_dots(&dots);
+            }
+            Err(err) => tracing::error!("Couldn't stipple {path}: {err}"),
+        },
+        #[cfg(target_arch = "wasm32")]
+        Some(path) => tracing::warn!("Ignoring the image {path}, it can only be stippled natively"),
+        None => {}
+    }
+    if config.particles {
+        renderer.resources.surface_mut().set_particles(Some(ParticleSettings::default()));
+    }
+    renderer.resources.surface_mut().set_lod(config.lod);
+    if config.even_strokes {
+        renderer.resources.surface_mut().set_stroke_overlap(StrokeOverlap::Once);
+    }
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    let audio = open_audio_input(&config, seed);
+    #[cfg(not(all(feature = "audio", not(target_arch = "wasm32"))))]
+    if config.audio {
+        tracing::warn!("Not painting to sound, built without the audio feature or for the web");
+    }
+    let mut painting = Painting::new(session.brush);
+    painting.set_metadata(StrokeMetadata {
+        author: config.author.clone(),
+        ..StrokeMetadata::default()
+    });
+    let view = session.view;
+    let guides = config.guides.or(view.guides);
+    renderer.resources.set_guides(guides);
+    if config.snap || view.snap {
+        painting.set_snap(guides);
+    }
+    renderer.resources.set_flip(view.flip);
+    renderer
+        .resources
+        .post_process_mut()
+        .set_color_blindness(&renderer.device, view.color_blindness);
+    if !view.onion_skin {
+        renderer.resources.timeline_mut().set_onion_skin(OnionSkin::OFF);
+    }
+    let stress = config
+        .stress
+        .map(|dots_per_frame| StressTest::new(dots_per_frame, config.frame_budget_ms, rng));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let recent_paths = recent_documents.paths().to_vec();
+    #[cfg(target_arch = "wasm32")]
+    let recent_paths = Vec::new();
+    let new_document = ask_document.then(|| NewDocumentDialog::new(config.canvas_setup(), &recent_paths));
+
+    let mut app = App {
+        input: Input::new(&window, config.readonly),
+        window,
+        renderer,
+        bus: CommandBus::new(),
+        painting,
+        palette,
+        recent_colors: RecentColors::load(config.recent_colors),
+        #[cfg(not(target_arch = "wasm32"))]
+        document_path: config.open.clone().filter(|_| opened).map(Into::into),
+        #[cfg(not(target_arch = "wasm32"))]
+        unsaved: false,
+        #[cfg(not(target_arch = "wasm32"))]
+        recent_documents,
+        new_document,
+        new_canvas: config.canvas_setup(),
+        stroke_listener: None,
+        export_options,
+        framing,
+        export_viewport: config.export_viewport,
+        #[cfg(not(target_arch = "wasm32"))]
+        video: (config.video.clone().into(), video_options),
+        replay: None,
+        replay_speed: config.replay_speed,
+        script: config.script.clone(),
+        next_generator: 0,
+        next_lsystem: 0,
+        tasks: Tasks::new(event_loop.create_proxy()),
+        proxy: event_loop.create_proxy(),
+        stress,
+        #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+        audio,
+        #[cfg(not(target_arch = "wasm32"))]
+        _chrome_trace: chrome_trace,
+        #[cfg(target_arch = "wasm32")]
+        storage,
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        shader_watcher: ShaderWatcher::new()
+            .map_err(|err| tracing::warn!("Shader hot reload is disabled: {err}"))
+            .ok(),
+    };
+
+    if app.script.is_some() && !config.readonly {
+        app.apply(Action::RunScript);
+    }
+
+    let event_handler = move |event: Event<'_, UserEvent>,
+                              _: &EventLoopWindowTarget<UserEvent>,
+                              control_flow: &mut ControlFlow| {
+        app.handle_event(event, control_flow);
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    event_loop.run(event_handler);
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::EventLoopExtWebSys;
+        event_loop.spawn(event_handler);
+        Ok(())
+    }
+}
+
+/// Everything the app keeps between events. Owned by the event loop, which natively never
+/// returns, so it's dropped when the app quits.
+struct App {
+    window: Rc<Window>,
+    renderer: Renderer,
+    input: Input,
+    bus: CommandBus,
+    painting: Painting,
+    palette: Palette,
+    recent_colors: RecentColors,
+    /// Where the document was opened from or last saved to with Ctrl+D, which saves to
+    /// [`DOCUMENT_FILE_NAME`] without one.
+    #[cfg(not(target_arch = "wasm32"))]
+    document_path: Option<std::path::PathBuf>,
+    /// Strokes were painted since the document was opened or saved, so closing saves it.
+    #[cfg(not(target_arch = "wasm32"))]
+    unsaved: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    recent_documents: RecentDocuments,
+    /// Shown over the canvas, which gets no input meanwhile.
+    new_document: Option<NewDocumentDialog>,
+    /// What the dialog offers new documents to start on: the configured canvas, then the last one
+    /// picked.
+    new_canvas: CanvasSetup,
+    stroke_listener: Option<StrokeListener>,
+    export_options: ExportOptions,
+    /// How PNG exports are framed, cropped to what the window shows if `export_viewport`.
+    framing: Framing,
+    export_viewport: bool,
+    /// Where the timeline is exported as a video to, and how.
+    #[cfg(not(target_arch = "wasm32"))]
+    video: (std::path::PathBuf, VideoOptions),
+    /// Playing on the current frame, timed by the clock.
+    replay: Option<(Replay, Clock)>,
+    replay_speed: f64,
+    /// Path of the script F5 runs.
+    script: Option<String>,
+    /// Index into [`Generator::ALL`] of the one G uses next.
+    next_generator: usize,
+    /// Index into [`LSystem::presets`] of the one Shift+G draws next.
+    next_lsystem: usize,
+    tasks: Tasks,
+    proxy: EventLoopProxy<UserEvent>,
+    stress: Option<StressTest>,
+    /// Paints dots to the sound it records, placed by the generator.
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    audio: Option<(AudioInput, ChaCha8Rng)>,
+    /// Writes the rest of the trace when the app is dropped.
+    #[cfg(not(target_arch = "wasm32"))]
+    _chrome_trace: Option<tracing_chrome::FlushGuard>,
+    #[cfg(target_arch = "wasm32")]
+    storage: Option<Rc<IndexedDbStorage>>,
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    shader_watcher: Option<ShaderWatcher>,
+}
+
+impl App {
+    fn handle_event(&mut self, event: Event<'_, UserEvent>, control_flow: &mut ControlFlow) {
+        *control_flow = ControlFlow::Wait;
+        // The watcher runs on its own thread and can't wake the event loop, so check on it regularly
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        if self.shader_watcher.is_some() {
+            *control_flow = ControlFlow::WaitUntil(std::time::Instant::now() + SHADER_POLL_INTERVAL);
+        }
+        match event {
+            Event::WindowEvent { event, .. } => self.handle_window_event(event, control_flow),
+            Event::UserEvent(event) => self.handle_user_event(event, control_flow),
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            Event::MainEventsCleared => {
+                if let Some(shader_watcher) = &self.shader_watcher {
+                    if self.renderer.reload_changed_shaders(shader_watcher) {
+                        self.window.request_redraw();
+                    }
+                }
+            }
+            Event::RedrawRequested(_) => {
+                #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+                self.paint_audio();
+                self.show_new_document();
+                match self.renderer.render(&self.proxy) {
+                    Ok(true) if self.stress.is_some() => self.step_stress_test(control_flow),
+                    // The magic wand grows its selection a bit with every render
+                    Ok(true) if self.renderer.resources.surface().is_selecting() => self.window.request_redraw(),
+                    // Animated dots change with the clock
+                    Ok(true) if self.renderer.resources.surface().is_animated() => self.window.request_redraw(),
+                    // Wet paint flows until it dried
+                    Ok(true) if self.renderer.resources.surface().is_wet() => self.window.request_redraw(),
+                    Ok(_) => {}
+                    Err(err) => {
+                        tracing::error!("Quitting: {err}");
+                        *control_flow = ControlFlow::ExitWithCode(1);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if let Some((replay, clock)) = &mut self.replay {
+            replay.advance_to(clock.elapsed_seconds(), self.renderer.resources.surface_mut());
+            if replay.is_finished() {
+                tracing::info!("Replay finished");
+                self.replay = None;
+            }
+            self.window.request_redraw();
+        }
+
+        let applied = self.bus.apply(&mut self.painting, self.renderer.resources.surface_mut());
+        if applied.redraw {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.unsaved = true;
+            }
+            self.window.request_redraw();
+        }
+        for stroke in applied.finished_strokes {
+            finish_stroke(stroke, &mut self.stroke_listener, &mut self.recent_colors);
+        }
+        if applied.export {
+            let framing = export_framing(&self.framing, self.export_viewport, &self.renderer.resources, &self.window);
+            export_canvas(self.renderer.resources.surface(), &framing, self.export_options, &self.tasks);
+        }
+    }
+
+    /// Adds dots for what the audio input recorded since the last frame, and keeps rendering
+    /// while it listens. The oldest dots make room once there are too many.
+    #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+    fn paint_audio(&mut self) {
+        let Some((input, rng)) = &mut self.audio else {
+            return;
+        };
+        let dots = audio::spawn_dots(&input.levels(), AUDIO_THRESHOLD, rng);
+        let surface = self.renderer.resources.surface_mut();
+        if surface.dots().len() + dots.len() > MAX_AUDIO_DOTS {
+            let keep = surface.dots()[surface.dots().len() - MAX_AUDIO_DOTS / 2..].to_vec();
+            surface.set_dots(keep);
+        }
+        surface.add_dots(&dots);
+        self.window.request_redraw();
+    }
+
+    /// Adds the stress test's dots for the next frame, or reports it and quits once frames take
+    /// too long. On the web the app keeps running.
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    fn step_stress_test(&mut self, control_flow: &mut ControlFlow) {
+        let Some(stress) = &mut self.stress else {
+            return;
+        };
+        let surface = self.renderer.resources.surface_mut();
+        match stress.frame_rendered(surface.dots().len()) {
+            StressStep::AddDots(dots) => {
+                surface.add_dots(&dots);
+                self.window.request_redraw();
+            }
+            StressStep::Done(report) => {
+                tracing::info!("Stress test drew {} dots within {}ms", report.dots, report.budget_milliseconds);
+                report.publish();
+                self.stress = None;
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+        }
+    }
+
+    fn handle_window_event(&mut self, event: WindowEvent<'_>, control_flow: &mut ControlFlow) {
+        if self.new_document.is_some() && self.renderer.overlay.handle(&event) {
+            self.window.request_redraw();
+        }
+        match event {
+            WindowEvent::Resized(size) => {
+                self.renderer.resize(size);
+                // On macos the window needs to be redrawn manually after resizing
+                self.window.request_redraw();
+            }
+            // On the web this fires when devicePixelRatio changes (zoom, moving between monitors)
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                self.renderer.resize(*new_inner_size);
+                self.window.request_redraw();
+            }
+            WindowEvent::CloseRequested => {
+                // Like the page being hidden does on the web
+                #[cfg(not(target_arch = "wasm32"))]
+                if self.unsaved {
+                    self.save_document();
+                }
+                self.session().save();
+                *control_flow = ControlFlow::Exit;
+            }
+            // The dialog has the input
+            _ if self.new_document.is_some() => {}
+            event => {
+                let (resources, window) = (&self.renderer.resources, &self.window);
+                if let Some(action) = self.input.handle(&event, |position| to_canvas(resources, window, position)) {
+                    self.apply(action);
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, action: Action) {
+        let resources = &mut self.renderer.resources;
+        match action {
+            Action::Command(command) => self.bus.publish(command),
+            Action::ToggleLutBypass => {
+                let post_process = resources.post_process_mut();
+                let bypass = !post_process.lut_bypass();
+                post_process.set_lut_bypass(bypass);
+                tracing::info!("LUT {}", if bypass { "bypassed" } else { "applied" });
+                self.window.request_redraw();
+            }
+            Action::CycleColorBlindness => {
+                let post_process = resources.post_process_mut();
+                let next = match post_process.color_blindness() {
+                    None => ColorBlindness::ALL.first(),
+                    Some(current) => ColorBlindness::ALL.iter().skip_while(|&&kind| kind != current).nth(1),
+                };
+                post_process.set_color_blindness(&self.renderer.device, next.copied());
+                tracing::info!("Simulating {}", next.map_or("normal color vision", |kind| kind.name()));
+                self.window.request_redraw();
+            }
+            Action::AddBlur => {
+                let filter = Filter::new(FilterKind::GaussianBlur { radius: 8.0 });
+                add_filter(resources.surface_mut(), &mut self.painting, filter);
+                self.window.request_redraw();
+            }
+            Action::ClearFilters => {
+                clear_filters(resources.surface_mut(), &mut self.painting);
+                self.window.request_redraw();
+            }
+            Action::CountHistogram => count_histogram(resources.surface(), &self.tasks),
+            Action::SelectAt(position) => {
+                select_at(resources.surface_mut(), position);
+                self.window.request_redraw();
+            }
+            Action::ClearSelection => {
+                resources.surface_mut().clear_selection();
+                self.window.request_redraw();
+            }
+            Action::PickColorAt(position) => pick_color_at(resources.surface(), position, &self.tasks),
+            Action::InspectDotAt(position) => {
+                let surface = resources.surface();
+                match surface.dot_at(position) {
+                    Some(index) => {
+                        tracing::info!("Dot {index} of {}: {:?}", surface.dots().len(), surface.dots()[index]);
+                        if let Some(stroke) = surface.stroke_of(index) {
+                            let dots = surface.stroke_dots(stroke).len();
+                            tracing::info!("Of stroke {} with {dots} dots: {:?}", stroke.0, surface.stroke_info(stroke));
+                        }
+                    }
+                    None => tracing::info!("No dot at {position:?}"),
+                }
+            }
+            Action::PickSwatch(index) => match self.palette.all_swatches().nth(index) {
+                Some(swatch) => {
+                    self.bus.publish(Command::SetBrushColor(swatch.color));
+                    tracing::info!("Painting with {:?}", swatch.name);
+                }
+                None => tracing::info!("The palette has no swatch {}", index + 1),
+            },
+            Action::PickRecentColor(index) => match self.recent_colors.colors().get(index) {
+                Some(&color) => self.bus.publish(Command::SetBrushColor(color)),
+                None => tracing::info!("There is no recent color {}", index + 1),
+            },
+            Action::ToggleWetBrush => {
+                let brush = *self.painting.brush();
+                let wet = !brush.wet;
+                tracing::info!("Painting {}", if wet { "wet watercolor" } else { "dots" });
+                self.bus.publish(Command::SetBrush(Brush { wet, ..brush }));
+            }
+            Action::NextFrame => match resources.timeline_mut().next_frame() {
+                Ok(()) => self.switched_frame(),
+                Err(err) => tracing::error!("Couldn't switch frames: {err}"),
+            },
+            Action::PreviousFrame => match resources.timeline_mut().previous_frame() {
+                Ok(()) => self.switched_frame(),
+                Err(err) => tracing::error!("Couldn't switch frames: {err}"),
+            },
+            Action::AddFrame => match resources.timeline_mut().add_frame() {
+                Ok(_) => self.switched_frame(),
+                Err(err) => tracing::error!("Couldn't add a frame: {err}"),
+            },
+            Action::DuplicateFrame => match resources.timeline_mut().duplicate_frame() {
+                Ok(_) => self.switched_frame(),
+                Err(err) => tracing::error!("Couldn't duplicate the frame: {err}"),
+            },
+            Action::ToggleOnionSkin => {
+                let timeline = resources.timeline_mut();
+                let enabled = !timeline.onion_skin().is_enabled();
+                timeline.set_onion_skin(if enabled { OnionSkin::default() } else { OnionSkin::OFF });
+                tracing::info!("Onion skin {}", if enabled { "shown" } else { "hidden" });
+                self.window.request_redraw();
+            }
+            Action::Replay if self.painting.recording().is_empty() => tracing::info!("There's nothing to replay yet"),
+            Action::Replay => {
+                let replay = Replay::new(self.painting.recording(), self.replay_speed);
+                match resources.timeline_mut().add_frame() {
+                    Ok(_) => {
+                        tracing::info!("Replaying {:.1} seconds of painting on a new frame", replay.seconds());
+                        self.switched_frame();
+                        self.replay = Some((replay, Clock::new()));
+                    }
+                    Err(err) => tracing::error!("Couldn't add a frame to replay on: {err}"),
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Action::ExportReplay => {
+                let (path, options) = self.video.clone();
+                let recording = self.painting.recording().clone();
+                export_replay(recording, self.replay_speed, path, options, &self.tasks);
+            }
+            #[cfg(target_arch = "wasm32")]
+            Action::ExportReplay => tracing::warn!("Videos can only be exported natively"),
+            #[cfg(not(target_arch = "wasm32"))]
+            Action::ExportVideo => {
+                let (path, options) = self.video.clone();
+                export_video(resources.timeline(), path, options, &self.tasks);
+            }
+            #[cfg(target_arch = "wasm32")]
+            Action::ExportVideo => tracing::warn!("Videos can only be exported natively"),
+            #[cfg(not(target_arch = "wasm32"))]
+            Action::SaveDocument => self.save_document(),
+            Action::NewDocument => self.ask_new_document(),
+            #[cfg(target_arch = "wasm32")]
+            Action::SaveDocument => save_autosave(self.storage.clone(), resources.surface().document()),
+            Action::Generate => {
+                let generator = Generator::ALL[self.next_generator];
+                self.next_generator = (self.next_generator + 1) % Generator::ALL.len();
+                let dots = generator.dots(self.painting.brush());
+                tracing::info!("Adding {} dots in a {} layout", dots.len(), generator.name());
+                self.bus.publish(Command::AddDots(dots));
+            }
+            Action::DrawLSystem => {
+                let presets = LSystem::presets();
+                let (name, lsystem) = &presets[self.next_lsystem];
+                self.next_lsystem = (self.next_lsystem + 1) % presets.len();
+                match lsystem.dots(self.painting.brush()) {
+                    Ok(dots) => {
+                        tracing::info!("Drawing a {name} with {} dots", dots.len());
+                        self.bus.publish(Command::AddDots(dots));
+                    }
+                    Err(err) => tracing::error!("Couldn't draw a {name}: {err}"),
+                }
+            }
+            #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+            Action::RunScript => self.run_script(),
+            #[cfg(not(all(feature = "scripting", not(target_arch = "wasm32"))))]
+            Action::RunScript => tracing::warn!("Scripts only run natively, in builds with the scripting feature"),
+            Action::DumpInstances => dump_instances(resources.surface(), &self.tasks),
+            Action::CaptureFrame => {
+                self.renderer.capture_next_frame();
+                self.window.request_redraw();
+            }
+            Action::CycleComparison => {
+                let comparison = match resources.comparison() {
+                    None => Some(Comparison::SideBySide),
+                    Some(Comparison::SideBySide) => Some(Comparison::Split(0.5)),
+                    Some(Comparison::Split(_)) => None,
+                };
+                if comparison.is_some() && !resources.has_snapshot() {
+                    resources.take_snapshot(&self.renderer.device, &self.renderer.queue);
+                    tracing::info!("Took a snapshot to compare with");
+                }
+                resources.set_comparison(comparison);
+                tracing::info!("Comparing with the snapshot: {comparison:?}");
+                self.window.request_redraw();
+            }
+            Action::TakeSnapshot => {
+                resources.take_snapshot(&self.renderer.device, &self.renderer.queue);
+                tracing::info!("Took a snapshot to compare with");
+                self.window.request_redraw();
+            }
+            Action::CycleGuides => {
+                let guides = match resources.guides() {
+                    None => Guides::PRESETS.first(),
+                    Some(current) => Guides::PRESETS.iter().skip_while(|guides| guides.name() != current.name()).nth(1),
+                };
+                resources.set_guides(guides.copied());
+                if self.painting.snap().is_some() {
+                    self.painting.set_snap(guides.copied());
+                }
+                tracing::info!("Guides: {}", guides.map_or("none", |guides| guides.name()));
+                self.window.request_redraw();
+            }
+            Action::ToggleSnap => {
+                let snap = match self.painting.snap() {
+                    Some(_) => None,
+                    None => resources.guides().copied(),
+                };
+                match (snap, resources.guides()) {
+                    (Some(_), _) => tracing::info!("Snapping strokes to the guides"),
+                    (None, Some(_)) => tracing::info!("Not snapping strokes anymore"),
+                    (None, None) => tracing::info!("There are no guides to snap to, show some with P"),
+                }
+                self.painting.set_snap(snap);
+            }
+            Action::FlipHorizontally | Action::FlipVertically => {
+                let ViewFlip { horizontal, vertical } = resources.flip();
+                let flip = match action {
+                    Action::FlipHorizontally => ViewFlip {
+                        horizontal: !horizontal,
+                        vertical,
+                    },
+                    _ => ViewFlip {
+                        horizontal,
+                        vertical: !vertical,
+                    },
+                };
+                resources.set_flip(flip);
+                tracing::info!("View flipped {flip:?}");
+                self.window.request_redraw();
+            }
+            Action::MoveSplit([x, _]) => {
+                if let Some(Comparison::Split(_)) = resources.comparison() {
+                    resources.set_comparison(Some(Comparison::Split((x + 1.0) / 2.0)));
+                    self.window.request_redraw();
+                }
+            }
+        }
+    }
+
+    /// Runs the script again, publishing what it painted. The file is read anew, so it can be
+    /// edited in between.
+    #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+    fn run_script(&mut self) {
+        let Some(path) = &self.script else {
+            tracing::warn!("There is no script to run, pass one with --script");
+            return;
+        };
+        let surface = self.renderer.resources.surface();
+        let context = ScriptContext {
+            canvas_size: surface.global.texture_desc.size.width,
+            dot_count: surface.dots().len(),
+            brush: *self.painting.brush(),
+        };
+        match script::run_script_file(path, context) {
+            Ok(commands) => {
+                tracing::info!("Ran {path}, painting {} commands", commands.len());
+                for command in commands {
+                    self.bus.publish(command);
+                }
+                self.window.request_redraw();
+            }
+            Err(err) => tracing::error!("Couldn't run {path}: {err}"),
+        }
+    }
+
+    /// Saves the document where it was opened from or last saved to, or to [`DOCUMENT_FILE_NAME`].
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_document(&mut self) {
+        let path = self.document_path.clone().unwrap_or_else(|| DOCUMENT_FILE_NAME.into());
+        let result = self
+            .renderer
+            .resources
+            .surface()
+            .document()
+            .to_json()
+            .map_err(|err| err.to_string())
+            .and_then(|json| std::fs::write(&path, json).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => {
+                tracing::info!("Saved the document to {}", path.display());
+                self.recent_documents.push(&path);
+                self.recent_documents.save();
+                self.document_path = Some(path);
+                self.unsaved = false;
+            }
+            Err(err) => tracing::error!("Couldn't save the document to {}: {err}", path.display()),
+        }
+    }
+
+    /// Asks which document to start next over the canvas.
+    fn ask_new_document(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let recent = self.recent_documents.paths();
+        #[cfg(target_arch = "wasm32")]
+        let recent = &[];
+        self.new_document = Some(NewDocumentDialog::new(self.new_canvas, recent));
+        self.window.request_redraw();
+    }
+
+    /// Lays out the new-document dialog for the next frame if it's shown, carrying out its answer
+    /// once there is one.
+    fn show_new_document(&mut self) {
+        let Some(dialog) = &mut self.new_document else {
+            return;
+        };
+        let mut answer = None;
+        if self.renderer.overlay.run(&self.window, |context| answer = dialog.show(context)) {
+            self.window.request_redraw();
+        }
+        let Some(answer) = answer else {
+            return;
+        };
+        self.new_document = None;
+        match answer {
+            DialogAnswer::New(canvas) => {
+                self.new_canvas = canvas;
+                if self.start_document(canvas, None) {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        self.document_path = None;
+                    }
+                    tracing::info!("Started a new {} x {} document", canvas.size, canvas.size);
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            DialogAnswer::Open(path) => match open_document(&path) {
+                Ok(document) => {
+                    let canvas = document.canvas.unwrap_or(self.new_canvas);
+                    if self.start_document(canvas, Some(document)) {
+                        tracing::info!("Opened {}", path.display());
+                        self.recent_documents.push(&path);
+                        self.recent_documents.save();
+                        self.document_path = Some(path);
+                    }
+                }
+                Err(err) => tracing::error!("Couldn't open {}: {err}", path.display()),
+            },
+            // The web has no recent documents to pick
+            #[cfg(target_arch = "wasm32")]
+            DialogAnswer::Open(_) => {}
+            DialogAnswer::Cancel => {}
+        }
+        self.window.request_redraw();
+    }
+
+    /// Replaces the canvas with one of `canvas`'s size and background, painted with `document` if
+    /// there is one, returning whether it could. Natively, unsaved strokes are saved first, like
+    /// when the window closes.
+    fn start_document(&mut self, canvas: CanvasSetup, document: Option<Document>) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.unsaved {
+            self.save_document();
+        }
+        if let Err(err) = self.renderer.new_document(canvas) {
+            tracing::error!("Couldn't start the document: {err}");
+            return false;
+        }
+        if let Some(document) = document {
+            self.renderer.resources.surface_mut().set_document(document);
+        }
+        self.replay = None;
+        self.painting.forget_history();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.unsaved = false;
+        }
+        true
+    }
+
+    /// What to pick up from when the app starts next.
+    fn session(&self) -> Session {
+        let resources = &self.renderer.resources;
+        Session {
+            #[cfg(not(target_arch = "wasm32"))]
+            // The session is kept in the config directory, not where the app was started
+            document: self.document_path.as_deref().map(|path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())),
+            brush: *self.painting.brush(),
+            view: ViewState {
+                flip: resources.flip(),
+                guides: resources.guides().copied(),
+                snap: self.painting.snap().is_some(),
+                color_blindness: resources.post_process().color_blindness(),
+                onion_skin: resources.timeline().onion_skin().is_enabled(),
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            window: WindowGeometry::of(&self.window),
+            #[cfg(target_arch = "wasm32")]
+            window: None,
+        }
+    }
+
+    /// Follows up on another frame of the timeline becoming current.
+    fn switched_frame(&mut self) {
+        let timeline = self.renderer.resources.timeline();
+        tracing::info!("Frame {} of {}", timeline.current_index() + 1, timeline.frame_count());
+        // Undoing would take back strokes of the previous frame on this one
+        self.painting.forget_history();
+        self.window.request_redraw();
+    }
+
+    fn handle_user_event(&mut self, event: UserEvent, control_flow: &mut ControlFlow) {
+        let Renderer {
+            device,
+            queue,
+            resources,
+            ..
+        } = &mut self.renderer;
+        match event {
+            #[cfg(target_arch = "wasm32")]
+            UserEvent::PageHidden => {
+                save_autosave(self.storage.clone(), resources.surface().document());
+                self.session().save();
+            }
+            UserEvent::Command(command) => self.bus.publish(command),
+            UserEvent::SetLut(lut) => {
+                resources.post_process_mut().set_lut(device, queue, lut.as_ref());
+                self.window.request_redraw();
+            }
+            UserEvent::SetLutBypass(bypass) => {
+                resources.post_process_mut().set_lut_bypass(bypass);
+                self.window.request_redraw();
+            }
+            UserEvent::SetColorBlindness(color_blindness) => {
+                resources.post_process_mut().set_color_blindness(device, color_blindness);
+                self.window.request_redraw();
+            }
+            UserEvent::SetAdjustmentLayers(layers) => {
+                resources.post_process_mut().set_adjustment_layers(device, queue, layers);
+                self.window.request_redraw();
+            }
+            UserEvent::SetPalette(palette) => self.palette = palette,
+            UserEvent::SetGuides(guides) => {
+                resources.set_guides(guides);
+                if self.painting.snap().is_some() {
+                    self.painting.set_snap(guides);
+                }
+                self.window.request_redraw();
+            }
+            UserEvent::AddFilter(filter) => {
+                add_filter(resources.surface_mut(), &mut self.painting, filter);
+                self.window.request_redraw();
+            }
+            UserEvent::ClearFilters => {
+                clear_filters(resources.surface_mut(), &mut self.painting);
+                self.window.request_redraw();
+            }
+            UserEvent::PreviewFilter(filter) => {
+                if let Err(err) = resources.surface_mut().set_preview(filter) {
+                    tracing::error!("Couldn't preview the filter: {err}");
+                }
+                self.window.request_redraw();
+            }
+            UserEvent::NewDocument => self.ask_new_document(),
+            UserEvent::CommitPreview => {
+                let surface = resources.surface_mut();
+                let filters = surface.filters().to_vec();
+                if surface.commit_preview() {
+                    self.painting.filters_changed(filters);
+                } else {
+                    tracing::warn!("There is no filter preview to commit");
+                }
+            }
+            UserEvent::RegisterKernel(name, kernel) => {
+                match resources.surface().global.register_kernel(name, kernel) {
+                    Ok(()) => self.window.request_redraw(),
+                    Err(err) => tracing::error!("Couldn't register the kernel: {err}"),
+                }
+            }
+            UserEvent::Select(wand) => {
+                match wand {
+                    Some(wand) => resources.surface_mut().select(wand),
+                    None => resources.surface_mut().clear_selection(),
+                }
+                self.window.request_redraw();
+            }
+            UserEvent::RecentColors(sender) => {
+                sender.send(self.recent_colors.colors().to_vec()).ok();
+            }
+            UserEvent::DotsIn(rect, sender) => {
+                sender.send(resources.surface().dots_in(rect)).ok();
+            }
+            UserEvent::SetDotSnippet(snippet, sender) => {
+                let result = resources.surface().global.set_dot_snippet(snippet.as_deref());
+                if result.is_ok() {
+                    self.window.request_redraw();
+                }
+                sender.send(result).ok();
+            }
+            UserEvent::Histogram(sender) => {
+                let readback = resources.surface().histogram();
+                self.tasks.spawn(async move {
+                    let histogram = match readback {
+                        Some(readback) => readback.into_histogram().await.map_err(|err| {
+                            tracing::error!("Couldn't read back the histogram: {err}");
+                        }),
+                        None => Err(()),
+                    };
+                    sender.send(histogram.ok()).ok();
+                });
+            }
+            UserEvent::ExportPng(sender) => {
+                let framing = export_framing(&self.framing, self.export_viewport, resources, &self.window);
+                let readback = detailed_readback(resources.surface(), &framing);
+                let options = self.export_options;
+                self.tasks.spawn(async move {
+                    let png = match readback {
+                        Ok(readback) => export::export_png(readback, options).await,
+                        Err(err) => Err(err),
+                    };
+                    sender.send(png).ok();
+                });
+            }
+            UserEvent::Task(TaskEvent::Exported(result)) => match result {
+                Ok(file_name) => tracing::info!("Exported canvas to {file_name}"),
+                Err(err) => tracing::error!("Export failed: {err}"),
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            UserEvent::Task(TaskEvent::ExportedVideo(result)) => match result {
+                Ok((path, frames)) => tracing::info!("Exported {frames} frames to {}", path.display()),
+                Err(err) => tracing::error!("Video export failed: {err}"),
+            },
+            UserEvent::Task(TaskEvent::Histogram(result)) => match result {
+                Ok(histogram) => log_histogram(&histogram),
+                Err(err) => tracing::error!("Couldn't read back the histogram: {err}"),
+            },
+            UserEvent::Task(TaskEvent::PickedColor(result)) => match result {
+                Ok(color) => {
+                    tracing::info!("Painting with the picked color {:?}", color.to_srgb8());
+                    self.bus.publish(Command::SetBrushColor(color));
+                }
+                Err(err) => tracing::error!("Couldn't pick the color: {err}"),
+            },
+            UserEvent::Task(TaskEvent::DumpedInstances(result)) => match result {
+                Ok(dump) => log_dump(&dump),
+                Err(err) => tracing::error!("Couldn't dump the instance buffers: {err}"),
+            },
+            UserEvent::SetStrokeListener(listener) => self.stroke_listener = Some(listener),
+            UserEvent::DeviceRecreated(recreated) => match self.renderer.recover(recreated) {
+                Ok(()) => {
+                    tracing::info!("Recovered from losing the GPU device");
+                    self.window.request_redraw();
+                }
+                Err(err) => {
+                    tracing::error!("Quitting, couldn't recover from losing the GPU device: {err}");
+                    *control_flow = ControlFlow::ExitWithCode(1);
+                }
+            },
+        }
+    }
+}
+
+/// Maps a position in `window` to canvas coordinates.
+fn to_canvas(render_resources: &SurfaceRenderResources, window: &Window, position: PhysicalPosition<f64>) -> [f32; 2] {
+    let size = window.inner_size();
+    render_resources.viewport_to_canvas(
+        [position.x as f32, position.y as f32],
+        [size.width as f32, size.height as f32],
+    )
+}
+
+/// The canvas pixel, from the top left, at `position` in canvas coordinates, if it's on the
+/// canvas.
+fn canvas_pixel(surface: &HpSurface, [x, y]: [f32; 2]) -> Option<(u32, u32)> {
+    let canvas_size = surface.global.texture_desc.size;
+    if !(-1.0..1.0).contains(&x) || !(-1.0..1.0).contains(&y) {
+        return None;
+    }
+    Some((
+        ((x + 1.0) / 2.0 * canvas_size.width as f32) as u32,
+        ((1.0 - y) / 2.0 * canvas_size.height as f32) as u32,
+    ))
+}
+
+/// Starts a magic wand selection at the canvas pixel under `position`, in canvas coordinates.
+fn select_at(surface: &mut HpSurface, position: [f32; 2]) {
+    let Some((x, y)) = canvas_pixel(surface, position) else {
+        return;
+    };
+    surface.select(MagicWand { x, y, tolerance: 0.1 });
+}
+
+/// Reads back the color of the canvas pixel under `position`, in canvas coordinates, to paint
+/// with it. Like exports, it's picked from all of the dots, rendering them again if the view
+/// merged tiny ones.
+fn pick_color_at(surface: &HpSurface, position: [f32; 2], tasks: &Tasks) {
+    let Some((x, y)) = canvas_pixel(surface, position) else {
+        return;
+    };
+    if surface.lod_level() > 0 {
+        if let Err(err) = surface.render_detailed() {
+            tracing::error!("Couldn't pick the color: {err}");
+            return;
+        }
+    }
+    let readback = surface.readback();
+    tasks.spawn_reporting(async move { TaskEvent::PickedColor(readback.into_color_at(x, y).await) });
+}
+
+/// Adds `filter` to the canvas, logging how far the GPU got with it. Undo takes it off again.
+fn add_filter(surface: &mut HpSurface, painting: &mut Painting, filter: Filter) {
+    let filters = surface.filters().to_vec();
+    let progress: FilterProgress = Arc::new(|fraction| tracing::info!("Filtering: {:.0}%", fraction * 100.0));
+    match surface.add_filter(filter, Some(progress)) {
+        Ok(()) => painting.filters_changed(filters),
+        Err(err) => tracing::error!("Couldn't add the filter: {err}"),
+    }
+}
+
+/// Removes the canvas's filters so that undo puts them back.
+fn clear_filters(surface: &mut HpSurface, painting: &mut Painting) {
+    let filters = surface.filters().to_vec();
+    surface.clear_filters();
+    if !filters.is_empty() {
+        painting.filters_changed(filters);
+    }
+}
+
+fn finish_stroke(stroke: Stroke, listener: &mut Option<StrokeListener>, recent_colors: &mut RecentColors) {
+    if !stroke.dots().is_empty() && recent_colors.push(stroke.brush().color) {
+        recent_colors.save();
+    }
+    if let Some(listener) = listener {
+        listener(stroke.dots());
+    }
+}
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+const SHADER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Band amplitude below which the audio input paints nothing, keeping room noise off the canvas.
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+const AUDIO_THRESHOLD: f32 = 0.02;
+/// Dots the audio input paints before the oldest half makes room.
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+const MAX_AUDIO_DOTS: usize = 100_000;
+
+/// Starts listening to the default input device if `--audio` asks to, with dots placed from `seed`.
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+fn open_audio_input(config: &Config, seed: u64) -> Option<(AudioInput, ChaCha8Rng)> {
+    if !config.audio {
+        return None;
+    }
+    match AudioInput::open_default() {
+        Ok(input) => {
+            tracing::info!("Painting to the sound of the default input device");
+            Some((input, ChaCha8Rng::seed_from_u64(seed)))
+        }
+        Err(err) => {
+            tracing::error!("Not painting to sound: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_palette(path: &str) -> Result<Palette, Box<dyn std::error::Error>> {
+    Ok(Palette::load(path, &std::fs::read(path)?)?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_watermark(path: &str) -> Result<Watermark, Box<dyn std::error::Error>> {
+    Ok(Watermark::from_png(&std::fs::read(path)?)?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_stipples(path: &str) -> Result<Vec<Dot>, Box<dyn std::error::Error>> {
+    let image = StippleImage::from_png(&std::fs::read(path)?)?;
+    Ok(stipple::stipple(&image, &StippleOptions::default())?)
+}
+
+/// Records the spans of the session as a chrome://tracing file at `path`, finished when the returned
+/// guard is dropped. Log messages still go to env_logger.
+#[cfg(not(target_arch = "wasm32"))]
+fn record_chrome_trace(path: &str) -> Option<tracing_chrome::FlushGuard> {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let file = match std::fs::File::create(path) {
+        Ok(file) => std::io::BufWriter::new(file),
+        Err(err) => {
+            tracing::error!("Couldn't create the trace file {path}: {err}");
+            return None;
+        }
+    };
+    let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+        .writer(file)
+        .include_args(true)
+        .build();
+    if let Err(err) = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)) {
+        tracing::error!("Couldn't record a trace: {err}");
+        return None;
+    }
+    tracing::info!("Recording a trace to {path}, open it in chrome://tracing or ui.perfetto.dev");
+    Some(guard)
+}
+
+const EXPORT_FILE_NAME: &str = "hellopaint.png";
+
+/// Reads back the canvas with all of its dots, rendering it again if the view merged tiny ones,
+/// framed by `framing`.
+fn detailed_readback(surface: &HpSurface, framing: &Framing) -> Result<TextureReadback, ExportError> {
+    if surface.lod_level() > 0 {
+        surface.render_detailed().map_err(ExportError::Render)?;
+    }
+    Ok(surface.framed_readback(framing))
+}
+
+/// `framing`, cropped to the part of the canvas `window` shows if `viewport`.
+fn export_framing(framing: &Framing, viewport: bool, resources: &SurfaceRenderResources, window: &Window) -> Framing {
+    let mut framing = framing.clone();
+    if viewport {
+        let size = window.inner_size();
+        framing.crop = Some(CanvasRect::from_corners(
+            to_canvas(resources, window, PhysicalPosition::new(0.0, 0.0)),
+            to_canvas(resources, window, PhysicalPosition::new(size.width as f64, size.height as f64)),
+        ));
+    }
+    framing
+}
+
+/// Exports the canvas as PNG in the background: written to the working directory natively,
+/// offered as a download on the web.
+fn export_canvas(surface: &HpSurface, framing: &Framing, options: ExportOptions, tasks: &Tasks) {
+    let readback = detailed_readback(surface, framing);
+    tasks.spawn_reporting(async move {
+        let png = match readback {
+            Ok(readback) => export::export_png(readback, options).await,
+            Err(err) => Err(err),
+        };
+        let result = png.and_then(|png| {
+            #[cfg(not(target_arch = "wasm32"))]
+            return export::save_png(EXPORT_FILE_NAME, &png);
+            #[cfg(target_arch = "wasm32")]
+            return export::download_png(&png, EXPORT_FILE_NAME);
+        });
+        TaskEvent::Exported(result.map(|()| EXPORT_FILE_NAME))
+    });
+}
+
+/// Renders the frames of `timeline` into a video at `path` in the background, on a device of its
+/// own.
+#[cfg(not(target_arch = "wasm32"))]
+fn export_video(timeline: &Timeline, path: std::path::PathBuf, options: VideoOptions, tasks: &Tasks) {
+    let frames: Vec<VideoFrame> = timeline.frames().iter().map(VideoFrame::of).collect();
+    tracing::info!("Exporting {} frames to {}", frames.len(), path.display());
+    tasks.spawn_reporting(async move {
+        let result = video::export_video(&frames, &path, options);
+        TaskEvent::ExportedVideo(result.map(|frames| (path, frames)))
+    });
+}
+
+/// Renders a replay of `recording` at `speed` into a video at `path` in the background, on a
+/// device of its own.
+#[cfg(not(target_arch = "wasm32"))]
+fn export_replay(recording: Recording, speed: f64, path: std::path::PathBuf, options: VideoOptions, tasks: &Tasks) {
+    tracing::info!("Exporting a replay to {}", path.display());
+    tasks.spawn_reporting(async move {
+        let result = VideoExporter::new(&path, options).and_then(|mut exporter| {
+            exporter.add_replay(&recording, speed)?;
+            exporter.finish()
+        });
+        TaskEvent::ExportedVideo(result.map(|frames| (path, frames)))
+    });
+}
+
+/// Reads back the instance buffers in the background, writing them to disk next to the dots
+/// natively, to be logged by [`log_dump`].
+fn dump_instances(surface: &HpSurface, tasks: &Tasks) {
+    let readback = surface.instance_readback();
+    tasks.spawn_reporting(async move {
+        let result = readback.into_dump().await;
+        #[cfg(not(target_arch = "wasm32"))]
+        let result = result.and_then(|dump| dump.save().map(|()| dump));
+        TaskEvent::DumpedInstances(result)
+    });
+}
+
+/// Logs where a dump went and whether the GPU's instances match the dots.
+fn log_dump(dump: &dump::InstanceDump) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tracing::info!(
+        "Wrote {} dots to {} and {} bytes of instance buffers to {}",
+        dump.dots.len(),
+        dump::DOTS_FILE_NAME,
+        dump.instance_bytes.len(),
+        dump::INSTANCES_FILE_NAME,
+    );
+    match dump.mismatches().as_slice() {
+        [] => tracing::info!("The instance buffers match the {} dots", dump.dots.len()),
+        mismatches => tracing::warn!(
+            "{} of {} dots differ in the instance buffers, the first at index {}",
+            mismatches.len(),
+            dump.dots.len(),
+            mismatches[0],
+        ),
+    }
+}
+
+/// Counts the canvas into histograms in the background, to be logged by [`log_histogram`].
+fn count_histogram(surface: &HpSurface, tasks: &Tasks) {
+    let Some(readback) = surface.histogram() else {
+        tracing::warn!("Histograms need compute shaders, which this device doesn't have");
+        return;
+    };
+    tasks.spawn_reporting(async move { TaskEvent::Histogram(readback.into_histogram().await) });
+}
+
+/// Logs the luminance histogram of the canvas as a sparkline, with its 1% and 99% points.
+fn log_histogram(histogram: &Histogram) {
+    const BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let buckets: Vec<u64> = histogram
+        .luminance
+        .chunks(8)
+        .map(|bins| bins.iter().map(|&count| count as u64).sum())
+        .collect();
+    let max = buckets.iter().copied().max().unwrap_or_default().max(1);
+    let sparkline: String = buckets
+        .iter()
+        .map(|&count| BARS[((count * (BARS.len() as u64 - 1) + max - 1) / max) as usize])
+        .collect();
+    tracing::info!(
+        "Luminance {sparkline} 1%: {:?} 99%: {:?}",
+        histogram.luminance_percentile(0.01),
+        histogram.luminance_percentile(0.99),
+    );
+}
+
+/// Loads a document saved with Ctrl+D.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_document(path: impl AsRef<std::path::Path>) -> Result<Document, Box<dyn std::error::Error>> {
+    Ok(Document::from_json(&std::fs::read_to_string(path)?)?)
+}
+
+/// The document saved when the page was last hidden, if there is one.
+#[cfg(target_arch = "wasm32")]
+async fn load_autosave(storage: Option<&IndexedDbStorage>) -> Option<Document> {
+    match storage?.load(AUTOSAVE_DOCUMENT).await {
+        Ok(document) => document,
+        Err(err) => {
+            tracing::error!("Couldn't restore the autosaved document: {err}");
+            None
+        }
+    }
+}
+
+/// Saves `document` in the background, to be restored when the page is next opened.
+#[cfg(target_arch = "wasm32")]
+fn save_autosave(storage: Option<Rc<IndexedDbStorage>>, document: Document) {
+    if let Some(storage) = storage {
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = storage.save(AUTOSAVE_DOCUMENT, &document).await {
+                tracing::error!("Autosave failed: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn notify_when_page_hidden(proxy: winit::event_loop::EventLoopProxy<UserEvent>) {
+    use wasm_bindgen::JsCast;
+
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let on_visibility_change = {
+        let document = document.clone();
+        wasm_bindgen::closure::Closure::<dyn FnMut()>::new(move || {
+            if document.visibility_state() == web_sys::VisibilityState::Hidden {
+                proxy.send_event(UserEvent::PageHidden).ok();
+            }
+        })
+    };
+    document
+        .add_event_listener_with_callback("visibilitychange", on_visibility_change.as_ref().unchecked_ref())
+        .expect("couldn't register visibilitychange listener");
+    on_visibility_change.forget();
+}
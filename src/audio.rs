@@ -0,0 +1,332 @@
+//! Live visuals from sound: an [`AudioAnalyzer`] turns samples into [`AudioLevels`], the loudness
+//! and how much of it falls into each of [`BANDS`] frequency bands, which can drive a brush with
+//! [`drive_brush`] or paint dots on their own with [`spawn_dots`]. With the `audio` feature,
+//! `AudioInput` feeds it from the default input device natively.
+
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::color::{Color, Hsv};
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+/// Samples the bands are computed from, a power of two. About 20ms at 48kHz.
+pub const FFT_SIZE: usize = 1024;
+/// Frequency bands, spaced evenly on a log scale between [`LOWEST_FREQUENCY`] and
+/// [`HIGHEST_FREQUENCY`] like pitch is heard.
+pub const BANDS: usize = 8;
+pub const LOWEST_FREQUENCY: f32 = 40.0;
+pub const HIGHEST_FREQUENCY: f32 = 16_000.0;
+
+/// How loud the latest samples are, all roughly 0..1 for full scale signals.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AudioLevels {
+    /// Root mean square of the samples.
+    pub rms: f32,
+    /// Peak amplitude in each band, from the lowest.
+    pub bands: [f32; BANDS],
+}
+
+impl AudioLevels {
+    /// The band with the most amplitude, `None` in silence.
+    pub fn loudest_band(&self) -> Option<usize> {
+        self.bands
+            .iter()
+            .enumerate()
+            .filter(|(_, &level)| level > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(band, _)| band)
+    }
+}
+
+/// Keeps the latest [`FFT_SIZE`] mono samples and analyzes them.
+#[derive(Debug, Clone)]
+pub struct AudioAnalyzer {
+    sample_rate: f32,
+    /// A ring buffer, `next` is the oldest sample.
+    samples: Vec<f32>,
+    next: usize,
+}
+
+impl AudioAnalyzer {
+    /// Starts in silence, for samples at `sample_rate` per second.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f32,
+            samples: vec![0.0; FFT_SIZE],
+            next: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate as u32
+    }
+
+    /// Adds mono samples in -1..1, forgetting the oldest.
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.samples[self.next] = sample;
+            self.next = (self.next + 1) % FFT_SIZE;
+        }
+    }
+
+    /// Adds `channels` interleaved channels, mixed down to mono.
+    pub fn push_interleaved(&mut self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        for frame in samples.chunks(channels) {
+            let mono = frame.iter().sum::<f32>() / frame.len() as f32;
+            self.push(&[mono]);
+        }
+    }
+
+    /// Levels of the latest samples.
+    pub fn levels(&self) -> AudioLevels {
+        let ordered = self.samples[self.next..].iter().chain(&self.samples[..self.next]);
+        let rms = (self.samples.iter().map(|sample| sample * sample).sum::<f32>() / FFT_SIZE as f32).sqrt();
+
+        // A Hann window keeps the edges of the buffer from smearing into every band
+        let mut real: Vec<f32> = ordered
+            .enumerate()
+            .map(|(i, sample)| sample * 0.5 * (1.0 - (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+            .collect();
+        let mut imaginary = vec![0.0; FFT_SIZE];
+        fft(&mut real, &mut imaginary);
+
+        // Peak amplitude of a sine in its bin: 2/N for the one-sided spectrum, 2 more for the window
+        let scale = 4.0 / FFT_SIZE as f32;
+        let bin_hz = self.sample_rate / FFT_SIZE as f32;
+        let mut bands = [0.0; BANDS];
+        for (band, level) in bands.iter_mut().enumerate() {
+            let [low, high] = [band, band + 1].map(|edge| {
+                let hz = LOWEST_FREQUENCY * (HIGHEST_FREQUENCY / LOWEST_FREQUENCY).powf(edge as f32 / BANDS as f32);
+                ((hz / bin_hz).round() as usize).clamp(1, FFT_SIZE / 2)
+            });
+            // Low bands can be narrower than a bin, they get the one they're in
+            let power = (low..high.max(low + 1))
+                .map(|bin| (real[bin] * real[bin] + imaginary[bin] * imaginary[bin]) * scale * scale)
+                .fold(0.0, f32::max);
+            *level = power.sqrt();
+        }
+        AudioLevels { rms, bands }
+    }
+}
+
+/// In-place radix-2 FFT of a power of two samples.
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let n = real.len();
+    debug_assert!(n.is_power_of_two());
+    // A single sample is its own transform, and has no bits to reverse
+    if n < 2 {
+        return;
+    }
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (even, odd) = (start + k, start + k + length / 2);
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        length *= 2;
+    }
+}
+
+/// The hue of `band`, from red for the lowest to violet for the highest.
+fn band_hue(band: usize) -> f32 {
+    band as f32 / (BANDS - 1) as f32 * 280.0
+}
+
+/// `brush` swelling with the loudness, up to `1 + gain` times its radius at full scale, and
+/// colored by the loudest band.
+pub fn drive_brush(brush: Brush, levels: &AudioLevels, gain: f32) -> Brush {
+    let color = match levels.loudest_band() {
+        Some(band) => {
+            let hsv = brush.color.to_hsv();
+            Color::from_hsv(Hsv { h: band_hue(band), ..hsv }, brush.color.a)
+        }
+        None => brush.color,
+    };
+    Brush {
+        radius: brush.radius * (1.0 + levels.rms.clamp(0.0, 1.0) * gain),
+        color,
+        ..brush
+    }
+}
+
+/// Dots for one frame of `levels`: one for every band louder than `threshold`, somewhere in its
+/// column of the canvas, lowest band on the left, sized and lit by how much louder it is.
+pub fn spawn_dots(levels: &AudioLevels, threshold: f32, rng: &mut impl Rng) -> Vec<Dot> {
+    let column = 2.0 / BANDS as f32;
+    levels
+        .bands
+        .iter()
+        .enumerate()
+        .filter(|(_, &level)| level > threshold)
+        .map(|(band, &level)| {
+            let loudness = ((level - threshold) / (1.0 - threshold).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let x = -1.0 + column * (band as f32 + rng.gen::<f32>());
+            let color = Color::from_hsv(
+                Hsv {
+                    h: band_hue(band),
+                    s: 0.8,
+                    v: 0.5 + 0.5 * loudness,
+                },
+                1.0,
+            );
+            Dot::new([x, rng.gen_range(-1.0..1.0)], 0.01 + 0.05 * loudness, rng.gen_range(0.2..0.8), color)
+        })
+        .collect()
+}
+
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub use input::{AudioError, AudioInput};
+
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+mod input {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{Sample, SampleFormat};
+
+    use super::{AudioAnalyzer, AudioLevels};
+
+    #[derive(Debug)]
+    pub enum AudioError {
+        NoDevice,
+        Config(cpal::DefaultStreamConfigError),
+        /// The device records samples in a format that isn't supported.
+        SampleFormat(SampleFormat),
+        Build(cpal::BuildStreamError),
+        Play(cpal::PlayStreamError),
+    }
+
+    impl fmt::Display for AudioError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                AudioError::NoDevice => write!(f, "there is no audio input device"),
+                AudioError::Config(err) => write!(f, "failed to configure the audio input: {err}"),
+                AudioError::SampleFormat(format) => write!(f, "unsupported sample format {format}"),
+                AudioError::Build(err) => write!(f, "failed to open the audio input: {err}"),
+                AudioError::Play(err) => write!(f, "failed to start the audio input: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for AudioError {}
+
+    /// Analyzes what the default input device records, on its own thread, while it's kept.
+    pub struct AudioInput {
+        analyzer: Arc<Mutex<AudioAnalyzer>>,
+        _stream: cpal::Stream,
+    }
+
+    impl AudioInput {
+        /// Starts recording from the default input device, e.g. a microphone or loopback.
+        pub fn open_default() -> Result<Self, AudioError> {
+            let device = cpal::default_host().default_input_device().ok_or(AudioError::NoDevice)?;
+            let config = device.default_input_config().map_err(AudioError::Config)?;
+            let channels = config.channels() as usize;
+            let analyzer = Arc::new(Mutex::new(AudioAnalyzer::new(config.sample_rate().0)));
+            let on_error = |err| tracing::warn!("Audio input failed: {err}");
+            let stream = match config.sample_format() {
+                SampleFormat::F32 => build::<f32>(&device, &config.into(), channels, analyzer.clone(), on_error),
+                SampleFormat::I16 => build::<i16>(&device, &config.into(), channels, analyzer.clone(), on_error),
+                SampleFormat::U16 => build::<u16>(&device, &config.into(), channels, analyzer.clone(), on_error),
+                format => return Err(AudioError::SampleFormat(format)),
+            }
+            .map_err(AudioError::Build)?;
+            stream.play().map_err(AudioError::Play)?;
+            Ok(Self {
+                analyzer,
+                _stream: stream,
+            })
+        }
+
+        /// Levels of what was recorded last.
+        pub fn levels(&self) -> AudioLevels {
+            self.analyzer.lock().unwrap().levels()
+        }
+    }
+
+    fn build<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        channels: usize,
+        analyzer: Arc<Mutex<AudioAnalyzer>>,
+        on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample,
+        f32: cpal::FromSample<T>,
+    {
+        device.build_input_stream(
+            config,
+            move |data: &[T], _| {
+                let samples: Vec<f32> = data.iter().map(|&sample| f32::from_sample(sample)).collect();
+                analyzer.lock().unwrap().push_interleaved(&samples, channels);
+            },
+            on_error,
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// The discrete Fourier transform as defined, in `f64`.
+    fn dft(real: &[f32], imaginary: &[f32]) -> Vec<(f64, f64)> {
+        let n = real.len();
+        (0..n)
+            .map(|k| {
+                (0..n).fold((0.0, 0.0), |(sum_real, sum_imaginary), t| {
+                    let angle = -2.0 * std::f64::consts::PI * (k * t % n) as f64 / n as f64;
+                    let (sin, cos) = angle.sin_cos();
+                    let (x, y) = (f64::from(real[t]), f64::from(imaginary[t]));
+                    (sum_real + x * cos - y * sin, sum_imaginary + x * sin + y * cos)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fft_matches_the_dft() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for n in [1, 2, 8, 64, FFT_SIZE] {
+            let real: Vec<f32> = (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            let imaginary: Vec<f32> = (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            let expected = dft(&real, &imaginary);
+            let (mut fft_real, mut fft_imaginary) = (real, imaginary);
+            fft(&mut fft_real, &mut fft_imaginary);
+            // Rounding grows with the number of butterflies each bin goes through
+            let tolerance = 1e-5 * n as f64;
+            for (k, &(x, y)) in expected.iter().enumerate() {
+                let (real, imaginary) = (f64::from(fft_real[k]), f64::from(fft_imaginary[k]));
+                assert!(
+                    (real - x).abs() < tolerance && (imaginary - y).abs() < tolerance,
+                    "bin {k} of {n}: {real} + {imaginary}i, expected {x} + {y}i"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Generative collections: a [`BatchJob`] renders the same [generator](Generator) or script once
+//! for every seed and combination of swept parameters on a headless canvas, writing a PNG per run
+//! and a `manifest.json` listing what each was rendered with. The `hellopaint-batch` binary runs
+//! one.
+//!
+//! Scripts read the seed as `seed` and every swept parameter by its name. Generators take the
+//! seed as their own, see [`Generator::with_seed`], and the parameters as fields of theirs, see
+//! [`Generator::with_param`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::export::{self, ExportError, ExportOptions};
+use crate::generators::Generator;
+use crate::stroke::Brush;
+use crate::surface::HpSurface;
+
+/// The file in the output directory listing the runs.
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug)]
+pub enum BatchError {
+    /// The headless canvas couldn't be set up.
+    Canvas(Error),
+    Export(ExportError),
+    Io(std::io::Error),
+    /// The generator has no parameter by the name of a sweep.
+    UnknownParam { generator: &'static str, name: String },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Canvas(err) => write!(f, "failed to set up the canvas: {err}"),
+            BatchError::Export(err) => write!(f, "failed to export a run: {err}"),
+            BatchError::Io(err) => write!(f, "failed to write the batch: {err}"),
+            BatchError::UnknownParam { generator, name } => {
+                write!(f, "the {generator} generator has no parameter {name:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// What paints each run.
+#[derive(Debug, Clone)]
+pub enum BatchSource {
+    Generator(Generator),
+    /// The source of a script, see [`crate::script`].
+    #[cfg(feature = "scripting")]
+    Script(String),
+}
+
+/// A parameter rendered with each of `values` in turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+/// One run of a batch, as listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchRun {
+    /// The PNG's name in the output directory, `None` if the run failed.
+    pub file: Option<String>,
+    pub seed: u64,
+    pub params: BTreeMap<String, f64>,
+    pub dots: usize,
+    /// Why the script failed, the other runs go on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    size: u32,
+    runs: &'a [BatchRun],
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub source: BatchSource,
+    pub seeds: Vec<u64>,
+    /// Every combination of their values is rendered with every seed.
+    pub sweeps: Vec<Sweep>,
+    /// Width and height of the PNGs.
+    pub size: u32,
+    pub export: ExportOptions,
+    /// Paints generated dots, and scripts until they set another.
+    pub brush: Brush,
+}
+
+impl BatchJob {
+    pub fn new(source: BatchSource, seeds: Vec<u64>) -> Self {
+        Self {
+            source,
+            seeds,
+            sweeps: Vec::new(),
+            size: 1024,
+            export: ExportOptions::default(),
+            brush: Brush::default(),
+        }
+    }
+
+    /// The seed and parameters of every run, parameter combinations in the order of the sweeps
+    /// with the last changing fastest, each with all seeds.
+    pub fn runs(&self) -> Vec<(u64, BTreeMap<String, f64>)> {
+        let mut combinations = vec![BTreeMap::new()];
+        for sweep in &self.sweeps {
+            combinations = combinations
+                .iter()
+                .flat_map(|params| {
+                    sweep.values.iter().map(move |&value| {
+                        let mut params = params.clone();
+                        params.insert(sweep.name.clone(), value);
+                        params
+                    })
+                })
+                .collect();
+        }
+        combinations
+            .into_iter()
+            .flat_map(|params| self.seeds.iter().map(move |&seed| (seed, params.clone())))
+            .collect()
+    }
+
+    /// Renders every run into `directory`, creating it, and writes the manifest there. Files
+    /// already there are overwritten.
+    pub fn render(&self, directory: impl AsRef<Path>) -> Result<Vec<BatchRun>, BatchError> {
+        let directory = directory.as_ref();
+        match &self.source {
+            BatchSource::Generator(generator) => {
+                if let Some(sweep) = self.sweeps.iter().find(|sweep| generator.with_param(&sweep.name, 0.0).is_none()) {
+                    return Err(BatchError::UnknownParam {
+                        generator: generator.name(),
+                        name: sweep.name.clone(),
+                    });
+                }
+            }
+            // Scripts read whichever they like
+            #[cfg(feature = "scripting")]
+            BatchSource::Script(_) => {}
+        }
+        std::fs::create_dir_all(directory).map_err(BatchError::Io)?;
+        let mut canvas = HpSurface::headless(self.size).map_err(BatchError::Canvas)?;
+
+        let mut runs = Vec::new();
+        for (index, (seed, params)) in self.runs().into_iter().enumerate() {
+            let _span = tracing::info_span!("batch_run", index, seed).entered();
+            canvas.set_dots(Vec::new());
+            canvas.clear_watercolor();
+            let error = self.paint(&mut canvas, seed, &params).err();
+            if let Some(err) = &error {
+                tracing::warn!("Run {index} with seed {seed} failed: {err}");
+            }
+            let file = match error {
+                Some(_) => None,
+                None => {
+                    let file = format!("run_{index:04}_seed_{seed}.png");
+                    canvas.render().map_err(|err| BatchError::Export(ExportError::Render(err)))?;
+                    let png = pollster::block_on(export::export_png(canvas.readback(), self.export))
+                        .map_err(BatchError::Export)?;
+                    std::fs::write(directory.join(&file), png).map_err(BatchError::Io)?;
+                    Some(file)
+                }
+            };
+            runs.push(BatchRun {
+                file,
+                seed,
+                params,
+                dots: canvas.dots().len(),
+                error,
+            });
+        }
+
+        let manifest = Manifest {
+            size: self.size,
+            runs: &runs,
+        };
+        let json = serde_json::to_string_pretty(&manifest).expect("manifest serializes");
+        std::fs::write(directory.join(MANIFEST), json).map_err(BatchError::Io)?;
+        Ok(runs)
+    }
+
+    /// Paints one run on the empty `canvas`, or says why the script failed.
+    fn paint(&self, canvas: &mut HpSurface, seed: u64, params: &BTreeMap<String, f64>) -> Result<(), String> {
+        match &self.source {
+            BatchSource::Generator(generator) => {
+                let generator = params
+                    .iter()
+                    .fold(generator.with_seed(seed), |generator, (name, &value)| {
+                        generator.with_param(name, value).expect("checked before rendering")
+                    });
+                canvas.set_dots(generator.dots(&self.brush));
+                Ok(())
+            }
+            #[cfg(feature = "scripting")]
+            BatchSource::Script(source) => {
+                use crate::command::{Applied, Painting};
+                use crate::script::{self, ScriptContext};
+
+                let context = ScriptContext {
+                    canvas_size: self.size,
+                    dot_count: 0,
+                    brush: self.brush,
+                };
+                let seed = seed as f64;
+                let params: Vec<_> = std::iter::once(("seed", seed))
+                    .chain(params.iter().map(|(name, &value)| (name.as_str(), value)))
+                    .collect();
+                let commands = script::run_script_with_params(source, context, &params).map_err(|err| err.to_string())?;
+                let mut painting = Painting::new(self.brush);
+                let mut applied = Applied::default();
+                for command in commands {
+                    painting.apply(command, canvas, &mut applied);
+                }
+                Ok(())
+            }
+        }
+    }
+}
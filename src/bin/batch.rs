@@ -0,0 +1,123 @@
+//! Renders a generator or script for many seeds and parameters into PNGs, see
+//! `hellopaint_wgpu::batch`.
+
+use std::path::PathBuf;
+
+use hellopaint_wgpu::batch::{BatchJob, BatchSource, Sweep};
+use hellopaint_wgpu::generators::Generator;
+
+const USAGE: &str = "\
+Usage: hellopaint-batch (--generator <name> | --script <file>) [options]
+
+Options:
+    --generator <name>     One of poisson-disk, spiral, grid and halton
+    --script <file>        A Rhai script reading `seed` and the swept parameters, with the
+                           scripting feature
+    --seeds <seeds>        A list like 1,2,3 or a range like 0..10 [default: 0..10]
+    --sweep <name=values>  Renders every seed with each value, a list like spread=0.02,0.04 or
+                           evenly spaced like spread=0.02..0.06:5. Repeat to sweep several
+    --size <pixels>        Width and height of the PNGs [default: 1024]
+    --out <dir>            Where the PNGs and manifest.json go [default: batch]";
+
+fn main() {
+    env_logger::init();
+    let (job, out) = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{USAGE}");
+        std::process::exit(2);
+    });
+    match job.render(&out) {
+        Ok(runs) => {
+            let failed = runs.iter().filter(|run| run.error.is_some()).count();
+            println!("Rendered {} of {} runs into {}", runs.len() - failed, runs.len(), out.display());
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<(BatchJob, PathBuf), String> {
+    let mut source = None;
+    let mut seeds = (0..10).collect();
+    let mut sweeps = Vec::new();
+    let mut size = 1024;
+    let mut out = PathBuf::from("batch");
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_owned(), Some(value.to_owned())),
+            _ => (arg, None),
+        };
+        let mut value = || value.clone().or_else(|| args.next()).ok_or_else(|| format!("{name} needs a value"));
+        let invalid = |value: &str| format!("invalid value {value:?} for {name}");
+        match name.as_str() {
+            "--generator" => {
+                let value = value()?;
+                let generator = Generator::from_name(&value).ok_or_else(|| invalid(&value))?;
+                source = Some(BatchSource::Generator(generator));
+            }
+            #[cfg(feature = "scripting")]
+            "--script" => {
+                let path = value()?;
+                let script = std::fs::read_to_string(&path).map_err(|err| format!("failed to read {path}: {err}"))?;
+                source = Some(BatchSource::Script(script));
+            }
+            #[cfg(not(feature = "scripting"))]
+            "--script" => return Err("--script needs the scripting feature".to_owned()),
+            "--seeds" => {
+                let value = value()?;
+                seeds = parse_seeds(&value).ok_or_else(|| invalid(&value))?;
+            }
+            "--sweep" => {
+                let value = value()?;
+                sweeps.push(parse_sweep(&value).ok_or_else(|| invalid(&value))?);
+            }
+            "--size" => {
+                let value = value()?;
+                size = value.parse().ok().filter(|&size| size > 0).ok_or_else(|| invalid(&value))?;
+            }
+            "--out" => out = value()?.into(),
+            _ => return Err(format!("unexpected argument {name:?}")),
+        }
+    }
+    let source = source.ok_or("--generator or --script is needed")?;
+    let mut job = BatchJob::new(source, seeds);
+    job.sweeps = sweeps;
+    job.size = size;
+    Ok((job, out))
+}
+
+/// `1,2,3`, or `0..10` for 0 to 9.
+fn parse_seeds(value: &str) -> Option<Vec<u64>> {
+    match value.split_once("..") {
+        Some((start, end)) => Some((start.trim().parse().ok()?..end.trim().parse().ok()?).collect()),
+        None => value.split(',').map(|seed| seed.trim().parse().ok()).collect(),
+    }
+}
+
+/// `name=1,2,3`, or `name=0..1:5` for 5 values from 0 to 1.
+fn parse_sweep(value: &str) -> Option<Sweep> {
+    let (name, values) = value.split_once('=')?;
+    let values = match values.split_once("..") {
+        Some((start, rest)) => {
+            let (end, steps) = rest.split_once(':')?;
+            let [start, end]: [f64; 2] = [start.trim().parse().ok()?, end.trim().parse().ok()?];
+            let steps: usize = steps.trim().parse().ok().filter(|&steps| steps > 0)?;
+            (0..steps)
+                .map(|step| match steps {
+                    1 => start,
+                    _ => start + (end - start) * step as f64 / (steps - 1) as f64,
+                })
+                .collect()
+        }
+        None => values.split(',').map(|value| value.trim().parse().ok()).collect::<Option<_>>()?,
+    };
+    Some(Sweep {
+        name: name.trim().to_owned(),
+        values,
+    })
+}
@@ -0,0 +1,50 @@
+//! Renders documents sent over HTTP into PNGs, see `hellopaint_wgpu::server`.
+
+use hellopaint_wgpu::export::ExportOptions;
+use hellopaint_wgpu::server::RenderServer;
+
+const USAGE: &str = "\
+Usage: hellopaint-server [options]
+
+Options:
+    --address <host:port>  Where to listen [default: 127.0.0.1:8080]
+    --size <pixels>        Width and height of the rendered PNGs [default: 256]";
+
+fn main() {
+    env_logger::init();
+    let (address, size) = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{USAGE}");
+        std::process::exit(2);
+    });
+    let result = RenderServer::new(size, ExportOptions::default()).and_then(|server| server.serve(address));
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<(String, u32), String> {
+    let mut address = "127.0.0.1:8080".to_owned();
+    let mut size = 256;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        let mut value = || value.clone().or_else(|| args.next()).ok_or_else(|| format!("{name} needs a value"));
+        match name.as_str() {
+            "--address" => address = value()?,
+            "--size" => {
+                let value = value()?;
+                size = value
+                    .parse()
+                    .ok()
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| format!("invalid value {value:?} for --size"))?;
+            }
+            _ => return Err(format!("unexpected argument {name:?}")),
+        }
+    }
+    Ok((address, size))
+}
@@ -0,0 +1,294 @@
+//! The passes behind [`Effect::Bloom`](crate::post_process::Effect::Bloom).
+//!
+//! Bright parts of the image are extracted at half size, blurred down a chain of ever smaller
+//! textures and back up again, then added on top of the image.
+
+use std::num::NonZeroU64;
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::preprocessor::ShaderDefines;
+use crate::renderer;
+use crate::shaders::{Shader, ShaderError};
+
+/// The most levels the blur goes down, each half the size of the previous one.
+const MAX_LEVELS: usize = 6;
+
+struct Pipelines {
+    threshold: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+pub(crate) struct Bloom {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Adds the blurred texture at binding 3.
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipelines when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    composite_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    pipelines: Pipelines,
+    /// Half the target size and smaller, largest first. Never empty once prepared.
+    levels: Vec<wgpu::TextureView>,
+    size: Option<wgpu::Extent3d>,
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, uniforms_size: u64) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let entries = [
+            texture_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(uniforms_size),
+                },
+                count: None,
+            },
+            texture_entry(3),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom_bind_group_layout"),
+            entries: &entries[..3],
+        });
+        let composite_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom_composite_bind_group_layout"),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom_composite_pipeline_layout"),
+            bind_group_layouts: &[&composite_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = create_pipelines(
+            device,
+            Shader::Bloom.embedded_source(),
+            &pipeline_layout,
+            &composite_pipeline_layout,
+            format,
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+
+        Self {
+            bind_group_layout,
+            composite_bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            composite_pipeline_layout,
+            format,
+            pipelines,
+            levels: Vec::new(),
+            size: None,
+        }
+    }
+
+    /// Rebuilds the pipelines from `source`, keeping the current ones if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: String) -> Result<(), ShaderError> {
+        self.pipelines = catch_validation_error(device, || {
+            create_pipelines(
+                device,
+                &source,
+                &self.pipeline_layout,
+                &self.composite_pipeline_layout,
+                self.format,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Sizes the blur levels for a target of `size`.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: wgpu::Extent3d) {
+        if self.size == Some(size) {
+            return;
+        }
+        self.size = Some(size);
+        self.levels.clear();
+        let (mut width, mut height) = ((size.width / 2).max(1), (size.height / 2).max(1));
+        // At least one level, however small the target
+        while self.levels.is_empty() || (self.levels.len() < MAX_LEVELS && width >= 2 && height >= 2) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("bloom_level"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            self.levels.push(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+            width /= 2;
+            height /= 2;
+        }
+    }
+
+    /// Writes `input` with bloom added to `output`, with the threshold and intensity in `uniforms`.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        sampler: &wgpu::Sampler,
+        uniforms: &wgpu::Buffer,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = |source: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom_bind_group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniforms.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        let largest = &self.levels[0];
+
+        encoder.push_debug_group("bloom");
+        draw(encoder, "bloom_threshold", &self.pipelines.threshold, &bind_group(input), largest, true);
+        for pair in self.levels.windows(2) {
+            draw(encoder, "bloom_downsample", &self.pipelines.downsample, &bind_group(&pair[0]), &pair[1], true);
+        }
+        for pair in self.levels.windows(2).rev() {
+            draw(encoder, "bloom_upsample", &self.pipelines.upsample, &bind_group(&pair[1]), &pair[0], false);
+        }
+        let bind_group = self.composite_bind_group(device, sampler, uniforms, input, largest);
+        draw(encoder, "bloom_composite", &self.pipelines.composite, &bind_group, output, true);
+        encoder.pop_debug_group();
+    }
+
+    fn composite_bind_group(
+        &self,
+        device: &wgpu::Device,
+        sampler: &wgpu::Sampler,
+        uniforms: &wgpu::Buffer,
+        input: &wgpu::TextureView,
+        bloom: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bloom_composite_bind_group"),
+            layout: &self.composite_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(bloom),
+                },
+            ],
+        })
+    }
+}
+
+/// Draws a full screen triangle into `target`, replacing its contents if `clear` is set and
+/// blending onto them otherwise.
+fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+    clear: bool,
+) {
+    let load = if clear {
+        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+    } else {
+        wgpu::LoadOp::Load
+    };
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+fn create_pipelines(
+    device: &wgpu::Device,
+    source: &str,
+    pipeline_layout: &wgpu::PipelineLayout,
+    composite_pipeline_layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+) -> Result<Pipelines, ShaderError> {
+    let shader = Shader::Bloom.create_module(device, source, &ShaderDefines::new())?;
+
+    let create = |entry_point: &'static str, layout: &wgpu::PipelineLayout, blend: Option<wgpu::BlendState>| {
+        renderer::vertexless_pipeline(device, entry_point, layout, &shader, entry_point, format, blend)
+    };
+    let additive = wgpu::BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent::OVER,
+    };
+
+    Ok(Pipelines {
+        threshold: create("fs_threshold", pipeline_layout, None),
+        downsample: create("fs_downsample", pipeline_layout, None),
+        upsample: create("fs_upsample", pipeline_layout, Some(additive)),
+        composite: create("fs_composite", composite_pipeline_layout, None),
+    })
+}
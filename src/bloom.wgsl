@@ -0,0 +1,70 @@
+// Bloom: the bright parts of the image are blurred and added back on top, so they glow.
+//
+// The passes, in order:
+// - fs_threshold keeps what's brighter than the threshold, at half size
+// - fs_downsample halves the previous level again, blurring as it goes
+// - fs_upsample blurs each level back up, blended additively onto the next larger one
+// - fs_composite adds the largest level to the image
+
+#include "post_process_common.wgsl"
+
+// params x: threshold, y: intensity
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> effect: Effect;
+// Only bound for fs_composite
+@group(0) @binding(3)
+var bloom: texture_2d<f32>;
+
+fn texel_size() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(source));
+}
+
+// Four bilinear taps around `uv`, averaging 4x4 source texels
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let t = texel_size();
+    var sum = textureSample(source, source_sampler, uv + vec2<f32>(-t.x, -t.y)).rgb;
+    sum += textureSample(source, source_sampler, uv + vec2<f32>(t.x, -t.y)).rgb;
+    sum += textureSample(source, source_sampler, uv + vec2<f32>(-t.x, t.y)).rgb;
+    sum += textureSample(source, source_sampler, uv + vec2<f32>(t.x, t.y)).rgb;
+    return sum * 0.25;
+}
+
+@fragment
+fn fs_threshold(in: VertexOut) -> @location(0) vec4<f32> {
+    let color = downsample(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - effect.params.x, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// A 3x3 tent filter over the smaller level
+@fragment
+fn fs_upsample(in: VertexOut) -> @location(0) vec4<f32> {
+    let t = texel_size();
+    var sum = textureSample(source, source_sampler, in.uv).rgb * 4.0;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(-t.x, 0.0)).rgb * 2.0;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(t.x, 0.0)).rgb * 2.0;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(0.0, -t.y)).rgb * 2.0;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(0.0, t.y)).rgb * 2.0;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(-t.x, -t.y)).rgb;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(t.x, -t.y)).rgb;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(-t.x, t.y)).rgb;
+    sum += textureSample(source, source_sampler, in.uv + vec2<f32>(t.x, t.y)).rgb;
+    return vec4<f32>(sum / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOut) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    let glow = textureSample(bloom, source_sampler, in.uv).rgb * effect.params.y;
+    return vec4<f32>(color.rgb + glow, color.a);
+}
@@ -0,0 +1,37 @@
+// Separable gaussian blur of the canvas, one direction per dispatch, see filter.rs.
+//
+// `Blur` mirrors `BlurUniforms` in filter.rs, keep them in sync when changing either side.
+
+struct Blur {
+    origin: vec2<u32>,
+    size: vec2<u32>,
+    direction: vec2<i32>,
+    radius: i32,
+    sigma: f32,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> blur: Blur;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= blur.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(blur.origin + id.xy);
+    // Edges repeat the outermost pixels, so they don't fade out
+    let last = vec2<i32>(textureDimensions(input)) - 1;
+
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -blur.radius; i <= blur.radius; i += 1) {
+        let weight = exp(-f32(i * i) / (2.0 * blur.sigma * blur.sigma));
+        sum += weight * textureLoad(input, clamp(pixel + blur.direction * i, vec2<i32>(0), last), 0);
+        total += weight;
+    }
+    textureStore(output, pixel, sum / total);
+}
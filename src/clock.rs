@@ -0,0 +1,56 @@
+//! A monotonic clock for frame timing. `std::time::Instant` panics in browsers, so there this
+//! uses `performance.now()`, which also exists in Web Workers.
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start_millis: f64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    /// A clock starting at zero now.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start_millis: performance_now(),
+        }
+    }
+
+    /// Seconds since the clock was created.
+    pub fn elapsed_seconds(&self) -> f64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed().as_secs_f64();
+        #[cfg(target_arch = "wasm32")]
+        return (performance_now() - self.start_millis) / 1000.0;
+    }
+}
+
+/// Milliseconds since the Unix epoch by the wall clock, e.g. to note when something happened.
+pub fn unix_millis() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64);
+    #[cfg(target_arch = "wasm32")]
+    return js_sys::Date::now() as u64;
+}
+
+#[cfg(target_arch = "wasm32")]
+fn performance_now() -> f64 {
+    use wasm_bindgen::JsCast;
+
+    js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+        .expect("no performance in this JavaScript context")
+        .unchecked_into::<web_sys::Performance>()
+        .now()
+}
@@ -0,0 +1,428 @@
+//! Colors and conversions between the spaces brushes and pickers work in.
+//!
+//! [`Color`] is what dots carry: linear RGB with straight alpha, ready for blending on the GPU.
+//! Pickers think in sRGB, HSV or HSL, and hue jitter uses OKLCH, where equal hue steps look
+//! equally large.
+//!
+//! A color picked in the UI goes through several encodings on its way to the canvas and back:
+//! 8 bit sRGB in egui and palette files, linear floats in [`Color`] and the dot instances, and
+//! premultiplied sRGB bytes in the canvas texture and its readback. The conversions between them
+//! are all here, so the eyedropper picks the color that was painted.
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+/// Linear RGB with straight alpha, all in 0..1. Serialized as `[r, g, b, a]`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(from = "[f32; 4]", into = "[f32; 4]")]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Hue in degrees (0..360), saturation and value in 0..1, of sRGB encoded color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+/// Hue in degrees (0..360), saturation and lightness in 0..1, of sRGB encoded color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+/// Perceptual lightness (0..1) and the green-red and blue-yellow axes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// [`Oklab`] in polar form: lightness, chroma and hue in degrees (0..360).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+    pub const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
+
+    /// A color from linear components.
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// A color from sRGB encoded components, as in CSS or color pickers. Alpha is kept as is.
+    pub fn from_srgb([r, g, b, a]: [f32; 4]) -> Self {
+        Self::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+    }
+
+    pub fn to_srgb(self) -> [f32; 4] {
+        [linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b), self.a]
+    }
+
+    /// A color from 8 bit sRGB encoded components with straight alpha, like palette files and
+    /// egui's pickers have them.
+    pub fn from_srgb8(rgba: [u8; 4]) -> Self {
+        Self::from_srgb(rgba.map(|value| value as f32 / 255.0))
+    }
+
+    /// The nearest 8 bit sRGB encoded components, straight alpha.
+    pub fn to_srgb8(self) -> [u8; 4] {
+        self.to_srgb().map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    /// A color written like in CSS, `#rrggbb` or `#rrggbbaa`, the `#` optional.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return None;
+        }
+        let mut rgba = [255; 4];
+        for (channel, index) in rgba.iter_mut().zip((0..hex.len()).step_by(2)) {
+            *channel = u8::from_str_radix(&hex[index..index + 2], 16).ok()?;
+        }
+        Some(Self::from_srgb8(rgba))
+    }
+
+    /// The color of a canvas pixel as [`TextureReadback::into_rgba8`] returns it: premultiplied,
+    /// and sRGB encoded if `srgb`. Fully transparent pixels have no color, they're transparent
+    /// black.
+    ///
+    /// [`TextureReadback::into_rgba8`]: crate::export::TextureReadback::into_rgba8
+    pub fn from_canvas_pixel([r, g, b, a]: [u8; 4], srgb: bool) -> Self {
+        if a == 0 {
+            return Self::new(0.0, 0.0, 0.0, 0.0);
+        }
+        let alpha = a as f32 / 255.0;
+        // The canvas premultiplies in linear space and encodes afterwards
+        let decode = |value: u8| {
+            let value = value as f32 / 255.0;
+            let linear = if srgb { srgb_to_linear(value) } else { value };
+            (linear / alpha).min(1.0)
+        };
+        Self::new(decode(r), decode(g), decode(b), alpha)
+    }
+
+    /// The pixel a canvas holds where this color was painted over nothing, the inverse of
+    /// [`Self::from_canvas_pixel`].
+    pub fn to_canvas_pixel(self, srgb: bool) -> [u8; 4] {
+        let encode = |value: f32| {
+            let premultiplied = value * self.a;
+            let value = if srgb { linear_to_srgb(premultiplied) } else { premultiplied };
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        [encode(self.r), encode(self.g), encode(self.b), (self.a.clamp(0.0, 1.0) * 255.0).round() as u8]
+    }
+
+    pub fn from_hsv(hsv: Hsv, alpha: f32) -> Self {
+        let chroma = hsv.v * hsv.s;
+        let [r, g, b] = hue_to_rgb(hsv.h, chroma);
+        let m = hsv.v - chroma;
+        Self::from_srgb([r + m, g + m, b + m, alpha])
+    }
+
+    pub fn to_hsv(self) -> Hsv {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        Hsv {
+            h: rgb_to_hue(r, g, b),
+            s: if max > 0.0 { (max - min) / max } else { 0.0 },
+            v: max,
+        }
+    }
+
+    pub fn from_hsl(hsl: Hsl, alpha: f32) -> Self {
+        let chroma = (1.0 - (2.0 * hsl.l - 1.0).abs()) * hsl.s;
+        let [r, g, b] = hue_to_rgb(hsl.h, chroma);
+        let m = hsl.l - chroma / 2.0;
+        Self::from_srgb([r + m, g + m, b + m, alpha])
+    }
+
+    pub fn to_hsl(self) -> Hsl {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let s = if max > min {
+            (max - min) / (1.0 - (2.0 * l - 1.0).abs())
+        } else {
+            0.0
+        };
+        Hsl {
+            h: rgb_to_hue(r, g, b),
+            s,
+            l,
+        }
+    }
+
+    pub fn from_oklab(lab: Oklab, alpha: f32) -> Self {
+        let l = (lab.l + 0.396_337_78 * lab.a + 0.215_803_76 * lab.b).powi(3);
+        let m = (lab.l - 0.105_561_346 * lab.a - 0.063_854_17 * lab.b).powi(3);
+        let s = (lab.l - 0.089_484_18 * lab.a - 1.291_485_5 * lab.b).powi(3);
+        Self::new(
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+            alpha,
+        )
+    }
+
+    pub fn to_oklab(self) -> Oklab {
+        let l = (0.412_221_46 * self.r + 0.536_332_55 * self.g + 0.051_445_995 * self.b).cbrt();
+        let m = (0.211_903_5 * self.r + 0.680_699_5 * self.g + 0.107_396_96 * self.b).cbrt();
+        let s = (0.088_302_46 * self.r + 0.281_718_85 * self.g + 0.629_978_7 * self.b).cbrt();
+        Oklab {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+
+    pub fn from_oklch(lch: Oklch, alpha: f32) -> Self {
+        let (sin, cos) = lch.h.to_radians().sin_cos();
+        Self::from_oklab(
+            Oklab {
+                l: lch.l,
+                a: lch.c * cos,
+                b: lch.c * sin,
+            },
+            alpha,
+        )
+    }
+
+    pub fn to_oklch(self) -> Oklch {
+        let lab = self.to_oklab();
+        Oklch {
+            l: lab.l,
+            c: lab.a.hypot(lab.b),
+            h: lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0),
+        }
+    }
+
+    /// Relative luminance (the Y of CIE XYZ), 0 for black and 1 for white.
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Rotates the hue by `degrees` in OKLCH, keeping lightness and chroma. Colors that end up
+    /// outside of sRGB are clamped.
+    pub fn shift_hue(self, degrees: f32) -> Self {
+        let lch = self.to_oklch();
+        let shifted = Self::from_oklch(
+            Oklch {
+                h: lch.h + degrees,
+                ..lch
+            },
+            self.a,
+        );
+        Self::new(
+            shifted.r.clamp(0.0, 1.0),
+            shifted.g.clamp(0.0, 1.0),
+            shifted.b.clamp(0.0, 1.0),
+            shifted.a,
+        )
+    }
+}
+
+/// egui's 8 bit colors are sRGB encoded and premultiplied.
+impl From<egui::Color32> for Color {
+    fn from(color: egui::Color32) -> Self {
+        Self::from_srgb8(color.to_srgba_unmultiplied())
+    }
+}
+
+impl From<Color> for egui::Color32 {
+    fn from(color: Color) -> Self {
+        let [r, g, b, a] = color.to_srgb8();
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+}
+
+/// egui's float colors are linear and premultiplied.
+impl From<egui::Rgba> for Color {
+    fn from(color: egui::Rgba) -> Self {
+        let [r, g, b, a] = color.to_rgba_unmultiplied();
+        Self::new(r, g, b, a)
+    }
+}
+
+impl From<Color> for egui::Rgba {
+    fn from(color: Color) -> Self {
+        egui::Rgba::from_rgba_unmultiplied(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl From<[f32; 4]> for Color {
+    fn from([r, g, b, a]: [f32; 4]) -> Self {
+        Self::new(r, g, b, a)
+    }
+}
+
+impl From<Color> for [f32; 4] {
+    fn from(color: Color) -> Self {
+        [color.r, color.g, color.b, color.a]
+    }
+}
+
+/// Decodes one sRGB encoded channel.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes one linear channel as sRGB.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The hue of encoded `r`, `g`, `b` in degrees, 0 for grays.
+fn rgb_to_hue(r: f32, g: f32, b: f32) -> f32 {
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta <= 0.0 {
+        return 0.0;
+    }
+    let sector = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    sector * 60.0
+}
+
+/// The most saturated encoded color with `hue` (degrees) and `chroma`, before adding the gray
+/// offset shared by HSV and HSL.
+fn hue_to_rgb(hue: f32, chroma: f32) -> [f32; 3] {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    match sector as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surface::{Dot, HpSurface};
+
+    /// UI colors, sRGB encoded with straight alpha, including ones dark enough to hit the linear
+    /// part of the sRGB curve.
+    const UI_COLORS: [[u8; 4]; 6] = [
+        [255, 0, 0, 255],
+        [12, 200, 97, 255],
+        [1, 2, 3, 255],
+        [128, 128, 128, 255],
+        [240, 180, 30, 128],
+        [90, 10, 200, 17],
+    ];
+
+    fn assert_close(actual: [u8; 4], expected: [u8; 4], tolerance: u8) {
+        let close = actual.iter().zip(expected).all(|(&a, e)| a.abs_diff(e) <= tolerance);
+        assert!(close, "{actual:?} isn't within {tolerance} of {expected:?}");
+    }
+
+    #[test]
+    fn srgb8_round_trips_every_value() {
+        for value in 0..=255 {
+            let rgba = [value, 255 - value, value / 2, value];
+            assert_eq!(Color::from_srgb8(rgba).to_srgb8(), rgba);
+        }
+    }
+
+    #[test]
+    fn hex_colors_parse() {
+        assert_eq!(Color::from_hex("#0c61c8").map(Color::to_srgb8), Some([12, 97, 200, 255]));
+        assert_eq!(Color::from_hex("FFFFFF80").map(Color::to_srgb8), Some([255, 255, 255, 128]));
+        for invalid in ["", "#fff", "#0c61c", "#0c61c8f", "#0g61c8", "#é0c61c"] {
+            assert_eq!(Color::from_hex(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn egui_colors_round_trip() {
+        for [r, g, b, a] in UI_COLORS {
+            let ui = egui::Color32::from_rgba_unmultiplied(r, g, b, a);
+            assert_eq!(egui::Color32::from(Color::from(ui)), ui);
+        }
+    }
+
+    #[test]
+    fn egui_rgba_is_linear() {
+        for rgba in UI_COLORS {
+            let color = Color::from_srgb8(rgba);
+            let linear = Color::from(egui::Rgba::from(color));
+            assert!((linear.r - color.r).abs() < 1e-6 && (linear.a - color.a).abs() < 1e-6);
+            assert_close(linear.to_srgb8(), rgba, 0);
+        }
+    }
+
+    #[test]
+    fn opaque_canvas_pixels_round_trip() {
+        for rgba in UI_COLORS.into_iter().filter(|rgba| rgba[3] == 255) {
+            let color = Color::from_srgb8(rgba);
+            // An sRGB canvas stores opaque colors exactly as the UI has them
+            assert_eq!(color.to_canvas_pixel(true), rgba);
+            assert_eq!(Color::from_canvas_pixel(rgba, true).to_srgb8(), rgba);
+        }
+    }
+
+    #[test]
+    fn translucent_canvas_pixels_round_trip() {
+        for rgba in UI_COLORS {
+            let color = Color::from_srgb8(rgba);
+            let pixel = color.to_canvas_pixel(true);
+            // Premultiplying into 8 bits loses precision the fainter the color
+            let tolerance = (255 / rgba[3]).min(32);
+            assert_close(Color::from_canvas_pixel(pixel, true).to_srgb8(), rgba, tolerance);
+        }
+    }
+
+    #[test]
+    fn transparent_canvas_pixels_are_transparent_black() {
+        assert_eq!(Color::from_canvas_pixel([10, 20, 30, 0], true), Color::new(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn eyedropper_picks_the_painted_ui_color() {
+        let Ok(mut surface) = HpSurface::headless(64) else {
+            eprintln!("skipped, no adapter");
+            return;
+        };
+        for rgba in UI_COLORS.into_iter().filter(|rgba| rgba[3] == 255) {
+            let color = Color::from(egui::Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]));
+            surface.set_dots(vec![Dot::new([0.0, 0.0], 0.5, 1.0, color)]);
+            surface.render().unwrap();
+            let picked = pollster::block_on(surface.readback().into_color_at(32, 32)).unwrap();
+            assert_close(egui::Color32::from(picked).to_array(), rgba, 1);
+        }
+    }
+}
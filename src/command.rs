@@ -0,0 +1,270 @@
+//! Commands decoupling input from the painting.
+//!
+//! The frontends, the window of [`app`](crate::app) and the Web Worker's `OffscreenRenderer`,
+//! turn their input into [`Command`]s and publish them on a [`CommandBus`]. A [`Painting`]
+//! consumes them, changing the canvas the same way for every frontend, and reports in [`Applied`]
+//! what's left for the frontend to do, like redrawing.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::color::Color;
+use crate::clock;
+use crate::document::{StrokeId, StrokeInfo, StrokeMetadata};
+use crate::filter::Filter;
+use crate::guides::Guides;
+use crate::replay::Recording;
+use crate::stroke::{Brush, Stroke};
+use crate::surface::{ClearedCanvas, Dot, HpSurface};
+
+/// The pointer drawing a stroke, so several fingers can paint at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PointerId {
+    Mouse,
+    Touch(u64),
+    /// The `pointerId` of a DOM pointer event.
+    Pointer(i32),
+    /// Strokes painted by scripts, see `crate::script`.
+    Script,
+}
+
+impl PointerId {
+    /// What's painting, for the [metadata](StrokeMetadata::device) of its strokes.
+    pub fn device(&self) -> &'static str {
+        match self {
+            PointerId::Mouse => "mouse",
+            PointerId::Touch(_) => "touch",
+            PointerId::Pointer(_) => "pointer",
+            PointerId::Script => "script",
+        }
+    }
+}
+
+/// A change to the painting. Positions are in canvas coordinates, -1..1 with y up.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Starts a stroke of `pointer` with the current brush.
+    BeginStroke {
+        pointer: PointerId,
+        position: [f32; 2],
+        pressure: f32,
+    },
+    /// Paints `pointer`'s stroke up to `position`, if it has one.
+    ContinueStroke {
+        pointer: PointerId,
+        position: [f32; 2],
+        pressure: f32,
+    },
+    EndStroke(PointerId),
+    /// Puts dots on the canvas as if they had been painted.
+    AddDots(Vec<Dot>),
+    /// Removes all dots and watercolor from the canvas, until undone.
+    Clear,
+    /// Paints the next strokes with this brush.
+    SetBrush(Brush),
+    SetBrushColor(Color),
+    /// Takes back the last stroke, added dots, clearing or change to the filters.
+    Undo,
+    /// Asks the frontend to export the canvas, wherever it puts exports.
+    Export,
+}
+
+/// Commands published by input, waiting to be applied.
+#[derive(Debug, Default)]
+pub struct CommandBus {
+    commands: VecDeque<Command>,
+}
+
+impl CommandBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&mut self, command: Command) {
+        self.commands.push_back(command);
+    }
+
+    /// Applies the published commands to `painting` on `surface`, in order.
+    pub fn apply(&mut self, painting: &mut Painting, surface: &mut HpSurface) -> Applied {
+        let mut applied = Applied::default();
+        for command in self.commands.drain(..) {
+            painting.apply(command, surface, &mut applied);
+        }
+        applied
+    }
+}
+
+/// What applying commands left for the frontend to do.
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// The canvas changed.
+    pub redraw: bool,
+    /// Strokes that ended, e.g. to remember their colors.
+    pub finished_strokes: Vec<Stroke>,
+    /// [`Command::Export`] was applied.
+    pub export: bool,
+}
+
+/// How to take back an undoable command.
+#[derive(Debug)]
+enum UndoStep {
+    /// Remove the stroke with its dots.
+    RemoveStroke(StrokeId),
+    /// Put back the strokes and watercolor that were cleared.
+    Restore(Box<ClearedCanvas>),
+    /// Put back the filters from before one was added, committed or they were cleared.
+    RestoreFilters(Vec<Filter>),
+}
+
+/// The state input acts on besides the canvas: the brush, the strokes in progress, what can be
+/// undone and when which command came in.
+#[derive(Debug, Default)]
+pub struct Painting {
+    brush: Brush,
+    /// With the canvas stroke they paint.
+    strokes: HashMap<PointerId, (Stroke, StrokeId)>,
+    undo: Vec<UndoStep>,
+    recording: Recording,
+    /// Given to the strokes painted from now on.
+    metadata: StrokeMetadata,
+    /// What strokes snap to.
+    snap: Option<Guides>,
+}
+
+impl Painting {
+    pub fn new(brush: Brush) -> Self {
+        Self {
+            brush,
+            ..Self::default()
+        }
+    }
+
+    pub fn brush(&self) -> &Brush {
+        &self.brush
+    }
+
+    pub fn metadata(&self) -> &StrokeMetadata {
+        &self.metadata
+    }
+
+    /// Gives the strokes painted from now on `metadata`, e.g. their author. Their timestamp is
+    /// when they start, and their device the pointer's unless it's set.
+    pub fn set_metadata(&mut self, metadata: StrokeMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn snap(&self) -> Option<&Guides> {
+        self.snap.as_ref()
+    }
+
+    /// Snaps strokes to `guides` from now on, or lets them go wherever the pointer does with
+    /// `None`. Strokes are recorded snapped, so replays follow the guides too.
+    pub fn set_snap(&mut self, guides: Option<Guides>) {
+        self.snap = guides;
+    }
+
+    /// The commands applied so far, to be replayed as a timelapse.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Forgets what can be undone, e.g. when painting on another canvas from now on.
+    pub fn forget_history(&mut self) {
+        self.undo.clear();
+    }
+
+    /// Lets [`Command::Undo`] put back `filters`, those of the canvas before the frontend changed
+    /// them, e.g. committing an adjustment's preview.
+    pub fn filters_changed(&mut self, filters: Vec<Filter>) {
+        self.undo.push(UndoStep::RestoreFilters(filters));
+    }
+
+    /// Applies `command` to `surface`, noting in `applied` what the frontend should follow up on.
+    pub fn apply(&mut self, mut command: Command, surface: &mut HpSurface, applied: &mut Applied) {
+        if let (Some(guides), Command::ContinueStroke { pointer, position, .. }) = (&self.snap, &mut command) {
+            // Along the guide through the stroke's first dot
+            if let Some(start) = self.strokes.get(pointer).and_then(|(stroke, _)| stroke.dots().first()) {
+                *position = guides.snap(start.position(), *position);
+            }
+        }
+        self.recording.record(&command);
+        match command {
+            Command::BeginStroke {
+                pointer,
+                position,
+                pressure,
+            } => {
+                let info = StrokeInfo {
+                    metadata: self.stroke_metadata(Some(pointer)),
+                    ..StrokeInfo::painted(self.brush, self.recording.seconds())
+                };
+                let id = surface.begin_stroke(info);
+                self.undo.push(UndoStep::RemoveStroke(id));
+                let mut stroke = Stroke::new(self.brush);
+                surface.add_stroke_dots(id, stroke.add_point(position, pressure));
+                self.strokes.insert(pointer, (stroke, id));
+                applied.redraw |= surface.take_dirty_rect().is_some();
+            }
+            Command::ContinueStroke {
+                pointer,
+                position,
+                pressure,
+            } => {
+                if let Some((stroke, id)) = self.strokes.get_mut(&pointer) {
+                    surface.add_stroke_dots(*id, stroke.add_point(position, pressure));
+                    if let Some(info) = surface.stroke_info_mut(*id) {
+                        info.end = self.recording.seconds();
+                    }
+                    // Most samples are closer to the last dot than the brush's spacing, adding none
+                    applied.redraw |= surface.take_dirty_rect().is_some();
+                }
+            }
+            Command::EndStroke(pointer) => {
+                if let Some((stroke, _)) = self.strokes.remove(&pointer) {
+                    applied.finished_strokes.push(stroke);
+                }
+            }
+            Command::AddDots(dots) => {
+                let info = StrokeInfo {
+                    metadata: self.stroke_metadata(None),
+                    ..StrokeInfo::added(self.recording.seconds())
+                };
+                let id = surface.begin_stroke(info);
+                self.undo.push(UndoStep::RemoveStroke(id));
+                surface.add_stroke_dots(id, &dots);
+                applied.redraw = true;
+            }
+            Command::Clear => {
+                self.undo.push(UndoStep::Restore(Box::new(surface.clear())));
+                applied.redraw = true;
+            }
+            Command::SetBrush(brush) => self.brush = brush,
+            Command::SetBrushColor(color) => self.brush.color = color,
+            Command::Undo => {
+                match self.undo.pop() {
+                    Some(UndoStep::RemoveStroke(stroke)) => {
+                        surface.remove_stroke(stroke);
+                    }
+                    Some(UndoStep::Restore(cleared)) => surface.restore(*cleared),
+                    Some(UndoStep::RestoreFilters(filters)) => {
+                        if let Err(err) = surface.set_filters(filters) {
+                            tracing::error!("Couldn't put back the filters: {err}");
+                        }
+                    }
+                    None => tracing::info!("Nothing to undo"),
+                }
+                applied.redraw = true;
+            }
+            Command::Export => applied.export = true,
+        }
+    }
+
+    /// The metadata of a stroke starting now, painted by `pointer` or added.
+    fn stroke_metadata(&self, pointer: Option<PointerId>) -> StrokeMetadata {
+        let device = pointer.map(|pointer| pointer.device().to_owned());
+        StrokeMetadata {
+            timestamp: Some(clock::unix_millis()),
+            device: self.metadata.device.clone().or(device),
+            ..self.metadata.clone()
+        }
+    }
+}
@@ -0,0 +1,535 @@
+//! Run configuration, from command line flags natively or URL query parameters on the web.
+//!
+//! Both use the same names: `--canvas-size 2048 --seed 7` is `?canvas-size=2048&seed=7`.
+
+use std::fmt;
+
+use crate::color::Color;
+use crate::export::Dither;
+use crate::framing::Corner;
+use crate::guides::Guides;
+use crate::lod::LodSettings;
+use crate::new_document::{Background, CanvasPreset, CanvasSetup};
+use crate::post_process::Effect;
+use crate::surface::{CanvasColorSpace, DisplayGamut, DEFAULT_CANVAS_SIZE};
+
+pub const USAGE: &str = "\
+Options:
+    --canvas-size <pixels>   Width and height of the canvas [default: 1024]
+    --new <preset>           Start a small, medium, large or poster sized canvas instead of the default size
+    --background <kind>      Paint on a green, white, black, paper or transparent background [default: green]
+    --open <file>            Open a document saved with Ctrl+D, on the canvas it was painted on (native only)
+    --seed <number>          Seed for generated content [default: random]
+    --dots <count>           Number of random dots to start with [default: 0]
+    --backend <list>         Comma separated wgpu backends: vulkan, metal, dx12, dx11, gl, webgpu
+    --readonly               View the canvas without painting
+    --linear                 Paint in 16 bit linear color instead of 8 bit sRGB
+    --display-p3             Show and export colors as Display P3 where the display supports it
+    --dither                 Dither exports of linear canvases to avoid banding in soft gradients
+    --effects <list>         Comma separated post-processing effects, applied in order: vignette, grain, aberration, bloom
+    --lut <file>             A .cube LUT to grade the image with, toggled with L (native only)
+    --palette <file>         A .gpl or .ase palette, keys 1 to 9 pick its swatches (native only)
+    --recent-colors <count>  Number of recently painted colors to remember, Shift+1 to 9 pick them [default: 8]
+    --trace <dir>            Record a wgpu API trace here, like WGPU_TRACE (native, trace feature only)
+    --chrome-trace <file>    Record when frames are encoded, submitted and presented, for chrome://tracing (native only)
+    --recover-stalls         Recreate the GPU device when it stops finishing work for 5 seconds
+    --stress <dots>          Add this many random dots every frame until frames exceed the budget, then print a JSON report and quit
+    --frame-budget <ms>      Average frame time that ends the stress test [default: 33.3]
+    --particles              Move the dots as particles swirling around the canvas center (not on WebGL)
+    --video <path>           Where Ctrl+Shift+S writes the timeline: a .gif, an .mp4 or a directory of PNGs [default: hellopaint.mp4] (native only, GIFs and mp4s need ffmpeg)
+    --video-size <pixels>    Width and height of exported videos [default: 512]
+    --video-fps <fps>        Frames per second of exported videos [default: 12]
+    --replay-speed <factor>  How many times as fast as it was painted R replays the session, and Shift+R exports it as a video [default: 10]
+    --audio                  Paint dots to the sound of the default input device, colored by pitch (native, audio feature only)
+    --stipple <image>        Start with a PNG drawn as stipples, denser where it's darker (native only)
+    --script <file>          Run this Rhai script against the canvas at startup, F5 runs it again (native, scripting feature only)
+    --author <name>          Attribute painted strokes to this name, saved with them
+    --even-strokes           Paint each pixel once per stroke, so translucent strokes don't darken where their dots overlap
+    --lod <pixels>           Merge dots less wide than this on screen while the canvas is shown smaller than it is [default: draw all]
+    --guides <kind>          Show 1-point, 2-point or 3-point perspective or isometric guides, P switches between them. Place their vanishing points like 2-point:-1.5,0,1.5,0 or space the grid like isometric:0.2
+    --snap                   Snap strokes to the guides, toggled with Shift+P
+    --export-viewport        Export only the part of the canvas the window shows
+    --export-border <pixels> Frame exports with a border this wide [default: 0]
+    --border-color <hex>     Color of the export border, like #f5f0e4 [default: #ffffff]
+    --watermark <png>        Sign exports with this image (native only)
+    --watermark-corner <c>   Put the watermark top-left, top-right, bottom-left or bottom-right [default: bottom-right]
+    --watermark-opacity <o>  How opaque the watermark is, 0 to 1 [default: 1]
+    --no-restore             Start afresh instead of with the last session's document, brush, view and window
+
+Run natively without --canvas-size, --new, --background or --open, the app opens the document of
+the last session, or asks in the window which canvas to start with or which of the recent
+documents to open. Ctrl+N asks again.";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Width and height of the square canvas texture in pixels.
+    pub canvas_size: u32,
+    /// What the canvas is cleared to.
+    pub background: Background,
+    /// Path of a document to open instead of starting a new one.
+    pub open: Option<String>,
+    /// Asks in the window which document to start with, unless one of the flags above was given.
+    pub ask_document: bool,
+    /// Restores the last session, see [`crate::session`].
+    pub restore_session: bool,
+    /// Seed for generated content, random if not set.
+    pub seed: Option<u64>,
+    /// Number of random dots to start with.
+    pub initial_dots: usize,
+    /// The wgpu backends the adapter may come from.
+    pub backends: wgpu::Backends,
+    /// Disables painting, the canvas can only be viewed.
+    pub readonly: bool,
+    /// How the canvas stores color.
+    pub color_space: CanvasColorSpace,
+    /// The gamut canvas colors are shown and exported in.
+    pub gamut: DisplayGamut,
+    /// How linear canvases are quantized to 8 bits on export.
+    pub dither: Dither,
+    /// Effects applied to the composited canvas, in order.
+    pub effects: Vec<Effect>,
+    /// Path of a `.cube` LUT to grade the image with.
+    pub lut: Option<String>,
+    /// Path of a `.gpl` or `.ase` palette to pick brush colors from.
+    pub palette: Option<String>,
+    /// Number of recently painted colors to remember between sessions.
+    pub recent_colors: usize,
+    /// Directory to record a wgpu API trace into.
+    pub trace: Option<String>,
+    /// File to record the session's spans into, for chrome://tracing or Perfetto.
+    pub chrome_trace: Option<String>,
+    /// Recreates the GPU device when it stalls, instead of only logging it.
+    pub recover_stalls: bool,
+    /// Dots the stress test adds every frame, no stress test if not set.
+    pub stress: Option<usize>,
+    /// Average frame time in milliseconds that ends the stress test.
+    pub frame_budget_ms: f64,
+    /// Moves the dots as particles with the default settings.
+    pub particles: bool,
+    /// Path the timeline is exported to as a video, its extension picks the format.
+    pub video: String,
+    /// Width and height of exported videos in pixels.
+    pub video_size: u32,
+    /// Frames per second of exported videos.
+    pub video_fps: f32,
+    /// How many times as fast as it was painted the session is replayed.
+    pub replay_speed: f64,
+    /// Paints dots to the sound of the default input device.
+    pub audio: bool,
+    /// Path of a PNG to start with as stipples.
+    pub stipple: Option<String>,
+    /// Path of a Rhai script painting on the canvas.
+    pub script: Option<String>,
+    /// Who painted strokes are attributed to.
+    pub author: Option<String>,
+    /// Paints each pixel once per stroke.
+    pub even_strokes: bool,
+    /// Merges dots too small to see when zoomed out.
+    pub lod: Option<LodSettings>,
+    /// Shown over the canvas.
+    pub guides: Option<Guides>,
+    /// Snaps strokes to the guides.
+    pub snap: bool,
+    /// Crops exports to what the window shows.
+    pub export_viewport: bool,
+    /// Width of the border around exports in pixels.
+    pub export_border: u32,
+    pub border_color: Color,
+    /// Path of a PNG composited over a corner of exports.
+    pub watermark: Option<String>,
+    pub watermark_corner: Corner,
+    pub watermark_opacity: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            canvas_size: DEFAULT_CANVAS_SIZE,
+            background: Background::default(),
+            open: None,
+            ask_document: true,
+            restore_session: true,
+            seed: None,
+            initial_dots: 0,
+            backends: wgpu::Backends::all(),
+            readonly: false,
+            color_space: CanvasColorSpace::default(),
+            gamut: DisplayGamut::default(),
+            dither: Dither::default(),
+            effects: Vec::new(),
+            lut: None,
+            palette: None,
+            recent_colors: 8,
+            trace: None,
+            chrome_trace: None,
+            recover_stalls: false,
+            stress: None,
+            frame_budget_ms: 33.3,
+            particles: false,
+            video: "hellopaint.mp4".to_owned(),
+            video_size: 512,
+            video_fps: 12.0,
+            replay_speed: 10.0,
+            audio: false,
+            stipple: None,
+            script: None,
+            author: None,
+            even_strokes: false,
+            lod: None,
+            guides: None,
+            snap: false,
+            export_viewport: false,
+            export_border: 0,
+            border_color: Color::WHITE,
+            watermark: None,
+            watermark_corner: Corner::default(),
+            watermark_opacity: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Parses `--name value` and `--name=value` flags, without the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = arg
+                .strip_prefix("--")
+                .ok_or_else(|| ConfigError(format!("unexpected argument {arg:?}")))?;
+            match flag.split_once('=') {
+                Some((name, value)) => config.set(name, Some(value))?,
+                None if takes_value(flag) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError(format!("--{flag} needs a value")))?;
+                    config.set(flag, Some(&value))?;
+                }
+                None => config.set(flag, None)?,
+            }
+        }
+        Ok(config)
+    }
+
+    /// Parses a URL query string like `?seed=7&readonly`, percent-decoding names and values.
+    pub fn from_query(query: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let pairs = query
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|pair| !pair.is_empty());
+        for pair in pairs {
+            let decode = |part: &str| percent_decode(part).ok_or_else(|| ConfigError(format!("invalid escape in {part:?}")));
+            match pair.split_once('=') {
+                Some((name, value)) => config.set(&decode(name)?, Some(&decode(value)?))?,
+                None => config.set(&decode(pair)?, None)?,
+            }
+        }
+        Ok(config)
+    }
+
+    /// The canvas a new document starts with.
+    pub fn canvas_setup(&self) -> CanvasSetup {
+        CanvasSetup {
+            size: self.canvas_size,
+            background: self.background,
+        }
+    }
+
+    /// Starts with `canvas` instead.
+    pub fn set_canvas_setup(&mut self, canvas: CanvasSetup) {
+        self.canvas_size = canvas.size;
+        self.background = canvas.background;
+    }
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), ConfigError> {
+        let invalid = |value: &str| ConfigError(format!("invalid value {value:?} for {name}"));
+        // Switches are on without a value, or with true or 1, and off with false or 0
+        let switch = || match value.unwrap_or_default() {
+            "" | "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            other => Err(invalid(other)),
+        };
+        let value = || value.ok_or_else(|| ConfigError(format!("{name} needs a value")));
+
+        if matches!(name, "canvas-size" | "new" | "background" | "open") {
+            self.ask_document = false;
+        }
+        match name {
+            "canvas-size" => {
+                let value = value()?;
+                self.canvas_size = value.parse().ok().filter(|&size| size > 0).ok_or_else(|| invalid(value))?;
+            }
+            "new" => {
+                let value = value()?;
+                self.canvas_size = CanvasPreset::from_name(value).ok_or_else(|| invalid(value))?.size();
+            }
+            "background" => {
+                let value = value()?;
+                self.background = Background::from_name(value).ok_or_else(|| invalid(value))?;
+            }
+            "open" => self.open = Some(value()?.to_owned()),
+            "seed" => {
+                let value = value()?;
+                self.seed = Some(value.parse().map_err(|_| invalid(value))?);
+            }
+            "dots" => {
+                let value = value()?;
+                self.initial_dots = value.parse().map_err(|_| invalid(value))?;
+            }
+            "backend" => {
+                let value = value()?;
+                self.backends = parse_backends(value).ok_or_else(|| invalid(value))?;
+            }
+            "effects" => {
+                let value = value()?;
+                self.effects = value
+                    .split(',')
+                    .map(|name| Effect::from_name(name.trim()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "lut" => self.lut = Some(value()?.to_owned()),
+            "palette" => self.palette = Some(value()?.to_owned()),
+            "trace" => self.trace = Some(value()?.to_owned()),
+            "chrome-trace" => self.chrome_trace = Some(value()?.to_owned()),
+            "stipple" => self.stipple = Some(value()?.to_owned()),
+            "script" => self.script = Some(value()?.to_owned()),
+            "author" => self.author = Some(value()?.to_owned()),
+            "lod" => {
+                let value = value()?;
+                let min_screen_size = value.parse().ok().filter(|&size: &f32| size.is_finite() && size > 0.0).ok_or_else(|| invalid(value))?;
+                self.lod = Some(LodSettings { min_screen_size });
+            }
+            "guides" => {
+                let value = value()?;
+                self.guides = Some(Guides::parse(value).ok_or_else(|| invalid(value))?);
+            }
+            "export-border" => {
+                let value = value()?;
+                self.export_border = value.parse().map_err(|_| invalid(value))?;
+            }
+            "border-color" => {
+                let value = value()?;
+                self.border_color = Color::from_hex(value).ok_or_else(|| invalid(value))?;
+            }
+            "watermark" => self.watermark = Some(value()?.to_owned()),
+            "watermark-corner" => {
+                let value = value()?;
+                self.watermark_corner = Corner::from_name(value).ok_or_else(|| invalid(value))?;
+            }
+            "watermark-opacity" => {
+                let value = value()?;
+                self.watermark_opacity = value
+                    .parse()
+                    .ok()
+                    .filter(|opacity: &f32| (0.0..=1.0).contains(opacity))
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "recent-colors" => {
+                let value = value()?;
+                self.recent_colors = value.parse().map_err(|_| invalid(value))?;
+            }
+            "stress" => {
+                let value = value()?;
+                self.stress = Some(value.parse().ok().filter(|&dots| dots > 0).ok_or_else(|| invalid(value))?);
+            }
+            "frame-budget" => {
+                let value = value()?;
+                self.frame_budget_ms = value
+                    .parse()
+                    .ok()
+                    .filter(|&budget: &f64| budget.is_finite() && budget > 0.0)
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "video" => self.video = value()?.to_owned(),
+            "video-size" => {
+                let value = value()?;
+                self.video_size = value.parse().ok().filter(|&size| size > 0).ok_or_else(|| invalid(value))?;
+            }
+            "video-fps" => {
+                let value = value()?;
+                self.video_fps = value
+                    .parse()
+                    .ok()
+                    .filter(|&fps: &f32| fps.is_finite() && fps > 0.0)
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "replay-speed" => {
+                let value = value()?;
+                self.replay_speed = value
+                    .parse()
+                    .ok()
+                    .filter(|&speed: &f64| speed.is_finite() && speed > 0.0)
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "readonly" => self.readonly = switch()?,
+            "linear" => {
+                self.color_space = if switch()? {
+                    CanvasColorSpace::Linear
+                } else {
+                    CanvasColorSpace::Srgb
+                };
+            }
+            "display-p3" => {
+                self.gamut = if switch()? {
+                    DisplayGamut::DisplayP3
+                } else {
+                    DisplayGamut::Srgb
+                };
+            }
+            "dither" => self.dither = if switch()? { Dither::Ordered } else { Dither::None },
+            "recover-stalls" => self.recover_stalls = switch()?,
+            "particles" => self.particles = switch()?,
+            "audio" => self.audio = switch()?,
+            "even-strokes" => self.even_strokes = switch()?,
+            "snap" => self.snap = switch()?,
+            "export-viewport" => self.export_viewport = switch()?,
+            "no-restore" => self.restore_session = !switch()?,
+            _ => return Err(ConfigError(format!("unknown option {name:?}"))),
+        }
+        Ok(())
+    }
+}
+
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "canvas-size"
+            | "new"
+            | "background"
+            | "open"
+            | "seed"
+            | "dots"
+            | "backend"
+            | "effects"
+            | "lut"
+            | "palette"
+            | "recent-colors"
+            | "trace"
+            | "chrome-trace"
+            | "stress"
+            | "frame-budget"
+            | "video"
+            | "video-size"
+            | "video-fps"
+            | "replay-speed"
+            | "stipple"
+            | "script"
+            | "author"
+            | "lod"
+            | "guides"
+            | "export-border"
+            | "border-color"
+            | "watermark"
+            | "watermark-corner"
+            | "watermark-opacity"
+    )
+}
+
+/// `text` with `%XX` escapes decoded and `+` taken as a space, as browsers encode queries. `None`
+/// for malformed escapes or bytes that aren't UTF-8.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(rest.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &rest[2..];
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn parse_backends(list: &str) -> Option<wgpu::Backends> {
+    list.split(',').try_fold(wgpu::Backends::empty(), |backends, name| {
+        let backend = match name.trim().to_lowercase().as_str() {
+            "vulkan" | "vk" => wgpu::Backends::VULKAN,
+            "metal" | "mtl" => wgpu::Backends::METAL,
+            "dx12" | "d3d12" => wgpu::Backends::DX12,
+            "dx11" | "d3d11" => wgpu::Backends::DX11,
+            "gl" | "gles" | "opengl" | "webgl" => wgpu::Backends::GL,
+            "webgpu" => wgpu::Backends::BROWSER_WEBGPU,
+            "primary" => wgpu::Backends::PRIMARY,
+            "all" => wgpu::Backends::all(),
+            _ => return None,
+        };
+        Some(backends | backend)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Config, ConfigError> {
+        Config::from_args(line.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn switches_take_an_optional_value() {
+        assert!(args("--readonly").unwrap().readonly);
+        assert!(args("--readonly=true").unwrap().readonly);
+        assert!(!args("--readonly=false").unwrap().readonly);
+        assert!(Config::from_query("?readonly").unwrap().readonly);
+        assert!(Config::from_query("?readonly=1").unwrap().readonly);
+        assert!(!Config::from_query("?readonly=0").unwrap().readonly);
+        assert!(Config::from_query("?readonly=maybe").is_err());
+
+        assert_eq!(args("--linear=0").unwrap().color_space, CanvasColorSpace::Srgb);
+        assert_eq!(args("--display-p3").unwrap().gamut, DisplayGamut::DisplayP3);
+        assert_eq!(args("--dither=false").unwrap().dither, Dither::None);
+        assert!(!args("--no-restore").unwrap().restore_session);
+        assert!(args("--no-restore=false").unwrap().restore_session);
+    }
+
+    #[test]
+    fn values_come_from_the_next_argument_or_after_the_equals_sign() {
+        let config = args("--seed 7 --canvas-size=2048 --effects vignette,grain").unwrap();
+        assert_eq!(config.seed, Some(7));
+        assert_eq!(config.canvas_size, 2048);
+        assert_eq!(config.effects.len(), 2);
+        assert!(!config.ask_document);
+        assert!(args("--seed").is_err());
+        assert!(args("seed 7").is_err());
+        assert!(args("--unknown").is_err());
+    }
+
+    #[test]
+    fn queries_are_percent_decoded() {
+        let config = Config::from_query("?author=Ada%20L+Lovelace&border-color=%23f5f0e4").unwrap();
+        assert_eq!(config.author.as_deref(), Some("Ada L Lovelace"));
+        assert_eq!(config.border_color, Color::from_hex("#f5f0e4").unwrap());
+        assert_eq!(Config::from_query("?author=%C3%A9").unwrap().author.as_deref(), Some("\u{e9}"));
+        assert!(Config::from_query("?author=%2").is_err());
+        assert!(Config::from_query("?author=%zz").is_err());
+        assert!(Config::from_query("?author=%ff").is_err());
+    }
+
+    #[test]
+    fn rates_and_sizes_must_be_finite() {
+        for name in ["video-fps", "replay-speed", "frame-budget", "lod"] {
+            for value in ["inf", "NaN", "0", "-1"] {
+                assert!(args(&format!("--{name}={value}")).is_err(), "--{name}={value}");
+            }
+            assert!(args(&format!("--{name}=2.5")).is_ok(), "--{name}=2.5");
+        }
+    }
+}
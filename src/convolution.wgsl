@@ -0,0 +1,44 @@
+// Convolves the canvas with a kernel, see `Kernel` in filter.rs.
+//
+// `Convolution` mirrors `ConvolutionUniforms` in filter.rs, keep them in sync when changing either side.
+
+struct Convolution {
+    origin: vec2<u32>,
+    size: vec2<u32>,
+    radius: i32,
+    bias: f32,
+    _padding: vec2<f32>,
+    // Row by row, four weights to an element
+    weights: array<vec4<f32>, 21>,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> convolution: Convolution;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= convolution.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(convolution.origin + id.xy);
+    // Edges repeat the outermost pixels
+    let last = vec2<i32>(textureDimensions(input)) - 1;
+    let size = 2 * convolution.radius + 1;
+
+    var sum = vec3<f32>(0.0);
+    for (var y = 0; y < size; y += 1) {
+        for (var x = 0; x < size; x += 1) {
+            let index = y * size + x;
+            let weight = convolution.weights[index / 4][index % 4];
+            let offset = vec2<i32>(x, y) - convolution.radius;
+            sum += weight * textureLoad(input, clamp(pixel + offset, vec2<i32>(0), last), 0).rgb;
+        }
+    }
+    // Alpha is kept, and the color stays premultiplied by it
+    let alpha = textureLoad(input, pixel, 0).a;
+    textureStore(output, pixel, vec4<f32>(clamp(sum + convolution.bias, vec3<f32>(0.0), vec3<f32>(alpha)), alpha));
+}
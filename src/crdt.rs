@@ -0,0 +1,388 @@
+//! Documents that merge: a [`StrokeLog`] is an append-only log of strokes and layer operations
+//! that every replica of a document keeps. Each operation has an [`OpId`] unique to the replica
+//! that made it, so merging two logs is taking both sets of operations, which gives the same
+//! document whichever order logs are merged in and however often. Two people painting offline
+//! keep all of each other's strokes once their logs meet.
+//!
+//! Nothing is ever deleted from the log: removed strokes and layers stay as tombstones, and later
+//! changes to a layer win over earlier ones.
+//!
+//! The app doesn't keep a log yet, as there's no transport in this crate to exchange operations
+//! over. Embedders syncing documents themselves send each other [`StrokeLog::ops_since`] the
+//! other's [`Version`], [`StrokeLog::merge`] what they receive, and draw
+//! [`StrokeLog::document`] with [`HpSurface::set_document`](crate::surface::HpSurface::set_document).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::document::{Document, StrokeInfo};
+use crate::surface::Dot;
+
+/// Tells replicas of a document apart, e.g. picked randomly when a device first opens it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ReplicaId(pub u64);
+
+/// When and where an operation was made. Counters are Lamport clocks, ahead of every operation
+/// the replica had seen, so operations made after others sort after them. Concurrent ones are
+/// ordered by replica, the same way everywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OpId {
+    pub counter: u64,
+    pub replica: ReplicaId,
+}
+
+/// A layer strokes are painted on, named by the operation that added it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LayerId {
+    /// The layer every document starts with, at the bottom.
+    Base,
+    Added(OpId),
+}
+
+/// A change to the document, see [`StrokeLog`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Op {
+    /// A finished stroke on `layer`, drawn over the strokes before it, with what it was painted
+    /// with, when and by whom.
+    AddStroke {
+        layer: LayerId,
+        #[serde(default)]
+        info: StrokeInfo,
+        dots: Vec<Dot>,
+    },
+    /// Removes the stroke added by the operation `stroke`, e.g. when it's undone.
+    RemoveStroke { stroke: OpId },
+    /// A layer over the layers before it.
+    AddLayer { name: String },
+    /// Removes `layer` with all strokes painted on it, even ones added concurrently.
+    RemoveLayer { layer: LayerId },
+    /// Shows or hides `layer`, the latest change wins.
+    SetLayerVisible { layer: LayerId, visible: bool },
+}
+
+/// A layer of a [`StrokeLog`] as the operations so far leave it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    pub visible: bool,
+    /// The strokes on it in the order they're drawn, by the operations that added them.
+    pub strokes: Vec<OpId>,
+}
+
+/// The operations of a document, by replica the latest counter seen from each. Replicas send
+/// each other the operations the other's version doesn't have yet, see [`StrokeLog::ops_since`].
+pub type Version = BTreeMap<ReplicaId, u64>;
+
+/// A replica of a document as its log of operations.
+#[derive(Debug, Clone)]
+pub struct StrokeLog {
+    replica: ReplicaId,
+    /// The latest counter seen from any replica.
+    clock: u64,
+    ops: BTreeMap<OpId, Op>,
+}
+
+impl StrokeLog {
+    /// An empty document edited as `replica`, which no other replica of it may use.
+    pub fn new(replica: ReplicaId) -> Self {
+        Self {
+            replica,
+            clock: 0,
+            ops: BTreeMap::new(),
+        }
+    }
+
+    /// Starts a replica from a document saved without a log, its strokes on the base layer.
+    /// Replicas merging with it have to start from the same log, not the same document, or
+    /// they'd have its dots twice.
+    pub fn from_document(replica: ReplicaId, document: &Document) -> Self {
+        let mut log = Self::new(replica);
+        for stroke in document.strokes.iter().filter(|stroke| !stroke.dots.is_empty()) {
+            log.add_stroke(LayerId::Base, stroke.info.clone(), stroke.dots.clone());
+        }
+        log
+    }
+
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    /// Makes an operation on this replica, returning its id.
+    pub fn apply(&mut self, op: Op) -> OpId {
+        self.clock += 1;
+        let id = OpId {
+            counter: self.clock,
+            replica: self.replica,
+        };
+        self.ops.insert(id, op);
+        id
+    }
+
+    pub fn add_stroke(&mut self, layer: LayerId, info: StrokeInfo, dots: Vec<Dot>) -> OpId {
+        self.apply(Op::AddStroke { layer, info, dots })
+    }
+
+    pub fn remove_stroke(&mut self, stroke: OpId) -> OpId {
+        self.apply(Op::RemoveStroke { stroke })
+    }
+
+    pub fn add_layer(&mut self, name: impl Into<String>) -> LayerId {
+        LayerId::Added(self.apply(Op::AddLayer { name: name.into() }))
+    }
+
+    pub fn remove_layer(&mut self, layer: LayerId) -> OpId {
+        self.apply(Op::RemoveLayer { layer })
+    }
+
+    pub fn set_layer_visible(&mut self, layer: LayerId, visible: bool) -> OpId {
+        self.apply(Op::SetLayerVisible { layer, visible })
+    }
+
+    /// Takes in operations from another replica, e.g. the ones it sent since the last merge.
+    /// Operations this replica already has are skipped, so sending too many is harmless.
+    pub fn merge(&mut self, ops: impl IntoIterator<Item = (OpId, Op)>) {
+        for (id, op) in ops {
+            self.clock = self.clock.max(id.counter);
+            self.ops.entry(id).or_insert(op);
+        }
+    }
+
+    /// Takes in everything `other` has.
+    pub fn merge_log(&mut self, other: &StrokeLog) {
+        self.merge(other.ops.iter().map(|(id, op)| (*id, op.clone())));
+    }
+
+    /// All operations, oldest first.
+    pub fn ops(&self) -> impl Iterator<Item = (&OpId, &Op)> {
+        self.ops.iter()
+    }
+
+    /// What this replica has seen.
+    pub fn version(&self) -> Version {
+        let mut version = Version::new();
+        for id in self.ops.keys() {
+            let counter = version.entry(id.replica).or_default();
+            *counter = (*counter).max(id.counter);
+        }
+        version
+    }
+
+    /// The operations a replica at `version` hasn't seen, to send it.
+    pub fn ops_since(&self, version: &Version) -> Vec<(OpId, Op)> {
+        self.ops
+            .iter()
+            .filter(|(id, _)| version.get(&id.replica).map_or(true, |&seen| id.counter > seen))
+            .map(|(id, op)| (*id, op.clone()))
+            .collect()
+    }
+
+    /// The layers that weren't removed, from the bottom up, with their strokes.
+    pub fn layers(&self) -> Vec<Layer> {
+        let mut layers = vec![Layer {
+            id: LayerId::Base,
+            name: "Base".to_owned(),
+            visible: true,
+            strokes: Vec::new(),
+        }];
+        for (id, op) in &self.ops {
+            if let Op::AddLayer { name } = op {
+                layers.push(Layer {
+                    id: LayerId::Added(*id),
+                    name: name.clone(),
+                    visible: true,
+                    strokes: Vec::new(),
+                });
+            }
+        }
+
+        let mut removed_strokes = BTreeSet::new();
+        let mut removed_layers = BTreeSet::new();
+        for (id, op) in &self.ops {
+            match op {
+                Op::AddStroke { layer, .. } => {
+                    if let Some(layer) = layers.iter_mut().find(|candidate| candidate.id == *layer) {
+                        layer.strokes.push(*id);
+                    }
+                }
+                Op::RemoveStroke { stroke } => {
+                    removed_strokes.insert(*stroke);
+                }
+                Op::RemoveLayer { layer } => {
+                    removed_layers.insert(*layer);
+                }
+                // Later operations are visited later, so the latest one is left
+                Op::SetLayerVisible { layer, visible } => {
+                    if let Some(layer) = layers.iter_mut().find(|candidate| candidate.id == *layer) {
+                        layer.visible = *visible;
+                    }
+                }
+                Op::AddLayer { .. } => {}
+            }
+        }
+        layers.retain(|layer| !removed_layers.contains(&layer.id));
+        for layer in &mut layers {
+            layer.strokes.retain(|stroke| !removed_strokes.contains(stroke));
+        }
+        layers
+    }
+
+    /// The dots of `stroke`, if it's a stroke.
+    pub fn stroke(&self, stroke: OpId) -> Option<&[Dot]> {
+        self.stroke_with_info(stroke).map(|(_, dots)| dots)
+    }
+
+    /// What `stroke` was painted with, when and by whom, and its dots, if it's a stroke.
+    pub fn stroke_with_info(&self, stroke: OpId) -> Option<(&StrokeInfo, &[Dot])> {
+        match self.ops.get(&stroke)? {
+            Op::AddStroke { info, dots, .. } => Some((info, dots)),
+            _ => None,
+        }
+    }
+
+    /// The document to draw: the visible layers' strokes with their info, bottom layer first.
+    pub fn document(&self) -> Document {
+        let mut document = Document::default();
+        let layers = self.layers();
+        let strokes = layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .flat_map(|layer| &layer.strokes)
+            .filter_map(|&stroke| self.stroke_with_info(stroke));
+        for (info, dots) in strokes {
+            document.add_dots(info.clone(), dots.to_vec());
+        }
+        document
+    }
+
+    /// The operations as JSON, to save or send. [`StrokeLog::from_json`] reads them back.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.ops.iter().collect::<Vec<_>>())
+    }
+
+    /// Reads operations saved by [`StrokeLog::to_json`] into a log edited as `replica`. Their
+    /// dots come from a file and are [validated](Dot::validated).
+    pub fn from_json(replica: ReplicaId, json: &str) -> serde_json::Result<Self> {
+        use serde::de::Error as _;
+
+        let mut ops: Vec<(OpId, Op)> = serde_json::from_str(json)?;
+        for (id, op) in &mut ops {
+            if let Op::AddStroke { dots, .. } = op {
+                for (index, dot) in dots.iter_mut().enumerate() {
+                    *dot = dot.validated().map_err(|err| {
+                        serde_json::Error::custom(format!("stroke {}@{}, dot {index}: {err}", id.counter, id.replica.0))
+                    })?;
+                }
+            }
+        }
+        let mut log = Self::new(replica);
+        log.merge(ops);
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::document::StrokeMetadata;
+
+    fn stroke(x: f32) -> Vec<Dot> {
+        vec![Dot::new([x, 0.0], 0.1, 0.5, Color::RED)]
+    }
+
+    /// The dots' x positions by layer, from the bottom up.
+    fn drawn(log: &StrokeLog) -> Vec<(String, Vec<f32>)> {
+        log.layers()
+            .into_iter()
+            .map(|layer| {
+                let xs = layer.strokes.iter().map(|&stroke| log.stroke(stroke).unwrap()[0].position()[0]);
+                (layer.name, xs.collect())
+            })
+            .collect()
+    }
+
+    /// Two replicas that painted offline from the same start: `a` added a layer and strokes on
+    /// it, `b` removed a stroke they shared and painted over it.
+    fn diverged() -> (StrokeLog, StrokeLog) {
+        let mut a = StrokeLog::new(ReplicaId(1));
+        let shared = a.add_stroke(LayerId::Base, StrokeInfo::default(), stroke(0.1));
+        a.add_stroke(LayerId::Base, StrokeInfo::default(), stroke(0.2));
+        let mut b = StrokeLog::new(ReplicaId(2));
+        b.merge_log(&a);
+
+        let layer = a.add_layer("Sketch");
+        a.add_stroke(layer, StrokeInfo::default(), stroke(0.3));
+        a.add_stroke(LayerId::Base, StrokeInfo::default(), stroke(0.4));
+        b.remove_stroke(shared);
+        b.add_stroke(LayerId::Base, StrokeInfo::default(), stroke(0.5));
+        (a, b)
+    }
+
+    #[test]
+    fn merging_is_commutative() {
+        let (a, b) = diverged();
+        let mut ab = a.clone();
+        ab.merge_log(&b);
+        let mut ba = b.clone();
+        ba.merge_log(&a);
+        assert_eq!(drawn(&ab), drawn(&ba));
+        assert_eq!(ab.version(), ba.version());
+    }
+
+    #[test]
+    fn merging_is_idempotent() {
+        let (mut a, b) = diverged();
+        a.merge_log(&b);
+        let once = drawn(&a);
+        a.merge_log(&b);
+        a.merge(b.ops_since(&Version::new()));
+        let copy = a.clone();
+        a.merge_log(&copy);
+        assert_eq!(drawn(&a), once);
+        assert_eq!(a.ops().count(), copy.ops().count());
+    }
+
+    #[test]
+    fn concurrent_adds_and_removes_converge() {
+        let (mut a, mut b) = diverged();
+        // Each sends what the other hasn't seen
+        let to_b = a.ops_since(&b.version());
+        let to_a = b.ops_since(&a.version());
+        a.merge(to_a);
+        b.merge(to_b);
+
+        // b's stroke has the lower counter, as b made fewer operations since they diverged
+        let expected = vec![("Base".to_owned(), vec![0.2, 0.5, 0.4]), ("Sketch".to_owned(), vec![0.3])];
+        assert_eq!(drawn(&a), expected);
+        assert_eq!(drawn(&b), expected);
+
+        // A layer removed on one replica takes the strokes the other concurrently painted on it
+        let sketch = a.layers()[1].id;
+        a.remove_layer(sketch);
+        b.add_stroke(sketch, StrokeInfo::default(), stroke(0.6));
+        a.merge_log(&b);
+        b.merge_log(&a);
+        assert_eq!(drawn(&a), drawn(&b));
+        assert_eq!(drawn(&a), vec![("Base".to_owned(), vec![0.2, 0.5, 0.4])]);
+    }
+
+    #[test]
+    fn strokes_keep_their_info() {
+        let info = StrokeInfo {
+            start: 1.0,
+            end: 2.5,
+            metadata: StrokeMetadata {
+                author: Some("ada".to_owned()),
+                ..StrokeMetadata::default()
+            },
+            ..StrokeInfo::default()
+        };
+        let mut document = Document::default();
+        document.add_dots(info.clone(), stroke(0.1));
+        let log = StrokeLog::from_document(ReplicaId(1), &document);
+        let merged = StrokeLog::from_json(ReplicaId(2), &log.to_json().unwrap()).unwrap();
+        assert_eq!(merged.document().strokes[0].info, info);
+    }
+}
@@ -0,0 +1,90 @@
+//! Clear messages for the GPU errors of passes and resources.
+//!
+//! wgpu reports a validation error as the chain of checks that failed, naming objects by their
+//! ids. [`scoped`] catches the errors of a pass, or of creating a resource, and logs them along
+//! with what was being done, explaining the mistakes this renderer is prone to. The most common
+//! one is drawing into a texture of another format than the pipeline was built for, e.g. an
+//! sRGB swapchain texture with a pipeline for its non-sRGB variant.
+
+use std::fmt;
+
+use crate::error::Error;
+
+/// What [`scoped`] wraps, to name it in errors.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Scope {
+    /// Drawing with `pipeline`, built for `pipeline_format`, into `target`.
+    Pass {
+        pipeline: &'static str,
+        pipeline_format: wgpu::TextureFormat,
+        target: &'static str,
+        target_format: wgpu::TextureFormat,
+    },
+    /// Anything else, like a compute pass or creating a resource.
+    Other(&'static str),
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Pass { pipeline, target, .. } => write!(f, "drawing the {pipeline} pipeline into the {target}"),
+            Scope::Other(what) => write!(f, "{what}"),
+        }
+    }
+}
+
+/// Runs `f` in an error scope, logging the validation errors it caused in terms of `scope`.
+///
+/// Natively the errors are known right away. On the web they arrive asynchronously and are logged
+/// a little later.
+pub(crate) fn scoped<T>(device: &wgpu::Device, scope: Scope, f: impl FnOnce() -> T) -> T {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+    let error = device.pop_error_scope();
+    let log = async move {
+        if let Some(err) = error.await {
+            tracing::error!("{}", explain(scope, &err));
+        }
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(log);
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(log);
+    result
+}
+
+/// Runs `f` in an error scope like [`scoped`], failing with the validation errors it caused
+/// explained in terms of `scope`.
+///
+/// On the web the errors only arrive after `f` returned, so they're logged instead and this always
+/// succeeds.
+pub(crate) fn checked<T>(device: &wgpu::Device, scope: Scope, f: impl FnOnce() -> T) -> Result<T, Error> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = f();
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(err) => Err(Error::Gpu(explain(scope, &err))),
+            None => Ok(result),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    Ok(scoped(device, scope, f))
+}
+
+/// Describes `err`, which happened during `scope`.
+fn explain(scope: Scope, err: &wgpu::Error) -> String {
+    match scope {
+        Scope::Pass {
+            pipeline,
+            pipeline_format,
+            target,
+            target_format,
+        } if pipeline_format != target_format => format!(
+            "Failed {scope}: the pipeline was built for {pipeline_format:?} targets, but the {target} is \
+             {target_format:?}. Build the {pipeline} pipeline for the format of the texture it draws into.\n\
+             {err}"
+        ),
+        _ => format!("Failed {scope}: {err}"),
+    }
+}
@@ -0,0 +1,303 @@
+//! Saved paintings. A [`Document`] is made of strokes: the dots painted from pressing the pointer
+//! to lifting it, or added at once, e.g. by a generator. Every [`DocumentStroke`] keeps the brush
+//! it was painted with and when, so painted dots are saved as little more than where they are and
+//! how large, and drawn again from the brush when loaded. Their [`StrokeMetadata`] says who
+//! painted them, when and with what, for attribution and to pick strokes with a [`StrokeFilter`].
+
+use std::ops::Range;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+
+use crate::new_document::CanvasSetup;
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+/// Tells the strokes of a canvas apart, counting up in the order they were started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct StrokeId(pub u64);
+
+/// Who painted a stroke, when and with what, kept and saved with it but never drawn. Fields not
+/// known are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StrokeMetadata {
+    /// E.g. the name of the collaborator who painted it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// When the stroke started, in milliseconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// What painted it, e.g. `mouse`, `touch` or `script`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl StrokeMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Picks strokes by their metadata, e.g. one collaborator's for a timelapse. Fields not set
+/// match all strokes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrokeFilter {
+    pub author: Option<String>,
+    pub device: Option<String>,
+    /// Strokes with this among their tags.
+    pub tag: Option<String>,
+    /// Strokes started in this range, in milliseconds since the Unix epoch. Strokes without a
+    /// timestamp don't match.
+    pub timestamps: Option<Range<u64>>,
+}
+
+impl StrokeFilter {
+    pub fn matches(&self, metadata: &StrokeMetadata) -> bool {
+        let matches = |wanted: &Option<String>, value: &Option<String>| wanted.is_none() || wanted == value;
+        matches(&self.author, &metadata.author)
+            && matches(&self.device, &metadata.device)
+            && self.tag.as_ref().map_or(true, |tag| metadata.tags.contains(tag))
+            && self.timestamps.as_ref().map_or(true, |range| {
+                metadata.timestamp.is_some_and(|timestamp| range.contains(&timestamp))
+            })
+    }
+}
+
+/// What a stroke was painted with and when.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StrokeInfo {
+    /// `None` for dots added rather than painted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brush: Option<Brush>,
+    /// Seconds into the painting session the stroke started and ended at.
+    #[serde(default)]
+    pub start: f64,
+    #[serde(default)]
+    pub end: f64,
+    #[serde(default, skip_serializing_if = "StrokeMetadata::is_empty")]
+    pub metadata: StrokeMetadata,
+}
+
+impl StrokeInfo {
+    /// A stroke of `brush` starting at `seconds`.
+    pub fn painted(brush: Brush, seconds: f64) -> Self {
+        Self {
+            brush: Some(brush),
+            start: seconds,
+            end: seconds,
+            metadata: StrokeMetadata::default(),
+        }
+    }
+
+    /// Whether the stroke was painted with watercolor, see [`crate::watercolor`].
+    pub fn is_wet(&self) -> bool {
+        self.brush.is_some_and(|brush| brush.wet)
+    }
+
+    /// Dots added at once at `seconds`.
+    pub fn added(seconds: f64) -> Self {
+        Self {
+            brush: None,
+            start: seconds,
+            end: seconds,
+            metadata: StrokeMetadata::default(),
+        }
+    }
+}
+
+/// A stroke as it's saved: its dots, in drawing order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedStroke", into = "SavedStroke")]
+pub struct DocumentStroke {
+    pub id: StrokeId,
+    pub info: StrokeInfo,
+    pub dots: Vec<Dot>,
+}
+
+/// How a [`DocumentStroke`] is written: dots its brush stamps again as just `[x, y, radius]`,
+/// any others in full.
+#[derive(Serialize, Deserialize)]
+struct SavedStroke {
+    id: StrokeId,
+    #[serde(flatten)]
+    info: StrokeInfo,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stamps: Vec<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dots: Vec<Dot>,
+}
+
+impl From<DocumentStroke> for SavedStroke {
+    fn from(stroke: DocumentStroke) -> Self {
+        let DocumentStroke { id, info, dots } = stroke;
+        // Dots moved or changed after painting no longer match their stamps
+        let stamps: Option<Vec<[f32; 3]>> = info.brush.and_then(|brush| {
+            dots.iter()
+                .enumerate()
+                .map(|(index, dot)| {
+                    let [x, y] = dot.position();
+                    (brush.stamp(index, [x, y], dot.radius()) == *dot).then_some([x, y, dot.radius()])
+                })
+                .collect()
+        });
+        match stamps {
+            Some(stamps) => Self {
+                id,
+                info,
+                stamps,
+                dots: Vec::new(),
+            },
+            None => Self {
+                id,
+                info,
+                stamps: Vec::new(),
+                dots,
+            },
+        }
+    }
+}
+
+impl From<SavedStroke> for DocumentStroke {
+    fn from(saved: SavedStroke) -> Self {
+        let SavedStroke { id, info, stamps, mut dots } = saved;
+        if let Some(brush) = info.brush.filter(|_| dots.is_empty()) {
+            dots = stamps
+                .iter()
+                .enumerate()
+                .map(|(index, &[x, y, radius])| brush.stamp(index, [x, y], radius))
+                .collect();
+        }
+        Self { id, info, dots }
+    }
+}
+
+/// A saved painting: everything needed to redraw the canvas.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "SavedDocument")]
+pub struct Document {
+    /// In the order they're drawn. Strokes painted at the same time are drawn one after the other.
+    pub strokes: Vec<DocumentStroke>,
+    /// The canvas it was painted on, `None` for documents saved before it was kept, which open on
+    /// the canvas the app starts with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canvas: Option<CanvasSetup>,
+}
+
+/// Reads documents saved before there were strokes too, as a stroke of added dots. Strokes are
+/// renumbered in their order if their ids don't count up, e.g. when a file repeats one, as
+/// canvases keep strokes apart and in order by their ids.
+#[derive(Deserialize)]
+struct SavedDocument {
+    #[serde(default)]
+    strokes: Vec<DocumentStroke>,
+    #[serde(default)]
+    dots: Vec<Dot>,
+    #[serde(default)]
+    canvas: Option<CanvasSetup>,
+}
+
+impl From<SavedDocument> for Document {
+    fn from(saved: SavedDocument) -> Self {
+        let mut document = Document::new(saved.dots);
+        document.strokes.extend(saved.strokes);
+        document.canvas = saved.canvas;
+        // Ids out of order, or so large the next stroke's can't follow, are counted anew
+        let unordered = document.strokes.windows(2).any(|pair| pair[0].id >= pair[1].id);
+        if unordered || document.strokes.last().is_some_and(|stroke| stroke.id.0 == u64::MAX) {
+            document.renumber();
+        }
+        document
+    }
+}
+
+impl Document {
+    /// A document of `dots` added at once, as a single stroke.
+    pub fn new(dots: Vec<Dot>) -> Self {
+        let mut document = Self::default();
+        document.add_dots(StrokeInfo::default(), dots);
+        document
+    }
+
+    /// Adds `dots` as a stroke after the others, unless there are none.
+    pub fn add_dots(&mut self, info: StrokeInfo, dots: Vec<Dot>) {
+        if dots.is_empty() {
+            return;
+        }
+        let next = |strokes: &[DocumentStroke]| strokes.last().map_or(Some(0), |stroke| stroke.id.0.checked_add(1));
+        let id = match next(&self.strokes) {
+            Some(id) => id,
+            None => {
+                self.renumber();
+                next(&self.strokes).expect("fewer strokes than ids")
+            }
+        };
+        self.strokes.push(DocumentStroke {
+            id: StrokeId(id),
+            info,
+            dots,
+        });
+    }
+
+    /// Counts the strokes' ids up from 0 again, in drawing order.
+    fn renumber(&mut self) {
+        for (id, stroke) in (0..).zip(&mut self.strokes) {
+            stroke.id = StrokeId(id);
+        }
+    }
+
+    /// Adds `dot` to the last stroke if it was added rather than painted, or as a stroke of its
+    /// own.
+    pub fn push_dot(&mut self, dot: Dot) {
+        match self.strokes.last_mut() {
+            Some(stroke) if stroke.info.brush.is_none() => stroke.dots.push(dot),
+            _ => self.add_dots(StrokeInfo::default(), vec![dot]),
+        }
+    }
+
+    /// The document with only the strokes `filter` matches.
+    pub fn filtered(&self, filter: &StrokeFilter) -> Document {
+        let strokes = self
+            .strokes
+            .iter()
+            .filter(|stroke| filter.matches(&stroke.info.metadata))
+            .cloned()
+            .collect();
+        Document {
+            strokes,
+            canvas: self.canvas,
+        }
+    }
+
+    /// The dots of all strokes, in drawing order.
+    pub fn dots(&self) -> Vec<Dot> {
+        self.strokes.iter().flat_map(|stroke| &stroke.dots).copied().collect()
+    }
+
+    pub fn dot_count(&self) -> usize {
+        self.strokes.iter().map(|stroke| stroke.dots.len()).sum()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Parses a saved document. Its dots come from a file and are [validated](Dot::validated), one
+    /// that can't be drawn fails the whole document, as does a canvas of 0 pixels.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut document: Document = serde_json::from_str(json)?;
+        if document.canvas.is_some_and(|canvas| canvas.size == 0) {
+            return Err(serde_json::Error::custom("the canvas is 0 pixels wide"));
+        }
+        for stroke in &mut document.strokes {
+            for (index, dot) in stroke.dots.iter_mut().enumerate() {
+                *dot = dot.validated().map_err(|err| {
+                    serde_json::Error::custom(format!("stroke {} dot {index}: {err}", stroke.id.0))
+                })?;
+            }
+        }
+        Ok(document)
+    }
+}
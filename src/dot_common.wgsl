@@ -0,0 +1,112 @@
+// Declarations shared by the shaders that draw dots.
+//
+// `Canvas`, `VertexInput` and `Dot` mirror `CanvasUniforms`, `Vertex::ATTRIBUTES` and
+// `Dot::ATTRIBUTES` in surface.rs, keep them in sync when changing either side. The tests in
+// surface.rs compare the vertex inputs against the attributes.
+
+struct Canvas {
+    size: vec2<f32>,
+    opacity: f32,
+    // The clock animated dots follow
+    seconds: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> canvas: Canvas;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+#ifdef STORAGE_DOTS
+// Devices with too few vertex attributes pull the dots out of a storage buffer instead, see
+// `DotInput` in surface.rs.
+struct Dot {
+    screenPosition: vec2<f32>,
+    radius: f32,
+    hardness: f32,
+    color: vec4<f32>,
+    noise: vec3<f32>,
+    animation: vec4<f32>,
+    hueSpeed: f32,
+    depth: f32,
+    instanceIndex: u32,
+}
+
+// `Dot`s as laid out in Rust, 17 floats each. Read by the float, WGSL would align the vectors.
+@group(1) @binding(0)
+var<storage, read> dots: array<f32>;
+
+fn load_dot(index: u32) -> Dot {
+    let base = index * 17u;
+    var dot: Dot;
+    dot.screenPosition = vec2<f32>(dots[base], dots[base + 1u]);
+    dot.radius = dots[base + 2u];
+    dot.hardness = dots[base + 3u];
+    dot.color = vec4<f32>(dots[base + 4u], dots[base + 5u], dots[base + 6u], dots[base + 7u]);
+    dot.noise = vec3<f32>(dots[base + 8u], dots[base + 9u], dots[base + 10u]);
+    dot.animation = vec4<f32>(dots[base + 11u], dots[base + 12u], dots[base + 13u], dots[base + 14u]);
+    dot.hueSpeed = dots[base + 15u];
+    dot.depth = dots[base + 16u];
+    dot.instanceIndex = index;
+    return dot;
+}
+#else
+struct Dot {
+    @location(1) screenPosition: vec2<f32>,
+    @location(2) radius: f32,
+    @location(3) hardness: f32,
+    @location(4) color: vec4<f32>,
+    // scale, seed, strength
+    @location(5) noise: vec3<f32>,
+    // start, duration, pulse, fade
+    @location(6) animation: vec4<f32>,
+    @location(7) hueSpeed: f32,
+    // 0 at the back to 1 at the front, only used with DEPTH_DOTS
+    @location(8) depth: f32,
+    @builtin(instance_index) instanceIndex: u32,
+}
+#endif
+
+// A dot's radius and color at `canvas.seconds`, see `DotAnimation` and `Dot::animated` in
+// surface.rs, which does the same on the CPU.
+struct Animated {
+    radius: f32,
+    color: vec4<f32>,
+}
+
+fn animate(instance: Dot) -> Animated {
+    let start = instance.animation.x;
+    let duration = instance.animation.y;
+    let elapsed = max(canvas.seconds - start, 0.0);
+    let progress = clamp((canvas.seconds - start) / max(duration, 1e-6), 0.0, 1.0);
+    let tau = 6.283185307179586;
+
+    let wave = select(0.0, sin(elapsed / duration * tau), duration > 0.0);
+    var out: Animated;
+    out.radius = instance.radius * max(1.0 + instance.animation.z * wave, 0.0);
+
+    let fade = instance.animation.w;
+    let faded = select(1.0 - progress, progress, fade >= 0.0);
+    let alpha = instance.color.a * (1.0 + (faded - 1.0) * abs(fade));
+
+    // Rotates the color around the gray axis, which keeps its brightness
+    let angle = elapsed * instance.hueSpeed * tau;
+    let k = vec3<f32>(0.5773502691896258);
+    let rgb = instance.color.rgb * cos(angle) + cross(k, instance.color.rgb) * sin(angle) + k * dot(k, instance.color.rgb) * (1.0 - cos(angle));
+    out.color = vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), alpha);
+    return out;
+}
+
+// Opacity of a dot at `distance` from its center, from 1 in the middle to 0 at 0.5.
+// Harder dots stay opaque further out before fading, but all fade over at least `edge`, how much
+// `distance` changes from one pixel to the next. That keeps the edges of hard dots about a pixel
+// wide however large they're drawn, neither blurry on large dots nor aliased on small ones.
+fn dot_falloff(distance: f32, hardness: f32, edge: f32) -> f32 {
+    return 1.0 - smoothstep(min(hardness / 2.0, 0.5 - edge), 0.5, distance);
+}
+
+// The canvas stores colors premultiplied by their alpha, so soft edges blend without dark fringes.
+fn premultiply(color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
@@ -1,16 +1,6 @@
 // Shader that draws circles
 
-struct VertexInput {
-    @location(0) position: vec2<f32>,
-}
-
-struct Dot {
-    @location(1) screenPosition: vec2<f32>,
-    @location(2) radius: f32,
-    @location(3) hardness: f32,
-    @location(4) color: vec4<f32>,
-    @builtin(instance_index) instanceIndex: u32,
-}
+#include "dot_common.wgsl"
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
@@ -18,31 +8,121 @@ struct VertexOutput {
     @location(1) radius: f32,
     @location(2) color: vec4<f32>,
     @location(3) hardness: f32,
+    @location(4) noise: vec3<f32>,
 }
 
 
 @vertex
+#ifdef STORAGE_DOTS
+fn vs_main(vertex: VertexInput, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let dot = load_dot(instance);
+#else
 fn vs_main(vertex: VertexInput, dot: Dot) -> VertexOutput {
+#endif
     var out: VertexOutput;
+    let animated = animate(dot);
 
-    out.position = vec4<f32>((vertex.position - 0.5) * dot.radius + dot.screenPosition * 0.01, 0.0, 1.0);
+    out.position = vec4<f32>((vertex.position - 0.5) * 2.0 * animated.radius + dot.screenPosition, clamp(dot.depth, 0.0, 1.0), 1.0);
     out.dot =  vertex.position - 0.25;
-    out.radius = dot.radius;
-    out.color = dot.color;
+    out.radius = animated.radius;
+    out.color = animated.color;
     out.hardness = dot.hardness;
+    out.noise = dot.noise;
 
     return out;
 }
 
 
+fn hash2(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+// Smoothly interpolated random values on a grid, 0..1
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(cell);
+    let b = hash2(cell + vec2<f32>(1.0, 0.0));
+    let c = hash2(cell + vec2<f32>(0.0, 1.0));
+    let d = hash2(cell + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn permute(x: vec3<f32>) -> vec3<f32> {
+    return ((x * 34.0 + 1.0) * x) % vec3<f32>(289.0);
+}
+
+// 2D simplex noise, -1..1, after Ashima Arts' webgl-noise
+fn simplex_noise(v: vec2<f32>) -> f32 {
+    let C = vec4<f32>(0.211324865405187, 0.366025403784439, -0.577350269189626, 0.024390243902439);
+    var i = floor(v + dot(v, C.yy));
+    let x0 = v - i + dot(i, C.xx);
+    let i1 = select(vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), x0.x > x0.y);
+    var x12 = x0.xyxy + C.xxzz;
+    x12 = vec4<f32>(x12.xy - i1, x12.zw);
+    i = i % vec2<f32>(289.0);
+    let p = permute(permute(i.y + vec3<f32>(0.0, i1.y, 1.0)) + i.x + vec3<f32>(0.0, i1.x, 1.0));
+    var m = max(0.5 - vec3<f32>(dot(x0, x0), dot(x12.xy, x12.xy), dot(x12.zw, x12.zw)), vec3<f32>(0.0));
+    m = m * m;
+    m = m * m;
+    let x = 2.0 * fract(p * C.www) - 1.0;
+    let h = abs(x) - 0.5;
+    let ox = floor(x + 0.5);
+    let a0 = x - ox;
+    m = m * (1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h));
+    let g = vec3<f32>(a0.x * x0.x + h.x * x0.y, a0.yz * x12.xz + h.yz * x12.yw);
+    return 130.0 * dot(m, g);
+}
+
+// How much of the stamp is left at `position` (0..1 across the stamp), with `noise` holding
+// scale, seed and strength. Simplex noise gives the organic shapes, value noise adds grain.
+fn stamp_texture(position: vec2<f32>, noise: vec3<f32>) -> f32 {
+    let p = position * noise.x + noise.y * vec2<f32>(17.13, 31.71);
+    let shape = simplex_noise(p) * 0.5 + 0.5;
+    let grain = value_noise(p * 4.0);
+    return mix(1.0, shape * 0.75 + grain * 0.25, noise.z);
+}
+
+// The color of a dot at `position` in its stamp (0..1), given its hardness and color, with
+// straight alpha. Apps can replace it at runtime, see `GlobalSurface::set_dot_snippet`.
+#ifdef CUSTOM_DOT
+#snippet CUSTOM_DOT
+#else
+fn custom_dot(position: vec2<f32>, hardness: f32, color: vec4<f32>) -> vec4<f32> {
+    return color;
+}
+#endif
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
-
-//    let a = input.dot - vec2(0.25, 0.25);
-//    let distance = dot(a, a) * 2.0;
-//
-//    let circle = (1.0) - smoothstep(0.0 + input.hardness / 2.0, 0.5, distance);
-//
-//    return vec4(input.color.xyz, input.color.w * circle);
+    let texture = stamp_texture(input.dot + 0.25, input.noise);
+    let a = input.dot - vec2<f32>(0.25, 0.25);
+    let distance = dot(a, a) * 2.0;
+
+    let circle = dot_falloff(distance, input.hardness, fwidth(distance));
+
+    // Linear straight alpha, like `Color`, the sRGB canvas encodes it on write
+    let coverage = circle * texture;
+    let alpha = input.color.a * coverage;
+    let color = custom_dot(input.dot + 0.25, input.hardness, vec4<f32>(input.color.rgb, alpha));
+#ifdef DISCARD_TRANSPARENT
+    // Keep the corners of the quad from hiding the dots behind it
+    if color.a <= 0.0 {
+        discard;
+    }
+#endif
+    // Strokes painting each pixel once mark and paint where their dots cover at least half of it,
+    // and blend the edges around that, see `StrokeOverlap::Once`
+#ifdef STROKE_CORE
+    if coverage < 0.5 {
+        discard;
+    }
+#endif
+#ifdef STROKE_EDGE
+    if coverage >= 0.5 {
+        discard;
+    }
+#endif
+    return premultiply(vec4<f32>(color.rgb, color.a * canvas.opacity));
 }
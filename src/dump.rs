@@ -0,0 +1,153 @@
+//! Dumps of a canvas's dots as the CPU keeps them next to the bytes its instance buffers hold on
+//! the GPU, for when drawing looks wrong and it's unclear which side is.
+//!
+//! The demo writes one natively with F8: [`DOTS_FILE_NAME`] is a document the Python module can
+//! load, [`INSTANCES_FILE_NAME`] the raw instance buffers, batch after batch. Both can be diffed
+//! against earlier dumps, and [`InstanceDump::mismatches`] already compares the two.
+
+use std::fmt;
+use std::sync::Arc;
+
+use futures_channel::oneshot;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::document::Document;
+use crate::surface::Dot;
+
+/// Where the demo writes the dots, as a [`Document`].
+pub const DOTS_FILE_NAME: &str = "hellopaint-dots.json";
+/// Where the demo writes the instance buffers' bytes.
+pub const INSTANCES_FILE_NAME: &str = "hellopaint-instances.bin";
+
+#[derive(Debug)]
+pub enum DumpError {
+    Map(wgpu::BufferAsyncError),
+    Json(serde_json::Error),
+    #[cfg(not(target_arch = "wasm32"))]
+    Io(std::io::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Map(err) => write!(f, "failed to map the instance readback buffer: {err}"),
+            DumpError::Json(err) => write!(f, "failed to serialize the dots: {err}"),
+            #[cfg(not(target_arch = "wasm32"))]
+            DumpError::Io(err) => write!(f, "failed to write the dump: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+/// A pending copy of a canvas's instance buffers into a mappable buffer, see
+/// [`HpSurface::instance_readback`](crate::surface::HpSurface::instance_readback).
+pub struct InstanceReadback {
+    device: Arc<wgpu::Device>,
+    /// `None` without dots, there's nothing to copy then.
+    buffer: Option<wgpu::Buffer>,
+    dots: Vec<Dot>,
+}
+
+impl InstanceReadback {
+    /// Copies `buffers` one after the other, keeping `dots` as the CPU's side of the dump.
+    pub fn new<'a>(
+        device: Arc<wgpu::Device>,
+        queue: &wgpu::Queue,
+        buffers: impl Iterator<Item = &'a wgpu::Buffer>,
+        dots: Vec<Dot>,
+    ) -> Self {
+        let buffers: Vec<&wgpu::Buffer> = buffers.collect();
+        let size: wgpu::BufferAddress = buffers.iter().map(|buffer| buffer.size()).sum();
+        if size == 0 {
+            return Self {
+                device,
+                buffer: None,
+                dots,
+            };
+        }
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Instance Readback Encoder"),
+        });
+        encoder.push_debug_group("instance readback");
+        let mut offset = 0;
+        for source in buffers {
+            encoder.copy_buffer_to_buffer(source, 0, &buffer, offset, source.size());
+            offset += source.size();
+        }
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            device,
+            buffer: Some(buffer),
+            dots,
+        }
+    }
+
+    /// Waits for the copy.
+    pub async fn into_dump(self) -> Result<InstanceDump, DumpError> {
+        let Some(buffer) = self.buffer else {
+            return Ok(InstanceDump {
+                dots: self.dots,
+                instance_bytes: Vec::new(),
+            });
+        };
+        let slice = buffer.slice(..);
+        let (sender, receiver) = oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        // Native backends only resolve the mapping when polled, on the web this is a no-op
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .await
+            .expect("map_async callback dropped")
+            .map_err(DumpError::Map)?;
+
+        let instance_bytes = slice.get_mapped_range().to_vec();
+        Ok(InstanceDump {
+            dots: self.dots,
+            instance_bytes,
+        })
+    }
+}
+
+/// The dots of a canvas and the bytes of its instance buffers, read back at the same time.
+#[derive(Debug, Clone)]
+pub struct InstanceDump {
+    pub dots: Vec<Dot>,
+    pub instance_bytes: Vec<u8>,
+}
+
+impl InstanceDump {
+    /// The indices of the dots whose instance differs from them, including ones the buffers are
+    /// missing or have in excess.
+    pub fn mismatches(&self) -> Vec<usize> {
+        let expected: &[u8] = bytemuck::cast_slice(&self.dots);
+        let dot_size = std::mem::size_of::<Dot>();
+        let count = (expected.len().max(self.instance_bytes.len()) + dot_size - 1) / dot_size;
+        (0..count)
+            .filter(|&index| {
+                let range = index * dot_size..(index + 1) * dot_size;
+                expected.get(range.clone()) != self.instance_bytes.get(range)
+            })
+            .collect()
+    }
+
+    /// Writes the dots to [`DOTS_FILE_NAME`] and the instance bytes to [`INSTANCES_FILE_NAME`] in
+    /// the working directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> Result<(), DumpError> {
+        let json = Document::new(self.dots.clone()).to_json().map_err(DumpError::Json)?;
+        std::fs::write(DOTS_FILE_NAME, json).map_err(DumpError::Io)?;
+        std::fs::write(INSTANCES_FILE_NAME, &self.instance_bytes).map_err(DumpError::Io)
+    }
+}
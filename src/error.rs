@@ -0,0 +1,61 @@
+use std::fmt;
+
+/// Why setting up the GPU, or a canvas on it, failed.
+#[derive(Debug)]
+pub enum Error {
+    CreateSurface(wgpu::CreateSurfaceError),
+    /// No adapter can present to the window, e.g. a browser without WebGPU or WebGL2.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The surface offers no formats on the adapter.
+    IncompatibleSurface,
+    /// Canvases need at least one pixel.
+    EmptyCanvas,
+    /// The device's textures can't be as large as the requested canvas.
+    CanvasTooLarge { size: u32, max: u32 },
+    /// The device has neither enough vertex attributes for the dot pipelines nor storage buffers
+    /// to pull dots from instead.
+    TooFewVertexAttributes { needed: u32, max: u32 },
+    /// Canvases can only be multisampled with 1, 2, 4 or 8 samples.
+    UnsupportedSampleCount(u32),
+    /// A texture handed to [`HpSurface::from_texture`](crate::surface::HpSurface::from_texture)
+    /// doesn't match the canvases, saying how.
+    IncompatibleTexture(String),
+    /// The GPU rejected drawing or computing something, explaining what was being done.
+    Gpu(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CreateSurface(err) => write!(f, "failed to create surface: {err}"),
+            Error::NoAdapter => write!(f, "no graphics adapter can draw to this window"),
+            Error::RequestDevice(err) => write!(f, "failed to create device: {err}"),
+            Error::IncompatibleSurface => write!(f, "the surface isn't compatible with the adapter"),
+            Error::EmptyCanvas => write!(f, "canvases can't be 0 pixels wide"),
+            Error::CanvasTooLarge { size, max } => {
+                write!(f, "canvases of {size} pixels are larger than this device's {max} pixel textures")
+            }
+            Error::TooFewVertexAttributes { needed, max } => {
+                write!(f, "dots need {needed} vertex attributes or storage buffers, this device has {max} and none")
+            }
+            Error::UnsupportedSampleCount(count) => write!(f, "canvases can't be multisampled {count} times"),
+            Error::IncompatibleTexture(reason) => write!(f, "the texture can't be a canvas: {reason}"),
+            Error::Gpu(explanation) => write!(f, "{explanation}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        Error::CreateSurface(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        Error::RequestDevice(err)
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use futures_channel::oneshot;
+
+use crate::color::{linear_to_srgb, srgb_to_linear, Color};
+use crate::error::Error;
+use crate::icc;
+use crate::surface::DisplayGamut;
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// The canvas couldn't be rendered to be exported.
+    Render(Error),
+    Map(wgpu::BufferAsyncError),
+    Encode(png::EncodingError),
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// A pixel was asked for outside of the texture.
+    OutOfBounds { x: u32, y: u32, width: u32, height: u32 },
+    #[cfg(not(target_arch = "wasm32"))]
+    Io(std::io::Error),
+    #[cfg(target_arch = "wasm32")]
+    Js(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Render(err) => write!(f, "failed to render the canvas: {err}"),
+            ExportError::Map(err) => write!(f, "failed to map readback buffer: {err}"),
+            ExportError::Encode(err) => write!(f, "failed to encode png: {err}"),
+            ExportError::UnsupportedFormat(format) => write!(f, "can't export textures of format {format:?}"),
+            ExportError::OutOfBounds { x, y, width, height } => {
+                write!(f, "pixel {x}, {y} is outside of the {width}x{height} texture")
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            ExportError::Io(err) => write!(f, "failed to write png: {err}"),
+            #[cfg(target_arch = "wasm32")]
+            ExportError::Js(err) => write!(f, "failed to download png: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// A pending copy of a texture into a mappable buffer.
+///
+/// The copy is submitted on creation, so the texture can keep being drawn to while the
+/// readback is awaited.
+pub struct TextureReadback {
+    device: Arc<wgpu::Device>,
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl TextureReadback {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let bytes_per_row = size.width * format.describe().block_size as u32;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (bytes_per_row + align - 1) / align * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.push_debug_group("readback");
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..size
+            },
+        );
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            device,
+            buffer,
+            width: size.width,
+            height: size.height,
+            padded_bytes_per_row,
+            format,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Waits for the copy and returns tightly packed RGBA8 rows, premultiplied like the canvas.
+    ///
+    /// Float textures hold linear color, which is encoded to sRGB here and quantized with
+    /// `dither`. 8 bit textures are returned as they are.
+    pub async fn into_rgba8(self, dither: Dither) -> Result<Vec<u8>, ExportError> {
+        let layout = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => PixelLayout::Rgba8,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => PixelLayout::Bgra8,
+            wgpu::TextureFormat::Rgba16Float => PixelLayout::Rgba16Float,
+            format => return Err(ExportError::UnsupportedFormat(format)),
+        };
+
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        // Native backends only resolve the mapping when polled, on the web this is a no-op
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .await
+            .expect("map_async callback dropped")
+            .map_err(ExportError::Map)?;
+
+        let row_bytes = (self.width * self.format.describe().block_size as u32) as usize;
+        let mut pixels = Vec::with_capacity(self.width as usize * 4 * self.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for (y, row) in data.chunks(self.padded_bytes_per_row as usize).enumerate() {
+                let row = &row[..row_bytes];
+                match layout {
+                    PixelLayout::Rgba8 => pixels.extend_from_slice(row),
+                    PixelLayout::Bgra8 => {
+                        for pixel in row.chunks_exact(4) {
+                            pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                        }
+                    }
+                    PixelLayout::Rgba16Float => {
+                        for (index, channel) in row.chunks_exact(2).enumerate() {
+                            let value = f16_to_f32(u16::from_le_bytes([channel[0], channel[1]])).clamp(0.0, 1.0);
+                            // Every fourth channel is alpha, which stays linear and undithered
+                            let value = if index % 4 == 3 {
+                                value
+                            } else {
+                                linear_to_srgb(value) + dither.offset(index / 4, y) / 255.0
+                            };
+                            pixels.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+                        }
+                    }
+                }
+            }
+        }
+        self.buffer.unmap();
+
+        Ok(pixels)
+    }
+
+    /// Waits for the copy and returns the color of the pixel `x`, `y` from the top left, what an
+    /// eyedropper picks there.
+    pub async fn into_color_at(self, x: u32, y: u32) -> Result<Color, ExportError> {
+        let (width, height, srgb) = (self.width, self.height, self.is_srgb());
+        if x >= width || y >= height {
+            return Err(ExportError::OutOfBounds { x, y, width, height });
+        }
+        let rgba = self.into_rgba8(Dither::None).await?;
+        let index = (y * width + x) as usize * 4;
+        let pixel = [rgba[index], rgba[index + 1], rgba[index + 2], rgba[index + 3]];
+        Ok(Color::from_canvas_pixel(pixel, srgb))
+    }
+
+    /// Whether the color from [`Self::into_rgba8`] is sRGB encoded.
+    pub(crate) fn is_srgb(&self) -> bool {
+        self.format.describe().srgb || self.format == wgpu::TextureFormat::Rgba16Float
+    }
+}
+
+/// How float canvases are quantized to 8 bits on export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Round to the nearest value. Smooth gradients of soft dots band.
+    #[default]
+    None,
+    /// Offset each pixel by an 8x8 Bayer pattern before rounding, trading the bands for a fine
+    /// regular texture.
+    Ordered,
+}
+
+impl Dither {
+    /// What to add to the pixel at `x`, `y` before rounding, in 8 bit steps (-0.5..0.5).
+    fn offset(self, x: usize, y: usize) -> f32 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => (BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5,
+        }
+    }
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// How exported images are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExportOptions {
+    /// The gamut the image is tagged with.
+    pub gamut: DisplayGamut,
+    pub dither: Dither,
+}
+
+/// How texels of the formats we can read back are laid out.
+enum PixelLayout {
+    Rgba8,
+    Bgra8,
+    Rgba16Float,
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Encodes sRGB encoded pixels, tagged with the ICC profile of the gamut their primaries are in.
+pub fn encode_png(width: u32, height: u32, rgba: &[u8], gamut: DisplayGamut) -> Result<Vec<u8>, ExportError> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Gamma and chromaticities for viewers without ICC support, the embedded profile wins
+        // everywhere else. PNG can only describe the sRGB curve as plain gamma, which is close.
+        let primaries = match gamut {
+            DisplayGamut::Srgb => [(0.640, 0.330), (0.300, 0.600), (0.150, 0.060)],
+            DisplayGamut::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+        };
+        encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
+        encoder.set_source_chromaticities(png::SourceChromaticities::new(
+            (0.3127, 0.3290),
+            primaries[0],
+            primaries[1],
+            primaries[2],
+        ));
+        let mut writer = encoder.write_header().map_err(ExportError::Encode)?;
+        writer
+            .write_chunk(png::chunk::iCCP, &iccp_chunk(gamut))
+            .map_err(ExportError::Encode)?;
+        writer.write_image_data(rgba).map_err(ExportError::Encode)?;
+    }
+    Ok(bytes)
+}
+
+/// Decodes a PNG of any color type and bit depth to its width, height and straight alpha sRGB
+/// encoded RGBA8 pixels, rows from the top.
+pub fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), png::DecodingError> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer)?;
+    let samples = &buffer[..frame.buffer_size()];
+    let rgba: Vec<u8> = match frame.color_type {
+        png::ColorType::Grayscale => samples.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => samples.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::Rgba => samples.to_vec(),
+        // EXPAND turns palettes into RGB
+        png::ColorType::Indexed => unreachable!("expanded"),
+    };
+    Ok((frame.width, frame.height, rgba))
+}
+
+/// The `iCCP` chunk embedding the profile of `gamut`: its name, the compression method and the
+/// zlib compressed profile.
+fn iccp_chunk(gamut: DisplayGamut) -> Vec<u8> {
+    let mut chunk = icc::profile_name(gamut).as_bytes().to_vec();
+    chunk.extend([0, 0]);
+    chunk.extend(miniz_oxide::deflate::compress_to_vec_zlib(&icc::profile(gamut), 9));
+    chunk
+}
+
+/// Converts premultiplied pixels, as the canvas stores them, to the straight alpha PNG expects.
+///
+/// sRGB textures hold the encoded premultiplied linear color, so for those the division happens
+/// in linear space.
+pub fn unpremultiply(rgba: &mut [u8], srgb: bool) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        if alpha == 0 || alpha == 255 {
+            continue;
+        }
+        let alpha = alpha as f32 / 255.0;
+        for channel in &mut pixel[..3] {
+            let value = *channel as f32 / 255.0;
+            let straight = if srgb {
+                linear_to_srgb(srgb_to_linear(value) / alpha)
+            } else {
+                value / alpha
+            };
+            *channel = (straight.min(1.0) * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Reads back the texture and encodes it as an in-memory PNG.
+pub async fn export_png(readback: TextureReadback, options: ExportOptions) -> Result<Vec<u8>, ExportError> {
+    let (width, height) = (readback.width(), readback.height());
+    let srgb = readback.is_srgb();
+    let mut rgba = readback.into_rgba8(options.dither).await?;
+    unpremultiply(&mut rgba, srgb);
+    encode_png(width, height, &rgba, options.gamut)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_png(path: impl AsRef<std::path::Path>, png: &[u8]) -> Result<(), ExportError> {
+    std::fs::write(path, png).map_err(ExportError::Io)
+}
+
+/// Offers the PNG as a file download by clicking a temporary anchor pointing at a Blob URL.
+#[cfg(target_arch = "wasm32")]
+pub fn download_png(png: &[u8], file_name: &str) -> Result<(), ExportError> {
+    use wasm_bindgen::JsCast;
+
+    let js_err = |err: wasm_bindgen::JsValue| ExportError::Js(format!("{err:?}"));
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(png));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_("image/png");
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(js_err)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_err)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| ExportError::Js("no document".to_owned()))?;
+    let anchor: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_err)?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(js_err)
+}
@@ -0,0 +1,183 @@
+//! A C API for embedding the dot renderer in applications not written in Rust, built with the
+//! `ffi` feature into the crate's cdylib.
+//!
+//! A context owns a headless device and one canvas, drawn on the CPU where there's no adapter:
+//!
+//! ```c
+//! HpContext *context = hp_context_create(1024);
+//! HpDot dot = { .position = { 0.0, 0.0 }, .radius = 0.1, .hardness = 1.0, .color = { 1, 0, 0, 1 } };
+//! hp_add_dots(context, &dot, 1);
+//! hp_render(context);
+//! uint8_t *pixels = malloc(1024 * 1024 * 4);
+//! hp_read_pixels(context, pixels, 1024 * 1024 * 4);
+//! hp_context_destroy(context);
+//! ```
+//!
+//! Functions that can fail return `NULL` or `false` and leave a message for [`hp_last_error`].
+//! Panics are caught the same way instead of unwinding into the caller.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::color::Color;
+use crate::raster::HeadlessCanvas;
+use crate::surface::Dot;
+
+/// A headless device with a canvas on it, see [`hp_context_create`].
+pub struct HpContext {
+    canvas: HeadlessCanvas,
+}
+
+/// A dot as C lays it out.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HpDot {
+    /// In canvas coordinates, -1..1 with y up.
+    pub position: [f32; 2],
+    pub radius: f32,
+    /// 0 for a soft falloff to 1 for a hard edge.
+    pub hardness: f32,
+    /// Straight alpha sRGB, 0..1.
+    pub color: [f32; 4],
+}
+
+impl From<HpDot> for Dot {
+    fn from(dot: HpDot) -> Self {
+        Dot::new(dot.position, dot.radius, dot.hardness, Color::from_srgb(dot.color))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Runs `f`, turning its error or panic into `failed` and a message for [`hp_last_error`].
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => message,
+        Err(panic) => match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => panic.downcast_ref::<&str>().map_or("panicked", |message| message).to_owned(),
+        },
+    };
+    tracing::error!("{message}");
+    LAST_ERROR.with(|error| *error.borrow_mut() = CString::new(message).ok());
+    failed
+}
+
+/// Creates a context with an empty square canvas of `canvas_size` pixels, or returns `NULL` if
+/// the device can't hold the canvas. Without an adapter the canvas is drawn on the CPU. Free it
+/// with [`hp_context_destroy`].
+#[no_mangle]
+pub extern "C" fn hp_context_create(canvas_size: u32) -> *mut HpContext {
+    guard(std::ptr::null_mut(), || {
+        let canvas = HeadlessCanvas::new(canvas_size).map_err(|err| err.to_string())?;
+        Ok(Box::into_raw(Box::new(HpContext { canvas })))
+    })
+}
+
+/// Frees `context` and its device. `NULL` is ignored.
+///
+/// # Safety
+///
+/// `context` must come from [`hp_context_create`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hp_context_destroy(context: *mut HpContext) {
+    if !context.is_null() {
+        drop(Box::from_raw(context));
+    }
+}
+
+/// Adds `count` dots from `dots` on top of the canvas, drawn by the next [`hp_render`]. Adds none
+/// if one of them can't be drawn, e.g. has a NaN position, leaving a message for [`hp_last_error`].
+///
+/// # Safety
+///
+/// `context` must be a live context and `dots` point to `count` dots.
+#[no_mangle]
+pub unsafe extern "C" fn hp_add_dots(context: *mut HpContext, dots: *const HpDot, count: usize) {
+    if count == 0 {
+        return;
+    }
+    let context = &mut *context;
+    let dots = std::slice::from_raw_parts(dots, count);
+    guard((), || {
+        let dots: Vec<Dot> = dots
+            .iter()
+            .map(|&dot| Dot::from(dot).validated())
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string())?;
+        context.canvas.add_dots(&dots);
+        Ok(())
+    })
+}
+
+/// Removes all dots.
+///
+/// # Safety
+///
+/// `context` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn hp_clear_dots(context: *mut HpContext) {
+    let context = &mut *context;
+    guard((), || {
+        context.canvas.set_dots(Vec::new());
+        Ok(())
+    })
+}
+
+/// Renders the dots into the canvas texture, returning whether it worked.
+///
+/// # Safety
+///
+/// `context` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn hp_render(context: *mut HpContext) -> bool {
+    let context = &mut *context;
+    guard(false, || {
+        context.canvas.render().map_err(|err| err.to_string())?;
+        Ok(true)
+    })
+}
+
+/// The width and height of the canvas in pixels.
+///
+/// # Safety
+///
+/// `context` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn hp_canvas_size(context: *const HpContext) -> u32 {
+    (*context).canvas.size()
+}
+
+/// Copies the canvas, as last rendered, into `pixels` as straight alpha sRGB RGBA8 rows, top
+/// first. Returns `false` if `pixels` is `NULL`, `len` isn't the 4 bytes per pixel of the
+/// canvas, or reading it back failed.
+///
+/// # Safety
+///
+/// `context` must be a live context and `pixels` `NULL` or point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hp_read_pixels(context: *const HpContext, pixels: *mut u8, len: usize) -> bool {
+    let context = &*context;
+    if pixels.is_null() {
+        return guard(false, || Err("pixels is NULL".to_owned()));
+    }
+    let pixels = std::slice::from_raw_parts_mut(pixels, len);
+    guard(false, || {
+        let rgba = context.canvas.straight_rgba8().map_err(|err| err.to_string())?;
+        if rgba.len() != len {
+            return Err(format!("the canvas has {} bytes of pixels, not {len}", rgba.len()));
+        }
+        pixels.copy_from_slice(&rgba);
+        Ok(true)
+    })
+}
+
+/// The message of the last failure on this thread, or `NULL`. Valid until the next failure.
+#[no_mangle]
+pub extern "C" fn hp_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
@@ -0,0 +1,751 @@
+//! Image filters over the canvas, like gaussian blur, convolutions with a [`Kernel`], color
+//! [`Adjustment`]s and gradient maps.
+//!
+//! The canvas is redrawn from its dots on every render, so filters aren't baked into it: a
+//! surface keeps a list of them and runs them in order right after the dots, see
+//! [`HpSurface::add_filter`](crate::surface::HpSurface::add_filter). They are compute shaders,
+//! which WebGL2 doesn't have, so there they are skipped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::gradient::Gradient;
+use crate::preprocessor::ShaderDefines;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::renderer;
+use crate::selection;
+use crate::shaders::{Shader, ShaderError};
+
+/// Called with the fraction (0..1) of the filter work the GPU has finished.
+pub type FilterProgress = Arc<dyn Fn(f32) + Send + Sync>;
+
+/// A rectangle of the canvas in pixels, from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The part of the region inside a canvas of `size`, `None` if nothing is.
+    fn clamp(self, size: wgpu::Extent3d) -> Option<Region> {
+        let right = (self.x.saturating_add(self.width)).min(size.width);
+        let bottom = (self.y.saturating_add(self.height)).min(size.height);
+        (self.x < right && self.y < bottom).then_some(Region {
+            x: self.x,
+            y: self.y,
+            width: right - self.x,
+            height: bottom - self.y,
+        })
+    }
+}
+
+/// What a filter does. Serialized with its name in `kind`, like
+/// `{ "kind": "gaussian_blur", "radius": 8 }`, `{ "kind": "convolution", "kernel": "sharpen" }` or
+/// `{ "kind": "adjustment", "contrast": 0.2, "saturation": -0.5 }`. Gradient maps take the stops
+/// of a [`Gradient`], like `{ "kind": "gradient_map", "stops": [...] }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FilterKind {
+    /// Blurs with a gaussian reaching `radius` pixels (three standard deviations), up to
+    /// [`MAX_BLUR_RADIUS`].
+    GaussianBlur { radius: f32 },
+    /// Convolves with the kernel registered under this name, see
+    /// [`GlobalSurface::register_kernel`](crate::surface::GlobalSurface::register_kernel).
+    Convolution { kernel: String },
+    Adjustment(Adjustment),
+    /// Replaces colors by the gradient's color at their luminance, see
+    /// [`map_position`](crate::gradient::map_position). Transparent stops let the original color
+    /// through.
+    GradientMap(Gradient),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Filter {
+    #[serde(flatten)]
+    pub kind: FilterKind,
+    /// The part of the canvas to filter, all of it if `None`.
+    #[serde(default)]
+    pub region: Option<Region>,
+}
+
+impl Filter {
+    /// A filter over the whole canvas.
+    pub fn new(kind: FilterKind) -> Self {
+        Self { kind, region: None }
+    }
+
+    /// Limits the filter to `region`, e.g. the selection.
+    pub fn in_region(self, region: Region) -> Self {
+        Self {
+            region: Some(region),
+            ..self
+        }
+    }
+}
+
+/// Changes to the colors of the canvas, all 0 leaving them as they are. Applied to sRGB encoded,
+/// unpremultiplied colors in the order of the fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Adjustment {
+    /// Added to every channel, -1 to 1.
+    pub brightness: f32,
+    /// -1 flattens the image to mid gray, 1 doubles the distance from it.
+    pub contrast: f32,
+    /// Degrees to rotate the hue by.
+    pub hue: f32,
+    /// -1 removes all color, 1 doubles it.
+    pub saturation: f32,
+}
+
+#[derive(Debug)]
+pub enum FilterError {
+    /// A convolution names a kernel nobody registered.
+    UnknownKernel(String),
+    /// Kernels are odd sized squares, so they have a center pixel.
+    EvenKernelSize(u32),
+    KernelTooLarge(u32),
+    WrongWeightCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::UnknownKernel(name) => write!(f, "there is no kernel named {name:?}"),
+            FilterError::EvenKernelSize(size) => write!(f, "kernels need an odd size, not {size}"),
+            FilterError::KernelTooLarge(size) => {
+                write!(f, "kernels can be at most {MAX_KERNEL_SIZE}x{MAX_KERNEL_SIZE}, not {size}x{size}")
+            }
+            FilterError::WrongWeightCount { expected, found } => {
+                write!(f, "expected {expected} kernel weights, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// The weights a convolution multiplies the pixels around each pixel with, row by row.
+///
+/// Weights aren't normalized: they should add up to 1 to keep the brightness, or to 0 for
+/// kernels finding edges, which `bias` then shifts into view. Color channels are convolved,
+/// alpha stays as it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Kernel {
+    size: u32,
+    weights: Vec<f32>,
+    #[serde(default)]
+    bias: f32,
+}
+
+impl Kernel {
+    /// A `size` by `size` kernel, `size` being odd and at most [`MAX_KERNEL_SIZE`].
+    pub fn new(size: u32, weights: Vec<f32>) -> Result<Self, FilterError> {
+        Self {
+            size,
+            weights,
+            bias: 0.0,
+        }
+        .validated()
+    }
+
+    /// Adds `bias` to every convolved channel.
+    pub fn with_bias(self, bias: f32) -> Self {
+        Self { bias, ..self }
+    }
+
+    /// Checks the size and weights, for kernels that didn't come from [`Self::new`], like
+    /// deserialized ones.
+    pub fn validated(self) -> Result<Self, FilterError> {
+        if self.size % 2 == 0 {
+            return Err(FilterError::EvenKernelSize(self.size));
+        }
+        if self.size > MAX_KERNEL_SIZE {
+            return Err(FilterError::KernelTooLarge(self.size));
+        }
+        let expected = (self.size * self.size) as usize;
+        if self.weights.len() != expected {
+            return Err(FilterError::WrongWeightCount {
+                expected,
+                found: self.weights.len(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    /// The kernels every surface starts with: `sharpen`, `emboss` and `edge_detect`.
+    pub fn builtins() -> HashMap<String, Kernel> {
+        let kernel = |weights: [f32; 9]| Kernel::new(3, weights.to_vec()).unwrap();
+        HashMap::from([
+            ("sharpen".to_owned(), kernel([0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0])),
+            ("emboss".to_owned(), kernel([-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0])),
+            (
+                "edge_detect".to_owned(),
+                kernel([-1.0, -1.0, -1.0, -1.0, 8.0, -1.0, -1.0, -1.0, -1.0]),
+            ),
+        ])
+    }
+}
+
+pub const MAX_BLUR_RADIUS: f32 = 64.0;
+
+/// The largest width and height of a [`Kernel`].
+pub const MAX_KERNEL_SIZE: u32 = 9;
+
+/// Filters run in bands of this many rows, each its own submission, so progress can be
+/// reported and a large canvas doesn't stall the GPU in one go.
+const BAND_ROWS: u32 = 256;
+
+/// `@workgroup_size` of the compute shaders, in both dimensions.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The format of [`FilterTargets`], which compute shaders can write.
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// `Blur` in blur.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BlurUniforms {
+    origin: [u32; 2],
+    size: [u32; 2],
+    direction: [i32; 2],
+    radius: i32,
+    sigma: f32,
+}
+
+/// `Convolution` in convolution.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ConvolutionUniforms {
+    origin: [u32; 2],
+    size: [u32; 2],
+    radius: i32,
+    bias: f32,
+    _padding: [f32; 2],
+    /// Packed four to an element, uniform arrays have a 16 byte stride.
+    weights: [[f32; 4]; KERNEL_VEC4S],
+}
+
+/// `Adjustment` in adjustment.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct AdjustmentUniforms {
+    origin: [u32; 2],
+    size: [u32; 2],
+    brightness: f32,
+    contrast: f32,
+    hue: f32,
+    saturation: f32,
+}
+
+/// `GradientMap` in gradient_map.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GradientMapUniforms {
+    origin: [u32; 2],
+    size: [u32; 2],
+    /// The gradient sampled evenly, linear with straight alpha.
+    ramp: [Color; GRADIENT_RAMP_SIZE],
+}
+
+const GRADIENT_RAMP_SIZE: usize = 256;
+
+const KERNEL_VEC4S: usize = ((MAX_KERNEL_SIZE * MAX_KERNEL_SIZE + 3) / 4) as usize;
+
+/// Whether `device` can run filters.
+pub(crate) fn is_supported(device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension > 0 && limits.max_storage_textures_per_shader_stage > 0
+}
+
+/// Canvas sized textures filters write their passes to.
+pub(crate) struct FilterTargets {
+    size: wgpu::Extent3d,
+    views: [wgpu::TextureView; 2],
+}
+
+impl FilterTargets {
+    pub fn new(device: &wgpu::Device, size: wgpu::Extent3d) -> Self {
+        let view = |_| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("filter_target"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: TARGET_FORMAT,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        Self {
+            size,
+            views: [0, 1].map(view),
+        }
+    }
+}
+
+fn input_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        },
+        count: None,
+    }
+}
+
+/// A compute shader reading one texture and writing another, with uniforms of type `U`.
+struct ComputeFilter<U> {
+    shader: Shader,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+    uniforms: wgpu::Buffer,
+    _uniforms: std::marker::PhantomData<U>,
+}
+
+impl<U: Pod> ComputeFilter<U> {
+    fn new(device: &wgpu::Device, shader: Shader) -> Self {
+        let uniforms_size = std::mem::size_of::<U>() as u64;
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(shader.file_name()),
+            entries: &[
+                input_entry(0, wgpu::ShaderStages::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: TARGET_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(uniforms_size),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(shader.file_name()),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_compute_pipeline(device, shader, shader.embedded_source(), &pipeline_layout)
+            .unwrap_or_else(|err| panic!("{err}"));
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(shader.file_name()),
+            size: uniforms_size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            shader,
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            pipeline,
+            uniforms,
+            _uniforms: std::marker::PhantomData,
+        }
+    }
+
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || {
+            create_compute_pipeline(device, self.shader, source, &self.pipeline_layout)
+        })?;
+        Ok(())
+    }
+
+    /// Runs the shader over `area` of `input` into the same area of `output`, band by band with
+    /// the uniforms `uniforms` returns for each band.
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        area: Region,
+        uniforms: impl Fn(Region) -> U,
+        band_submitted: &mut impl FnMut(),
+    ) {
+        let _span = tracing::info_span!("filter_pass", shader = self.shader.file_name()).entered();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(self.shader.file_name()),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(output),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+            ],
+        });
+        for band_top in (area.y..area.y + area.height).step_by(BAND_ROWS as usize) {
+            let band = Region {
+                y: band_top,
+                height: BAND_ROWS.min(area.y + area.height - band_top),
+                ..area
+            };
+            // Takes effect before the submission below, and after the previous one
+            queue.write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&uniforms(band)));
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(self.shader.file_name()),
+            });
+            encoder.push_debug_group(self.shader.file_name());
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some(self.shader.file_name()),
+                });
+                compute_pass.set_pipeline(&self.pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(
+                    (band.width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    (band.height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    1,
+                );
+            }
+            encoder.pop_debug_group();
+            queue.submit(Some(encoder.finish()));
+            band_submitted();
+        }
+    }
+}
+
+/// The pipelines of all filters, shared by the surfaces of a [`GlobalSurface`](crate::surface::GlobalSurface).
+pub(crate) struct FilterPipelines {
+    blur: ComputeFilter<BlurUniforms>,
+    convolution: ComputeFilter<ConvolutionUniforms>,
+    adjustment: ComputeFilter<AdjustmentUniforms>,
+    gradient_map: ComputeFilter<GradientMapUniforms>,
+    copy_bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    copy_pipeline_layout: wgpu::PipelineLayout,
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    format: wgpu::TextureFormat,
+    /// Writes a target back into the canvas.
+    copy: wgpu::RenderPipeline,
+    /// The mask to write back through without a selection.
+    full_mask: wgpu::TextureView,
+}
+
+impl FilterPipelines {
+    /// Pipelines for canvases of `format`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let copy_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("filter_copy_bind_group_layout"),
+            entries: &[
+                input_entry(0, wgpu::ShaderStages::FRAGMENT),
+                input_entry(1, wgpu::ShaderStages::FRAGMENT),
+            ],
+        });
+        let copy_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("filter_copy_pipeline_layout"),
+            bind_group_layouts: &[&copy_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let copy = create_copy_pipeline(device, Shader::Filter.embedded_source(), &copy_pipeline_layout, format)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        Self {
+            blur: ComputeFilter::new(device, Shader::Blur),
+            convolution: ComputeFilter::new(device, Shader::Convolution),
+            adjustment: ComputeFilter::new(device, Shader::Adjustment),
+            gradient_map: ComputeFilter::new(device, Shader::GradientMap),
+            copy_bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            copy_pipeline_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            format,
+            copy,
+            full_mask: selection::create_full_mask(device, queue),
+        }
+    }
+
+    /// Rebuilds the pipelines of `shader` from `source`, keeping the current ones if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), ShaderError> {
+        match shader {
+            Shader::Blur => self.blur.reload_shader(device, source),
+            Shader::Convolution => self.convolution.reload_shader(device, source),
+            Shader::Adjustment => self.adjustment.reload_shader(device, source),
+            Shader::GradientMap => self.gradient_map.reload_shader(device, source),
+            Shader::Filter => {
+                self.copy = catch_validation_error(device, || {
+                    create_copy_pipeline(device, source, &self.copy_pipeline_layout, self.format)
+                })?;
+                Ok(())
+            }
+            _ => unreachable!("{shader:?} isn't a filter shader"),
+        }
+    }
+
+    /// Runs `filters` over the canvas in order, reporting to `progress` as the GPU finishes them.
+    /// Convolutions look their kernel up in `kernels`, and are skipped if it isn't there. With a
+    /// `selection` mask, only selected pixels change.
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        canvas_view: &wgpu::TextureView,
+        targets: &FilterTargets,
+        filters: &[&Filter],
+        kernels: &HashMap<String, Kernel>,
+        selection: Option<&wgpu::TextureView>,
+        progress: Option<FilterProgress>,
+    ) {
+        let mask = selection.unwrap_or(&self.full_mask);
+        let size = targets.size;
+        let whole_canvas = Region {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        };
+        // Filters with something to do, and where
+        let work: Vec<(Work<'_>, Region)> = filters
+            .iter()
+            .filter_map(|filter| {
+                let work = match &filter.kind {
+                    FilterKind::GaussianBlur { radius } => match blur_radius(*radius) {
+                        0 => return None,
+                        radius => Work::Blur(radius),
+                    },
+                    FilterKind::Convolution { kernel } => Work::Convolution(kernels.get(kernel)?),
+                    FilterKind::Adjustment(adjustment) => Work::Adjustment(*adjustment),
+                    FilterKind::GradientMap(gradient) => Work::GradientMap(gradient),
+                };
+                Some((work, filter.region.unwrap_or(whole_canvas).clamp(size)?))
+            })
+            .collect();
+
+        let bands = |rows: u32| (rows + BAND_ROWS - 1) / BAND_ROWS;
+        let total_bands: u32 = work
+            .iter()
+            .map(|&(work, region)| match work {
+                Work::Blur(radius) => bands(blur_rows(region, radius, size).height) + bands(region.height),
+                Work::Convolution(_) | Work::Adjustment(_) | Work::GradientMap(_) => bands(region.height),
+            })
+            .sum();
+        let mut finished_bands = 0;
+        let mut band_submitted = || {
+            finished_bands += 1;
+            if let Some(progress) = &progress {
+                let progress = progress.clone();
+                let fraction = finished_bands as f32 / total_bands as f32;
+                queue.on_submitted_work_done(move || progress(fraction));
+            }
+        };
+
+        let [first, second] = &targets.views;
+        for (work, region) in work {
+            match work {
+                Work::Blur(radius) => {
+                    let uniforms = |direction| {
+                        move |band: Region| BlurUniforms {
+                            origin: [band.x, band.y],
+                            size: [band.width, band.height],
+                            direction,
+                            radius: radius as i32,
+                            sigma: radius as f32 / 3.0,
+                        }
+                    };
+                    let horizontal = blur_rows(region, radius, size);
+                    self.blur.run(device, queue, canvas_view, first, horizontal, uniforms([1, 0]), &mut band_submitted);
+                    self.blur.run(device, queue, first, second, region, uniforms([0, 1]), &mut band_submitted);
+                    self.copy(device, queue, second, mask, canvas_view, region);
+                }
+                Work::Convolution(kernel) => {
+                    let mut weights = [[0.0; 4]; KERNEL_VEC4S];
+                    for (index, &weight) in kernel.weights.iter().enumerate() {
+                        weights[index / 4][index % 4] = weight;
+                    }
+                    let uniforms = |band: Region| ConvolutionUniforms {
+                        origin: [band.x, band.y],
+                        size: [band.width, band.height],
+                        radius: (kernel.size / 2) as i32,
+                        bias: kernel.bias,
+                        _padding: [0.0; 2],
+                        weights,
+                    };
+                    self.convolution.run(device, queue, canvas_view, first, region, uniforms, &mut band_submitted);
+                    self.copy(device, queue, first, mask, canvas_view, region);
+                }
+                Work::Adjustment(adjustment) => {
+                    let uniforms = |band: Region| AdjustmentUniforms {
+                        origin: [band.x, band.y],
+                        size: [band.width, band.height],
+                        brightness: adjustment.brightness,
+                        contrast: adjustment.contrast,
+                        hue: adjustment.hue.to_radians(),
+                        saturation: adjustment.saturation,
+                    };
+                    self.adjustment.run(device, queue, canvas_view, first, region, uniforms, &mut band_submitted);
+                    self.copy(device, queue, first, mask, canvas_view, region);
+                }
+                Work::GradientMap(gradient) => {
+                    let ramp: [Color; GRADIENT_RAMP_SIZE] = gradient.ramp(GRADIENT_RAMP_SIZE).try_into().unwrap();
+                    let uniforms = |band: Region| GradientMapUniforms {
+                        origin: [band.x, band.y],
+                        size: [band.width, band.height],
+                        ramp,
+                    };
+                    self.gradient_map.run(device, queue, canvas_view, first, region, uniforms, &mut band_submitted);
+                    self.copy(device, queue, first, mask, canvas_view, region);
+                }
+            }
+        }
+    }
+
+    /// Replaces `region` of the canvas with the same region of `result`.
+    fn copy(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        result: &wgpu::TextureView,
+        mask: &wgpu::TextureView,
+        canvas_view: &wgpu::TextureView,
+        region: Region,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("filter_copy_bind_group"),
+            layout: &self.copy_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(result),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(mask),
+                },
+            ],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("filter_copy"),
+        });
+        encoder.push_debug_group("filter copy");
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("filter_copy"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: canvas_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.copy);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_scissor_rect(region.x, region.y, region.width, region.height);
+            render_pass.draw(0..3, 0..1);
+        }
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// The radius in whole pixels the shader loops over.
+fn blur_radius(radius: f32) -> u32 {
+    radius.clamp(0.0, MAX_BLUR_RADIUS).round() as u32
+}
+
+/// What the horizontal blur pass covers for `region`: the vertical pass reads it up to `radius`
+/// rows beyond the region.
+fn blur_rows(region: Region, radius: u32, size: wgpu::Extent3d) -> Region {
+    let top = region.y.saturating_sub(radius);
+    let bottom = (region.y + region.height + radius).min(size.height);
+    Region {
+        y: top,
+        height: bottom - top,
+        ..region
+    }
+}
+
+/// A filter resolved for running.
+#[derive(Clone, Copy)]
+enum Work<'a> {
+    /// With the radius in whole pixels.
+    Blur(u32),
+    Convolution(&'a Kernel),
+    Adjustment(Adjustment),
+    GradientMap(&'a Gradient),
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    shader: Shader,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, ShaderError> {
+    let label = shader.file_name();
+    let shader = shader.create_module(device, source, &ShaderDefines::new())?;
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: "cs_main",
+    }))
+}
+
+fn create_copy_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let shader = Shader::Filter.create_module(device, source, &ShaderDefines::new())?;
+    Ok(renderer::vertexless_pipeline(device, "filter_copy", layout, &shader, "fs_main", format, None))
+}
@@ -0,0 +1,25 @@
+// Copies a filter's result back into the canvas. The scissor rect limits it to the filter's region,
+// and the selection mask to the selected pixels.
+
+@group(0) @binding(0)
+var result: texture_2d<f32>;
+// 1 where selected, a single texel covering everything without a selection
+@group(0) @binding(1)
+var selection: texture_2d<f32>;
+
+// A triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let mask_size = vec2<i32>(textureDimensions(selection));
+    if textureLoad(selection, min(pixel, mask_size - 1), 0).r < 0.5 {
+        discard;
+    }
+    return textureLoad(result, pixel, 0);
+}
@@ -0,0 +1,473 @@
+//! Framing exported images: [`Framing`] crops the canvas, e.g. to the part the window shows, puts
+//! a border around it and signs it with a [`Watermark`] in one of its corners.
+//!
+//! It's a last pass on the GPU, drawing the framed image into a texture of its own that is read
+//! back instead of the canvas, see [`HpSurface::framed_readback`](crate::surface::HpSurface::framed_readback).
+//! The canvas itself is left as it is.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::color::Color;
+use crate::export;
+use crate::filter::Region;
+use crate::preprocessor::ShaderDefines;
+use crate::renderer;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::shaders::{Shader, ShaderError};
+use crate::spatial::CanvasRect;
+
+#[derive(Debug)]
+pub enum WatermarkError {
+    Decode(png::DecodingError),
+    /// The pixels don't fill the size given.
+    Size { width: u32, height: u32, bytes: usize },
+}
+
+impl fmt::Display for WatermarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatermarkError::Decode(err) => write!(f, "failed to decode the watermark: {err}"),
+            WatermarkError::Size { width, height, bytes } => {
+                write!(f, "{bytes} bytes aren't {width}x{height} RGBA pixels")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WatermarkError {}
+
+/// A corner of the exported image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Corner; 4] = [Corner::TopLeft, Corner::TopRight, Corner::BottomLeft, Corner::BottomRight];
+
+    /// The corner called `name` in the configuration: `top-left`, `top-right`, `bottom-left` or
+    /// `bottom-right`.
+    pub fn from_name(name: &str) -> Option<Corner> {
+        Self::ALL.into_iter().find(|corner| corner.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        }
+    }
+}
+
+/// An image, like a signature or a logo, composited over a corner of the painting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    width: u32,
+    height: u32,
+    /// sRGB encoded with straight alpha, rows from the top.
+    rgba: Arc<[u8]>,
+    pub corner: Corner,
+    /// How opaque the image is drawn, 0 to 1, on top of its own alpha.
+    pub opacity: f32,
+    /// Exported pixels per pixel of the image.
+    pub scale: f32,
+    /// Distance from the edges of the painting, in exported pixels.
+    pub margin: u32,
+}
+
+impl Watermark {
+    /// From sRGB encoded RGBA pixels with straight alpha, rows from the top, drawn as they are
+    /// in the bottom right corner.
+    pub fn from_rgba8(width: u32, height: u32, rgba: &[u8]) -> Result<Self, WatermarkError> {
+        if width == 0 || height == 0 || rgba.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+            return Err(WatermarkError::Size {
+                width,
+                height,
+                bytes: rgba.len(),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            rgba: rgba.into(),
+            corner: Corner::default(),
+            opacity: 1.0,
+            scale: 1.0,
+            margin: 16,
+        })
+    }
+
+    /// Decodes a PNG of any color type and bit depth.
+    pub fn from_png(bytes: &[u8]) -> Result<Self, WatermarkError> {
+        let (width, height, rgba) = export::decode_png(bytes).map_err(WatermarkError::Decode)?;
+        Self::from_rgba8(width, height, &rgba)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The width, height and pixels to upload on a device whose textures are at most `max_side`
+    /// pixels wide and high: the image as it is if it fits, or scaled down by a whole factor until
+    /// it does, averaging the pixels by their alpha. It's drawn as large either way.
+    fn texels(&self, max_side: u32) -> ([u32; 2], Cow<'_, [u8]>) {
+        let step = self.width.max(self.height).div_ceil(max_side.max(1));
+        if step <= 1 {
+            return ([self.width, self.height], Cow::Borrowed(&self.rgba));
+        }
+        let size = [self.width.div_ceil(step), self.height.div_ceil(step)];
+        let mut texels = Vec::with_capacity(size[0] as usize * size[1] as usize * 4);
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                let mut sum = [0u64; 4];
+                for source_y in y * step..((y + 1) * step).min(self.height) {
+                    for source_x in x * step..((x + 1) * step).min(self.width) {
+                        let index = (source_y as usize * self.width as usize + source_x as usize) * 4;
+                        let pixel = &self.rgba[index..index + 4];
+                        let alpha = u64::from(pixel[3]);
+                        for channel in 0..3 {
+                            sum[channel] += u64::from(pixel[channel]) * alpha;
+                        }
+                        sum[3] += alpha;
+                    }
+                }
+                let count = u64::from(((y + 1) * step).min(self.height) - y * step)
+                    * u64::from(((x + 1) * step).min(self.width) - x * step);
+                let rgb = sum[..3].iter().map(|&channel| channel.checked_div(sum[3]).unwrap_or(0) as u8);
+                texels.extend(rgb.chain([(sum[3] / count) as u8]));
+            }
+        }
+        (size, Cow::Owned(texels))
+    }
+}
+
+/// How exported images are framed. The default leaves them as the canvas is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framing {
+    /// The part of the canvas exported, all of it if `None` or if the rectangle misses the canvas.
+    pub crop: Option<CanvasRect>,
+    /// Width of the border around the painting, in pixels.
+    pub border: u32,
+    pub border_color: Color,
+    pub watermark: Option<Watermark>,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self {
+            crop: None,
+            border: 0,
+            border_color: Color::WHITE,
+            watermark: None,
+        }
+    }
+}
+
+impl Framing {
+    /// Whether exports are the canvas as it is, without a framing pass.
+    pub fn is_none(&self) -> bool {
+        self.crop.is_none() && self.border == 0 && self.watermark.is_none()
+    }
+
+    /// Where everything goes in the export of a canvas of `canvas_size` pixels, exports being at
+    /// most `max_size` pixels wide and high. Borders too wide for that are narrowed.
+    pub(crate) fn layout(&self, canvas_size: u32, max_size: u32) -> FramingLayout {
+        let crop = self
+            .crop
+            .and_then(|crop| crop.to_region(canvas_size))
+            .unwrap_or(Region {
+                x: 0,
+                y: 0,
+                width: canvas_size,
+                height: canvas_size,
+            });
+        let border = self
+            .border
+            .min(max_size.saturating_sub(crop.width.max(crop.height)) / 2);
+        let size = [crop.width + 2 * border, crop.height + 2 * border];
+        let watermark = self.watermark.as_ref().map(|watermark| {
+            let mark_size = [watermark.width, watermark.height].map(|length| length as f32 * watermark.scale);
+            let inset = (border + watermark.margin) as f32;
+            let left = match watermark.corner {
+                Corner::TopLeft | Corner::BottomLeft => inset,
+                Corner::TopRight | Corner::BottomRight => size[0] as f32 - inset - mark_size[0],
+            };
+            let top = match watermark.corner {
+                Corner::TopLeft | Corner::TopRight => inset,
+                Corner::BottomLeft | Corner::BottomRight => size[1] as f32 - inset - mark_size[1],
+            };
+            [left, top, mark_size[0], mark_size[1]]
+        });
+        FramingLayout {
+            crop,
+            border,
+            size,
+            watermark,
+        }
+    }
+}
+
+/// [`Framing`] worked out for a canvas, in exported pixels from the top left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FramingLayout {
+    /// Of the canvas.
+    pub crop: Region,
+    pub border: u32,
+    /// Width and height of the export.
+    pub size: [u32; 2],
+    /// Left, top, width and height.
+    pub watermark: Option<[f32; 4]>,
+}
+
+/// `Framing` in framing.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct FramingUniforms {
+    crop_origin: [u32; 2],
+    crop_size: [u32; 2],
+    /// Premultiplied, like canvas pixels.
+    border_color: [f32; 4],
+    watermark_min: [f32; 2],
+    watermark_size: [f32; 2],
+    border: u32,
+    /// 0 without a watermark.
+    opacity: f32,
+    _padding: [u32; 2],
+}
+
+pub(crate) struct FramingPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    /// The format of the canvas, which exports keep.
+    format: wgpu::TextureFormat,
+    sampler: wgpu::Sampler,
+}
+
+impl FramingPass {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let texture_entry = |binding, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("framing_bind_group_layout"),
+            entries: &[
+                texture_entry(0, false),
+                texture_entry(1, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<FramingUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("framing_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, Shader::Framing.embedded_source(), format)
+            .unwrap_or_else(|err| panic!("{err}"));
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("framing_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            pipeline,
+            format,
+            sampler,
+        }
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || {
+            create_pipeline(device, &self.pipeline_layout, source, self.format)
+        })?;
+        Ok(())
+    }
+
+    /// Draws `canvas_view` framed as `framing` lays it out into a new texture, ready to be read
+    /// back.
+    pub fn frame(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        canvas_view: &wgpu::TextureView,
+        framing: &Framing,
+        layout: &FramingLayout,
+    ) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: layout.size[0],
+            height: layout.size[1],
+            depth_or_array_layers: 1,
+        };
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("framed_export"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        // Without a watermark a transparent texel stands in for it
+        let (mark_size, mark_texels) = match &framing.watermark {
+            Some(watermark) => watermark.texels(device.limits().max_texture_dimension_2d),
+            None => ([1, 1], Cow::Borrowed(&[0; 4][..])),
+        };
+        let mark_extent = wgpu::Extent3d {
+            width: mark_size[0],
+            height: mark_size[1],
+            depth_or_array_layers: 1,
+        };
+        let mark = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("watermark"),
+            size: mark_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Sampled as linear straight color, premultiplied in the shader
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            mark.as_image_copy(),
+            &mark_texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * mark_size[0]),
+                rows_per_image: std::num::NonZeroU32::new(mark_size[1]),
+            },
+            mark_extent,
+        );
+
+        let Color { r, g, b, a } = framing.border_color;
+        let [left, top, width, height] = layout.watermark.unwrap_or([0.0, 0.0, 1.0, 1.0]);
+        let opacity = match &framing.watermark {
+            Some(watermark) => watermark.opacity.clamp(0.0, 1.0),
+            None => 0.0,
+        };
+        let uniforms = FramingUniforms {
+            crop_origin: [layout.crop.x, layout.crop.y],
+            crop_size: [layout.crop.width, layout.crop.height],
+            border_color: [r * a, g * a, b * a, a],
+            watermark_min: [left, top],
+            watermark_size: [width.max(f32::MIN_POSITIVE), height.max(f32::MIN_POSITIVE)],
+            border: layout.border,
+            opacity,
+            _padding: [0; 2],
+        };
+        let uniform_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("framing_uniforms"),
+                contents: bytemuck::bytes_of(&uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+        let mark_view = mark.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("framing_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(canvas_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mark_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("framing_encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("framing"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+        output
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    source: &str,
+    format: wgpu::TextureFormat,
+) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let shader = Shader::Framing.create_module(device, source, &ShaderDefines::new())?;
+    Ok(renderer::vertexless_pipeline(device, "framing", pipeline_layout, &shader, "fs_main", format, None))
+}
@@ -0,0 +1,48 @@
+// Frames exported images: the cropped canvas inside a border, with a watermark over a corner.
+//
+// Everything is premultiplied linear color, like the canvas. The watermark texture is sRGB, so
+// sampling it decodes to linear straight color, which is premultiplied here.
+
+#include "post_process_common.wgsl"
+
+// `FramingUniforms` in framing.rs
+struct Framing {
+    crop_origin: vec2<u32>,
+    crop_size: vec2<u32>,
+    border_color: vec4<f32>,
+    watermark_min: vec2<f32>,
+    watermark_size: vec2<f32>,
+    border: u32,
+    opacity: f32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0)
+var canvas: texture_2d<f32>;
+@group(0) @binding(1)
+var watermark: texture_2d<f32>;
+@group(0) @binding(2)
+var watermark_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> framing: Framing;
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    // The exported pixel, from the top left
+    let pixel = vec2<u32>(in.position.xy);
+    let border = vec2<u32>(framing.border);
+
+    var color = framing.border_color;
+    if all(pixel >= border) && all(pixel - border < framing.crop_size) {
+        color = textureLoad(canvas, vec2<i32>(framing.crop_origin + pixel - border), 0);
+    }
+
+    let uv = (in.position.xy - framing.watermark_min) / framing.watermark_size;
+    let mark = textureSampleLevel(watermark, watermark_sampler, uv, 0.0);
+    if framing.opacity > 0.0 && all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0)) {
+        let alpha = mark.a * framing.opacity;
+        color = vec4<f32>(mark.rgb * alpha, alpha) + color * (1.0 - alpha);
+    }
+    return color;
+}
@@ -0,0 +1,361 @@
+//! Dots laid out procedurally: every [`Generator`] places points on the canvas, which
+//! [`Generator::dots`] turns into dots painted with a [`Brush`]. Positions are in canvas
+//! coordinates, -1..1 with y up. All of them are deterministic, seeded ones included.
+
+use std::f32::consts::PI;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+/// The most points a layout is asked for, like the Poisson disk's minimum distance allows.
+pub const MAX_POINTS: usize = 100_000;
+
+/// Points no closer to each other than `min_distance`, filling the canvas evenly without looking
+/// regular, by Bridson's algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoissonDisk {
+    /// In canvas units, at least [`PoissonDisk::MIN_DISTANCE`]. Distances beyond the canvas,
+    /// infinite ones included, leave a single point.
+    pub min_distance: f32,
+    pub seed: u64,
+}
+
+impl PoissonDisk {
+    pub const DEFAULT: Self = Self {
+        min_distance: 0.05,
+        seed: 0,
+    };
+    /// Keeps the number of points to about a hundred thousand.
+    pub const MIN_DISTANCE: f32 = 0.005;
+    /// Tries around a point before it's done.
+    const ATTEMPTS: usize = 30;
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        // NaN becomes the minimum too
+        let min_distance = self.min_distance.max(Self::MIN_DISTANCE).min(2.0);
+        self.positions_spaced([1.0, 1.0], |_| min_distance)
+    }
+
+    /// Points within `half_extent` of the canvas center, each at least `spacing` of itself away
+    /// from the others, e.g. to pack them tighter in some parts. Spacings are taken as at least
+    /// [`Self::min_distance`] and at most the canvas's diagonal.
+    pub fn positions_spaced(&self, half_extent: [f32; 2], spacing: impl Fn([f32; 2]) -> f32) -> Vec<[f32; 2]> {
+        let min_distance = self.min_distance.max(Self::MIN_DISTANCE).min(2.0);
+        let spacing = |point| spacing(point).max(min_distance).min(2.0 * 2f32.sqrt());
+        let [half_width, half_height] = half_extent;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let mut scatter = Scatter::new(half_extent, min_distance);
+        scatter.add([rng.gen_range(-half_width..half_width), rng.gen_range(-half_height..half_height)]);
+        while !scatter.active.is_empty() {
+            let slot = rng.gen_range(0..scatter.active.len());
+            let [x, y] = scatter.points[scatter.active[slot]];
+            let distance = spacing([x, y]);
+            let candidate = (0..Self::ATTEMPTS).find_map(|_| {
+                let angle = rng.gen_range(0.0..2.0 * PI);
+                let offset = rng.gen_range(distance..2.0 * distance);
+                let point = [x + angle.cos() * offset, y + angle.sin() * offset];
+                let inside = (-half_width..half_width).contains(&point[0]) && (-half_height..half_height).contains(&point[1]);
+                (inside && scatter.is_free(point, spacing(point))).then_some(point)
+            });
+            match candidate {
+                Some(point) => scatter.add(point),
+                None => {
+                    scatter.active.swap_remove(slot);
+                }
+            }
+        }
+        scatter.points
+    }
+}
+
+/// Points of a [`PoissonDisk`] in a grid of cells, for finding the ones close by.
+struct Scatter {
+    half_extent: [f32; 2],
+    cell: f32,
+    columns: usize,
+    rows: usize,
+    /// The points in each cell, by index.
+    grid: Vec<Vec<usize>>,
+    points: Vec<[f32; 2]>,
+    /// Of the points that may still have room around them.
+    active: Vec<usize>,
+}
+
+impl Scatter {
+    fn new(half_extent: [f32; 2], min_distance: f32) -> Self {
+        // Cells small enough to hold one point at most
+        let cell = min_distance / 2f32.sqrt();
+        let [columns, rows] = half_extent.map(|half| ((2.0 * half / cell).ceil() as usize).max(1));
+        Self {
+            half_extent,
+            cell,
+            columns,
+            rows,
+            grid: vec![Vec::new(); columns * rows],
+            points: Vec::new(),
+            active: Vec::new(),
+        }
+    }
+
+    fn cell_of(&self, [x, y]: [f32; 2]) -> (usize, usize) {
+        let [half_width, half_height] = self.half_extent;
+        let column = (((x + half_width) / self.cell) as usize).min(self.columns - 1);
+        let row = (((y + half_height) / self.cell) as usize).min(self.rows - 1);
+        (column, row)
+    }
+
+    fn add(&mut self, point: [f32; 2]) {
+        let (column, row) = self.cell_of(point);
+        self.grid[row * self.columns + column].push(self.points.len());
+        self.active.push(self.points.len());
+        self.points.push(point);
+    }
+
+    /// No point is closer to `point` than `distance`.
+    fn is_free(&self, point: [f32; 2], distance: f32) -> bool {
+        let (column, row) = self.cell_of(point);
+        let reach = (distance / self.cell).ceil() as usize;
+        let columns = column.saturating_sub(reach)..(column + reach + 1).min(self.columns);
+        (row.saturating_sub(reach)..(row + reach + 1).min(self.rows))
+            .flat_map(|row| columns.clone().map(move |column| row * self.columns + column))
+            .flat_map(|cell| &self.grid[cell])
+            .all(|&index| {
+                let [x, y] = self.points[index];
+                (point[0] - x).powi(2) + (point[1] - y).powi(2) >= distance * distance
+            })
+    }
+}
+
+impl Default for PoissonDisk {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Points spiralling out from `center` like the seeds of a sunflower, each turned by the golden
+/// angle from the one before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spiral {
+    /// At most [`MAX_POINTS`].
+    pub count: usize,
+    pub center: [f32; 2],
+    /// Distance of the second point from the center, the nth is `sqrt(n)` times as far.
+    pub spread: f32,
+}
+
+impl Spiral {
+    pub const DEFAULT: Self = Self {
+        count: 500,
+        center: [0.0, 0.0],
+        spread: 0.04,
+    };
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        let [x, y] = self.center;
+        (0..self.count.min(MAX_POINTS))
+            .map(|n| {
+                let angle = n as f32 * golden_angle;
+                let distance = self.spread * (n as f32).sqrt();
+                [x + angle.cos() * distance, y + angle.sin() * distance]
+            })
+            .collect()
+    }
+}
+
+impl Default for Spiral {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Points in the centers of `columns` by `rows` cells, row by row from the top left. Rows beyond
+/// [`MAX_POINTS`] points are left out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub columns: usize,
+    pub rows: usize,
+    /// Left free around the grid, in canvas units.
+    pub margin: f32,
+}
+
+impl Grid {
+    pub const DEFAULT: Self = Self {
+        columns: 16,
+        rows: 16,
+        margin: 0.1,
+    };
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        let margin = self.margin.clamp(0.0, 1.0);
+        let size = 2.0 - 2.0 * margin;
+        let [width, height] = [size / self.columns.max(1) as f32, size / self.rows.max(1) as f32];
+        let columns = self.columns.min(MAX_POINTS);
+        let rows = self.rows.min(MAX_POINTS / columns.max(1));
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    let x = -1.0 + margin + (column as f32 + 0.5) * width;
+                    let y = 1.0 - margin - (row as f32 + 0.5) * height;
+                    [x, y]
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Points of the Halton sequence in bases 2 and 3, spread more evenly than random ones while
+/// every prefix of it is spread evenly too.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Halton {
+    /// At most [`MAX_POINTS`].
+    pub count: usize,
+    /// Points of the sequence left out before the first, for a different set.
+    pub skip: usize,
+}
+
+impl Halton {
+    pub const DEFAULT: Self = Self { count: 500, skip: 0 };
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        // The first point is the corner at 0. Huge skips leave fewer points rather than overflow
+        let end = self.skip.saturating_add(self.count.min(MAX_POINTS));
+        (self.skip..end)
+            .map(|index| [radical_inverse(index + 1, 2) * 2.0 - 1.0, radical_inverse(index + 1, 3) * 2.0 - 1.0])
+            .collect()
+    }
+}
+
+impl Default for Halton {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// `index` written in `base`, mirrored around the point, e.g. 6 = 110 in base 2 is 0.011.
+fn radical_inverse(mut index: usize, base: usize) -> f32 {
+    let mut inverse = 0.0;
+    let mut digit_value = 1.0 / base as f32;
+    while index > 0 {
+        inverse += (index % base) as f32 * digit_value;
+        index /= base;
+        digit_value /= base as f32;
+    }
+    inverse
+}
+
+/// One of the layouts, with its parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+    PoissonDisk(PoissonDisk),
+    Spiral(Spiral),
+    Grid(Grid),
+    Halton(Halton),
+}
+
+impl Generator {
+    /// Every layout with its defaults.
+    pub const ALL: [Generator; 4] = [
+        Generator::PoissonDisk(PoissonDisk::DEFAULT),
+        Generator::Spiral(Spiral::DEFAULT),
+        Generator::Grid(Grid::DEFAULT),
+        Generator::Halton(Halton::DEFAULT),
+    ];
+
+    /// The generator called `name` in the configuration, with its defaults.
+    pub fn from_name(name: &str) -> Option<Generator> {
+        match name {
+            "poisson-disk" => Some(Generator::PoissonDisk(PoissonDisk::DEFAULT)),
+            "spiral" => Some(Generator::Spiral(Spiral::DEFAULT)),
+            "grid" => Some(Generator::Grid(Grid::DEFAULT)),
+            "halton" => Some(Generator::Halton(Halton::DEFAULT)),
+            _ => None,
+        }
+    }
+
+    /// Lays out other points for every `seed`: seeds the Poisson disk and skips as many points of
+    /// the Halton sequence, wrapping around after 2^24 points, where `f32` positions stop telling
+    /// them apart. Spirals and grids are the same for all seeds.
+    pub fn with_seed(self, seed: u64) -> Self {
+        match self {
+            Generator::PoissonDisk(generator) => Generator::PoissonDisk(PoissonDisk { seed, ..generator }),
+            Generator::Halton(generator) => Generator::Halton(Halton {
+                skip: (seed % (1 << 24)) as usize,
+                ..generator
+            }),
+            Generator::Spiral(_) | Generator::Grid(_) => self,
+        }
+    }
+
+    /// With the parameter `name` set to `value`, `None` if it doesn't have one by that name. The
+    /// names are the fields of its parameters, counts are rounded and at most [`MAX_POINTS`].
+    pub fn with_param(self, name: &str, value: f64) -> Option<Self> {
+        let count = value.round().clamp(0.0, MAX_POINTS as f64) as usize;
+        let value = value as f32;
+        Some(match (self, name) {
+            (Generator::PoissonDisk(generator), "min_distance") => Generator::PoissonDisk(PoissonDisk {
+                min_distance: value,
+                ..generator
+            }),
+            (Generator::Spiral(generator), "count") => Generator::Spiral(Spiral { count, ..generator }),
+            (Generator::Spiral(generator), "spread") => Generator::Spiral(Spiral {
+                spread: value,
+                ..generator
+            }),
+            (Generator::Grid(generator), "columns") => Generator::Grid(Grid {
+                columns: count,
+                ..generator
+            }),
+            (Generator::Grid(generator), "rows") => Generator::Grid(Grid { rows: count, ..generator }),
+            (Generator::Grid(generator), "margin") => Generator::Grid(Grid {
+                margin: value,
+                ..generator
+            }),
+            (Generator::Halton(generator), "count") => Generator::Halton(Halton { count, ..generator }),
+            (Generator::Halton(generator), "skip") => Generator::Halton(Halton { skip: count, ..generator }),
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Generator::PoissonDisk(_) => "Poisson disk",
+            Generator::Spiral(_) => "spiral",
+            Generator::Grid(_) => "grid",
+            Generator::Halton(_) => "Halton",
+        }
+    }
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        match self {
+            Generator::PoissonDisk(generator) => generator.positions(),
+            Generator::Spiral(generator) => generator.positions(),
+            Generator::Grid(generator) => generator.positions(),
+            Generator::Halton(generator) => generator.positions(),
+        }
+    }
+
+    /// Dots at the positions, with `brush`'s radius, hardness, color and noise.
+    pub fn dots(&self, brush: &Brush) -> Vec<Dot> {
+        dots_at(&self.positions(), brush)
+    }
+}
+
+/// Dots at `positions` with `brush`'s radius, hardness, color and noise.
+pub fn dots_at(positions: &[[f32; 2]], brush: &Brush) -> Vec<Dot> {
+    positions
+        .iter()
+        .map(|&position| Dot::new(position, brush.radius, brush.hardness, brush.color).with_noise(brush.noise))
+        .collect()
+}
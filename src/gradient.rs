@@ -0,0 +1,74 @@
+//! Multi-stop color gradients, as used by gradient maps.
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::{linear_to_srgb, Color};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    /// Where along the gradient the stop sits, 0..1.
+    pub position: f32,
+    pub color: Color,
+}
+
+/// Colors blending into each other between stops, in sRGB encoded space like in other editors.
+/// Serialized as `{ "stops": [{ "position": 0, "color": [0, 0, 0, 1] }, ...] }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    /// In any order, sorted when sampling.
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    pub fn new(stops: Vec<GradientStop>) -> Self {
+        Self { stops }
+    }
+
+    /// From black at 0 to white at 1.
+    pub fn black_to_white() -> Self {
+        Self::new(vec![
+            GradientStop {
+                position: 0.0,
+                color: Color::BLACK,
+            },
+            GradientStop {
+                position: 1.0,
+                color: Color::WHITE,
+            },
+        ])
+    }
+
+    /// The color at `position`, that of the first or last stop outside of them. Transparent
+    /// without stops.
+    pub fn sample(&self, position: f32) -> Color {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+            return Color::new(0.0, 0.0, 0.0, 0.0);
+        };
+        if position <= first.position {
+            return first.color;
+        }
+        if position >= last.position {
+            return last.color;
+        }
+        let index = stops.windows(2).position(|pair| position < pair[1].position).unwrap();
+        let (from, to) = (stops[index], stops[index + 1]);
+        let t = (position - from.position) / (to.position - from.position);
+        let (from, to) = (from.color.to_srgb(), to.color.to_srgb());
+        Color::from_srgb([0, 1, 2, 3].map(|channel| from[channel] + (to[channel] - from[channel]) * t))
+    }
+
+    /// `count` colors sampled evenly from 0 to 1.
+    pub fn ramp(&self, count: usize) -> Vec<Color> {
+        (0..count)
+            .map(|index| self.sample(index as f32 / (count - 1).max(1) as f32))
+            .collect()
+    }
+}
+
+/// Where `color` lands on the gradient of a gradient map: its luminance, sRGB encoded so midtones
+/// land mid-gradient. gradient_map.wgsl does the same on the GPU.
+pub fn map_position(color: Color) -> f32 {
+    linear_to_srgb(color.luminance().clamp(0.0, 1.0))
+}
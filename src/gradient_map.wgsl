@@ -0,0 +1,47 @@
+// Gradient maps of the canvas, see `FilterKind::GradientMap` in filter.rs.
+//
+// `GradientMap` mirrors `GradientMapUniforms` in filter.rs, keep them in sync when changing either side.
+
+struct GradientMap {
+    origin: vec2<u32>,
+    size: vec2<u32>,
+    // Linear with straight alpha
+    ramp: array<vec4<f32>, 256>,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> gradient_map: GradientMap;
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if (value <= 0.0031308) {
+        return value * 12.92;
+    }
+    return 1.055 * pow(value, 1.0 / 2.4) - 0.055;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= gradient_map.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(gradient_map.origin + id.xy);
+    let color = textureLoad(input, pixel, 0);
+    if (color.a <= 0.0) {
+        textureStore(output, pixel, color);
+        return;
+    }
+
+    // `map_position` in gradient.rs
+    let rgb = color.rgb / color.a;
+    let luminance = clamp(dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    let position = linear_to_srgb(luminance) * 255.0;
+    let index = min(u32(position), 254u);
+    let mapped = mix(gradient_map.ramp[index], gradient_map.ramp[index + 1u], position - f32(index));
+
+    let result = mix(rgb, mapped.rgb, mapped.a);
+    textureStore(output, pixel, vec4<f32>(result * color.a, color.a));
+}
@@ -0,0 +1,127 @@
+//! Guides to paint along: perspective lines converging on one to three vanishing points, or an
+//! isometric grid. [`SurfaceRenderResources::set_guides`](crate::surface_view::SurfaceRenderResources::set_guides)
+//! shows them over the canvas without painting them, and strokes of a
+//! [`Painting`](crate::command::Painting) snap to them with its
+//! [`set_snap`](crate::command::Painting::set_snap).
+//!
+//! A snapping stroke follows the guide line through where it started that runs closest to the
+//! pointer, so it goes straight towards a vanishing point or along an isometric axis however the
+//! hand wobbles.
+
+use serde::{Deserialize, Serialize};
+
+/// Lines guiding strokes, in canvas coordinates (-1..1, y up). Vanishing points may lie beyond
+/// the canvas, as they usually do.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Guides {
+    /// Lines towards the vanishing point, with horizontal and vertical ones.
+    OnePoint([f32; 2]),
+    /// Lines towards either vanishing point, with vertical ones.
+    TwoPoint([[f32; 2]; 2]),
+    /// Lines towards any of the vanishing points.
+    ThreePoint([[f32; 2]; 3]),
+    /// Vertical lines and lines 30° either side of horizontal, `spacing` apart.
+    Isometric { spacing: f32 },
+}
+
+impl Guides {
+    /// Each kind of guides, with vanishing points on a horizon through the canvas center.
+    pub const PRESETS: [Guides; 4] = [
+        Guides::OnePoint([0.0, 0.0]),
+        Guides::TwoPoint([[-1.5, 0.0], [1.5, 0.0]]),
+        Guides::ThreePoint([[-1.5, 0.4], [1.5, 0.4], [0.0, -3.0]]),
+        Guides::Isometric { spacing: 0.1 },
+    ];
+
+    /// The preset called `name` in the configuration: `1-point`, `2-point`, `3-point` or
+    /// `isometric`.
+    pub fn from_name(name: &str) -> Option<Guides> {
+        Self::PRESETS.into_iter().find(|guides| guides.name() == name)
+    }
+
+    /// Guides as the configuration writes them: the name of a preset, or the name followed by the
+    /// vanishing points' x and y coordinates, like `2-point:-1.5,0,1.5,0`, or by the spacing of
+    /// the isometric grid, like `isometric:0.2`.
+    pub fn parse(value: &str) -> Option<Guides> {
+        let (name, numbers) = match value.split_once(':') {
+            Some((name, numbers)) => (name, Some(numbers)),
+            None => (value, None),
+        };
+        let preset = Self::from_name(name.trim())?;
+        let Some(numbers) = numbers else {
+            return Some(preset);
+        };
+        let numbers: Vec<f32> = numbers
+            .split(',')
+            .map(|number| number.trim().parse().ok().filter(|number: &f32| number.is_finite()))
+            .collect::<Option<_>>()?;
+        let point = |index: usize| [numbers[2 * index], numbers[2 * index + 1]];
+        match preset {
+            Guides::Isometric { .. } => match numbers[..] {
+                [spacing] if spacing > 0.0 => Some(Guides::Isometric { spacing }),
+                _ => None,
+            },
+            _ if numbers.len() != 2 * preset.vanishing_points().len() => None,
+            Guides::OnePoint(_) => Some(Guides::OnePoint(point(0))),
+            Guides::TwoPoint(_) => Some(Guides::TwoPoint([point(0), point(1)])),
+            Guides::ThreePoint(_) => Some(Guides::ThreePoint([point(0), point(1), point(2)])),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Guides::OnePoint(_) => "1-point",
+            Guides::TwoPoint(_) => "2-point",
+            Guides::ThreePoint(_) => "3-point",
+            Guides::Isometric { .. } => "isometric",
+        }
+    }
+
+    pub fn vanishing_points(&self) -> &[[f32; 2]] {
+        match self {
+            Guides::OnePoint(point) => std::slice::from_ref(point),
+            Guides::TwoPoint(points) => points,
+            Guides::ThreePoint(points) => points,
+            Guides::Isometric { .. } => &[],
+        }
+    }
+
+    /// `position` moved onto the guide line through `start` it's closest to.
+    pub fn snap(&self, start: [f32; 2], position: [f32; 2]) -> [f32; 2] {
+        let offset = [position[0] - start[0], position[1] - start[1]];
+        self.directions(start)
+            .into_iter()
+            .filter_map(|[x, y]| {
+                let length = (x * x + y * y).sqrt();
+                (length > f32::EPSILON).then_some([x / length, y / length])
+            })
+            .map(|[x, y]| {
+                let along = offset[0] * x + offset[1] * y;
+                [start[0] + x * along, start[1] + y * along]
+            })
+            .min_by(|a, b| distance_squared(*a, position).total_cmp(&distance_squared(*b, position)))
+            .unwrap_or(position)
+    }
+
+    /// Directions of the guide lines through `position`, not normalized. A line towards a
+    /// vanishing point at `position` itself has none.
+    fn directions(&self, position: [f32; 2]) -> Vec<[f32; 2]> {
+        let towards = |point: &[f32; 2]| [point[0] - position[0], point[1] - position[1]];
+        let mut directions: Vec<[f32; 2]> = self.vanishing_points().iter().map(towards).collect();
+        match self {
+            Guides::OnePoint(_) => directions.extend([[1.0, 0.0], [0.0, 1.0]]),
+            Guides::TwoPoint(_) => directions.push([0.0, 1.0]),
+            Guides::ThreePoint(_) => {}
+            Guides::Isometric { .. } => {
+                let (sin, cos) = 30f32.to_radians().sin_cos();
+                directions.extend([[cos, sin], [cos, -sin], [0.0, 1.0]]);
+            }
+        }
+        directions
+    }
+}
+
+fn distance_squared(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
@@ -0,0 +1,231 @@
+//! Per-channel histograms of the canvas, counted by a compute shader.
+//!
+//! Bins hold sRGB encoded, unpremultiplied values, as levels and curves see them. Fully
+//! transparent pixels aren't counted.
+
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+use futures_channel::oneshot;
+use serde::Serialize;
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::preprocessor::ShaderDefines;
+use crate::shaders::{Shader, ShaderError};
+
+/// Bins per channel, one per 8 bit value.
+pub const BINS: usize = 256;
+
+/// Red, green, blue and luminance, in the order histogram.wgsl writes them.
+const CHANNELS: usize = 4;
+
+const BUFFER_SIZE: u64 = (CHANNELS * BINS * std::mem::size_of::<u32>()) as u64;
+
+/// Pixel counts by value, [`BINS`] per channel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    pub red: Vec<u32>,
+    pub green: Vec<u32>,
+    pub blue: Vec<u32>,
+    /// Of the relative luminance, encoded like the color channels.
+    pub luminance: Vec<u32>,
+}
+
+impl Histogram {
+    /// The number of pixels counted.
+    pub fn pixel_count(&self) -> u64 {
+        self.luminance.iter().map(|&count| count as u64).sum()
+    }
+
+    /// The luminance value (0..255) that `fraction` of the pixels are at or below, e.g. 0.01 and
+    /// 0.99 for the black and white points of levels. `None` without pixels.
+    pub fn luminance_percentile(&self, fraction: f32) -> Option<u8> {
+        let total = self.pixel_count();
+        if total == 0 {
+            return None;
+        }
+        let target = (fraction.clamp(0.0, 1.0) as f64 * total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.luminance
+            .iter()
+            .position(|&count| {
+                seen += count as u64;
+                seen >= target
+            })
+            .map(|bin| bin as u8)
+    }
+}
+
+/// Whether `device` can count histograms.
+pub(crate) fn is_supported(device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension > 0 && limits.max_storage_buffers_per_shader_stage > 0
+}
+
+/// `@workgroup_size` of histogram.wgsl, in both dimensions.
+const WORKGROUP_SIZE: u32 = 8;
+
+pub(crate) struct HistogramPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl HistogramPass {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("histogram_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(BUFFER_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("histogram_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, Shader::Histogram.embedded_source(), &pipeline_layout)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        Self {
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || create_pipeline(device, source, &self.pipeline_layout))?;
+        Ok(())
+    }
+
+    /// Starts counting the pixels of `texture_view`, of `size`.
+    pub fn count(
+        &self,
+        device: Arc<wgpu::Device>,
+        queue: &wgpu::Queue,
+        texture_view: &wgpu::TextureView,
+        size: wgpu::Extent3d,
+    ) -> HistogramReadback {
+        // New buffers start out zeroed
+        let bins = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("histogram_bins"),
+            size: BUFFER_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("histogram_readback"),
+            size: BUFFER_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("histogram_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bins.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("histogram"),
+        });
+        encoder.push_debug_group("histogram");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("histogram"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                (size.width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (size.height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&bins, 0, &readback, 0, BUFFER_SIZE);
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+
+        HistogramReadback {
+            device,
+            buffer: readback,
+        }
+    }
+}
+
+/// A histogram being counted, see [`HpSurface::histogram`](crate::surface::HpSurface::histogram).
+pub struct HistogramReadback {
+    device: Arc<wgpu::Device>,
+    buffer: wgpu::Buffer,
+}
+
+impl HistogramReadback {
+    /// Waits for the GPU to finish counting.
+    pub async fn into_histogram(self) -> Result<Histogram, wgpu::BufferAsyncError> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        // Native backends only resolve the mapping when polled, on the web this is a no-op
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.await.expect("map_async callback dropped")?;
+
+        let counts: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        let mut channels = counts.chunks_exact(BINS).map(<[u32]>::to_vec);
+        Ok(Histogram {
+            red: channels.next().unwrap(),
+            green: channels.next().unwrap(),
+            blue: channels.next().unwrap(),
+            luminance: channels.next().unwrap(),
+        })
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, ShaderError> {
+    let shader = Shader::Histogram.create_module(device, source, &ShaderDefines::new())?;
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("histogram"),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: "cs_main",
+    }))
+}
@@ -0,0 +1,32 @@
+// Counts the canvas' pixels into per-channel histograms, see histogram.rs.
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+// 256 bins each of red, green, blue and luminance
+@group(0) @binding(1)
+var<storage, read_write> bins: array<atomic<u32>, 1024>;
+
+fn linear_to_srgb(color: vec4<f32>) -> vec4<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec4<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec4<f32>(0.0031308));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= vec2<u32>(textureDimensions(input)))) {
+        return;
+    }
+    let color = textureLoad(input, vec2<i32>(id.xy), 0);
+    if (color.a <= 0.0) {
+        return;
+    }
+
+    let rgb = clamp(color.rgb / color.a, vec3<f32>(0.0), vec3<f32>(1.0));
+    let luminance = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let bin = vec4<u32>(round(linear_to_srgb(vec4<f32>(rgb, luminance)) * 255.0));
+    atomicAdd(&bins[bin.r], 1u);
+    atomicAdd(&bins[256u + bin.g], 1u);
+    atomicAdd(&bins[512u + bin.b], 1u);
+    atomicAdd(&bins[768u + bin.a], 1u);
+}
@@ -0,0 +1,132 @@
+//! Minimal ICC v2 display profiles for tagging exports: the gamut's primaries as a matrix and
+//! the sRGB curve, which Display P3 shares, as a table.
+
+use crate::color::srgb_to_linear;
+use crate::surface::DisplayGamut;
+
+/// The PCS illuminant, D50. Primaries below are adapted to it with Bradford, as ICC requires.
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// Entries of the sampled transfer curve.
+const CURVE_SIZE: usize = 1024;
+
+/// The name embedded alongside the profile.
+pub fn profile_name(gamut: DisplayGamut) -> &'static str {
+    match gamut {
+        DisplayGamut::Srgb => "sRGB",
+        DisplayGamut::DisplayP3 => "Display P3",
+    }
+}
+
+/// The profile describing colors of `gamut`.
+pub fn profile(gamut: DisplayGamut) -> Vec<u8> {
+    // The XYZ of the red, green and blue primaries
+    let [red, green, blue] = match gamut {
+        DisplayGamut::Srgb => [
+            [0.436_074_7, 0.222_504_5, 0.013_932_2],
+            [0.385_064_9, 0.716_878_6, 0.097_104_5],
+            [0.143_080_4, 0.060_616_9, 0.714_173_3],
+        ],
+        DisplayGamut::DisplayP3 => [
+            [0.515_102, 0.241_196, -0.001_050],
+            [0.291_965, 0.692_236, 0.041_882],
+            [0.157_153, 0.066_574, 0.784_073],
+        ],
+    };
+
+    let mut curve = tag_type(b"curv");
+    curve.extend((CURVE_SIZE as u32).to_be_bytes());
+    for index in 0..CURVE_SIZE {
+        let value = srgb_to_linear(index as f32 / (CURVE_SIZE - 1) as f32);
+        curve.extend(((value * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let tags: [(&[u8; 4], Vec<u8>); 7] = [
+        (b"desc", description(profile_name(gamut))),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"rXYZ", xyz(red)),
+        (b"gXYZ", xyz(green)),
+        (b"bXYZ", xyz(blue)),
+        (b"rTRC", curve),
+    ];
+    // Green and blue share the red curve's data
+    let shared = [b"gTRC", b"bTRC"];
+
+    let tag_count = tags.len() + shared.len();
+    let data_start = 128 + 4 + 12 * tag_count;
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let mut add_entry = |signature: &[u8; 4], offset: usize, size: usize| {
+        table.extend(signature);
+        table.extend((offset as u32).to_be_bytes());
+        table.extend((size as u32).to_be_bytes());
+    };
+    let mut curve_entry = (0, 0);
+    for (signature, tag) in &tags {
+        let offset = data_start + data.len();
+        add_entry(signature, offset, tag.len());
+        if *signature == b"rTRC" {
+            curve_entry = (offset, tag.len());
+        }
+        data.extend(tag);
+        // Tags start on 4 byte boundaries
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    for signature in shared {
+        add_entry(signature, curve_entry.0, curve_entry.1);
+    }
+
+    let size = data_start + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend((size as u32).to_be_bytes());
+    profile.extend([0; 4]); // preferred CMM
+    profile.extend(0x0240_0000u32.to_be_bytes()); // version 2.4
+    profile.extend(b"mntrRGB XYZ ");
+    profile.extend([2023u16, 1, 1, 0, 0, 0].iter().flat_map(|part| part.to_be_bytes()));
+    profile.extend(b"acsp");
+    // Platform, flags, manufacturer, model, attributes and the perceptual intent, all zero
+    profile.extend([0; 28]);
+    profile.extend(D50.iter().flat_map(|&value| s15_fixed16(value)));
+    // Creator and reserved bytes
+    profile.resize(128, 0);
+    profile.extend((tag_count as u32).to_be_bytes());
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+/// The 8 byte start of tag data: its type signature and 4 reserved bytes.
+fn tag_type(signature: &[u8; 4]) -> Vec<u8> {
+    let mut tag = signature.to_vec();
+    tag.extend([0; 4]);
+    tag
+}
+
+fn xyz(value: [f64; 3]) -> Vec<u8> {
+    let mut tag = tag_type(b"XYZ ");
+    tag.extend(value.iter().flat_map(|&value| s15_fixed16(value)));
+    tag
+}
+
+fn text(value: &str) -> Vec<u8> {
+    let mut tag = tag_type(b"text");
+    tag.extend(value.as_bytes());
+    tag.push(0);
+    tag
+}
+
+/// A v2 `textDescriptionType`, with only the ASCII description filled in.
+fn description(value: &str) -> Vec<u8> {
+    let mut tag = tag_type(b"desc");
+    tag.extend((value.len() as u32 + 1).to_be_bytes());
+    tag.extend(value.as_bytes());
+    tag.push(0);
+    // Empty Unicode (language and count) and ScriptCode (code, count and 67 reserved bytes) parts
+    tag.extend([0; 8 + 3 + 67]);
+    tag
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
@@ -0,0 +1,307 @@
+//! The app's input: pointers painting, and the keyboard shortcuts of the demo.
+//!
+//! [`Input`] turns window events into [`Action`]s, painting ones into the same [`Command`]s the
+//! other frontends publish, leaving it to [`app`](crate::app) to carry them out.
+
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, KeyboardInput, ModifiersState, MouseButton, TouchPhase, VirtualKeyCode, WindowEvent};
+use winit::window::Window;
+
+use crate::command::{Command, PointerId};
+
+/// What a window event asks the app to do.
+#[derive(Debug)]
+pub(crate) enum Action {
+    /// Publishes a command on the app's bus.
+    Command(Command),
+    /// L: toggles skipping the LUT.
+    ToggleLutBypass,
+    /// V: simulates each kind of color blindness in turn, then none.
+    CycleColorBlindness,
+    /// B: blurs the canvas.
+    AddBlur,
+    /// Shift+B
+    ClearFilters,
+    /// H: logs a histogram of the canvas.
+    CountHistogram,
+    /// W: selects with the magic wand from this canvas position.
+    SelectAt([f32; 2]),
+    /// Shift+W
+    ClearSelection,
+    /// I: paints with the color at this canvas position, like an eyedropper.
+    PickColorAt([f32; 2]),
+    /// D: logs the topmost dot at this canvas position.
+    InspectDotAt([f32; 2]),
+    /// 1 to 9: paints with this swatch of the palette, counting from 0.
+    PickSwatch(usize),
+    /// Shift+1 to 9: paints with this recently painted color, counting from 0.
+    PickRecentColor(usize),
+    /// A: switches between painting wet watercolor and dots.
+    ToggleWetBrush,
+    /// Period: switches to the next animation frame.
+    NextFrame,
+    /// Comma
+    PreviousFrame,
+    /// N: adds an empty frame after the current one.
+    AddFrame,
+    /// Shift+N: adds a copy of the current frame after it.
+    DuplicateFrame,
+    /// O: toggles showing the frames around the current one as onion skins.
+    ToggleOnionSkin,
+    /// Ctrl+Shift+S: exports the frames of the timeline as a video.
+    ExportVideo,
+    /// Ctrl+D: saves the document, where it was opened from natively, as the autosave on the web.
+    SaveDocument,
+    /// Ctrl+N: asks which document to start next.
+    NewDocument,
+    /// R: replays the session so far on a new frame.
+    Replay,
+    /// Shift+R: exports a replay of the session so far as a video.
+    ExportReplay,
+    /// G: adds dots laid out by the next of the generators, with the brush.
+    Generate,
+    /// Shift+G: draws the next of the L-system presets with the brush.
+    DrawLSystem,
+    /// F5: runs the script passed with `--script` again.
+    RunScript,
+    /// F8: writes the dots and the instance buffers to disk.
+    DumpInstances,
+    /// F9: captures the next frame in RenderDoc or Xcode.
+    CaptureFrame,
+    /// C: compares the canvas with its snapshot side by side, split, and not anymore in turn.
+    CycleComparison,
+    /// Shift+C: takes a snapshot of the canvas to compare it with.
+    TakeSnapshot,
+    /// P: shows the next kind of guides, then none.
+    CycleGuides,
+    /// Shift+P: toggles snapping strokes to the guides.
+    ToggleSnap,
+    /// F: mirrors the view left to right, or back.
+    FlipHorizontally,
+    /// Shift+F: mirrors the view top to bottom, or back.
+    FlipVertically,
+    /// Dragging with the right mouse button: moves the comparison's split line to this canvas
+    /// position.
+    MoveSplit([f32; 2]),
+}
+
+/// The state of the pointers and modifier keys between events.
+pub(crate) struct Input {
+    readonly: bool,
+    modifiers: ModifiersState,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    /// Whether the right mouse button is held, dragging the split line.
+    dragging_split: bool,
+    mouse_pressure: Box<dyn Fn() -> f32>,
+}
+
+impl Input {
+    /// Input from `window`, which can't paint if `readonly`.
+    pub fn new(window: &Window, readonly: bool) -> Self {
+        // winit reports pens as mice without pressure, so on the web we read it from pointer events
+        #[cfg(target_arch = "wasm32")]
+        let mouse_pressure = {
+            let pen_pressure = track_pen_pressure(window);
+            move || pen_pressure.get().unwrap_or(1.0)
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let mouse_pressure = {
+            let _ = window;
+            || 1.0
+        };
+        Self {
+            readonly,
+            modifiers: ModifiersState::empty(),
+            cursor_position: None,
+            dragging_split: false,
+            mouse_pressure: Box::new(mouse_pressure),
+        }
+    }
+
+    /// What `event` asks for, with window positions mapped by `to_canvas`.
+    pub fn handle(
+        &mut self,
+        event: &WindowEvent<'_>,
+        to_canvas: impl Fn(PhysicalPosition<f64>) -> [f32; 2],
+    ) -> Option<Action> {
+        let action = match *event {
+            WindowEvent::CursorMoved { position, .. } if self.dragging_split => {
+                self.cursor_position = Some(position);
+                Action::MoveSplit(to_canvas(position))
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(position);
+                Command::ContinueStroke {
+                    pointer: PointerId::Mouse,
+                    position: to_canvas(position),
+                    pressure: (self.mouse_pressure)(),
+                }
+                .into()
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => match (state, self.cursor_position) {
+                (ElementState::Pressed, Some(position)) if !self.readonly => Command::BeginStroke {
+                    pointer: PointerId::Mouse,
+                    position: to_canvas(position),
+                    pressure: (self.mouse_pressure)(),
+                }
+                .into(),
+                (ElementState::Released, _) => Command::EndStroke(PointerId::Mouse).into(),
+                _ => return None,
+            },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                self.dragging_split = state == ElementState::Pressed;
+                match (self.dragging_split, self.cursor_position) {
+                    (true, Some(position)) => Action::MoveSplit(to_canvas(position)),
+                    _ => return None,
+                }
+            }
+            WindowEvent::Touch(touch) => {
+                let pointer = PointerId::Touch(touch.id);
+                let position = to_canvas(touch.location);
+                let pressure = touch.force.map_or(1.0, |force| force.normalized() as f32);
+                match touch.phase {
+                    TouchPhase::Started if !self.readonly => Command::BeginStroke {
+                        pointer,
+                        position,
+                        pressure,
+                    }
+                    .into(),
+                    TouchPhase::Moved => Command::ContinueStroke {
+                        pointer,
+                        position,
+                        pressure,
+                    }
+                    .into(),
+                    TouchPhase::Ended | TouchPhase::Cancelled => Command::EndStroke(pointer).into(),
+                    TouchPhase::Started => return None,
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                return None;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => return self.key_pressed(key, to_canvas),
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    fn key_pressed(
+        &self,
+        key: VirtualKeyCode,
+        to_canvas: impl Fn(PhysicalPosition<f64>) -> [f32; 2],
+    ) -> Option<Action> {
+        let command = self.modifiers.ctrl() || self.modifiers.logo();
+        let shift = self.modifiers.shift();
+        let action = match key {
+            VirtualKeyCode::S if command && shift => Action::ExportVideo,
+            VirtualKeyCode::S if command => Command::Export.into(),
+            VirtualKeyCode::Z if command && !self.readonly => Command::Undo.into(),
+            VirtualKeyCode::D if command => Action::SaveDocument,
+            VirtualKeyCode::N if command && !self.readonly => Action::NewDocument,
+            VirtualKeyCode::Delete if !self.readonly => Command::Clear.into(),
+            VirtualKeyCode::L => Action::ToggleLutBypass,
+            VirtualKeyCode::V => Action::CycleColorBlindness,
+            VirtualKeyCode::B if shift && !self.readonly => Action::ClearFilters,
+            VirtualKeyCode::B if !self.readonly => Action::AddBlur,
+            VirtualKeyCode::H => Action::CountHistogram,
+            VirtualKeyCode::G if shift && !self.readonly => Action::DrawLSystem,
+            VirtualKeyCode::G if !self.readonly => Action::Generate,
+            VirtualKeyCode::W if shift => Action::ClearSelection,
+            VirtualKeyCode::W => Action::SelectAt(to_canvas(self.cursor_position?)),
+            VirtualKeyCode::I => Action::PickColorAt(to_canvas(self.cursor_position?)),
+            VirtualKeyCode::D => Action::InspectDotAt(to_canvas(self.cursor_position?)),
+            VirtualKeyCode::A => Action::ToggleWetBrush,
+            VirtualKeyCode::Period => Action::NextFrame,
+            VirtualKeyCode::Comma => Action::PreviousFrame,
+            VirtualKeyCode::N if shift && !self.readonly => Action::DuplicateFrame,
+            VirtualKeyCode::N if !self.readonly => Action::AddFrame,
+            VirtualKeyCode::O => Action::ToggleOnionSkin,
+            VirtualKeyCode::R if shift => Action::ExportReplay,
+            VirtualKeyCode::R => Action::Replay,
+            VirtualKeyCode::F5 if !self.readonly => Action::RunScript,
+            VirtualKeyCode::F8 => Action::DumpInstances,
+            VirtualKeyCode::F9 => Action::CaptureFrame,
+            VirtualKeyCode::C if shift => Action::TakeSnapshot,
+            VirtualKeyCode::C => Action::CycleComparison,
+            VirtualKeyCode::F if shift => Action::FlipVertically,
+            VirtualKeyCode::F => Action::FlipHorizontally,
+            VirtualKeyCode::P if shift => Action::ToggleSnap,
+            VirtualKeyCode::P => Action::CycleGuides,
+            key => {
+                let index = swatch_index(key)?;
+                if shift {
+                    Action::PickRecentColor(index)
+                } else {
+                    Action::PickSwatch(index)
+                }
+            }
+        };
+        Some(action)
+    }
+}
+
+impl From<Command> for Action {
+    fn from(command: Command) -> Self {
+        Self::Command(command)
+    }
+}
+
+/// The swatch the number keys 1 to 9 pick, counting from 0.
+fn swatch_index(key: VirtualKeyCode) -> Option<usize> {
+    const KEYS: [VirtualKeyCode; 9] = [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+    ];
+    KEYS.iter().position(|&candidate| candidate == key)
+}
+
+/// Keeps track of the pressure of a pen touching the canvas, `None` while no pen is down.
+#[cfg(target_arch = "wasm32")]
+fn track_pen_pressure(window: &Window) -> Rc<std::cell::Cell<Option<f32>>> {
+    use wasm_bindgen::JsCast;
+    use winit::platform::web::WindowExtWebSys;
+
+    let pen_pressure = Rc::new(std::cell::Cell::new(None));
+    let on_pointer_event = {
+        let pen_pressure = pen_pressure.clone();
+        wasm_bindgen::closure::Closure::<dyn FnMut(web_sys::PointerEvent)>::new(move |event: web_sys::PointerEvent| {
+            let pressed = event.buttons() != 0;
+            pen_pressure.set((event.pointer_type() == "pen" && pressed).then(|| event.pressure()));
+        })
+    };
+    let canvas = window.canvas();
+    for event_type in ["pointerdown", "pointermove", "pointerup"] {
+        canvas
+            .add_event_listener_with_callback(event_type, on_pointer_event.as_ref().unchecked_ref())
+            .expect("couldn't register pointer listener");
+    }
+    on_pointer_event.forget();
+    pen_pressure
+}
@@ -1,5 +1,120 @@
+//! A wgpu renderer for painting with soft dots, usable on its own in other wgpu apps.
+//!
+//! The pieces, from the bottom up:
+//! - [`GlobalSurface`] holds what all canvases share: the device and queue, the dot pipelines and
+//!   the canvas size and format.
+//! - [`Canvas`] (also known by its original name [`HpSurface`]) is one canvas: its [`Dot`]s,
+//!   filters and selection, and the texture they are rendered into by [`Canvas::render`].
+//! - [`Brush`] and [`Stroke`] turn pointer samples into dots.
+//! - [`SurfaceRenderResources`] draws a canvas, with its post-processing effects, into a texture
+//!   or an existing render pass.
+//! - [`export_png`] reads a canvas back and encodes it.
+//!
+//! Setting up returns an [`Error`] rather than panicking, so embedders can fall back gracefully.
+//!
+//! Embedding into an app that already has a device, queue and swapchain format:
+//!
+//! ```no_run
+//! # fn embed(
+//! #     device: std::sync::Arc<wgpu::Device>,
+//! #     queue: std::sync::Arc<wgpu::Queue>,
+//! #     frame: &wgpu::Texture,
+//! # ) -> Result<(), hellopaint_wgpu::Error> {
+//! use std::sync::Arc;
+//! use hellopaint_wgpu::{Brush, Canvas, GlobalSurface, Stroke, SurfaceRenderResources};
+//!
+//! let global = Arc::new(GlobalSurface::new(device.clone(), queue.clone())?);
+//! let mut view = SurfaceRenderResources::new(&device, Canvas::new(global), frame.format());
+//!
+//! // Positions are in canvas coordinates, -1..1 with y up
+//! let mut stroke = Stroke::new(Brush::default());
+//! stroke.add_point([-0.5, 0.0], 1.0);
+//! stroke.add_point([0.5, 0.0], 1.0);
+//! view.surface_mut().add_dots(&stroke.finish());
+//!
+//! view.render_to_texture(&device, &queue, frame)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`app`] is the demo built on top of this, which the `hellopaint-wgpu` binary runs.
+
 #![warn(clippy::all, rust_2018_idioms)]
 
+pub mod adjustment_layer;
+pub mod app;
+pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod batch;
+mod bloom;
+pub mod clock;
+pub mod color;
+pub mod command;
+pub mod config;
+pub mod crdt;
+mod diagnostics;
+pub mod document;
+pub mod dump;
+mod error;
+pub mod export;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod filter;
+pub mod framing;
+pub mod generators;
+pub mod gradient;
+pub mod guides;
+pub mod histogram;
+mod icc;
+mod input;
+pub mod lod;
+pub mod lsystem;
+pub mod lut;
+pub mod new_document;
+mod overlay;
+pub mod palette;
+pub mod particles;
+mod persist;
+pub mod plugin;
+pub mod post_process;
+pub mod preprocessor;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+pub mod raster;
+pub mod recent_colors;
+pub mod replay;
+mod renderer;
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+pub mod script;
+pub mod selection;
+pub mod session;
+#[cfg(all(feature = "server", not(target_arch = "wasm32")))]
+pub mod server;
+pub mod shaders;
+pub mod spatial;
 pub mod surface_view;
 pub mod surface;
+pub mod stroke;
+mod stress;
+pub mod stipple;
+pub mod tasks;
+pub mod timeline;
+pub mod tween;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod video;
+mod watchdog;
+pub mod watercolor;
+#[cfg(target_arch = "wasm32")]
+pub mod storage;
+#[cfg(target_arch = "wasm32")]
+pub mod web_api;
+#[cfg(target_arch = "wasm32")]
+pub mod worker;
+
 
+pub use error::Error;
+pub use export::{export_png, ExportError, ExportOptions, TextureReadback};
+pub use plugin::RenderPassPlugin;
+pub use stroke::{Brush, Stroke};
+pub use surface::{BlendPreset, Dot, GlobalSurface, GlobalSurfaceBuilder, HpSurface, HpSurface as Canvas};
+pub use surface_view::{FrameHook, FrameStats, SurfaceRenderResources};
@@ -0,0 +1,124 @@
+//! Level of detail for canvases shown smaller than they are. Zoomed far out, a screen pixel
+//! covers many canvas pixels, and drawing every one of a million tiny dots in it is wasted work.
+//! At each [`level`], dots too small to see on their own are [merged](decimate) into one dot
+//! per screen pixel, with their average color and as much area as they had together.
+//!
+//! [`HpSurface`](crate::surface::HpSurface) draws these decimated dots instead of its own with
+//! [`HpSurface::set_lod`](crate::surface::HpSurface::set_lod), as far as the screen scale surface
+//! views set calls for it.
+
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::surface::Dot;
+
+/// When dots are merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodSettings {
+    /// Dots less wide than this on screen, in pixels, are merged with the small dots around them.
+    pub min_screen_size: f32,
+}
+
+impl LodSettings {
+    pub const DEFAULT: Self = Self { min_screen_size: 1.0 };
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The level to draw a canvas of `canvas_size` pixels at, shown with `screen_scale` screen pixels
+/// per canvas pixel: a screen pixel covers about `2^level` canvas pixels across, 0 draws every dot.
+pub fn level(screen_scale: f32, canvas_size: u32) -> u32 {
+    if !(screen_scale > 0.0 && screen_scale < 1.0) {
+        return 0;
+    }
+    ((1.0 / screen_scale).log2().floor() as u32).min(canvas_size.max(1).ilog2())
+}
+
+/// `dots` as drawn at `level` on a canvas of `canvas_size` pixels: the ones smaller than
+/// `settings` allow on screen merged into one dot per screen pixel, where the first of them was
+/// drawn. Larger and animated dots are kept as they are.
+pub fn decimate(dots: &[Dot], canvas_size: u32, level: u32, settings: &LodSettings) -> Vec<Dot> {
+    // A screen pixel, in canvas units
+    let cell = (1u32 << level) as f32 * 2.0 / canvas_size as f32;
+    let max_radius = settings.min_screen_size * cell / 2.0;
+
+    let mut decimated = Vec::new();
+    let mut merged: Vec<Merged> = Vec::new();
+    let mut cells: HashMap<[i32; 2], usize> = HashMap::new();
+    for dot in dots {
+        if dot.radius() >= max_radius || dot.is_animated() {
+            decimated.push(Slot::Dot(*dot));
+            continue;
+        }
+        let [x, y] = dot.position();
+        let key = [(x / cell).floor() as i32, (y / cell).floor() as i32];
+        let index = *cells.entry(key).or_insert_with(|| {
+            decimated.push(Slot::Merged(merged.len()));
+            merged.push(Merged::default());
+            merged.len() - 1
+        });
+        merged[index].add(dot);
+    }
+    decimated
+        .into_iter()
+        .map(|slot| match slot {
+            Slot::Dot(dot) => dot,
+            // Never larger than the screen pixel it stands for
+            Slot::Merged(index) => merged[index].dot(cell * std::f32::consts::FRAC_1_SQRT_2),
+        })
+        .collect()
+}
+
+enum Slot {
+    Dot(Dot),
+    Merged(usize),
+}
+
+/// Sums of the dots merged in a cell, weighted by their area.
+#[derive(Default)]
+struct Merged {
+    area: f32,
+    position: [f32; 2],
+    hardness: f32,
+    /// Straight color weighted by area and alpha, so transparent dots don't darken it.
+    rgb: [f32; 3],
+    alpha: f32,
+    /// Of the frontmost, see [`DotOrder::Depth`](crate::surface::DotOrder::Depth).
+    depth: f32,
+}
+
+impl Merged {
+    fn add(&mut self, dot: &Dot) {
+        let area = dot.radius() * dot.radius();
+        let [x, y] = dot.position();
+        let Color { r, g, b, a } = dot.color();
+        self.area += area;
+        self.position = [self.position[0] + x * area, self.position[1] + y * area];
+        self.hardness += dot.hardness() * area;
+        self.rgb = [self.rgb[0] + r * a * area, self.rgb[1] + g * a * area, self.rgb[2] + b * a * area];
+        self.alpha += a * area;
+        self.depth = self.depth.max(dot.depth());
+    }
+
+    fn dot(&self, max_radius: f32) -> Dot {
+        let area = self.area.max(f32::MIN_POSITIVE);
+        let alpha = self.alpha.max(f32::MIN_POSITIVE);
+        let [r, g, b] = self.rgb.map(|value| value / alpha);
+        Dot::new(
+            [self.position[0] / area, self.position[1] / area],
+            self.area.sqrt().min(max_radius),
+            self.hardness / area,
+            Color {
+                r,
+                g,
+                b,
+                a: self.alpha / area,
+            },
+        )
+        .with_depth(self.depth)
+    }
+}
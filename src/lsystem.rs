@@ -0,0 +1,212 @@
+//! L-systems: an axiom rewritten by rules again and again, then walked by a turtle that draws as
+//! it goes. The lines become dots stamped along them with a [`Brush`], like a stroke.
+//!
+//! The turtle understands:
+//! - `F` and `G`: moves a step forward, drawing.
+//! - `f`: moves a step forward without drawing.
+//! - `+` and `-`: turns left and right by the angle.
+//! - `|`: turns around.
+//! - `[` and `]`: remembers where it is and returns there, for branches.
+//!
+//! Other symbols only matter to the rules.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::generators;
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+/// Rewriting stops before the string grows longer, with [`LSystemError::TooLong`].
+pub const MAX_SYMBOLS: usize = 1_000_000;
+
+/// Rewriting stops once all iterations together wrote more symbols, with
+/// [`LSystemError::TooManyIterations`], e.g. when rules swap symbols back and forth forever.
+pub const MAX_REWRITTEN: usize = 16 * MAX_SYMBOLS;
+
+#[derive(Debug)]
+pub enum LSystemError {
+    /// The string has more than [`MAX_SYMBOLS`] symbols at this iteration.
+    TooLong { iteration: usize },
+    /// The iterations up to this one wrote more than [`MAX_REWRITTEN`] symbols.
+    TooManyIterations { iteration: usize },
+    /// A `]` without the `[` it returns to.
+    UnbalancedBracket { index: usize },
+}
+
+impl fmt::Display for LSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LSystemError::TooLong { iteration } => {
+                write!(f, "iteration {iteration} has more than {MAX_SYMBOLS} symbols")
+            }
+            LSystemError::TooManyIterations { iteration } => {
+                write!(f, "the first {iteration} iterations rewrite more than {MAX_REWRITTEN} symbols")
+            }
+            LSystemError::UnbalancedBracket { index } => write!(f, "the ] at {index} has no [ before it"),
+        }
+    }
+}
+
+impl std::error::Error for LSystemError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LSystem {
+    pub axiom: String,
+    /// What each symbol is replaced with, symbols without a rule stay.
+    pub rules: HashMap<char, String>,
+    /// Turned by `+` and `-`, in degrees.
+    pub angle: f32,
+    /// Walked by `F`, `G` and `f`, in canvas units.
+    pub step: f32,
+    pub iterations: usize,
+    /// Scales and moves the drawing to fill the canvas, `step` only sets its proportions then.
+    pub fit: bool,
+}
+
+impl LSystem {
+    pub fn new(axiom: impl Into<String>, rules: impl IntoIterator<Item = (char, String)>, angle: f32, iterations: usize) -> Self {
+        Self {
+            axiom: axiom.into(),
+            rules: rules.into_iter().collect(),
+            angle,
+            step: 0.01,
+            iterations,
+            fit: true,
+        }
+    }
+
+    /// The Koch snowflake.
+    pub fn koch() -> Self {
+        Self::new("F++F++F", [('F', "F-F++F-F".to_owned())], 60.0, 4)
+    }
+
+    /// The Heighway dragon.
+    pub fn dragon() -> Self {
+        Self::new("F", [('F', "F+G".to_owned()), ('G', "F-G".to_owned())], 90.0, 12)
+    }
+
+    /// The Sierpinski triangle as an arrowhead curve.
+    pub fn sierpinski() -> Self {
+        Self::new("F", [('F', "G-F-G".to_owned()), ('G', "F+G+F".to_owned())], 60.0, 7)
+    }
+
+    /// A branching plant.
+    pub fn plant() -> Self {
+        let rules = [('X', "F+[[X]-X]-F[-FX]+X".to_owned()), ('F', "FF".to_owned())];
+        Self::new("-X", rules, 25.0, 5)
+    }
+
+    /// The presets, for cycling through.
+    pub fn presets() -> [(&'static str, LSystem); 4] {
+        [
+            ("Koch snowflake", Self::koch()),
+            ("dragon", Self::dragon()),
+            ("Sierpinski triangle", Self::sierpinski()),
+            ("plant", Self::plant()),
+        ]
+    }
+
+    /// The axiom rewritten `iterations` times.
+    pub fn expand(&self) -> Result<String, LSystemError> {
+        let mut symbols = self.axiom.clone();
+        let mut rewritten = 0usize;
+        for iteration in 1..=self.iterations {
+            let mut next = String::with_capacity(symbols.len() * 2);
+            for symbol in symbols.chars() {
+                match self.rules.get(&symbol) {
+                    Some(replacement) => next.push_str(replacement),
+                    None => next.push(symbol),
+                }
+                if next.len() > MAX_SYMBOLS {
+                    return Err(LSystemError::TooLong { iteration });
+                }
+            }
+            if next == symbols {
+                // No rule changes it anymore, the iterations left wouldn't either
+                break;
+            }
+            rewritten += next.len();
+            if rewritten > MAX_REWRITTEN {
+                return Err(LSystemError::TooManyIterations { iteration });
+            }
+            symbols = next;
+        }
+        Ok(symbols)
+    }
+
+    /// The lines the turtle draws, from where to where. It starts at the center heading up.
+    pub fn segments(&self) -> Result<Vec<[[f32; 2]; 2]>, LSystemError> {
+        let turn = self.angle.to_radians();
+        let mut position = [0.0f32, 0.0];
+        let mut heading = std::f32::consts::FRAC_PI_2;
+        let mut stack = Vec::new();
+        let mut segments = Vec::new();
+        for (index, symbol) in self.expand()?.chars().enumerate() {
+            match symbol {
+                'F' | 'G' | 'f' => {
+                    let next = [position[0] + heading.cos() * self.step, position[1] + heading.sin() * self.step];
+                    if symbol != 'f' {
+                        segments.push([position, next]);
+                    }
+                    position = next;
+                }
+                '+' => heading += turn,
+                '-' => heading -= turn,
+                '|' => heading += std::f32::consts::PI,
+                '[' => stack.push((position, heading)),
+                ']' => (position, heading) = stack.pop().ok_or(LSystemError::UnbalancedBracket { index })?,
+                _ => {}
+            }
+        }
+        if self.fit {
+            fit(&mut segments);
+        }
+        Ok(segments)
+    }
+
+    /// Points every `spacing` along the lines, in canvas units. At most
+    /// [`generators::MAX_POINTS`], leaving out the lines drawn last beyond that.
+    pub fn stamps(&self, spacing: f32) -> Result<Vec<[f32; 2]>, LSystemError> {
+        let spacing = spacing.max(1e-4);
+        let mut stamps = Vec::new();
+        for [from, to] in self.segments()? {
+            let left = generators::MAX_POINTS - stamps.len();
+            if left == 0 {
+                break;
+            }
+            let length = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+            let count = ((length / spacing).ceil().max(1.0) as usize).min(left);
+            stamps.extend((0..count).map(|stamp| {
+                let t = stamp as f32 / count as f32;
+                [from[0] + (to[0] - from[0]) * t, from[1] + (to[1] - from[1]) * t]
+            }));
+        }
+        Ok(stamps)
+    }
+
+    /// Dots stamped along the lines with `brush`, as far apart as its spacing has a stroke's.
+    pub fn dots(&self, brush: &Brush) -> Result<Vec<Dot>, LSystemError> {
+        Ok(generators::dots_at(&self.stamps(brush.radius * brush.spacing)?, brush))
+    }
+}
+
+/// Scales and moves `segments` to fill the canvas with a margin, keeping their proportions.
+fn fit(segments: &mut [[[f32; 2]; 2]]) {
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for point in segments.iter().flatten() {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    let size = (max[0] - min[0]).max(max[1] - min[1]);
+    if size <= 0.0 {
+        return;
+    }
+    let scale = 1.8 / size;
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    for point in segments.iter_mut().flatten() {
+        *point = [(point[0] - center[0]) * scale, (point[1] - center[1]) * scale];
+    }
+}
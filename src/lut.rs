@@ -0,0 +1,374 @@
+//! Color grading with 3D lookup tables loaded from `.cube` files.
+//!
+//! The LUT is applied by [`PostProcess`](crate::post_process::PostProcess) after all effects, as
+//! the last pass before the target unless color blindness is simulated.
+
+use std::fmt;
+use std::num::NonZeroU64;
+
+use bytemuck::{Pod, Zeroable};
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::preprocessor::ShaderDefines;
+use crate::renderer;
+use crate::shaders::{Shader, ShaderError};
+
+/// The largest LUT accepted, bigger ones are unusual and would take a lot of GPU memory.
+const MAX_SIZE: u32 = 256;
+
+/// A 3D lookup table mapping display colors to graded ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    /// Number of entries along each axis.
+    pub size: u32,
+    /// The input colors mapping to the first and last entries.
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    /// `size³` colors, red changing fastest, then green, then blue.
+    pub table: Vec<[f32; 3]>,
+}
+
+#[derive(Debug)]
+pub enum LutError {
+    /// A line that couldn't be understood, 1-based.
+    Syntax { line: usize, message: String },
+    /// There is no `LUT_3D_SIZE` line.
+    MissingSize,
+    /// The number of colors doesn't match the size.
+    WrongEntryCount { expected: usize, found: usize },
+    /// A channel of `DOMAIN_MAX` isn't above the same channel of `DOMAIN_MIN`.
+    EmptyDomain { min: [f32; 3], max: [f32; 3] },
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LutError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            LutError::MissingSize => f.write_str("missing LUT_3D_SIZE"),
+            LutError::WrongEntryCount { expected, found } => {
+                write!(f, "expected {expected} colors but found {found}")
+            }
+            LutError::EmptyDomain { min, max } => {
+                write!(f, "DOMAIN_MAX {max:?} must be above DOMAIN_MIN {min:?} on every channel")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LutError {}
+
+impl Lut {
+    /// Parses a `.cube` file, as written by Resolve, Photoshop and most grading tools.
+    pub fn from_cube(source: &str) -> Result<Lut, LutError> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| LutError::Syntax {
+                line: line_number,
+                message,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&keyword, arguments)) = words.split_first() else {
+                continue;
+            };
+
+            match keyword {
+                _ if keyword.starts_with('#') => {}
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let value = arguments
+                        .first()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|size| (2..=MAX_SIZE).contains(size))
+                        .ok_or_else(|| error(format!("LUT_3D_SIZE must be between 2 and {MAX_SIZE}")))?;
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => return Err(error("1D LUTs aren't supported".to_owned())),
+                "DOMAIN_MIN" => domain_min = to_rgb(&numbers(line_number, arguments, 3)?),
+                "DOMAIN_MAX" => domain_max = to_rgb(&numbers(line_number, arguments, 3)?),
+                // Resolve's shorthand for the same range on all channels
+                "LUT_3D_INPUT_RANGE" => {
+                    let range = numbers(line_number, arguments, 2)?;
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    tracing::debug!("Ignoring .cube keyword {keyword}");
+                }
+                _ => table.push(to_rgb(&numbers(line_number, &words, 3)?)),
+            }
+        }
+
+        let size = size.ok_or(LutError::MissingSize)?;
+        if domain_min.iter().zip(&domain_max).any(|(min, max)| max <= min) {
+            return Err(LutError::EmptyDomain {
+                min: domain_min,
+                max: domain_max,
+            });
+        }
+        let expected = (size as usize).pow(3);
+        if table.len() != expected {
+            return Err(LutError::WrongEntryCount {
+                expected,
+                found: table.len(),
+            });
+        }
+        Ok(Lut {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+}
+
+/// Parses exactly `count` numbers from `words`, found on `line`.
+fn numbers(line: usize, words: &[&str], count: usize) -> Result<Vec<f32>, LutError> {
+    let error = |message: String| LutError::Syntax { line, message };
+    if words.len() != count {
+        return Err(error(format!("expected {count} numbers")));
+    }
+    words
+        .iter()
+        .map(|word| {
+            word.parse()
+                .ok()
+                .filter(|number: &f32| number.is_finite())
+                .ok_or_else(|| error(format!("{word:?} is not a number")))
+        })
+        .collect()
+}
+
+fn to_rgb(numbers: &[f32]) -> [f32; 3] {
+    [numbers[0], numbers[1], numbers[2]]
+}
+
+/// Mirrors `Domain` in lut.wgsl.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+struct LutUniforms {
+    domain_min: [f32; 4],
+    domain_max: [f32; 4],
+}
+
+/// A [`Lut`] uploaded to the GPU, with the pass applying it.
+pub(crate) struct LutPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    view: wgpu::TextureView,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl LutPass {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, lut: &Lut, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lut_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<LutUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lut_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, Shader::Lut.embedded_source(), format)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        let size = wgpu::Extent3d {
+            width: lut.size,
+            height: lut.size,
+            depth_or_array_layers: lut.size,
+        };
+        // Half floats, as 8 bits would band smooth grades and curves between the entries
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lut"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texels: Vec<u16> = lut
+            .table
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .map(f32_to_f16)
+            .collect();
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(8 * lut.size),
+                rows_per_image: std::num::NonZeroU32::new(lut.size),
+            },
+            size,
+        );
+
+        let [min_r, min_g, min_b] = lut.domain_min;
+        let [max_r, max_g, max_b] = lut.domain_max;
+        let uniforms = LutUniforms {
+            domain_min: [min_r, min_g, min_b, 0.0],
+            domain_max: [max_r, max_g, max_b, 1.0],
+        };
+        let uniform_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("lut_uniforms"),
+                contents: bytemuck::bytes_of(&uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+
+        Self {
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            format,
+            pipeline,
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            uniform_buffer,
+        }
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || {
+            create_pipeline(device, &self.pipeline_layout, source, self.format)
+        })?;
+        Ok(())
+    }
+
+    /// Writes `input` graded to `output`.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        sampler: &wgpu::Sampler,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lut_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("lut"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// `value` as the nearest half float, the largest finite one if it's beyond them.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.clamp(-65504.0, 65504.0).to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exponent = (bits >> 23 & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        // Subnormal, or too small for even that
+        if exponent < -10 {
+            return sign;
+        }
+        let shift = (14 - exponent) as u32;
+        let mantissa = mantissa | 0x80_0000;
+        return sign | ((mantissa >> shift) + (mantissa >> (shift - 1) & 1)) as u16;
+    }
+    // Rounding up may carry into the exponent, which is still the nearest half float
+    sign | (((exponent as u32) << 10 | mantissa >> 13) + (mantissa >> 12 & 1)) as u16
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    source: &str,
+    format: wgpu::TextureFormat,
+) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let mut defines = ShaderDefines::new();
+    if !renderer::stores_encoded(format) {
+        defines = defines.with("LINEAR_TARGET");
+    }
+    let shader = Shader::Lut.create_module(device, source, &defines)?;
+
+    Ok(renderer::vertexless_pipeline(device, "lut", pipeline_layout, &shader, "fs_main", format, None))
+}
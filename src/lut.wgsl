@@ -0,0 +1,53 @@
+// Color grading with a 3D lookup table, the last pass before the target.
+//
+// LUTs map display (sRGB encoded) colors, so with LINEAR_TARGET, where the input and output hold
+// linear colors like sRGB targets decoding and encoding them on their own or float ones, the
+// lookup happens in between an encode and a decode.
+
+#include "post_process_common.wgsl"
+
+// `LutUniforms` in lut.rs
+struct Domain {
+    min: vec4<f32>,
+    max: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+@group(0) @binding(2)
+var lut: texture_3d<f32>;
+@group(0) @binding(3)
+var<uniform> domain: Domain;
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    let color = textureSample(input, input_sampler, in.uv);
+    var rgb = color.rgb;
+#ifdef LINEAR_TARGET
+    rgb = linear_to_srgb(rgb);
+#endif
+
+    // Sample at texel centers, so the domain's ends hit the first and last entries exactly
+    let size = vec3<f32>(textureDimensions(lut));
+    let normalized = clamp((rgb - domain.min.rgb) / (domain.max.rgb - domain.min.rgb), vec3<f32>(0.0), vec3<f32>(1.0));
+    rgb = textureSample(lut, input_sampler, normalized * (size - 1.0) / size + 0.5 / size).rgb;
+
+#ifdef LINEAR_TARGET
+    rgb = srgb_to_linear(rgb);
+#endif
+    return vec4<f32>(rgb, color.a);
+}
@@ -0,0 +1,70 @@
+// One region grow pass of the magic wand, see selection.rs.
+//
+// `MagicWand` mirrors `MagicWandUniforms` in selection.rs, keep them in sync when changing either side.
+
+struct MagicWand {
+    seed: vec2<u32>,
+    tolerance: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var canvas: texture_2d<f32>;
+@group(0) @binding(1)
+var previous: texture_2d<f32>;
+@group(0) @binding(2)
+var next: texture_storage_2d<r32float, write>;
+@group(0) @binding(3)
+var<uniform> wand: MagicWand;
+// Set to 1 when the pass selects another pixel
+@group(0) @binding(4)
+var<storage, read_write> changed: atomic<u32>;
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+// Unpremultiplied and sRGB encoded, how the tolerance is meant
+fn display_color(pixel: vec2<i32>) -> vec4<f32> {
+    let color = textureLoad(canvas, pixel, 0);
+    if (color.a <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(linear_to_srgb(color.rgb / color.a), color.a);
+}
+
+fn is_selected(pixel: vec2<i32>, size: vec2<i32>) -> bool {
+    if (any(pixel < vec2<i32>(0)) || any(pixel >= size)) {
+        return false;
+    }
+    return textureLoad(previous, pixel, 0).r > 0.5;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(canvas));
+    let pixel = vec2<i32>(id.xy);
+    if (any(pixel >= size)) {
+        return;
+    }
+
+    let was_selected = is_selected(pixel, size);
+    var selected = was_selected;
+    if (!selected) {
+        let difference = abs(display_color(pixel) - display_color(vec2<i32>(wand.seed)));
+        if (all(difference <= vec4<f32>(wand.tolerance))) {
+            selected = all(id.xy == wand.seed)
+                || is_selected(pixel + vec2<i32>(1, 0), size)
+                || is_selected(pixel - vec2<i32>(1, 0), size)
+                || is_selected(pixel + vec2<i32>(0, 1), size)
+                || is_selected(pixel - vec2<i32>(0, 1), size);
+        }
+    }
+
+    textureStore(next, pixel, vec4<f32>(f32(selected), 0.0, 0.0, 1.0));
+    if (selected != was_selected) {
+        atomicStore(&changed, 1u);
+    }
+}
@@ -1,137 +1,58 @@
-use std::sync::Arc;
-
-use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::Window,
-};
-
-use hellopaint_wgpu::surface::{GlobalSurface, HpSurface};
-use hellopaint_wgpu::surface_view::SurfaceRenderResources;
-
-async fn run(event_loop: EventLoop<()>, window: Window) {
-    let size = window.inner_size();
-
-    let instance = wgpu::Instance::default();
-
-    let surface = unsafe { instance.create_surface(&window) }.unwrap();
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            force_fallback_adapter: false,
-            // Request an adapter which can render to our surface
-            compatible_surface: Some(&surface),
-        })
-        .await
-        .expect("Failed to find an appropriate adapter");
-
-    // Create the logical device and command queue
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                limits: wgpu::Limits::downlevel_webgl2_defaults()
-                    .using_resolution(adapter.limits()),
-            },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
-
-    let device = Arc::new(device);
-    let queue = Arc::new(queue);
-
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchain_format = swapchain_capabilities.formats[0];
-
-
-    let mut config = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: swapchain_format,
-        width: size.width,
-        height: size.height,
-        present_mode: wgpu::PresentMode::Fifo,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: vec![],
+use std::rc::Rc;
+
+use winit::event_loop::EventLoopBuilder;
+#[cfg(target_arch = "wasm32")]
+use winit::window::Window;
+
+use hellopaint_wgpu::app;
+use hellopaint_wgpu::config::Config;
+#[cfg(not(target_arch = "wasm32"))]
+use hellopaint_wgpu::session::Session;
+
+/// Sizes the canvas to the browser window's viewport.
+///
+/// winit sets both the canvas backing size (physical pixels, using devicePixelRatio) and its CSS
+/// size (logical pixels), and emits `WindowEvent::Resized` if the size actually changed.
+#[cfg(target_arch = "wasm32")]
+fn fit_canvas_to_browser_window(window: &Window) {
+    let Some(browser_window) = web_sys::window() else {
+        return;
     };
-
-    surface.configure(&device, &config);
-
-    let global_surface = Arc::new(GlobalSurface::new(device.clone(), queue.clone()));
-
-    let hp_surface = HpSurface::new(global_surface);
-
-    let render_resources = SurfaceRenderResources::new(&device, hp_surface, swapchain_format);
-
-    event_loop.run(move |event, _, control_flow| {
-        // Have the closure take ownership of the resources.
-        // `event_loop.run` never returns, therefore we must do this to ensure
-        // the resources are properly cleaned up.
-        let _ = (&instance, &adapter);
-
-        *control_flow = ControlFlow::Wait;
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::Resized(size),
-                ..
-            } => {
-                // Reconfigure the surface with the new size
-                config.width = size.width;
-                config.height = size.height;
-                surface.configure(&device, &config);
-                // On macos the window needs to be redrawn manually after resizing
-                window.request_redraw();
-            }
-            Event::RedrawRequested(_) => {
-                let frame = surface
-                    .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut encoder =
-                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-                {
-                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: None,
-                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                                store: true,
-                            },
-                        })],
-                        depth_stencil_attachment: None,
-                    });
-
-                    render_resources.prepare(&*device, &*queue);
-                    render_resources.paint(&mut rpass);
-                }
-
-                queue.submit(Some(encoder.finish()));
-                frame.present();
-            }
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => *control_flow = ControlFlow::Exit,
-            _ => {}
-        }
-    });
+    let width = browser_window.inner_width().ok().and_then(|w| w.as_f64());
+    let height = browser_window.inner_height().ok().and_then(|h| h.as_f64());
+    if let (Some(width), Some(height)) = (width, height) {
+        window.set_inner_size(winit::dpi::LogicalSize::new(width, height));
+    }
 }
 
-
 fn main() {
-    let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{}", hellopaint_wgpu::config::USAGE);
+        std::process::exit(2);
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    if config.ask_document {
+        let last_document = config
+            .restore_session
+            .then(Session::load)
+            .flatten()
+            .and_then(|session| session.document)
+            .filter(|path| path.exists());
+        if let Some(path) = last_document {
+            config.open = Some(path.display().to_string());
+        }
+    }
+
+    let event_loop = EventLoopBuilder::with_user_event().build();
+    let window = Rc::new(winit::window::Window::new(&event_loop).unwrap());
     #[cfg(not(target_arch = "wasm32"))]
     {
         env_logger::init();
-        // Temporarily avoid srgb formats for the swapchain on the web
-        pollster::block_on(run(event_loop, window));
+        if let Err(err) = pollster::block_on(app::run(event_loop, window, config)) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
     }
     #[cfg(target_arch = "wasm32")]
     {
@@ -147,6 +68,36 @@ fn main() {
                     .ok()
             })
             .expect("couldn't append canvas to document body");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+
+        // Keep the canvas filling the browser window
+        fit_canvas_to_browser_window(&window);
+        {
+            use wasm_bindgen::JsCast;
+
+            let window = window.clone();
+            let on_resize = wasm_bindgen::closure::Closure::<dyn FnMut()>::new(move || {
+                fit_canvas_to_browser_window(&window);
+            });
+            web_sys::window()
+                .expect("no browser window")
+                .add_event_listener_with_callback("resize", on_resize.as_ref().unchecked_ref())
+                .expect("couldn't register resize listener");
+            // The listener lives for the rest of the page
+            on_resize.forget();
+        }
+
+        let query = web_sys::window()
+            .and_then(|win| win.location().search().ok())
+            .unwrap_or_default();
+        let config = Config::from_query(&query).unwrap_or_else(|err| {
+            tracing::error!("Ignoring URL parameters: {err}");
+            Config::default()
+        });
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = app::run(event_loop, window, config).await {
+                tracing::error!("{err}");
+            }
+        });
     }
 }
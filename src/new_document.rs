@@ -0,0 +1,274 @@
+//! Starting a document: the size of its canvas, picked from [`CanvasPreset`]s, and the
+//! [`Background`] it's painted on, both saved with it as its [`CanvasSetup`]. The app asks for
+//! them in the window at startup, natively offering one of the [`RecentDocuments`] to open instead,
+//! unless the command line, URL or an autosave already said what to start with. Ctrl+N asks again.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::persist;
+
+/// Square canvas sizes to start documents with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasPreset {
+    Small,
+    Medium,
+    Large,
+    Poster,
+}
+
+impl CanvasPreset {
+    pub const ALL: [CanvasPreset; 4] = [
+        CanvasPreset::Small,
+        CanvasPreset::Medium,
+        CanvasPreset::Large,
+        CanvasPreset::Poster,
+    ];
+
+    /// The preset called `name` in the configuration: `small`, `medium`, `large` or `poster`.
+    pub fn from_name(name: &str) -> Option<CanvasPreset> {
+        Self::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CanvasPreset::Small => "small",
+            CanvasPreset::Medium => "medium",
+            CanvasPreset::Large => "large",
+            CanvasPreset::Poster => "poster",
+        }
+    }
+
+    /// Width and height of the canvas in pixels. Devices that can't make textures this large get
+    /// the largest they can.
+    pub fn size(&self) -> u32 {
+        match self {
+            CanvasPreset::Small => 512,
+            CanvasPreset::Medium => 1024,
+            CanvasPreset::Large => 2048,
+            CanvasPreset::Poster => 4096,
+        }
+    }
+}
+
+/// What the canvas is cleared to before the dots are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    /// The bright green canvases always had before they could have another.
+    #[default]
+    Green,
+    White,
+    Black,
+    /// A warm off-white.
+    Paper,
+    /// Nothing, exported PNGs keep their alpha.
+    Transparent,
+}
+
+impl Background {
+    pub const ALL: [Background; 5] = [
+        Background::Green,
+        Background::White,
+        Background::Black,
+        Background::Paper,
+        Background::Transparent,
+    ];
+
+    /// The background called `name` in the configuration: `green`, `white`, `black`, `paper` or
+    /// `transparent`.
+    pub fn from_name(name: &str) -> Option<Background> {
+        Self::ALL.into_iter().find(|background| background.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::Green => "green",
+            Background::White => "white",
+            Background::Black => "black",
+            Background::Paper => "paper",
+            Background::Transparent => "transparent",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Background::Green => Color {
+                r: 0.0,
+                g: 1.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            Background::White => Color::from_srgb([1.0, 1.0, 1.0, 1.0]),
+            Background::Black => Color::from_srgb([0.0, 0.0, 0.0, 1.0]),
+            Background::Paper => Color::from_srgb8([245, 240, 228, 255]),
+            Background::Transparent => Color::from_srgb([0.0, 0.0, 0.0, 0.0]),
+        }
+    }
+
+    /// The color canvases are cleared to, premultiplied like their pixels.
+    pub(crate) fn clear_color(&self) -> wgpu::Color {
+        let Color { r, g, b, a } = self.color();
+        let [r, g, b, a] = [r * a, g * a, b * a, a].map(f64::from);
+        wgpu::Color { r, g, b, a }
+    }
+}
+
+/// The canvas a document is painted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanvasSetup {
+    /// Width and height in pixels.
+    pub size: u32,
+    #[serde(default)]
+    pub background: Background,
+}
+
+/// Saved as `hellopaint-recent-documents.json`.
+#[cfg(not(target_arch = "wasm32"))]
+const RECENT_DOCUMENTS: &str = "hellopaint-recent-documents";
+
+/// The documents last opened or saved, most recent first, kept between sessions in a file in
+/// the platform's config directory.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Default)]
+pub struct RecentDocuments {
+    paths: Vec<PathBuf>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RecentDocuments {
+    /// How many documents are remembered.
+    pub const CAPACITY: usize = 8;
+
+    /// The documents saved by the last session that still exist, or none if there are none.
+    pub fn load() -> Self {
+        let paths = match persist::read(RECENT_DOCUMENTS) {
+            Ok(Some(json)) => serde_json::from_str::<Vec<PathBuf>>(&json).unwrap_or_else(|err| {
+                tracing::warn!("Ignoring the saved recent documents: {err}");
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                tracing::warn!("Couldn't read the recent documents: {err}");
+                Vec::new()
+            }
+        };
+        Self {
+            paths: paths
+                .into_iter()
+                .filter(|path| path.exists())
+                .take(Self::CAPACITY)
+                .collect(),
+        }
+    }
+
+    /// Persists the list for the next session.
+    pub fn save(&self) {
+        let result = serde_json::to_string(&self.paths)
+            .map_err(|err| err.to_string())
+            .and_then(|json| persist::write(RECENT_DOCUMENTS, &json));
+        if let Err(err) = result {
+            tracing::error!("Couldn't save the recent documents: {err}");
+        }
+    }
+
+    /// Most recent first.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the front, dropping the oldest document once full. Kept absolute, so it
+    /// still opens when the app is started elsewhere.
+    pub fn push(&mut self, path: &Path) {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        self.paths.retain(|recent| *recent != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(Self::CAPACITY);
+    }
+}
+
+/// What the new-document dialog was answered with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogAnswer {
+    New(CanvasSetup),
+    Open(PathBuf),
+    /// Keep painting on the current document.
+    Cancel,
+}
+
+/// Asks in the window which canvas to start a new document on, or which of the recent documents
+/// to open, drawn by the app's [overlay](crate::overlay). Enter starts the new document, Escape
+/// keeps the current one.
+pub(crate) struct NewDocumentDialog {
+    canvas: CanvasSetup,
+    /// Offered besides the presets when the canvas starts out with none of their sizes.
+    custom_size: Option<u32>,
+    recent: Vec<PathBuf>,
+}
+
+impl NewDocumentDialog {
+    /// Starting with `canvas` selected. The web has no `recent` documents.
+    pub fn new(canvas: CanvasSetup, recent: &[PathBuf]) -> Self {
+        let is_preset = CanvasPreset::ALL.iter().any(|preset| preset.size() == canvas.size);
+        Self {
+            canvas,
+            custom_size: (!is_preset).then_some(canvas.size),
+            recent: recent.to_vec(),
+        }
+    }
+
+    /// Lays the dialog out for the next frame, returning the answer once there is one.
+    pub fn show(&mut self, context: &egui::Context) -> Option<DialogAnswer> {
+        let mut answer = None;
+        egui::Window::new("New document")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(context, |ui| {
+                ui.label("Canvas");
+                let custom = self.custom_size.map(|size| ("custom", size));
+                let presets = CanvasPreset::ALL.iter().map(|preset| (preset.name(), preset.size()));
+                for (name, size) in presets.chain(custom) {
+                    ui.radio_value(&mut self.canvas.size, size, format!("{name} {size} x {size}"));
+                }
+                ui.separator();
+                ui.label("Background");
+                ui.horizontal(|ui| {
+                    for background in Background::ALL {
+                        ui.radio_value(&mut self.canvas.background, background, background.name());
+                    }
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Create").clicked() {
+                        answer = Some(DialogAnswer::New(self.canvas));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        answer = Some(DialogAnswer::Cancel);
+                    }
+                });
+                if !self.recent.is_empty() {
+                    ui.separator();
+                    ui.label("Recent documents");
+                    for path in &self.recent {
+                        if ui.link(path.display().to_string()).clicked() {
+                            answer = Some(DialogAnswer::Open(path.clone()));
+                        }
+                    }
+                }
+            });
+        context.input(|input| {
+            if input.key_pressed(egui::Key::Enter) {
+                answer = Some(DialogAnswer::New(self.canvas));
+            } else if input.key_pressed(egui::Key::Escape) {
+                answer = Some(DialogAnswer::Cancel);
+            }
+        });
+        answer
+    }
+}
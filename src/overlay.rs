@@ -0,0 +1,101 @@
+//! UI drawn over the canvas in the window with egui, like the
+//! [new-document dialog](crate::new_document). Only shown while the app has something to ask, the
+//! canvas doesn't get the window's input then.
+
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// egui's side of the window: its input, and the pipeline drawing it into the frames.
+pub(crate) struct Overlay {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    /// What the last run laid out, until it's painted.
+    output: Option<egui::FullOutput>,
+}
+
+impl Overlay {
+    /// Draws into frames of `format` on `device`.
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        // Without a clipboard, which needs no display
+        let mut state = egui_winit::State::new_with_wayland_display(None);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+        state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        Self {
+            context: egui::Context::default(),
+            state,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            output: None,
+        }
+    }
+
+    /// Draws into frames of `format` on `device` instead, e.g. after the previous device was lost.
+    /// Starts over with a new context, which uploads the fonts again.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        self.state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        self.context = egui::Context::default();
+        self.renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+        self.output = None;
+    }
+
+    /// Takes `event` into the next run, returning whether that should be soon.
+    pub fn handle(&mut self, event: &WindowEvent<'_>) -> bool {
+        self.state.on_event(&self.context, event).repaint
+    }
+
+    /// Lays out the UI `ui` shows for the next frame, returning whether it's animating and wants
+    /// another one right after.
+    pub fn run(&mut self, window: &Window, ui: impl FnOnce(&egui::Context)) -> bool {
+        let input = self.state.take_egui_input(window);
+        let mut output = self.context.run(input, ui);
+        let platform_output = std::mem::take(&mut output.platform_output);
+        self.state.handle_platform_output(window, &self.context, platform_output);
+        let animating = output.repaint_after.is_zero();
+        // A frame that wasn't presented still has to upload its textures, the fonts among them
+        if let Some(unpainted) = self.output.take() {
+            let mut textures_delta = unpainted.textures_delta;
+            textures_delta.append(output.textures_delta);
+            output.textures_delta = textures_delta;
+        }
+        self.output = Some(output);
+        animating
+    }
+
+    /// Draws what the last [`Self::run`] laid out over `target`, if it wasn't drawn yet.
+    pub fn paint(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, target: &wgpu::Texture) {
+        let Some(output) = self.output.take() else {
+            return;
+        };
+        let _span = tracing::info_span!("overlay").entered();
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let primitives = self.context.tessellate(output.shapes);
+        let screen = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: [target.width(), target.height()],
+            pixels_per_point: self.context.pixels_per_point(),
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("overlay") });
+        let callbacks = self.renderer.update_buffers(device, queue, &mut encoder, &primitives, &screen);
+        {
+            let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("overlay"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer.render(&mut render_pass, &primitives, &screen);
+        }
+        queue.submit(callbacks.into_iter().chain(Some(encoder.finish())));
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Color palettes imported from GIMP (`.gpl`) and Adobe swatch exchange (`.ase`) files.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::color::Color;
+
+/// A named color of a palette, converted to linear like every [`Color`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Swatch {
+    /// Empty if the file didn't name it.
+    pub name: String,
+    pub color: Color,
+}
+
+/// Swatches the file kept together under a name.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SwatchGroup {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Palette {
+    pub name: Option<String>,
+    /// Swatches outside of any group.
+    pub swatches: Vec<Swatch>,
+    pub groups: Vec<SwatchGroup>,
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    /// The file name doesn't end in `.gpl` or `.ase`.
+    UnknownFormat(String),
+    /// A line of a `.gpl` file that couldn't be understood, 1-based.
+    Syntax { line: usize, message: String },
+    /// A broken `.ase` file.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::UnknownFormat(file_name) => {
+                write!(f, "{file_name:?} is not a .gpl or .ase palette")
+            }
+            PaletteError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            PaletteError::Invalid(message) => write!(f, "invalid .ase file: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl Palette {
+    /// Parses `data` in the format its `file_name` extension names.
+    pub fn load(file_name: &str, data: &[u8]) -> Result<Palette, PaletteError> {
+        let extension = file_name.rsplit_once('.').map(|(_, extension)| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("gpl") => Palette::from_gpl(&String::from_utf8_lossy(data)),
+            Some("ase") => Palette::from_ase(data),
+            _ => Err(PaletteError::UnknownFormat(file_name.to_owned())),
+        }
+    }
+
+    /// Parses a GIMP palette. They have no groups, every color ends up in [`Self::swatches`].
+    pub fn from_gpl(source: &str) -> Result<Palette, PaletteError> {
+        let mut lines = source.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == "GIMP Palette" => {}
+            _ => {
+                return Err(PaletteError::Syntax {
+                    line: 1,
+                    message: "expected \"GIMP Palette\"".to_owned(),
+                })
+            }
+        }
+
+        let mut palette = Palette::default();
+        for (index, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = Some(name.trim().to_owned());
+                continue;
+            }
+            if line.starts_with("Columns:") {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let mut channel = || -> Result<f32, PaletteError> {
+                let word = words.next().unwrap_or_default();
+                let value: u8 = word.parse().map_err(|_| PaletteError::Syntax {
+                    line: index + 1,
+                    message: format!("{word:?} is not a channel value in 0..=255"),
+                })?;
+                Ok(value as f32 / 255.0)
+            };
+            let color = Color::from_srgb([channel()?, channel()?, channel()?, 1.0]);
+            palette.swatches.push(Swatch {
+                name: words.collect::<Vec<_>>().join(" "),
+                color,
+            });
+        }
+        Ok(palette)
+    }
+
+    /// Parses an Adobe swatch exchange file, keeping its groups.
+    pub fn from_ase(data: &[u8]) -> Result<Palette, PaletteError> {
+        let mut reader = AseReader { data };
+        if reader.bytes(4)? != b"ASEF" {
+            return Err(PaletteError::Invalid("missing ASEF signature"));
+        }
+        let _version = (reader.u16()?, reader.u16()?);
+        let block_count = reader.u32()?;
+
+        let mut palette = Palette::default();
+        let mut group: Option<SwatchGroup> = None;
+        for _ in 0..block_count {
+            let block_type = reader.u16()?;
+            let length = reader.u32()? as usize;
+            let mut block = AseReader {
+                data: reader.bytes(length)?,
+            };
+            match block_type {
+                GROUP_START => {
+                    let name = block.string()?;
+                    if let Some(open) = group.replace(SwatchGroup {
+                        name,
+                        swatches: Vec::new(),
+                    }) {
+                        palette.groups.push(open);
+                    }
+                }
+                GROUP_END => palette.groups.extend(group.take()),
+                COLOR_ENTRY => {
+                    let swatch = Swatch {
+                        name: block.string()?,
+                        color: block.color()?,
+                    };
+                    match &mut group {
+                        Some(group) => group.swatches.push(swatch),
+                        None => palette.swatches.push(swatch),
+                    }
+                }
+                // Blocks this parser doesn't know are skipped, their length is known
+                _ => {}
+            }
+        }
+        palette.groups.extend(group);
+        Ok(palette)
+    }
+
+    /// Every swatch, ungrouped ones first, then group by group.
+    pub fn all_swatches(&self) -> impl Iterator<Item = &Swatch> {
+        self.swatches
+            .iter()
+            .chain(self.groups.iter().flat_map(|group| &group.swatches))
+    }
+}
+
+const GROUP_START: u16 = 0xC001;
+const GROUP_END: u16 = 0xC002;
+const COLOR_ENTRY: u16 = 0x0001;
+
+/// Reads the big endian values `.ase` files are made of.
+struct AseReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AseReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], PaletteError> {
+        if self.data.len() < count {
+            return Err(PaletteError::Invalid("the file ends early"));
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, PaletteError> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, PaletteError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, PaletteError> {
+        Ok(f32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// A length prefixed, null terminated UTF-16 string.
+    fn string(&mut self) -> Result<String, PaletteError> {
+        let length = self.u16()? as usize;
+        let units: Vec<u16> = self
+            .bytes(length * 2)?
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        String::from_utf16(&units).map_err(|_| PaletteError::Invalid("a name isn't valid UTF-16"))
+    }
+
+    /// A component of an RGB, Gray or CMYK color, in 0..=1.
+    fn unit(&mut self) -> Result<f32, PaletteError> {
+        let value = self.f32()?;
+        if !(0.0..=1.0).contains(&value) {
+            return Err(PaletteError::Invalid("a color is out of range"));
+        }
+        Ok(value)
+    }
+
+    fn color(&mut self) -> Result<Color, PaletteError> {
+        let color = match self.bytes(4)? {
+            b"RGB " => Color::from_srgb([self.unit()?, self.unit()?, self.unit()?, 1.0]),
+            b"Gray" => {
+                let gray = self.unit()?;
+                Color::from_srgb([gray, gray, gray, 1.0])
+            }
+            b"CMYK" => {
+                let [c, m, y, k] = [self.unit()?, self.unit()?, self.unit()?, self.unit()?];
+                // Without the printer's profile, the naive conversion is as good as any
+                let white = 1.0 - k;
+                Color::from_srgb([(1.0 - c) * white, (1.0 - m) * white, (1.0 - y) * white, 1.0])
+            }
+            b"LAB " => {
+                let [l, a, b] = [self.f32()?, self.f32()?, self.f32()?];
+                if ![l, a, b].iter().all(|value| value.is_finite()) {
+                    return Err(PaletteError::Invalid("a color is out of range"));
+                }
+                lab_to_color(l * 100.0, a, b)
+            }
+            _ => return Err(PaletteError::Invalid("unknown color model")),
+        };
+        // Global, spot or normal, which makes no difference here
+        let _color_type = self.u16()?;
+        Ok(color)
+    }
+}
+
+/// Converts CIE L*a*b* with a D50 white point, as Adobe uses, to linear sRGB.
+fn lab_to_color(l: f32, a: f32, b: f32) -> Color {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let inverse = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t.powi(3)
+        } else {
+            3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0)
+        }
+    };
+    let (x, y, z) = (0.964_22 * inverse(fx), inverse(fy), 0.825_21 * inverse(fz));
+    // XYZ (D50) to linear sRGB, including the Bradford adaptation to D65
+    let clamp = |value: f32| value.clamp(0.0, 1.0);
+    Color::new(
+        clamp(3.133_856 * x - 1.616_866_7 * y - 0.490_614_6 * z),
+        clamp(-0.978_768_4 * x + 1.916_141_5 * y + 0.033_454 * z),
+        clamp(0.071_945_3 * x - 0.228_991_4 * y + 1.405_242_7 * z),
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A block of `block_type` holding `body`.
+    fn block(block_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut block = block_type.to_be_bytes().to_vec();
+        block.extend((body.len() as u32).to_be_bytes());
+        block.extend(body);
+        block
+    }
+
+    /// A length prefixed, null terminated UTF-16 string.
+    fn string(text: &str) -> Vec<u8> {
+        let units: Vec<u16> = text.encode_utf16().chain([0]).collect();
+        let mut bytes = (units.len() as u16).to_be_bytes().to_vec();
+        bytes.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        bytes
+    }
+
+    /// A color entry named `name` of `model` with `values`.
+    fn color_entry(name: &str, model: &[u8; 4], values: &[f32]) -> Vec<u8> {
+        let mut body = string(name);
+        body.extend(model);
+        body.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        body.extend(0u16.to_be_bytes());
+        block(COLOR_ENTRY, &body)
+    }
+
+    fn ase(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = b"ASEF".to_vec();
+        file.extend([0, 1, 0, 0]);
+        file.extend((blocks.len() as u32).to_be_bytes());
+        file.extend(blocks.concat());
+        file
+    }
+
+    #[test]
+    fn reads_swatches_and_groups() {
+        let file = ase(&[
+            color_entry("red", b"RGB ", &[1.0, 0.0, 0.0]),
+            block(GROUP_START, &string("grays")),
+            color_entry("mid", b"Gray", &[0.5]),
+            color_entry("black", b"CMYK", &[0.0, 0.0, 0.0, 1.0]),
+            block(GROUP_END, &[]),
+        ]);
+        let palette = Palette::from_ase(&file).unwrap();
+        assert_eq!(palette.swatches, [Swatch { name: "red".to_owned(), color: Color::from_srgb([1.0, 0.0, 0.0, 1.0]) }]);
+        assert_eq!(palette.groups.len(), 1);
+        let group = &palette.groups[0];
+        assert_eq!(group.name, "grays");
+        assert_eq!(group.swatches[0].color, Color::from_srgb([0.5, 0.5, 0.5, 1.0]));
+        assert_eq!(group.swatches[1].color, Color::from_srgb([0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let file = ase(&[color_entry("red", b"RGB ", &[1.0, 0.0, 0.0])]);
+        for length in 0..file.len() {
+            assert!(Palette::from_ase(&file[..length]).is_err(), "{length} bytes");
+        }
+        // A block whose length runs past the end
+        let mut file = ase(&[block(COLOR_ENTRY, &[])]);
+        file[14..18].copy_from_slice(&100u32.to_be_bytes());
+        assert!(Palette::from_ase(&file).is_err());
+        // A color entry too short for its color
+        let mut short = color_entry("red", b"RGB ", &[1.0, 0.0]);
+        short.truncate(short.len() - 2);
+        assert!(Palette::from_ase(&ase(&[short])).is_err());
+    }
+
+    #[test]
+    fn rejects_colors_out_of_range() {
+        for (model, values) in [
+            (b"RGB ", &[5.0, 0.0, 0.0][..]),
+            (b"RGB ", &[0.0, -1.0, 0.0]),
+            (b"RGB ", &[0.0, 0.0, f32::NAN]),
+            (b"Gray", &[1.5]),
+            (b"CMYK", &[0.0, 0.0, 0.0, -0.5]),
+            (b"LAB ", &[0.5, f32::INFINITY, 0.0]),
+        ] {
+            let file = ase(&[color_entry("", model, values)]);
+            assert!(Palette::from_ase(&file).is_err(), "{model:?} {values:?}");
+        }
+    }
+}
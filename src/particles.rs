@@ -0,0 +1,293 @@
+//! Dots as particles, moved on the GPU every frame, see
+//! [`HpSurface::set_particles`](crate::surface::HpSurface::set_particles).
+//!
+//! A compute pass moves the dots in the instance buffers the dot pipelines draw from, so the CPU
+//! never sees where they went: [`HpSurface::dots`](crate::surface::HpSurface::dots) keeps where
+//! they started. Each dot has a velocity next to it, pulled on by [`Attractor`]s and slowed by
+//! drag, and bounces off the canvas edges.
+
+use std::num::NonZeroU64;
+
+use bytemuck::{Pod, Zeroable};
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::preprocessor::ShaderDefines;
+use crate::shaders::{Shader, ShaderError};
+
+/// The most attractors a simulation can have, the size of their array in particles.wgsl.
+pub const MAX_ATTRACTORS: usize = 4;
+
+/// The longest step simulated at once, so a stalled frame doesn't fling the dots off.
+const MAX_STEP_SECONDS: f32 = 1.0 / 20.0;
+
+/// `@workgroup_size` of particles.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Bytes of a dot's velocity, `vec2<f32>` in particles.wgsl.
+const VELOCITY_SIZE: u64 = 8;
+
+/// A point pulling dots towards it and swirling them around it, weaker with distance.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Attractor {
+    /// In canvas coordinates, -1..1 with y up.
+    pub position: [f32; 2],
+    /// Pull towards the position, negative to push away.
+    pub strength: f32,
+    /// Push around the position, counterclockwise, negative for clockwise.
+    pub swirl: f32,
+}
+
+/// How dots move as particles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleSettings {
+    /// How quickly dots slow down, the share of their velocity lost per second is about this for
+    /// small values.
+    pub drag: f32,
+    /// At most [`MAX_ATTRACTORS`], the rest are ignored.
+    pub attractors: Vec<Attractor>,
+}
+
+impl Default for ParticleSettings {
+    /// A vortex in the canvas center.
+    fn default() -> Self {
+        Self {
+            drag: 0.3,
+            attractors: vec![Attractor {
+                position: [0.0, 0.0],
+                strength: 0.02,
+                swirl: 0.05,
+            }],
+        }
+    }
+}
+
+/// `Particles` in particles.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ParticleUniforms {
+    attractors: [Attractor; MAX_ATTRACTORS],
+    attractor_count: u32,
+    drag: f32,
+    delta_seconds: f32,
+    _padding: f32,
+}
+
+/// Whether `device` can simulate particles.
+pub(crate) fn is_supported(device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension > 0 && limits.max_storage_buffers_per_shader_stage >= 2
+}
+
+pub(crate) struct ParticlePass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl ParticlePass {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage_entry = |binding, min_size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(min_size),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("particles_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<ParticleUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                storage_entry(1, std::mem::size_of::<crate::surface::Dot>() as u64),
+                storage_entry(2, VELOCITY_SIZE),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("particles_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, Shader::Particles.embedded_source(), &pipeline_layout)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        Self {
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || create_pipeline(device, source, &self.pipeline_layout))?;
+        Ok(())
+    }
+}
+
+/// The velocities of a surface's dots, one buffer per instance buffer.
+pub(crate) struct ParticleSystem {
+    settings: ParticleSettings,
+    uniforms: wgpu::Buffer,
+    velocities: Vec<wgpu::Buffer>,
+}
+
+impl ParticleSystem {
+    pub fn new(device: &wgpu::Device, settings: ParticleSettings) -> Self {
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particles_uniforms"),
+            size: std::mem::size_of::<ParticleUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            settings,
+            uniforms,
+            velocities: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &ParticleSettings {
+        &self.settings
+    }
+
+    /// Gives the dots of new instance buffers, holding `counts` dots each, velocities. The dots
+    /// and velocities of the `previous` instance buffers carry over to the new buffers in the same
+    /// place, so dots added to the end keep the others moving. Without `previous` all dots start
+    /// out at rest where the new buffers have them.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        previous: &[&wgpu::Buffer],
+        instances: &[&wgpu::Buffer],
+        counts: &[u32],
+    ) {
+        // New buffers start out zeroed, at rest
+        let velocities: Vec<wgpu::Buffer> = counts
+            .iter()
+            .map(|&count| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("particle_velocities"),
+                    size: count as u64 * VELOCITY_SIZE,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+
+        if !previous.is_empty() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("particles_resize"),
+            });
+            encoder.push_debug_group("carry particles over");
+            let pairs = previous.iter().zip(instances).zip(self.velocities.iter().zip(&velocities));
+            for ((old_instances, new_instances), (old_velocities, new_velocities)) in pairs {
+                let size = old_instances.size().min(new_instances.size());
+                encoder.copy_buffer_to_buffer(old_instances, 0, new_instances, 0, size);
+                let size = old_velocities.size().min(new_velocities.size());
+                encoder.copy_buffer_to_buffer(old_velocities, 0, new_velocities, 0, size);
+            }
+            encoder.pop_debug_group();
+            queue.submit(Some(encoder.finish()));
+        }
+        self.velocities = velocities;
+    }
+
+    /// Moves the dots in `instances` on by `delta_seconds`, at most [`MAX_STEP_SECONDS`].
+    pub fn step<'a>(
+        &self,
+        pass: &ParticlePass,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: impl Iterator<Item = &'a wgpu::Buffer>,
+        delta_seconds: f32,
+    ) {
+        let mut attractors = [Attractor::default(); MAX_ATTRACTORS];
+        let count = self.settings.attractors.len().min(MAX_ATTRACTORS);
+        attractors[..count].copy_from_slice(&self.settings.attractors[..count]);
+        let uniforms = ParticleUniforms {
+            attractors,
+            attractor_count: count as u32,
+            drag: self.settings.drag,
+            delta_seconds: delta_seconds.clamp(0.0, MAX_STEP_SECONDS),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+
+        let bind_groups: Vec<(wgpu::BindGroup, u32)> = instances
+            .zip(&self.velocities)
+            .map(|(instances, velocities)| {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("particles_bind_group"),
+                    layout: &pass.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.uniforms.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: instances.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: velocities.as_entire_binding(),
+                        },
+                    ],
+                });
+                (bind_group, (velocities.size() / VELOCITY_SIZE) as u32)
+            })
+            .collect();
+
+        let max_workgroups = device.limits().max_compute_workgroups_per_dimension;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("particles"),
+        });
+        encoder.push_debug_group("particles");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("particles"),
+            });
+            compute_pass.set_pipeline(&pass.pipeline);
+            for (bind_group, dots) in &bind_groups {
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                // The shader loops over the dots beyond what the workgroups cover
+                let workgroups = (dots + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+                compute_pass.dispatch_workgroups(workgroups.min(max_workgroups), 1, 1);
+            }
+        }
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, ShaderError> {
+    let shader = Shader::Particles.create_module(device, source, &ShaderDefines::new())?;
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("particles"),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: "cs_main",
+    }))
+}
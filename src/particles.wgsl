@@ -0,0 +1,63 @@
+// Moves dots as particles, see particles.rs.
+//
+// `Particles` mirrors `ParticleUniforms` in particles.rs, keep them in sync when changing either side.
+
+struct Attractor {
+    position: vec2<f32>,
+    strength: f32,
+    swirl: f32,
+}
+
+struct Particles {
+    attractors: array<Attractor, 4>,
+    attractorCount: u32,
+    drag: f32,
+    deltaSeconds: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> particles: Particles;
+// `Dot`s as laid out in Rust, 17 floats each, see dot_common.wgsl. Only their positions move.
+@group(0) @binding(1)
+var<storage, read_write> dots: array<f32>;
+@group(0) @binding(2)
+var<storage, read_write> velocities: array<vec2<f32>>;
+
+fn move_dot(index: u32) {
+    let base = index * 17u;
+    var position = vec2<f32>(dots[base], dots[base + 1u]);
+    var velocity = velocities[index];
+    let dt = particles.deltaSeconds;
+
+    var acceleration = vec2<f32>(0.0);
+    for (var i = 0u; i < particles.attractorCount; i = i + 1u) {
+        let attractor = particles.attractors[i];
+        let offset = attractor.position - position;
+        // Softened, keeping the pull finite for dots right on the attractor
+        let distance_squared = dot(offset, offset) + 0.01;
+        let direction = offset * inverseSqrt(distance_squared);
+        let around = vec2<f32>(direction.y, -direction.x);
+        acceleration = acceleration + (direction * attractor.strength + around * attractor.swirl) / distance_squared;
+    }
+
+    velocity = (velocity + acceleration * dt) * exp(-particles.drag * dt);
+    position = position + velocity * dt;
+
+    // Bounce off the canvas edges
+    let outside = abs(position) > vec2<f32>(1.0);
+    velocity = select(velocity, -velocity, outside);
+    position = clamp(position, vec2<f32>(-1.0), vec2<f32>(1.0));
+
+    dots[base] = position.x;
+    dots[base + 1u] = position.y;
+    velocities[index] = velocity;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) workgroups: vec3<u32>) {
+    let count = arrayLength(&velocities);
+    for (var index = id.x; index < count; index = index + workgroups.x * 64u) {
+        move_dot(index);
+    }
+}
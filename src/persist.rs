@@ -0,0 +1,57 @@
+//! Small JSON state kept between sessions, like the recent colors: in a file in the platform's
+//! config directory natively, like `~/.config/hellopaint` on Linux, in local storage on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// Where `name` is saved natively: in the platform's config directory, or the working directory
+/// where there is none.
+#[cfg(not(target_arch = "wasm32"))]
+fn path(name: &str) -> PathBuf {
+    let file = format!("{name}.json");
+    match directories_next::ProjectDirs::from("", "", "hellopaint") {
+        Some(dirs) => dirs.config_dir().join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+/// The JSON saved as `name`, `None` if nothing is.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read(name: &str) -> Result<Option<String>, String> {
+    match std::fs::read_to_string(path(name)) {
+        Ok(json) => Ok(Some(json)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Saves `json` as `name`, replacing what was saved before.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn write(name: &str, json: &str) -> Result<(), String> {
+    let path = path(name);
+    if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+    }
+    std::fs::write(path, json).map_err(|err| err.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, String> {
+    web_sys::window()
+        .ok_or("no window")?
+        .local_storage()
+        .map_err(|err| format!("{err:?}"))?
+        .ok_or_else(|| "local storage is unavailable".to_owned())
+}
+
+/// The JSON saved as `name`, `None` if nothing is.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn read(name: &str) -> Result<Option<String>, String> {
+    local_storage()?.get_item(name).map_err(|err| format!("{err:?}"))
+}
+
+/// Saves `json` as `name`, replacing what was saved before.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn write(name: &str, json: &str) -> Result<(), String> {
+    local_storage()?.set_item(name, json).map_err(|err| format!("{err:?}"))
+}
@@ -0,0 +1,44 @@
+//! Drawing your own content into the canvas.
+//!
+//! A [`RenderPassPlugin`] added with [`HpSurface::add_plugin`](crate::surface::HpSurface::add_plugin)
+//! is called every time the canvas renders: before the canvas pass, inside it after the dots, and
+//! after it, all in the same command encoder. Like the dots, what it draws ends up in the canvas
+//! texture and goes through the filters, the selection and the post processing.
+
+/// The canvas being rendered, for building compatible pipelines and sizing draws.
+#[derive(Debug, Clone, Copy)]
+pub struct CanvasInfo {
+    pub format: wgpu::TextureFormat,
+    pub size: wgpu::Extent3d,
+    /// Of the canvas pass, which pipelines drawing in [`RenderPassPlugin::draw`] need to match.
+    pub sample_count: u32,
+}
+
+/// Access to the frame for the passes a plugin adds before or after the canvas pass.
+pub struct PluginContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Submitted after the last plugin's [`RenderPassPlugin::after_pass`].
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The canvas texture, resolved if multisampled.
+    pub canvas_view: &'a wgpu::TextureView,
+    pub canvas: CanvasInfo,
+}
+
+/// Contributes draws to the canvas. Every method does nothing by default.
+///
+/// Plugins hold resources of the device they were made for, so
+/// [`HpSurface::recreate`](crate::surface::HpSurface::recreate) doesn't carry them over to a new
+/// device. Add them again instead.
+pub trait RenderPassPlugin: Send + Sync {
+    /// Before the canvas pass, which clears the canvas: a place for uploads and compute passes.
+    fn before_pass(&self, _context: &mut PluginContext<'_>) {}
+
+    /// Inside the canvas pass, after the dots. Set your own pipeline and bind groups, the dots'
+    /// may still be set. Canvases ordered by depth draw the dots in a pass of their own, this pass
+    /// comes after it without a depth attachment.
+    fn draw<'pass>(&'pass self, _render_pass: &mut wgpu::RenderPass<'pass>, _canvas: &CanvasInfo) {}
+
+    /// After the canvas pass, e.g. for passes reading the finished canvas or drawing over it.
+    fn after_pass(&self, _context: &mut PluginContext<'_>) {}
+}
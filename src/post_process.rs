@@ -0,0 +1,585 @@
+//! Full screen effects applied between the composited canvas and the final target.
+//!
+//! With effects configured, the canvas is composited into an intermediate texture instead of the
+//! target. The [`AdjustmentLayer`]s run first, as one pass, then each effect as its own pass,
+//! reading the previous result, followed by the color grading [`Lut`] if there is one, and the
+//! simulated [`ColorBlindness`] if set. The last pass writes into the target.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::adjustment_layer::{self, AdjustmentLayer};
+use crate::bloom::Bloom;
+use crate::lut::{Lut, LutPass};
+use crate::preprocessor::ShaderDefines;
+use crate::renderer;
+use crate::shaders::{Shader, ShaderError};
+use crate::surface::DisplayGamut;
+
+/// An effect of the post-processing chain, with its settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Darkens the image towards its corners.
+    Vignette {
+        /// How dark the corners get, 0..1.
+        strength: f32,
+        /// Distance from the center where darkening starts, 0 at the center and 1 at the corners.
+        radius: f32,
+    },
+    /// Adds noise that changes every frame.
+    FilmGrain { strength: f32 },
+    /// Pulls the red and blue channels apart towards the edges, like a cheap lens.
+    ChromaticAberration {
+        /// The offset at the edges, as a fraction of the image size.
+        offset: f32,
+    },
+    /// Makes bright parts glow into their surroundings.
+    Bloom {
+        /// How bright a color has to be to glow, 0..1.
+        threshold: f32,
+        /// How strongly the glow is added.
+        intensity: f32,
+    },
+}
+
+impl Effect {
+    /// The effect called `name` in the configuration, with its default settings.
+    pub fn from_name(name: &str) -> Option<Effect> {
+        match name {
+            "vignette" => Some(Effect::Vignette {
+                strength: 0.6,
+                radius: 0.4,
+            }),
+            "grain" => Some(Effect::FilmGrain { strength: 0.08 }),
+            "aberration" => Some(Effect::ChromaticAberration { offset: 0.01 }),
+            "bloom" => Some(Effect::Bloom {
+                threshold: 0.7,
+                intensity: 0.8,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The define selecting the effect in post_process.wgsl, also used as its label.
+    fn define(self) -> &'static str {
+        match self {
+            Effect::Vignette { .. } => "VIGNETTE",
+            Effect::FilmGrain { .. } => "FILM_GRAIN",
+            Effect::ChromaticAberration { .. } => "CHROMATIC_ABERRATION",
+            Effect::Bloom { .. } => "BLOOM",
+        }
+    }
+
+    fn params(self) -> [f32; 4] {
+        match self {
+            Effect::Vignette { strength, radius } => [strength, radius, 0.0, 0.0],
+            Effect::FilmGrain { strength } => [strength, 0.0, 0.0, 0.0],
+            Effect::ChromaticAberration { offset } => [offset, 0.0, 0.0, 0.0],
+            Effect::Bloom { threshold, intensity } => [threshold, intensity, 0.0, 0.0],
+        }
+    }
+}
+
+/// A color vision deficiency the display simulates, to check that a palette still reads for
+/// everyone. Like the other passes it only changes what's shown, never the painting or exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorBlindness {
+    /// Without red cones.
+    Protanopia,
+    /// Without green cones, the most common.
+    Deuteranopia,
+    /// Without blue cones.
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    pub const ALL: [ColorBlindness; 3] = [
+        ColorBlindness::Protanopia,
+        ColorBlindness::Deuteranopia,
+        ColorBlindness::Tritanopia,
+    ];
+
+    pub fn from_name(name: &str) -> Option<ColorBlindness> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorBlindness::Protanopia => "protanopia",
+            ColorBlindness::Deuteranopia => "deuteranopia",
+            ColorBlindness::Tritanopia => "tritanopia",
+        }
+    }
+}
+
+/// The define selecting the color blindness simulation in post_process.wgsl.
+const COLOR_BLINDNESS: &str = "COLOR_BLINDNESS";
+
+/// The define selecting the conversion from Display P3 to the target's primaries in
+/// post_process.wgsl, see [`DisplayGamut::converts_for`].
+const CONVERT_P3: &str = "CONVERT_P3";
+
+/// Mirrors `Effect` in post_process_common.wgsl.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+struct EffectUniforms {
+    params: [f32; 4],
+    seconds: f32,
+    _padding: [f32; 3],
+}
+
+impl EffectUniforms {
+    const SIZE: u64 = std::mem::size_of::<Self>() as u64;
+}
+
+/// The two textures effects ping-pong between.
+struct Targets {
+    size: wgpu::Extent3d,
+    views: [wgpu::TextureView; 2],
+}
+
+pub struct PostProcess {
+    adjustment_layers: Vec<AdjustmentLayer>,
+    /// The visible adjustment layers baked into a lookup table, `None` without any.
+    adjustments: Option<LutPass>,
+    effects: Vec<Effect>,
+    /// One per effect, in the same order.
+    uniform_buffers: Vec<wgpu::Buffer>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    format: wgpu::TextureFormat,
+    /// By effect define, built when an effect is first used.
+    pipelines: HashMap<&'static str, wgpu::RenderPipeline>,
+    source: Cow<'static, str>,
+    /// Bloom takes several passes of its own, set up when it's first used.
+    bloom: Option<Bloom>,
+    lut: Option<LutPass>,
+    /// The table `lut` was built from, kept to build it again on another device.
+    lut_table: Option<Lut>,
+    /// Skips the LUT without unloading it, to compare against the ungraded image.
+    lut_bypass: bool,
+    color_blindness: Option<ColorBlindness>,
+    /// The uniforms of the color blindness pass.
+    color_blindness_buffer: wgpu::Buffer,
+    /// The gamut the image is shown in, converted to the target's primaries after all other passes.
+    gamut: DisplayGamut,
+    /// Created on the first frame with effects, and again when the target size changes.
+    targets: Option<Targets>,
+}
+
+impl PostProcess {
+    /// An empty chain for targets of `format`.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_process_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(EffectUniforms::SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_process_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post_process_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let color_blindness_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("color_blindness_uniforms"),
+            size: EffectUniforms::SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            adjustment_layers: Vec::new(),
+            adjustments: None,
+            effects: Vec::new(),
+            uniform_buffers: Vec::new(),
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+            format,
+            pipelines: HashMap::new(),
+            source: Cow::Borrowed(Shader::PostProcess.embedded_source()),
+            bloom: None,
+            lut: None,
+            lut_table: None,
+            lut_bypass: false,
+            color_blindness: None,
+            color_blindness_buffer,
+            gamut: DisplayGamut::default(),
+            targets: None,
+        }
+    }
+
+    /// The same chain on another device and for targets of `format`, e.g. after the previous
+    /// device was lost.
+    pub fn recreate(&self, device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let mut post_process = Self::new(device, format);
+        post_process.source = self.source.clone();
+        post_process.set_adjustment_layers(device, queue, self.adjustment_layers.clone());
+        post_process.set_effects(device, self.effects.clone());
+        post_process.set_lut(device, queue, self.lut_table.as_ref());
+        post_process.lut_bypass = self.lut_bypass;
+        post_process.set_color_blindness(device, self.color_blindness);
+        post_process.set_gamut(device, self.gamut);
+        post_process
+    }
+
+    pub fn adjustment_layers(&self) -> &[AdjustmentLayer] {
+        &self.adjustment_layers
+    }
+
+    /// Replaces the adjustment layers, applied bottom (first) to top (last) before the effects.
+    pub fn set_adjustment_layers(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layers: Vec<AdjustmentLayer>) {
+        self.adjustments = adjustment_layer::bake(&layers).map(|lut| LutPass::new(device, queue, &lut, self.format));
+        self.adjustment_layers = layers;
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    /// Replaces the chain, the effects run in the given order.
+    pub fn set_effects(&mut self, device: &wgpu::Device, effects: Vec<Effect>) {
+        for effect in &effects {
+            if let Effect::Bloom { .. } = effect {
+                if self.bloom.is_none() {
+                    self.bloom = Some(Bloom::new(device, self.format, EffectUniforms::SIZE));
+                }
+            } else if !self.pipelines.contains_key(effect.define()) {
+                let pipeline = self
+                    .create_pipeline(device, &self.source, effect.define())
+                    .unwrap_or_else(|err| panic!("{err}"));
+                self.pipelines.insert(effect.define(), pipeline);
+            }
+        }
+        self.uniform_buffers = effects
+            .iter()
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("post_process_uniforms"),
+                    size: EffectUniforms::SIZE,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        self.effects = effects;
+    }
+
+    /// Grades the image with `lut` after the effects, or stops grading with `None`.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: Option<&Lut>) {
+        self.lut = lut.map(|lut| LutPass::new(device, queue, lut, self.format));
+        self.lut_table = lut.cloned();
+    }
+
+    pub fn lut_bypass(&self) -> bool {
+        self.lut_bypass
+    }
+
+    /// Skips the LUT while `bypass` is set, keeping it loaded.
+    pub fn set_lut_bypass(&mut self, bypass: bool) {
+        self.lut_bypass = bypass;
+    }
+
+    pub fn color_blindness(&self) -> Option<ColorBlindness> {
+        self.color_blindness
+    }
+
+    /// Simulates `color_blindness` as the last pass, or stops with `None`.
+    pub fn set_color_blindness(&mut self, device: &wgpu::Device, color_blindness: Option<ColorBlindness>) {
+        if color_blindness.is_some() && !self.pipelines.contains_key(COLOR_BLINDNESS) {
+            let pipeline = self
+                .create_pipeline(device, &self.source, COLOR_BLINDNESS)
+                .unwrap_or_else(|err| panic!("{err}"));
+            self.pipelines.insert(COLOR_BLINDNESS, pipeline);
+        }
+        self.color_blindness = color_blindness;
+    }
+
+    pub fn gamut(&self) -> DisplayGamut {
+        self.gamut
+    }
+
+    /// Shows the image in `gamut`, converting it to the primaries of the target as the very last
+    /// pass where those differ.
+    pub fn set_gamut(&mut self, device: &wgpu::Device, gamut: DisplayGamut) {
+        if gamut.converts_for(self.format) && !self.pipelines.contains_key(CONVERT_P3) {
+            let pipeline = self
+                .create_pipeline(device, &self.source, CONVERT_P3)
+                .unwrap_or_else(|err| panic!("{err}"));
+            self.pipelines.insert(CONVERT_P3, pipeline);
+        }
+        self.gamut = gamut;
+    }
+
+    /// Whether the last pass converts Display P3 to the target's primaries.
+    fn converts_gamut(&self) -> bool {
+        self.gamut.converts_for(self.format)
+    }
+
+    /// The LUT pass, unless there is none or it's bypassed.
+    fn active_lut(&self) -> Option<&LutPass> {
+        self.lut.as_ref().filter(|_| !self.lut_bypass)
+    }
+
+    /// The number of passes after compositing the canvas.
+    fn pass_count(&self) -> usize {
+        usize::from(self.adjustments.is_some())
+            + self.effects.len()
+            + usize::from(self.active_lut().is_some())
+            + usize::from(self.color_blindness.is_some())
+            + usize::from(self.converts_gamut())
+    }
+
+    /// Rebuilds the pipelines of `shader` from `source`, keeping the current ones if it doesn't
+    /// compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: String) -> Result<(), ShaderError> {
+        match (shader, &mut self.bloom) {
+            (Shader::Bloom, Some(bloom)) => return bloom.reload_shader(device, source),
+            (Shader::Lut, _) if self.lut.is_some() || self.adjustments.is_some() => {
+                for lut in self.lut.iter_mut().chain(&mut self.adjustments) {
+                    lut.reload_shader(device, &source)?;
+                }
+                return Ok(());
+            }
+            // Nothing to rebuild, but still report mistakes
+            (Shader::Bloom | Shader::Lut, _) => {
+                return shader.validate(&source, &ShaderDefines::new(), device.features()).map(|_| ());
+            }
+            _ => {}
+        }
+        let rebuilt = catch_validation_error(device, || {
+            self.pipelines
+                .keys()
+                .map(|&define| Ok((define, self.create_pipeline(device, &source, define)?)))
+                .collect::<Result<HashMap<_, _>, ShaderError>>()
+        })?;
+        self.pipelines = rebuilt;
+        self.source = Cow::Owned(source);
+        Ok(())
+    }
+
+    /// Sizes the intermediate textures for a target of `size` and updates the effect uniforms.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: wgpu::Extent3d, seconds: f32) {
+        if self.pass_count() == 0 {
+            self.targets = None;
+            return;
+        }
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            self.targets = Some(self.create_targets(device, size));
+        }
+        if let Some(bloom) = &mut self.bloom {
+            bloom.prepare(device, size);
+        }
+        for (effect, buffer) in self.effects.iter().zip(&self.uniform_buffers) {
+            let uniforms = EffectUniforms {
+                params: effect.params(),
+                seconds,
+                ..Default::default()
+            };
+            queue.write_buffer(buffer, 0, bytemuck::bytes_of(&uniforms));
+        }
+        if let Some(color_blindness) = self.color_blindness {
+            // Targets that aren't sRGB or float hold encoded colors, see `ENCODE_SRGB` in
+            // surface_view_shader.wgsl
+            let encoded = renderer::stores_encoded(self.format);
+            let uniforms = EffectUniforms {
+                params: [color_blindness as u32 as f32, f32::from(u8::from(encoded)), 0.0, 0.0],
+                seconds,
+                ..Default::default()
+            };
+            queue.write_buffer(&self.color_blindness_buffer, 0, bytemuck::bytes_of(&uniforms));
+        }
+    }
+
+    /// Where the canvas should be composited: the first intermediate texture, or `None` to draw
+    /// straight into the target when there are no passes.
+    pub fn input_view(&self) -> Option<&wgpu::TextureView> {
+        self.targets.as_ref().map(|targets| &targets.views[0])
+    }
+
+    /// Runs the adjustment layers, the effects, the LUT, the color blindness simulation and the
+    /// gamut conversion on what was composited into [`Self::input_view`], writing the result to
+    /// `target`.
+    pub fn encode(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let _span = tracing::info_span!("post_process").entered();
+        encoder.push_debug_group("post processing");
+        let pass_count = self.pass_count();
+        let input = |index: usize| &targets.views[index % 2];
+        let output = |index: usize| {
+            if index + 1 == pass_count {
+                target
+            } else {
+                &targets.views[(index + 1) % 2]
+            }
+        };
+
+        let mut index = 0;
+        if let Some(adjustments) = &self.adjustments {
+            let _span = tracing::info_span!("adjustment_layers").entered();
+            adjustments.encode(device, encoder, &self.sampler, input(index), output(index));
+            index += 1;
+        }
+
+        for (effect, buffer) in self.effects.iter().zip(&self.uniform_buffers) {
+            let _span = tracing::info_span!("effect", name = effect.define()).entered();
+            let (input, output) = (input(index), output(index));
+            index += 1;
+            if let (Effect::Bloom { .. }, Some(bloom)) = (effect, &self.bloom) {
+                bloom.encode(device, encoder, &self.sampler, buffer, input, output);
+                continue;
+            }
+            self.encode_pass(device, encoder, effect.define(), buffer, input, output);
+        }
+
+        if let Some(lut) = self.active_lut() {
+            let _span = tracing::info_span!("lut").entered();
+            lut.encode(device, encoder, &self.sampler, input(index), output(index));
+            index += 1;
+        }
+
+        if self.color_blindness.is_some() {
+            let _span = tracing::info_span!("color_blindness").entered();
+            let buffer = &self.color_blindness_buffer;
+            self.encode_pass(device, encoder, COLOR_BLINDNESS, buffer, input(index), output(index));
+            index += 1;
+        }
+
+        if self.converts_gamut() {
+            let _span = tracing::info_span!("convert_gamut").entered();
+            // The conversion reads no uniforms, but the layout still wants a buffer bound
+            let buffer = &self.color_blindness_buffer;
+            self.encode_pass(device, encoder, CONVERT_P3, buffer, input(index), output(index));
+        }
+        encoder.pop_debug_group();
+    }
+
+    /// Draws the post_process.wgsl variant of `define` from `input` into `output`.
+    fn encode_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        define: &'static str,
+        buffer: &wgpu::Buffer,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post_process_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(define),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipelines[define]);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, size: wgpu::Extent3d) -> Targets {
+        let create_view = || {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("post_process_target"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        Targets {
+            size,
+            views: [create_view(), create_view()],
+        }
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        source: &str,
+        define: &'static str,
+    ) -> Result<wgpu::RenderPipeline, ShaderError> {
+        let shader = Shader::PostProcess.create_module(device, source, &ShaderDefines::new().with(define))?;
+
+        Ok(renderer::vertexless_pipeline(
+            device,
+            define,
+            &self.pipeline_layout,
+            &shader,
+            "fs_main",
+            self.format,
+            None,
+        ))
+    }
+}
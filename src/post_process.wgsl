@@ -0,0 +1,93 @@
+// Full screen effects applied after the canvas has been composited, one per pass.
+// Each effect is a variant of this shader, selected with its define.
+
+#include "post_process_common.wgsl"
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> effect: Effect;
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var color = textureSample(input, input_sampler, in.uv);
+
+#ifdef VIGNETTE
+    // x: strength, y: distance from the center where darkening starts
+    let distance = length(in.uv - 0.5) * 1.4142;
+    color = vec4<f32>(color.rgb * (1.0 - effect.params.x * smoothstep(effect.params.y, 1.0, distance)), color.a);
+#endif
+
+#ifdef FILM_GRAIN
+    // x: strength
+    let pixel = floor(in.uv * vec2<f32>(textureDimensions(input)));
+    let noise = hash(pixel + fract(effect.seconds) * 1000.0) - 0.5;
+    color = vec4<f32>(color.rgb + noise * effect.params.x, color.a);
+#endif
+
+#ifdef CHROMATIC_ABERRATION
+    // x: how far red and blue are pulled apart at the edges, in texture coordinates
+    let offset = (in.uv - 0.5) * effect.params.x;
+    let red = textureSample(input, input_sampler, in.uv + offset).r;
+    let blue = textureSample(input, input_sampler, in.uv - offset).b;
+    color = vec4<f32>(red, color.g, blue, color.a);
+#endif
+
+#ifdef COLOR_BLINDNESS
+    // x: 0 protanopia, 1 deuteranopia, 2 tritanopia, y: 1 if the input is sRGB encoded
+    // The full severity matrices of Machado, Oliveira and Fernandes (2009), rows for linear RGB
+    var rows = mat3x3<f32>(
+        vec3<f32>(0.152286, 1.052583, -0.204868),
+        vec3<f32>(0.114503, 0.786281, 0.099216),
+        vec3<f32>(-0.003882, -0.048116, 1.051998),
+    );
+    if effect.params.x == 1.0 {
+        rows = mat3x3<f32>(
+            vec3<f32>(0.367322, 0.860646, -0.227968),
+            vec3<f32>(0.280085, 0.672501, 0.047413),
+            vec3<f32>(-0.011820, 0.042940, 0.968881),
+        );
+    } else if effect.params.x == 2.0 {
+        rows = mat3x3<f32>(
+            vec3<f32>(1.255528, -0.076749, -0.178779),
+            vec3<f32>(-0.078411, 0.930809, 0.146378),
+            vec3<f32>(0.004733, 0.691367, 0.303900),
+        );
+    }
+    let encoded = effect.params.y > 0.5;
+    let linear = select(color.rgb, srgb_to_linear(color.rgb), encoded);
+    let simulated = clamp(linear * rows, vec3<f32>(0.0), vec3<f32>(color.a));
+    color = vec4<f32>(select(simulated, linear_to_srgb(simulated), encoded), color.a);
+#endif
+
+#ifdef CONVERT_P3
+    // Linear Display P3 to the linear sRGB primaries of scRGB targets, unclamped: colors outside
+    // sRGB go below 0 and above 1
+    let p3_to_srgb = mat3x3<f32>(
+        vec3<f32>(1.2249401, -0.0420569, -0.0196376),
+        vec3<f32>(-0.2249404, 1.0420571, -0.0786361),
+        vec3<f32>(0.0, 0.0, 1.0982735),
+    );
+    color = vec4<f32>(p3_to_srgb * color.rgb, color.a);
+#endif
+
+    return color;
+}
@@ -0,0 +1,26 @@
+// Declarations shared by the post-processing shaders.
+//
+// `Effect` mirrors `EffectUniforms` in post_process.rs, keep them in sync when changing either side.
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct Effect {
+    params: vec4<f32>,
+    seconds: f32,
+    _padding1: f32,
+    _padding2: f32,
+    _padding3: f32,
+}
+
+// A triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOut {
+    var out: VertexOut;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,432 @@
+//! A small preprocessor for WGSL, so one shader file can cover several pipeline variants and
+//! shaders can share declarations.
+//!
+//! Supported directives, each on its own line:
+//!
+//! ```text
+//! #include "dot_common.wgsl"
+//! #define NAME
+//! #ifdef NAME / #ifndef NAME
+//! #else
+//! #endif
+//! #snippet NAME
+//! ```
+//!
+//! `#snippet` pastes WGSL passed in at runtime with [`ShaderDefines::with_snippet`], e.g. a user's
+//! brush function. Errors in it are reported with the snippet's name as the file.
+//!
+//! Each file is included at most once. A snippet pasting itself, directly or through another, is
+//! an error. Directive lines and skipped lines are blanked rather than
+//! removed, and every output line remembers where it came from, so errors point at the right
+//! file and line.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::shaders::{Shader, ShaderError};
+
+/// The names defined when preprocessing a shader, e.g. `ERASER`.
+///
+/// Ordered, so equal sets hash equally and can key pipeline caches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines {
+    names: BTreeSet<&'static str>,
+    snippets: BTreeMap<&'static str, Arc<str>>,
+}
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `name`, compiling in its `#ifdef` blocks.
+    pub fn with(mut self, name: &'static str) -> Self {
+        self.names.insert(name);
+        self
+    }
+
+    /// Defines `name` and has `#snippet name` paste `source`.
+    pub fn with_snippet(mut self, name: &'static str, source: Arc<str>) -> Self {
+        self.snippets.insert(name, source);
+        self.with(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.names.iter().copied()
+    }
+
+    pub fn snippet(&self, name: &str) -> Option<&Arc<str>> {
+        self.snippets.get(name)
+    }
+}
+
+/// WGSL with all directives resolved.
+#[derive(Debug, Clone)]
+pub struct Preprocessed {
+    pub source: String,
+    file: &'static str,
+    /// The file and 1-based line each line of `source` came from.
+    origins: Vec<(&'static str, u32)>,
+}
+
+impl Preprocessed {
+    /// An error at `location` in the preprocessed source, reported at its original file and line.
+    pub(crate) fn error(
+        &self,
+        location: Option<naga::SourceLocation>,
+        message: String,
+        label: &str,
+        notes: &[String],
+    ) -> ShaderError {
+        let Some(location) = location else {
+            return ShaderError::Invalid {
+                file: self.file,
+                location: None,
+                report: report(None, &message, label, notes),
+                message,
+            };
+        };
+        let index = location.line_number as usize - 1;
+        let (file, line) = self
+            .origins
+            .get(index)
+            .copied()
+            .unwrap_or((self.file, location.line_number));
+        let text = self.source.lines().nth(index).unwrap_or_default();
+        let quote = Quote {
+            file,
+            line,
+            column: location.line_position,
+            length: location.length as usize,
+            text,
+        };
+        ShaderError::Invalid {
+            file,
+            location: Some((line, location.line_position)),
+            report: report(Some(quote), &message, label, notes),
+            message,
+        }
+    }
+}
+
+/// Resolves the directives in `source`, the source of `shader`.
+pub fn preprocess(shader: Shader, source: &str, defines: &ShaderDefines) -> Result<Preprocessed, ShaderError> {
+    let mut preprocessor = Preprocessor {
+        defined: defines.iter().map(str::to_owned).collect(),
+        snippets: defines.snippets.clone(),
+        included: vec![shader],
+        expanding: Vec::new(),
+        output: Preprocessed {
+            source: String::with_capacity(source.len()),
+            file: shader.file_name(),
+            origins: Vec::new(),
+        },
+    };
+    preprocessor.process(shader.file_name(), source)?;
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor {
+    defined: HashSet<String>,
+    snippets: BTreeMap<&'static str, Arc<str>>,
+    included: Vec<Shader>,
+    /// The snippets being pasted, innermost last.
+    expanding: Vec<&'static str>,
+    output: Preprocessed,
+}
+
+/// An open `#ifdef` or `#ifndef` block.
+struct Condition {
+    /// Whether the lines in the current branch are kept.
+    active: bool,
+    seen_else: bool,
+    line: u32,
+    text: String,
+}
+
+impl Preprocessor {
+    fn process(&mut self, file: &'static str, source: &str) -> Result<(), ShaderError> {
+        let mut conditions: Vec<Condition> = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let error = |message: String| directive_error(file, line_number, line, message);
+            let active = conditions.iter().all(|condition| condition.active);
+
+            match directive(line) {
+                Some(("include", argument)) => {
+                    if active {
+                        let name = argument
+                            .strip_prefix('"')
+                            .and_then(|name| name.strip_suffix('"'))
+                            .ok_or_else(|| error("#include needs a quoted file name".to_owned()))?;
+                        let shader = Shader::from_file_name(name)
+                            .ok_or_else(|| error(format!("there is no shader {name:?} to include")))?;
+                        if !self.included.contains(&shader) {
+                            self.included.push(shader);
+                            self.process(shader.file_name(), &shader.include_source())?;
+                        }
+                        continue;
+                    }
+                }
+                Some(("snippet", name)) => {
+                    if active {
+                        let (&name, snippet) = self
+                            .snippets
+                            .get_key_value(name)
+                            .ok_or_else(|| error(format!("there is no snippet {name:?}")))?;
+                        if self.expanding.contains(&name) {
+                            return Err(error(format!("snippet {name:?} pastes itself")));
+                        }
+                        let snippet = snippet.clone();
+                        self.expanding.push(name);
+                        let result = self.process(name, &snippet);
+                        self.expanding.pop();
+                        result?;
+                        continue;
+                    }
+                }
+                Some((kind @ ("ifdef" | "ifndef"), name)) => {
+                    if name.is_empty() {
+                        return Err(error(format!("#{kind} needs a name")));
+                    }
+                    conditions.push(Condition {
+                        active: self.defined.contains(name) == (kind == "ifdef"),
+                        seen_else: false,
+                        line: line_number,
+                        text: line.to_owned(),
+                    });
+                }
+                Some(("else", _)) => {
+                    let condition = conditions
+                        .last_mut()
+                        .ok_or_else(|| error("#else without #ifdef".to_owned()))?;
+                    if condition.seen_else {
+                        return Err(error("second #else for the same #ifdef".to_owned()));
+                    }
+                    condition.active = !condition.active;
+                    condition.seen_else = true;
+                }
+                Some(("endif", _)) => {
+                    conditions
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef".to_owned()))?;
+                }
+                Some(("define", name)) => {
+                    if name.is_empty() {
+                        return Err(error("#define needs a name".to_owned()));
+                    }
+                    if active {
+                        self.defined.insert(name.to_owned());
+                    }
+                }
+                Some((other, _)) => return Err(error(format!("unknown directive #{other}"))),
+                None if active => {
+                    self.push_line(file, line_number, line);
+                    continue;
+                }
+                None => {}
+            }
+            self.push_line(file, line_number, "");
+        }
+
+        match conditions.pop() {
+            Some(condition) => Err(directive_error(
+                file,
+                condition.line,
+                &condition.text,
+                "#ifdef without #endif".to_owned(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn push_line(&mut self, file: &'static str, line_number: u32, text: &str) {
+        self.output.source.push_str(text);
+        self.output.source.push('\n');
+        self.output.origins.push((file, line_number));
+    }
+}
+
+/// Splits `#name argument` into its name and argument.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim_start().strip_prefix('#')?;
+    let (name, argument) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some((name, argument.trim()))
+}
+
+fn directive_error(file: &'static str, line: u32, text: &str, message: String) -> ShaderError {
+    let quote = Quote {
+        file,
+        line,
+        column: 1,
+        length: text.len(),
+        text,
+    };
+    ShaderError::Invalid {
+        file,
+        location: Some((line, 1)),
+        report: report(Some(quote), &message, "", &[]),
+        message,
+    }
+}
+
+/// The source line an error points at.
+struct Quote<'a> {
+    file: &'static str,
+    line: u32,
+    /// 1-based, in characters.
+    column: u32,
+    /// In bytes.
+    length: usize,
+    text: &'a str,
+}
+
+/// Formats an error like naga does, quoting the offending line:
+///
+/// ```text
+/// error: invalid field accessor `radiuss`
+///    ┌─ dot_shader.wgsl:30:22
+///    │
+/// 30 │     out.radius = dot.radiuss;
+///    │                      ^^^^^^^ invalid accessor
+/// ```
+fn report(quote: Option<Quote<'_>>, message: &str, label: &str, notes: &[String]) -> String {
+    let mut report = format!("error: {message}");
+    let mut gutter = String::new();
+    if let Some(quote) = quote {
+        gutter = " ".repeat(quote.line.to_string().len());
+        let skipped = quote.column.saturating_sub(1) as usize;
+        let mut bytes = 0;
+        let carets = quote
+            .text
+            .chars()
+            .skip(skipped)
+            .take_while(|c| {
+                bytes += c.len_utf8();
+                bytes <= quote.length
+            })
+            .count()
+            .max(1);
+        write!(
+            report,
+            "\n{gutter} ┌─ {}:{}:{}\n{gutter} │\n{} │ {}",
+            quote.file, quote.line, quote.column, quote.line, quote.text,
+        )
+        .unwrap();
+        let underline = format!("{gutter} │ {}{} {label}", " ".repeat(skipped), "^".repeat(carets));
+        write!(report, "\n{}", underline.trim_end()).unwrap();
+    }
+    for note in notes {
+        write!(report, "\n{gutter} = {note}").unwrap();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The kept lines of `source`, preprocessed as if it were dot_shader.wgsl.
+    fn kept(source: &str, defines: &ShaderDefines) -> Vec<String> {
+        let preprocessed = preprocess(Shader::Dot, source, defines).unwrap();
+        preprocessed
+            .source
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The message and line of the error preprocessing `source` as dot_shader.wgsl.
+    fn error(source: &str) -> (String, u32) {
+        match preprocess(Shader::Dot, source, &ShaderDefines::new()) {
+            Err(ShaderError::Invalid {
+                message,
+                location: Some((line, _)),
+                ..
+            }) => (message, line),
+            other => panic!("expected an error, got {other:?}"),
+        }
+    }
+
+    const NESTED: &str = "\
+#ifdef A
+a
+#ifdef B
+ab
+#else
+a_not_b
+#endif
+#else
+#ifndef B
+not_a_not_b
+#endif
+#endif
+all";
+
+    #[test]
+    fn nested_conditions() {
+        let defines = |names: &[&'static str]| names.iter().fold(ShaderDefines::new(), |defines, name| defines.with(name));
+        assert_eq!(kept(NESTED, &defines(&["A", "B"])), ["a", "ab", "all"]);
+        assert_eq!(kept(NESTED, &defines(&["A"])), ["a", "a_not_b", "all"]);
+        assert_eq!(kept(NESTED, &defines(&[])), ["not_a_not_b", "all"]);
+        assert_eq!(kept(NESTED, &defines(&["B"])), ["all"]);
+    }
+
+    #[test]
+    fn lines_keep_their_origin() {
+        let preprocessed = preprocess(Shader::Dot, NESTED, &ShaderDefines::new().with("A")).unwrap();
+        assert_eq!(preprocessed.source.lines().count(), NESTED.lines().count());
+        assert_eq!(preprocessed.source.lines().nth(5), Some("a_not_b"));
+        assert_eq!(preprocessed.origins[5], ("dot_shader.wgsl", 6));
+    }
+
+    #[test]
+    fn defines_apply_to_the_lines_after_them() {
+        let source = "#ifdef A\nbefore\n#endif\n#define A\n#ifdef A\nafter\n#endif";
+        assert_eq!(kept(source, &ShaderDefines::new()), ["after"]);
+        // Skipped defines don't count
+        let source = "#ifdef B\n#define A\n#endif\n#ifdef A\na\n#endif";
+        assert!(kept(source, &ShaderDefines::new()).is_empty());
+        assert_eq!(error("#define").0, "#define needs a name");
+    }
+
+    #[test]
+    fn malformed_directives() {
+        assert_eq!(error("x\n#pragma once"), ("unknown directive #pragma".to_owned(), 2));
+        assert_eq!(error("#ifdef A\nx\n"), ("#ifdef without #endif".to_owned(), 1));
+        assert_eq!(error("#ifdef A\n#ifdef B\n#endif"), ("#ifdef without #endif".to_owned(), 1));
+        assert_eq!(error("#endif"), ("#endif without #ifdef".to_owned(), 1));
+        assert_eq!(error("#else"), ("#else without #ifdef".to_owned(), 1));
+        assert_eq!(error("#ifdef A\n#else\n#else\n#endif").1, 3);
+        assert_eq!(error("#ifdef\n#endif").0, "#ifdef needs a name");
+        assert_eq!(error("#include dot_common.wgsl").0, "#include needs a quoted file name");
+        assert_eq!(error("#include \"missing.wgsl\"").0, "there is no shader \"missing.wgsl\" to include");
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let common = Shader::DotCommon.include_source();
+        let source = "#include \"dot_common.wgsl\"\n#include \"dot_common.wgsl\"\n#include \"dot_shader.wgsl\"";
+        let preprocessed = preprocess(Shader::Dot, source, &ShaderDefines::new()).unwrap();
+        // The first include is replaced by the file, the others and the shader itself left out
+        assert_eq!(preprocessed.source.lines().count(), common.lines().count());
+        let from_common = preprocessed.origins.iter().filter(|(file, _)| *file == "dot_common.wgsl").count();
+        assert_eq!(from_common, common.lines().count());
+    }
+
+    #[test]
+    fn snippets_are_pasted_but_not_into_themselves() {
+        let defines = ShaderDefines::new().with_snippet("BRUSH", "fn brush() {}".into());
+        assert_eq!(kept("#snippet BRUSH", &defines), ["fn brush() {}"]);
+        assert_eq!(error("#snippet BRUSH").0, "there is no snippet \"BRUSH\"");
+        let defines = ShaderDefines::new().with_snippet("LOOP", "#snippet LOOP".into());
+        assert!(preprocess(Shader::Dot, "#snippet LOOP", &defines).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Python bindings, built with the `python` feature, e.g. by `maturin build` with the
+//! pyproject.toml next to Cargo.toml. For scripting paintings and rendering them without a window:
+//!
+//! ```python
+//! import hellopaint_wgpu as hp
+//!
+//! document = hp.Document()
+//! document.add_stroke([(-0.5, 0.0, 1.0), (0.5, 0.0, 0.5)], radius=0.05, color=(0.2, 0.4, 1.0, 1.0))
+//! document.add_dot((0.0, 0.5), radius=0.1, hardness=1.0)
+//! png = hp.Renderer(canvas_size=1024).render_png(document)
+//! ```
+//!
+//! Positions are in canvas coordinates, -1..1 with y up, and colors straight alpha sRGB 0..1.
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::color::Color;
+use crate::document::{self, StrokeInfo};
+use crate::export::{self, ExportOptions};
+use crate::raster::HeadlessCanvas;
+use crate::stroke::{Brush, Stroke};
+use crate::surface::Dot;
+
+/// The dots of a painting.
+#[pyclass]
+#[derive(Default)]
+struct Document {
+    document: document::Document,
+}
+
+#[pymethods]
+impl Document {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let document = document::Document::from_json(json).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(Self { document })
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        self.document.to_json().map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    #[pyo3(signature = (position, radius = 0.02, hardness = 0.5, color = (1.0, 0.0, 0.0, 1.0)))]
+    fn add_dot(
+        &mut self,
+        position: (f32, f32),
+        radius: f32,
+        hardness: f32,
+        color: (f32, f32, f32, f32),
+    ) -> PyResult<()> {
+        let (r, g, b, a) = color;
+        let dot = Dot::builder()
+            .position([position.0, position.1])
+            .radius(radius)
+            .hardness(hardness)
+            .color(Color::from_srgb([r, g, b, a]))
+            .build()
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.document.push_dot(dot);
+        Ok(())
+    }
+
+    /// Paints a stroke through `(x, y, pressure)` points with a brush of the given settings, see
+    /// [`Brush`].
+    #[pyo3(signature = (points, radius = 0.02, hardness = 0.5, color = (1.0, 0.0, 0.0, 1.0), spacing = 0.25))]
+    fn add_stroke(
+        &mut self,
+        points: Vec<(f32, f32, f32)>,
+        radius: f32,
+        hardness: f32,
+        color: (f32, f32, f32, f32),
+        spacing: f32,
+    ) -> PyResult<()> {
+        let (r, g, b, a) = color;
+        let brush = Brush {
+            radius,
+            hardness,
+            color: Color::from_srgb([r, g, b, a]),
+            spacing,
+            ..Brush::default()
+        }
+        .validated()
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        if let Some(point) = points.iter().find(|(x, y, pressure)| ![x, y, pressure].iter().all(|v| v.is_finite())) {
+            return Err(PyValueError::new_err(format!("stroke points need finite numbers, not {point:?}")));
+        }
+        let mut stroke = Stroke::new(brush);
+        for (x, y, pressure) in points {
+            stroke.add_point([x, y], pressure);
+        }
+        self.document.add_dots(StrokeInfo::painted(brush, 0.0), stroke.finish());
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.document.strokes.clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.document.dot_count()
+    }
+}
+
+/// A headless device with a square canvas to render documents on, or the CPU where there's no
+/// adapter.
+#[pyclass]
+struct Renderer {
+    canvas: HeadlessCanvas,
+}
+
+#[pymethods]
+impl Renderer {
+    #[new]
+    #[pyo3(signature = (canvas_size = 1024))]
+    fn new(canvas_size: u32) -> PyResult<Self> {
+        let canvas = HeadlessCanvas::new(canvas_size).map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        Ok(Self { canvas })
+    }
+
+    /// Renders `document` and encodes the canvas as PNG.
+    fn render_png<'py>(&mut self, py: Python<'py>, document: &Document) -> PyResult<&'py PyBytes> {
+        self.canvas.set_dots(document.document.dots());
+        self.canvas.render().map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        let size = self.canvas.size();
+        let png = self
+            .canvas
+            .straight_rgba8()
+            .and_then(|rgba| export::encode_png(size, size, &rgba, ExportOptions::default().gamut))
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        Ok(PyBytes::new(py, &png))
+    }
+}
+
+#[pymodule]
+fn hellopaint_wgpu(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<Document>()?;
+    module.add_class::<Renderer>()?;
+    Ok(())
+}
@@ -0,0 +1,346 @@
+//! A software rasterizer drawing dots with the math of dot_shader.wgsl, for checking what the GPU
+//! draws in tests, and for rendering without an adapter, e.g. on CI machines and in containers
+//! without a GPU.
+//!
+//! A [`CpuCanvas`] rounds its pixels to the canvas texture's precision after every dot like the
+//! GPU does, so the two agree up to how they rasterize edges and round. It's slow, going pixel by
+//! pixel, and draws the default dot, ignoring snippets set with
+//! [`GlobalSurface::set_dot_snippet`](crate::surface::GlobalSurface::set_dot_snippet). Noisy dots
+//! hash with `sin`, whose precision differs between GPUs, so their stamps only roughly match.
+//! Animated dots are drawn as they are at the time set with [`CpuCanvas::set_seconds`]. Dots are
+//! drawn in the order they were added, whatever their depth.
+
+use crate::color::{linear_to_srgb, srgb_to_linear, Color};
+use crate::surface::{BlendPreset, CanvasColorSpace, Dot, DotNoise};
+
+/// What canvases are cleared to before their dots are drawn, like [`HpSurface::render`] does.
+///
+/// [`HpSurface::render`]: crate::surface::HpSurface::render
+const CLEAR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+
+/// A square canvas drawn on the CPU, like an [`HpSurface`](crate::surface::HpSurface) without
+/// filters.
+#[derive(Debug, Clone)]
+pub struct CpuCanvas {
+    size: u32,
+    color_space: CanvasColorSpace,
+    blend: BlendPreset,
+    opacity: f32,
+    seconds: f32,
+    dots: Vec<Dot>,
+    /// Premultiplied linear color, rows from the top.
+    pixels: Vec<[f32; 4]>,
+}
+
+impl CpuCanvas {
+    /// A canvas of `size` pixels storing color like a GPU canvas of `color_space`.
+    pub fn new(size: u32, color_space: CanvasColorSpace) -> Self {
+        Self {
+            size,
+            color_space,
+            blend: BlendPreset::default(),
+            opacity: 1.0,
+            seconds: 0.0,
+            dots: Vec::new(),
+            pixels: vec![CLEAR; (size * size) as usize],
+        }
+    }
+
+    /// Blends the dots like a [`GlobalSurface`](crate::surface::GlobalSurface) built with `blend`.
+    pub fn with_blend(mut self, blend: BlendPreset) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Multiplies the alpha of every dot, like
+    /// [`GlobalSurface::set_opacity`](crate::surface::GlobalSurface::set_opacity).
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+
+    /// Moves the clock of animated dots, like
+    /// [`GlobalSurface::set_seconds`](crate::surface::GlobalSurface::set_seconds).
+    pub fn set_seconds(&mut self, seconds: f32) {
+        self.seconds = seconds;
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn dots(&self) -> &[Dot] {
+        &self.dots
+    }
+
+    pub fn add_dots(&mut self, dots: &[Dot]) {
+        self.dots.extend_from_slice(dots);
+    }
+
+    pub fn set_dots(&mut self, dots: Vec<Dot>) {
+        self.dots = dots;
+    }
+
+    /// Clears the canvas and draws the dots, in order.
+    pub fn render(&mut self) {
+        self.pixels.fill(CLEAR);
+        for index in 0..self.dots.len() {
+            self.draw(self.dots[index].animated(self.seconds));
+        }
+    }
+
+    /// The pixels as [`TextureReadback::into_rgba8`] returns the GPU's: premultiplied and sRGB
+    /// encoded, rows from the top.
+    ///
+    /// [`TextureReadback::into_rgba8`]: crate::export::TextureReadback::into_rgba8
+    pub fn rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&[r, g, b, a]| [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a])
+            .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    }
+
+    /// The color of pixel `x`, `y` from the top left, what an eyedropper picks there.
+    pub fn color_at(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.size || y >= self.size {
+            return None;
+        }
+        let [r, g, b, a] = self.pixels[(y * self.size + x) as usize];
+        if a <= 0.0 {
+            return Some(Color::new(0.0, 0.0, 0.0, 0.0));
+        }
+        Some(Color::new(r / a, g / a, b / a, a))
+    }
+
+    /// Draws `dot` over the pixels whose centers its quad covers.
+    fn draw(&mut self, dot: Dot) {
+        let size = self.size as f32;
+        let [center_x, center_y] = dot.position();
+        let radius = dot.radius();
+        // The quad in pixels, y flipped from canvas coordinates to rows from the top
+        let to_pixel = |clip: f32| (clip + 1.0) / 2.0 * size;
+        let left = to_pixel(center_x - radius).max(0.0);
+        let right = to_pixel(center_x + radius).min(size);
+        let top = to_pixel(-center_y - radius).max(0.0);
+        let bottom = to_pixel(-center_y + radius).min(size);
+
+        // `vertex.position` of the quad interpolated to a pixel, 0..1 with y up
+        let stamp_at = |x: i64, y: i64| {
+            let clip = [(x as f32 + 0.5) / size * 2.0 - 1.0, 1.0 - (y as f32 + 0.5) / size * 2.0];
+            [(clip[0] - center_x) / (2.0 * radius) + 0.5, (clip[1] - center_y) / (2.0 * radius) + 0.5]
+        };
+        let distance_at = |x: i64, y: i64| {
+            let [u, v] = stamp_at(x, y);
+            ((u - 0.5) * (u - 0.5) + (v - 0.5) * (v - 0.5)) * 2.0
+        };
+        for y in (top - 0.5).ceil() as i64..(bottom - 0.5).ceil() as i64 {
+            for x in (left - 0.5).ceil() as i64..(right - 0.5).ceil() as i64 {
+                // `fwidth(distance)`: GPUs take differences across the 2x2 quad of pixels they
+                // shade together, pixels beyond the dot's quad included
+                let [quad_x, quad_y] = [x & !1, y & !1];
+                let corner = distance_at(quad_x, quad_y);
+                let edge = (distance_at(quad_x + 1, quad_y) - corner).abs() + (distance_at(quad_x, quad_y + 1) - corner).abs();
+                let src = self.shade(&dot, stamp_at(x, y), edge);
+                let index = (y as u32 * self.size + x as u32) as usize;
+                self.pixels[index] = self.store(blend(self.blend, src, self.pixels[index]));
+            }
+        }
+    }
+
+    /// `fs_main` of dot_shader.wgsl: the premultiplied color of `dot` at `stamp`, with `edge`
+    /// what `fwidth` has for the distance from its center there.
+    fn shade(&self, dot: &Dot, stamp: [f32; 2], edge: f32) -> [f32; 4] {
+        let texture = stamp_texture(stamp, dot.noise());
+        let offset = [stamp[0] - 0.5, stamp[1] - 0.5];
+        let distance = (offset[0] * offset[0] + offset[1] * offset[1]) * 2.0;
+        let circle = 1.0 - smoothstep((dot.hardness() / 2.0).min(0.5 - edge), 0.5, distance);
+        let color = dot.color();
+        let alpha = color.a * circle * texture * self.opacity;
+        [color.r * alpha, color.g * alpha, color.b * alpha, alpha]
+    }
+
+    /// Rounds `pixel` to what the canvas texture can hold.
+    fn store(&self, pixel: [f32; 4]) -> [f32; 4] {
+        match self.color_space {
+            CanvasColorSpace::Srgb => {
+                let quantize = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() / 255.0;
+                let [r, g, b, a] = pixel;
+                let encoded = |value: f32| srgb_to_linear(quantize(linear_to_srgb(value)));
+                [encoded(r), encoded(g), encoded(b), quantize(a)]
+            }
+            // Half floats round much finer than the tolerances this is compared with
+            CanvasColorSpace::Linear => pixel,
+        }
+    }
+}
+
+/// Blends premultiplied `src` over `dst` with the blend state of `preset`.
+fn blend(preset: BlendPreset, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    let src_alpha = src[3];
+    let over = |s: f32, d: f32| s + d * (1.0 - src_alpha);
+    let mut out = [0.0; 4];
+    for channel in 0..3 {
+        let (s, d) = (src[channel], dst[channel]);
+        out[channel] = match preset {
+            BlendPreset::Over => over(s, d),
+            BlendPreset::Additive => s + d,
+            BlendPreset::Multiply => s * d + d * (1.0 - src_alpha),
+            BlendPreset::Screen => s + d * (1.0 - s),
+        };
+    }
+    out[3] = match preset {
+        BlendPreset::Additive => src[3] + dst[3],
+        _ => over(src[3], dst[3]),
+    };
+    out
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn fract(value: f32) -> f32 {
+    value - value.floor()
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+fn hash2([x, y]: [f32; 2]) -> f32 {
+    fract((x * 127.1 + y * 311.7).sin() * 43758.547)
+}
+
+fn value_noise([x, y]: [f32; 2]) -> f32 {
+    let cell = [x.floor(), y.floor()];
+    let f = [fract(x), fract(y)];
+    let u = f.map(|f| f * f * (3.0 - 2.0 * f));
+    let a = hash2(cell);
+    let b = hash2([cell[0] + 1.0, cell[1]]);
+    let c = hash2([cell[0], cell[1] + 1.0]);
+    let d = hash2([cell[0] + 1.0, cell[1] + 1.0]);
+    mix(mix(a, b, u[0]), mix(c, d, u[0]), u[1])
+}
+
+fn permute(x: [f32; 3]) -> [f32; 3] {
+    x.map(|x| ((x * 34.0 + 1.0) * x) % 289.0)
+}
+
+fn dot2(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+/// `simplex_noise` of dot_shader.wgsl, after Ashima Arts' webgl-noise.
+fn simplex_noise(v: [f32; 2]) -> f32 {
+    const C: [f32; 4] = [0.211_324_87, 0.366_025_42, -0.577_350_26, 0.024_390_243];
+    let skew = dot2(v, [C[1], C[1]]);
+    let mut i = [(v[0] + skew).floor(), (v[1] + skew).floor()];
+    let unskew = dot2(i, [C[0], C[0]]);
+    let x0 = [v[0] - i[0] + unskew, v[1] - i[1] + unskew];
+    let i1 = if x0[0] > x0[1] { [1.0, 0.0] } else { [0.0, 1.0] };
+    let x12 = [x0[0] + C[0] - i1[0], x0[1] + C[0] - i1[1], x0[0] + C[2], x0[1] + C[2]];
+    i = i.map(|i| i % 289.0);
+    let inner = permute([i[1], i[1] + i1[1], i[1] + 1.0]);
+    let p = permute([inner[0] + i[0], inner[1] + i[0] + i1[0], inner[2] + i[0] + 1.0]);
+    let mut m = [
+        (0.5 - dot2(x0, x0)).max(0.0),
+        (0.5 - dot2([x12[0], x12[1]], [x12[0], x12[1]])).max(0.0),
+        (0.5 - dot2([x12[2], x12[3]], [x12[2], x12[3]])).max(0.0),
+    ];
+    m = m.map(|m| m * m * m * m);
+    let x = p.map(|p| 2.0 * fract(p * C[3]) - 1.0);
+    let h = x.map(|x| x.abs() - 0.5);
+    let a0 = [0, 1, 2].map(|index| x[index] - (x[index] + 0.5).floor());
+    for index in 0..3 {
+        m[index] *= 1.792_842_9 - 0.853_734_73 * (a0[index] * a0[index] + h[index] * h[index]);
+    }
+    let g = [
+        a0[0] * x0[0] + h[0] * x0[1],
+        a0[1] * x12[0] + h[1] * x12[1],
+        a0[2] * x12[2] + h[2] * x12[3],
+    ];
+    130.0 * (m[0] * g[0] + m[1] * g[1] + m[2] * g[2])
+}
+
+/// `stamp_texture` of dot_shader.wgsl: how much of the stamp is left at `position`.
+fn stamp_texture(position: [f32; 2], noise: DotNoise) -> f32 {
+    let p = [
+        position[0] * noise.scale + noise.seed * 17.13,
+        position[1] * noise.scale + noise.seed * 31.71,
+    ];
+    let shape = simplex_noise(p) * 0.5 + 0.5;
+    let grain = value_noise([p[0] * 4.0, p[1] * 4.0]);
+    mix(1.0, shape * 0.75 + grain * 0.25, noise.strength)
+}
+
+/// A canvas without a window for the bindings: on a device of its own, or on the CPU where
+/// there's no adapter.
+#[cfg(all(any(feature = "python", feature = "ffi"), not(target_arch = "wasm32")))]
+pub(crate) enum HeadlessCanvas {
+    Gpu(Box<crate::surface::HpSurface>),
+    Cpu(CpuCanvas),
+}
+
+#[cfg(all(any(feature = "python", feature = "ffi"), not(target_arch = "wasm32")))]
+impl HeadlessCanvas {
+    /// A canvas of `size` pixels, drawn on the CPU if there's no adapter.
+    pub fn new(size: u32) -> Result<Self, crate::error::Error> {
+        match crate::surface::HpSurface::headless(size) {
+            Ok(surface) => Ok(Self::Gpu(Box::new(surface))),
+            Err(crate::error::Error::NoAdapter) => {
+                tracing::warn!("No graphics adapter, rendering on the CPU");
+                Ok(Self::Cpu(CpuCanvas::new(size, CanvasColorSpace::Srgb)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Gpu(surface) => surface.global.texture_desc.size.width,
+            Self::Cpu(canvas) => canvas.size(),
+        }
+    }
+
+    #[cfg(feature = "ffi")]
+    pub fn add_dots(&mut self, dots: &[Dot]) {
+        match self {
+            Self::Gpu(surface) => surface.add_dots(dots),
+            Self::Cpu(canvas) => canvas.add_dots(dots),
+        }
+    }
+
+    pub fn set_dots(&mut self, dots: Vec<Dot>) {
+        match self {
+            Self::Gpu(surface) => surface.set_dots(dots),
+            Self::Cpu(canvas) => canvas.set_dots(dots),
+        }
+    }
+
+    /// Renders the dots, which only fails on the GPU, see
+    /// [`HpSurface::render`](crate::surface::HpSurface::render).
+    pub fn render(&mut self) -> Result<(), crate::error::Error> {
+        match self {
+            Self::Gpu(surface) => surface.render(),
+            Self::Cpu(canvas) => {
+                canvas.render();
+                Ok(())
+            }
+        }
+    }
+
+    /// The canvas as last rendered, as straight alpha sRGB RGBA8 rows from the top.
+    pub fn straight_rgba8(&self) -> Result<Vec<u8>, crate::export::ExportError> {
+        let (mut rgba, srgb) = match self {
+            Self::Gpu(surface) => {
+                let readback = surface.readback();
+                let srgb = readback.is_srgb();
+                (pollster::block_on(readback.into_rgba8(crate::export::Dither::None))?, srgb)
+            }
+            Self::Cpu(canvas) => (canvas.rgba8(), true),
+        };
+        crate::export::unpremultiply(&mut rgba, srgb);
+        Ok(rgba)
+    }
+}
@@ -0,0 +1,74 @@
+//! The colors strokes were last painted with, most recent first, kept between sessions: in a
+//! file in the platform's config directory natively, in local storage on the web.
+
+use crate::color::Color;
+use crate::persist;
+use crate::stroke::Brush;
+
+/// Saved as `hellopaint-recent-colors.json` natively.
+const RECENT_COLORS: &str = "hellopaint-recent-colors";
+
+#[derive(Debug, Clone)]
+pub struct RecentColors {
+    colors: Vec<Color>,
+    capacity: usize,
+}
+
+impl RecentColors {
+    /// An empty history remembering up to `capacity` colors, 0 remembers none.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            colors: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The history saved by the last session, or an empty one if there is none. Colors a brush
+    /// can't paint with, e.g. edited by hand, are left out.
+    pub fn load(capacity: usize) -> Self {
+        let mut recent = Self::new(capacity);
+        match persist::read(RECENT_COLORS) {
+            Ok(Some(json)) => match serde_json::from_str::<Vec<Color>>(&json) {
+                Ok(colors) => {
+                    recent.colors = colors
+                        .into_iter()
+                        .filter_map(|color| Brush { color, ..Brush::default() }.validated().ok())
+                        .map(|brush| brush.color)
+                        .take(capacity)
+                        .collect();
+                }
+                Err(err) => tracing::warn!("Ignoring the saved recent colors: {err}"),
+            },
+            Ok(None) => {}
+            Err(err) => tracing::warn!("Couldn't read the recent colors: {err}"),
+        }
+        recent
+    }
+
+    /// Persists the history for the next session.
+    pub fn save(&self) {
+        let result = serde_json::to_string(&self.colors)
+            .map_err(|err| err.to_string())
+            .and_then(|json| persist::write(RECENT_COLORS, &json));
+        if let Err(err) = result {
+            tracing::error!("Couldn't save the recent colors: {err}");
+        }
+    }
+
+    /// Most recent first.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Moves `color` to the front, dropping the oldest color once full. Returns whether the
+    /// history changed, which it doesn't if `color` already was the most recent one.
+    pub fn push(&mut self, color: Color) -> bool {
+        if self.capacity == 0 || self.colors.first() == Some(&color) {
+            return false;
+        }
+        self.colors.retain(|&recent| recent != color);
+        self.colors.insert(0, color);
+        self.colors.truncate(self.capacity);
+        true
+    }
+}
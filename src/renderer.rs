@@ -0,0 +1,607 @@
+//! The app's GPU side: setting up the device, presenting the canvas in the window and getting
+//! it back when the device is lost. Also the pipeline setup shared by the passes, so fixes to it
+//! apply to all of them, and a cache sharing the pipelines and bind group layouts they build.
+
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use winit::dpi::PhysicalSize;
+use winit::event_loop::EventLoopProxy;
+use winit::window::Window;
+
+use crate::app::{GpuDevice, UserEvent};
+use crate::color::Color;
+use crate::config::Config;
+use crate::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::lut::Lut;
+use crate::new_document::CanvasSetup;
+use crate::overlay::Overlay;
+use crate::preprocessor::ShaderDefines;
+use crate::shaders::Shader;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::ShaderWatcher;
+use crate::surface::{CanvasColorSpace, DisplayGamut, Dot, GlobalSurface, HpSurface};
+use crate::surface_view::SurfaceRenderResources;
+use crate::tasks;
+use crate::watchdog::Watchdog;
+
+/// What tells pipeline variants apart in a [`PipelineCache`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    pub shader: Shader,
+    pub format: wgpu::TextureFormat,
+    pub blend: Option<wgpu::BlendState>,
+    pub sample_count: u32,
+    pub defines: ShaderDefines,
+}
+
+/// Pipelines and bind group layouts of one device, each built once and shared by whoever asks for
+/// the same one again, e.g. a dot pipeline variant by every canvas of a
+/// [`GlobalSurface`](crate::surface::GlobalSurface).
+#[derive(Default)]
+pub(crate) struct PipelineCache {
+    pipelines: RwLock<HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>>,
+    bind_group_layouts: RwLock<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+}
+
+impl PipelineCache {
+    /// The pipeline for `key`, built by `create` unless it already was.
+    pub fn pipeline<E>(
+        &self,
+        key: PipelineKey,
+        create: impl FnOnce(&PipelineKey) -> Result<wgpu::RenderPipeline, E>,
+    ) -> Result<Arc<wgpu::RenderPipeline>, E> {
+        if let Some(pipeline) = self.pipelines.read().unwrap().get(&key) {
+            return Ok(pipeline.clone());
+        }
+        let pipeline = Arc::new(create(&key)?);
+        self.pipelines.write().unwrap().insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Replaces the pipeline for `key`, e.g. with one built from an edited shader.
+    pub fn insert(&self, key: PipelineKey, pipeline: Arc<wgpu::RenderPipeline>) {
+        self.pipelines.write().unwrap().insert(key, pipeline);
+    }
+
+    /// The keys of the pipelines built from `shader`.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn keys(&self, shader: Shader) -> Vec<PipelineKey> {
+        let pipelines = self.pipelines.read().unwrap();
+        pipelines.keys().filter(|key| key.shader == shader).cloned().collect()
+    }
+
+    /// Drops the pipelines built from `shader`, whose source changed.
+    pub fn clear(&self, shader: Shader) {
+        self.pipelines.write().unwrap().retain(|key, _| key.shader != shader);
+    }
+
+    /// The bind group layout with `entries`, labeled `label` if it's built now.
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Arc<wgpu::BindGroupLayout> {
+        if let Some(layout) = self.bind_group_layouts.read().unwrap().get(entries) {
+            return layout.clone();
+        }
+        let layout = Arc::new(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries,
+        }));
+        self.bind_group_layouts.write().unwrap().insert(entries.to_vec(), layout.clone());
+        layout
+    }
+}
+
+/// A pipeline running `entry_point` of `module` into a single target of `format`, for passes
+/// without vertex buffers: their `vs_main` makes a triangle or quad from the vertex index.
+pub(crate) fn vertexless_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// The app's GPU side: the device, the window's swapchain and the canvas presented in it.
+/// Recreates the device when it's lost.
+pub(crate) struct Renderer {
+    // Shared with the task recreating the device when it's lost
+    instance: Rc<wgpu::Instance>,
+    surface: Rc<wgpu::Surface>,
+    // Kept as long as its device, like wgpu's examples do
+    _adapter: wgpu::Adapter,
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    surface_config: wgpu::SurfaceConfiguration,
+    gamut: DisplayGamut,
+    device_lost: Arc<AtomicBool>,
+    recovering: bool,
+    watchdog: Watchdog,
+    /// Recreate the device when the watchdog notices it stalled.
+    recover_stalls: bool,
+    /// Capture the next frame in the graphics debugger the app runs under.
+    capture_next_frame: bool,
+    /// The canvas and how it's drawn into the window.
+    pub resources: SurfaceRenderResources,
+    /// Drawn over the canvas.
+    pub overlay: Overlay,
+}
+
+impl Renderer {
+    /// Sets up the GPU for `window` with a canvas as `config` asks for, falling back where the
+    /// device can't do it.
+    pub async fn new(window: &Window, config: &Config) -> Result<Self, Error> {
+        #[cfg(target_arch = "wasm32")]
+        log_browser_webgpu_support();
+
+        let size = window.inner_size();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: config.backends,
+            ..Default::default()
+        });
+
+        let surface = unsafe { instance.create_surface(window) }?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let trace = trace_dir(config.trace.as_deref());
+        #[cfg(target_arch = "wasm32")]
+        let trace: Option<std::path::PathBuf> = None;
+        let (adapter, device, queue) = request_device(&instance, Some(&surface), trace.as_deref()).await?;
+        let device_lost = watch_device(&device);
+
+        let gamut = if config
+            .gamut
+            .swapchain_format(&surface.get_capabilities(&adapter).formats)
+            .is_some()
+        {
+            config.gamut
+        } else {
+            tracing::warn!("The display doesn't support {:?}, falling back to sRGB", config.gamut);
+            DisplayGamut::Srgb
+        };
+        let surface_config = surface_configuration(&surface, &adapter, size.width, size.height, gamut)?;
+        surface.configure(&device, &surface_config);
+
+        let color_space = if config.color_space.is_supported(&adapter) {
+            config.color_space
+        } else {
+            tracing::warn!("{:?} canvases aren't supported here, using the default", config.color_space);
+            CanvasColorSpace::default()
+        };
+        let global_surface = Arc::new(
+            GlobalSurface::builder()
+                .canvas_size(fit_canvas_size(config.canvas_size, &device.limits()))
+                .color_space(color_space)
+                .reinterpretable(supports_view_formats(&adapter))
+                .build(device.clone(), queue.clone())?,
+        );
+        global_surface.set_background(config.background);
+
+        let mut hp_surface = HpSurface::new(global_surface);
+        hp_surface.add_dots(&[Dot::new([0.5, 0.5], 0.1, 0.5, Color::RED)]);
+
+        let mut resources = SurfaceRenderResources::new(&device, hp_surface, surface_config.format);
+        resources.post_process_mut().set_gamut(&device, gamut);
+        resources.post_process_mut().set_effects(&device, config.effects.clone());
+        if let Some(path) = &config.lut {
+            #[cfg(not(target_arch = "wasm32"))]
+            match load_lut(path) {
+                Ok(lut) => resources.post_process_mut().set_lut(&device, &queue, Some(&lut)),
+                Err(err) => tracing::error!("Couldn't load the LUT {path}: {err}"),
+            }
+            #[cfg(target_arch = "wasm32")]
+            tracing::warn!("Ignoring the LUT {path}, on the web pass it to setLut instead");
+        }
+
+        let overlay = Overlay::new(window, &device, surface_config.format);
+        Ok(Self {
+            instance: Rc::new(instance),
+            surface: Rc::new(surface),
+            _adapter: adapter,
+            device,
+            queue,
+            surface_config,
+            gamut,
+            device_lost,
+            recovering: false,
+            watchdog: Watchdog::new(),
+            recover_stalls: config.recover_stalls,
+            capture_next_frame: false,
+            resources,
+            overlay,
+        })
+    }
+
+    /// Has the next frame captured by RenderDoc when the app runs in it, or by Xcode's Metal frame
+    /// capture. Everything from drawing the canvas to presenting ends up in the capture, with the
+    /// passes' debug groups.
+    pub fn capture_next_frame(&mut self) {
+        self.capture_next_frame = true;
+    }
+
+    /// The gamut the window shows, which exports match.
+    pub fn gamut(&self) -> DisplayGamut {
+        self.gamut
+    }
+
+    /// Reconfigures the swapchain for a window of `size`. Minimized windows have none, those are
+    /// ignored.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.surface_config.width = size.width;
+        self.surface_config.height = size.height;
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+
+    /// Presents the canvas in the window, returning whether it did. Once the device is lost,
+    /// starts recreating it instead, which ends in a [`UserEvent::DeviceRecreated`] for
+    /// [`Self::recover`]. A device the watchdog caught stalling is treated as lost if the config
+    /// asks for it.
+    pub fn render(&mut self, proxy: &EventLoopProxy<UserEvent>) -> Result<bool, wgpu::SurfaceError> {
+        if self.watchdog.check(&self.device) && self.recover_stalls && !self.recovering {
+            self.device_lost.store(true, Ordering::Release);
+        }
+        if self.device_lost.load(Ordering::Acquire) {
+            if !self.recovering {
+                tracing::warn!("The GPU device was lost, recreating it");
+                self.recovering = true;
+                let (instance, surface, proxy) = (self.instance.clone(), self.surface.clone(), proxy.clone());
+                tasks::spawn_local(async move {
+                    // Not traced, the trace of the lost device is the interesting one
+                    let device = request_device(&instance, Some(&surface), None).await;
+                    proxy.send_event(UserEvent::DeviceRecreated(device)).ok();
+                });
+            }
+            return Ok(false);
+        }
+        let _span = tracing::info_span!("frame", dots = self.resources.surface().dots().len()).entered();
+        let frame = {
+            let _span = tracing::info_span!("acquire").entered();
+            acquire_frame(&self.surface, &self.device, &self.surface_config)?
+        };
+        let Some(frame) = frame else {
+            return Ok(false);
+        };
+        let capture = std::mem::take(&mut self.capture_next_frame);
+        if capture {
+            self.device.start_capture();
+        }
+        if let Err(err) = self.resources.render_to_texture(&self.device, &self.queue, &frame.texture) {
+            tracing::error!("{err}");
+        }
+        self.overlay.paint(&self.device, &self.queue, &frame.texture);
+        let label = format!(
+            "frame of {} dots with effects {:?}",
+            self.resources.surface().dots().len(),
+            self.resources.post_process_mut().effects()
+        );
+        self.watchdog.submitted(&self.queue, label);
+        tracing::info_span!("present").in_scope(|| frame.present());
+        if capture {
+            self.device.stop_capture();
+            tracing::info!("Captured a frame, if a graphics debugger is attached");
+        }
+        Ok(true)
+    }
+
+    /// Moves the canvas onto the device replacing the lost one.
+    pub fn recover(&mut self, recreated: Result<GpuDevice, Error>) -> Result<(), Error> {
+        self.recovering = false;
+        let (adapter, device, queue) = recreated?;
+        let config = surface_configuration(
+            &self.surface,
+            &adapter,
+            self.surface_config.width,
+            self.surface_config.height,
+            self.gamut,
+        )?;
+        let resources = self.resources.recreate(&device, &queue, config.format)?;
+        self.overlay.recreate(&device, config.format);
+        self.surface_config = config;
+        self.surface.configure(&device, &self.surface_config);
+        self.device_lost = watch_device(&device);
+        self.watchdog = Watchdog::new();
+        (self._adapter, self.device, self.queue) = (adapter, device, queue);
+        self.resources = resources;
+        Ok(())
+    }
+
+    /// Starts a new document on a canvas of `canvas`'s background and size, or the largest the
+    /// device can make, see [`SurfaceRenderResources::with_new_canvas`].
+    pub fn new_document(&mut self, canvas: CanvasSetup) -> Result<(), Error> {
+        let canvas = CanvasSetup {
+            size: fit_canvas_size(canvas.size, &self.device.limits()),
+            ..canvas
+        };
+        self.resources = self.resources.with_new_canvas(&self.device, &self.queue, canvas)?;
+        Ok(())
+    }
+
+    /// Rebuilds the pipelines of shaders edited on disk, returning whether any was. Broken edits
+    /// are logged and the previous pipeline stays in use.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_changed_shaders(&mut self, shader_watcher: &ShaderWatcher) -> bool {
+        let mut reloaded = false;
+        for shader in shader_watcher.changed() {
+            let source = match shader.read_from_disk() {
+                Ok(source) => source,
+                Err(err) => {
+                    tracing::error!("Couldn't read {}: {err}", shader.file_name());
+                    continue;
+                }
+            };
+            let resources = &mut self.resources;
+            let result = match shader {
+                Shader::Dot => resources.surface().global.reload_shader(source),
+                Shader::SurfaceView => resources.reload_shader(&self.device, &source),
+                Shader::PostProcess | Shader::Bloom | Shader::Lut => {
+                    resources.post_process_mut().reload_shader(&self.device, shader, source)
+                }
+                Shader::Filter | Shader::Blur | Shader::Convolution | Shader::Adjustment | Shader::GradientMap => {
+                    resources.surface().global.reload_filter_shader(shader, source)
+                }
+                Shader::Histogram => resources.surface().global.reload_histogram_shader(source),
+                Shader::MagicWand => resources.surface().global.reload_magic_wand_shader(source),
+                Shader::Particles => resources.surface().global.reload_particles_shader(source),
+                Shader::Watercolor => resources.surface().global.reload_watercolor_shader(source),
+                Shader::Framing => resources.surface().global.reload_framing_shader(source),
+                // Only included by other shaders, `changed` lists those instead
+                Shader::DotCommon | Shader::PostProcessCommon => continue,
+            };
+            match result {
+                Ok(()) => {
+                    tracing::info!("Reloaded {}", shader.file_name());
+                    reloaded = true;
+                }
+                Err(err) => tracing::error!("Keeping the previous {}:\n{err}", shader.file_name()),
+            }
+        }
+        reloaded
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_lut(path: &str) -> Result<Lut, Box<dyn std::error::Error>> {
+    Ok(Lut::from_cube(&std::fs::read_to_string(path)?)?)
+}
+
+/// Logs GPU errors nothing else caught, instead of panicking like wgpu does by default. The
+/// returned flag is raised once they say the device is gone, so the app can recreate it.
+fn watch_device(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    device.on_uncaptured_error(Box::new(move |err| {
+        // wgpu 0.15 has no device lost callback, only errors mentioning it
+        let is_lost = match &err {
+            wgpu::Error::OutOfMemory { .. } => true,
+            wgpu::Error::Validation { description, .. } => description.contains("device is lost"),
+        };
+        tracing::error!("GPU error: {err}");
+        if is_lost {
+            flag.store(true, Ordering::Release);
+        }
+    }));
+    lost
+}
+
+/// Requests an adapter that can present to `surface`, or any adapter without one for rendering
+/// headless, and a device on it. With a `trace` directory the device records every call into it,
+/// see [`trace_dir`].
+pub(crate) async fn request_device(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    trace: Option<&Path>,
+) -> Result<GpuDevice, Error> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            // Request an adapter which can render to our surface
+            compatible_surface: surface,
+        })
+        .await
+        .ok_or(Error::NoAdapter)?;
+    open_device(adapter, trace).await
+}
+
+/// A device and queue on `adapter` with the features and limits the renderer asks for.
+pub(crate) async fn open_device(adapter: wgpu::Adapter, trace: Option<&Path>) -> Result<GpuDevice, Error> {
+    let adapter_info = adapter.get_info();
+    tracing::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
+
+    // Create the logical device and command queue
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("hellopaint"),
+                features: device_features(&adapter),
+                limits: device_limits(&adapter),
+            },
+            trace,
+        )
+        .await?;
+
+    Ok((adapter, Arc::new(device), Arc::new(queue)))
+}
+
+/// Where to record a wgpu API trace: `configured`, or the directory in the `WGPU_TRACE`
+/// environment variable like wgpu's own examples. The trace can be replayed with wgpu's `player`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn trace_dir(configured: Option<&str>) -> Option<PathBuf> {
+    let dir = configured.map(PathBuf::from).or_else(|| std::env::var_os("WGPU_TRACE").map(PathBuf::from))?;
+    if cfg!(not(feature = "trace")) {
+        tracing::warn!("Not tracing into {}, built without the trace feature", dir.display());
+        return None;
+    }
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        tracing::warn!("Not tracing, couldn't create {}: {err}", dir.display());
+        return None;
+    }
+    tracing::info!("Recording a wgpu trace into {}", dir.display());
+    Some(dir)
+}
+
+pub(crate) fn surface_configuration(
+    surface: &wgpu::Surface,
+    adapter: &wgpu::Adapter,
+    width: u32,
+    height: u32,
+    gamut: DisplayGamut,
+) -> Result<wgpu::SurfaceConfiguration, Error> {
+    let swapchain_capabilities = surface.get_capabilities(adapter);
+    let format = gamut
+        .swapchain_format(&swapchain_capabilities.formats)
+        .or(swapchain_capabilities.formats.first().copied())
+        .ok_or(Error::IncompatibleSurface)?;
+    if stores_encoded(format) && gamut == DisplayGamut::Srgb {
+        tracing::debug!("No sRGB swapchain format, presenting in {format:?} and encoding in the shader");
+    }
+    Ok(wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: swapchain_capabilities.alpha_modes[0],
+        view_formats: vec![],
+    })
+}
+
+/// Whether targets of `format` store the colors shaders write as they are, so the shaders encode
+/// the canvas's linear colors to sRGB for them. sRGB formats encode on their own, and float
+/// formats are presented as scRGB, which is linear.
+pub(crate) fn stores_encoded(format: wgpu::TextureFormat) -> bool {
+    !format.describe().srgb && format != wgpu::TextureFormat::Rgba16Float
+}
+
+/// How often [`acquire_frame`] tries before skipping the frame.
+const ACQUIRE_ATTEMPTS: u32 = 3;
+
+/// The texture to draw the next frame into. A lost or outdated surface, e.g. after a resize raced
+/// the frame or the display changed, is reconfigured with `config` and tried again, as is one
+/// that timed out, up to [`ACQUIRE_ATTEMPTS`] times. `None` skips the frame, only running out of
+/// memory is an error.
+pub(crate) fn acquire_frame(
+    surface: &wgpu::Surface,
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> Result<Option<wgpu::SurfaceTexture>, wgpu::SurfaceError> {
+    for attempt in 1..=ACQUIRE_ATTEMPTS {
+        match surface.get_current_texture() {
+            Ok(frame) => return Ok(Some(frame)),
+            Err(err @ wgpu::SurfaceError::OutOfMemory) => return Err(err),
+            Err(err) => {
+                tracing::warn!("Couldn't acquire a frame ({attempt} of {ACQUIRE_ATTEMPTS}): {err}");
+                if let wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated = err {
+                    surface.configure(device, config);
+                }
+            }
+        }
+    }
+    tracing::warn!("Skipping a frame");
+    Ok(None)
+}
+
+/// Whether textures on devices of `adapter` can be viewed in other formats than their own, see
+/// [`GlobalSurfaceBuilder::reinterpretable`](crate::surface::GlobalSurfaceBuilder::reinterpretable).
+pub(crate) fn supports_view_formats(adapter: &wgpu::Adapter) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::VIEW_FORMATS)
+}
+
+/// `requested`, or the largest canvas the device's textures can hold if that's smaller. Canvases
+/// have at least one pixel.
+pub(crate) fn fit_canvas_size(requested: u32, limits: &wgpu::Limits) -> u32 {
+    let max = limits.max_texture_dimension_2d;
+    if requested == 0 {
+        tracing::warn!("Canvases can't be 0 pixels wide, using 1 pixel instead");
+        return 1;
+    }
+    if requested > max {
+        tracing::warn!("Canvases of {requested} pixels don't fit this device, using {max} pixels instead");
+        return max;
+    }
+    requested
+}
+
+/// Per-frame data goes through push constants where the backend really has them.
+///
+/// GL emulates them with plain uniforms, which wgpu can't set when the shader optimizes them out.
+fn device_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+    match adapter.get_info().backend {
+        wgpu::Backend::Gl => wgpu::Features::empty(),
+        _ => adapter.features() & wgpu::Features::PUSH_CONSTANTS,
+    }
+}
+
+/// Picks device limits matching the backend the adapter actually runs on.
+fn device_limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+    let base = match adapter.get_info().backend {
+        // WebGL2 and GLES can't go beyond the WebGL2 baseline
+        wgpu::Backend::Gl => wgpu::Limits::downlevel_webgl2_defaults(),
+        _ => wgpu::Limits::downlevel_defaults(),
+    };
+    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+    let limits = base.using_resolution(adapter.limits());
+    wgpu::Limits {
+        max_push_constant_size: adapter.limits().max_push_constant_size,
+        // Adapters with fewer get dots from storage buffers, see `DotInput`
+        max_vertex_attributes: limits.max_vertex_attributes.min(adapter.limits().max_vertex_attributes),
+        ..limits
+    }
+}
+
+/// Logs whether the browser exposes `navigator.gpu` and how that relates to the compiled backend.
+///
+/// wgpu 0.15 selects WebGPU or WebGL2 at compile time (the `webgl` feature), so this can only
+/// point out a mismatch, not switch backends at runtime.
+#[cfg(target_arch = "wasm32")]
+fn log_browser_webgpu_support() {
+    let has_webgpu = web_sys::window()
+        .map(|window| window.navigator())
+        .and_then(|navigator| js_sys::Reflect::get(&navigator, &"gpu".into()).ok())
+        .map_or(false, |gpu| !gpu.is_undefined());
+
+    match (has_webgpu, cfg!(feature = "webgl")) {
+        (true, true) => tracing::info!("navigator.gpu is available, but this build uses WebGL2 (build without the `webgl` feature for WebGPU)"),
+        (false, false) => tracing::error!("navigator.gpu is not available, but this build requires WebGPU (build with the `webgl` feature for WebGL2)"),
+        _ => {}
+    }
+}
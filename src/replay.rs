@@ -0,0 +1,111 @@
+//! Timelapses of painting sessions: a [`Recording`] of the commands a [`Painting`] applied, each
+//! with when it came in, and a [`Replay`] applying them to another canvas again at a faster pace.
+//! Painting is deterministic, so the replay ends up with the same dots, undos and clears
+//! included.
+
+use crate::clock::Clock;
+use crate::command::{Applied, Command, Painting};
+use crate::surface::HpSurface;
+
+/// The longest pause between two commands in a replay, in seconds of playback. Longer pauses
+/// while painting are cut short.
+const MAX_PAUSE_SECONDS: f64 = 1.0;
+
+/// A command as it was applied.
+#[derive(Debug, Clone)]
+pub struct TimedCommand {
+    /// Since the recording started.
+    pub seconds: f64,
+    pub command: Command,
+}
+
+/// The commands of a painting session, in the order they were applied.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    clock: Clock,
+    commands: Vec<TimedCommand>,
+}
+
+impl Recording {
+    /// An empty recording, its clock starting now.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes that `command` is being applied now. Exports don't change the painting and aren't
+    /// recorded.
+    pub fn record(&mut self, command: &Command) {
+        if let Command::Export = command {
+            return;
+        }
+        self.commands.push(TimedCommand {
+            seconds: self.clock.elapsed_seconds(),
+            command: command.clone(),
+        });
+    }
+
+    /// Since the recording started.
+    pub fn seconds(&self) -> f64 {
+        self.clock.elapsed_seconds()
+    }
+
+    pub fn commands(&self) -> &[TimedCommand] {
+        &self.commands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// A recording applied again, `speed` times as fast.
+pub struct Replay {
+    commands: Vec<TimedCommand>,
+    /// Applies the commands, with a brush and undo history of its own.
+    painting: Painting,
+    /// Of the next command to apply.
+    next: usize,
+}
+
+impl Replay {
+    /// Replays `recording` `speed` times as fast as it was painted, from its first command on.
+    pub fn new(recording: &Recording, speed: f64) -> Self {
+        let speed = speed.max(f64::EPSILON);
+        let mut commands = Vec::with_capacity(recording.commands.len());
+        let mut previous = recording.commands.first().map_or(0.0, |first| first.seconds);
+        let mut seconds = 0.0;
+        for timed in &recording.commands {
+            seconds += ((timed.seconds - previous) / speed).min(MAX_PAUSE_SECONDS);
+            previous = timed.seconds;
+            commands.push(TimedCommand {
+                seconds,
+                command: timed.command.clone(),
+            });
+        }
+        Self {
+            commands,
+            painting: Painting::default(),
+            next: 0,
+        }
+    }
+
+    /// How long playing the whole recording takes.
+    pub fn seconds(&self) -> f64 {
+        self.commands.last().map_or(0.0, |last| last.seconds)
+    }
+
+    /// Applies the commands due `seconds` into the replay to `surface`, noting in the returned
+    /// [`Applied`] whether it changed.
+    pub fn advance_to(&mut self, seconds: f64, surface: &mut HpSurface) -> Applied {
+        let mut applied = Applied::default();
+        while let Some(timed) = self.commands.get(self.next).filter(|timed| timed.seconds <= seconds) {
+            self.painting.apply(timed.command.clone(), surface, &mut applied);
+            self.next += 1;
+        }
+        applied
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.commands.len()
+    }
+}
@@ -0,0 +1,366 @@
+//! Generative art without recompiling: [`run_script`] runs a [Rhai](https://rhai.rs) script that
+//! paints, returning what it painted as [`Command`]s for the app's bus, so a script's dots can be
+//! undone and replayed like painted ones.
+//!
+//! Scripts can call:
+//! - `add_dot(x, y)`, `add_dot(x, y, radius)` and `add_dot(x, y, radius, hardness, color)`, with
+//!   the brush's radius, hardness and color for what's left out.
+//! - `add_dots(points)` adds dots with the brush at `[x, y]` points.
+//! - `stroke(points)` paints a stroke with the brush through `[x, y]` or `[x, y, pressure]` points.
+//! - `set_brush(#{ radius: 0.05, color: [0.0, 0.0, 1.0] })` changes the brush fields named, any of
+//!   `radius`, `hardness`, `color`, `hue_jitter`, `spacing` and `wet`, and `set_color(color)`.
+//! - `clear()` removes all dots.
+//! - `canvas_size()` and `dot_count()`, of the canvas when the script started.
+//! - `random(seed)` returns a generator: `rng.float()` is in 0..1, `rng.range(low, high)` and
+//!   `rng.int(low, high)` are in low..high.
+//! - `hsv(h, s, v)` returns a color from hue in degrees, saturation and value.
+//! - `poisson_disk(min_distance, seed)`, `spiral(count, spread)`, `grid(columns, rows)` and
+//!   `halton(count)` return the `[x, y]` points of the [generators].
+//! - `lsystem(axiom, rules, angle, iterations, spacing)` returns points every `spacing` along the
+//!   lines of an [L-system](crate::lsystem) filling the canvas, with `rules` a map like
+//!   `#{ F: "F+F-F" }`.
+//!
+//! Batch runs, see [`run_script_with_params`], also see their `seed` and parameters as constants.
+//!
+//! Positions are in canvas coordinates, -1..1 with y up. Colors are `[r, g, b]` or
+//! `[r, g, b, a]` arrays of linear values in 0..1. `print` logs.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, FLOAT, INT};
+
+use crate::color::{Color, Hsv};
+use crate::command::{Command, PointerId};
+use crate::generators::{self, Grid, Halton, PoissonDisk, Spiral};
+use crate::lsystem::LSystem;
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+/// Operations a script may run, so one looping forever ends with an error instead of hanging.
+pub const MAX_OPERATIONS: u64 = 100_000_000;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(std::io::Error),
+    /// The script doesn't parse, or failed while running, with where.
+    Script(Box<EvalAltResult>),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(err) => write!(f, "failed to read the script: {err}"),
+            ScriptError::Script(err) => write!(f, "the script failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// What a script can see of the canvas it paints on.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptContext {
+    pub canvas_size: u32,
+    pub dot_count: usize,
+    /// Painted with until the script sets another.
+    pub brush: Brush,
+}
+
+/// What a script painted so far.
+struct Painted {
+    commands: Vec<Command>,
+    /// Added dots, published as one [`Command::AddDots`] before any other command.
+    dots: Vec<Dot>,
+    brush: Brush,
+}
+
+impl Painted {
+    fn publish(&mut self, command: Command) {
+        self.publish_dots();
+        self.commands.push(command);
+    }
+
+    fn publish_dots(&mut self) {
+        if !self.dots.is_empty() {
+            self.commands.push(Command::AddDots(std::mem::take(&mut self.dots)));
+        }
+    }
+}
+
+/// `random(seed)` in scripts.
+#[derive(Clone)]
+struct ScriptRng(ChaCha8Rng);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Runs `source`, returning the commands painting what it painted.
+pub fn run_script(source: &str, context: ScriptContext) -> Result<Vec<Command>, ScriptError> {
+    run_script_with_params(source, context, &[])
+}
+
+/// Runs `source` with `params` as constants it can read by name, e.g. the `seed` of a batch run.
+/// Whole numbers are integers, so they can seed `random`.
+pub fn run_script_with_params(
+    source: &str,
+    context: ScriptContext,
+    params: &[(&str, f64)],
+) -> Result<Vec<Command>, ScriptError> {
+    let mut scope = Scope::new();
+    for &(name, value) in params {
+        if value.fract() == 0.0 && value.abs() < INT::MAX as f64 {
+            scope.push_constant(name, value as INT);
+        } else {
+            scope.push_constant(name, value as FLOAT);
+        }
+    }
+    let painted = Rc::new(RefCell::new(Painted {
+        commands: Vec::new(),
+        dots: Vec::new(),
+        brush: context.brush,
+    }));
+    let engine = engine(&painted, context);
+    engine.run_with_scope(&mut scope, source).map_err(ScriptError::Script)?;
+    let mut painted = painted.borrow_mut();
+    painted.publish_dots();
+    Ok(std::mem::take(&mut painted.commands))
+}
+
+/// Runs the script at `path`, see [`run_script`].
+pub fn run_script_file(path: impl AsRef<Path>, context: ScriptContext) -> Result<Vec<Command>, ScriptError> {
+    let source = std::fs::read_to_string(path).map_err(ScriptError::Io)?;
+    run_script(&source, context)
+}
+
+fn engine(painted: &Rc<RefCell<Painted>>, context: ScriptContext) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| tracing::info!("Script: {text}"));
+    engine.on_debug(|text, _, position| tracing::debug!("Script at {position}: {text}"));
+
+    let state = painted.clone();
+    engine.register_fn("add_dot", move |x: Dynamic, y: Dynamic| -> ScriptResult<()> {
+        let mut painted = state.borrow_mut();
+        let brush = painted.brush;
+        let dot = Dot::new([number(&x)?, number(&y)?], brush.radius, brush.hardness, brush.color);
+        painted.dots.push(validated(dot.with_noise(brush.noise))?);
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn("add_dot", move |x: Dynamic, y: Dynamic, radius: Dynamic| -> ScriptResult<()> {
+        let mut painted = state.borrow_mut();
+        let brush = painted.brush;
+        let dot = Dot::new([number(&x)?, number(&y)?], number(&radius)?, brush.hardness, brush.color);
+        painted.dots.push(validated(dot.with_noise(brush.noise))?);
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn(
+        "add_dot",
+        move |x: Dynamic, y: Dynamic, radius: Dynamic, hardness: Dynamic, color: Array| -> ScriptResult<()> {
+            let mut painted = state.borrow_mut();
+            let noise = painted.brush.noise;
+            let dot = Dot::new([number(&x)?, number(&y)?], number(&radius)?, number(&hardness)?, to_color(&color)?);
+            painted.dots.push(validated(dot.with_noise(noise))?);
+            Ok(())
+        },
+    );
+    let state = painted.clone();
+    engine.register_fn("add_dots", move |points: Array| -> ScriptResult<()> {
+        let positions = points
+            .iter()
+            .map(|point| {
+                let point = point.clone().try_cast::<Array>().ok_or("points are [x, y] arrays")?;
+                match point.as_slice() {
+                    [x, y] => Ok([number(x)?, number(y)?]),
+                    _ => Err("points are [x, y] arrays".into()),
+                }
+            })
+            .collect::<ScriptResult<Vec<_>>>()?;
+        let mut painted = state.borrow_mut();
+        let dots = generators::dots_at(&positions, &painted.brush).into_iter().map(validated);
+        let dots = dots.collect::<ScriptResult<Vec<_>>>()?;
+        painted.dots.extend(dots);
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn("stroke", move |points: Array| -> ScriptResult<()> {
+        let points = points
+            .iter()
+            .map(|point| {
+                let point = point.clone().try_cast::<Array>().ok_or("points are [x, y] or [x, y, pressure] arrays")?;
+                match point.as_slice() {
+                    [x, y] => Ok(([number(x)?, number(y)?], 1.0)),
+                    [x, y, pressure] => Ok(([number(x)?, number(y)?], number(pressure)?)),
+                    _ => Err("points are [x, y] or [x, y, pressure] arrays".into()),
+                }
+            })
+            .collect::<ScriptResult<Vec<_>>>()?;
+        if points.iter().any(|([x, y], pressure)| ![x, y, pressure].iter().all(|value| value.is_finite())) {
+            return Err("stroke points are finite numbers".into());
+        }
+        let Some((&(position, pressure), rest)) = points.split_first() else {
+            return Ok(());
+        };
+        let mut painted = state.borrow_mut();
+        let pointer = PointerId::Script;
+        painted.publish(Command::BeginStroke {
+            pointer,
+            position,
+            pressure,
+        });
+        for &(position, pressure) in rest {
+            painted.publish(Command::ContinueStroke {
+                pointer,
+                position,
+                pressure,
+            });
+        }
+        painted.publish(Command::EndStroke(pointer));
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn("set_brush", move |fields: Map| -> ScriptResult<()> {
+        let mut painted = state.borrow_mut();
+        let mut brush = painted.brush;
+        for (name, value) in &fields {
+            match name.as_str() {
+                "radius" => brush.radius = number(value)?,
+                "hardness" => brush.hardness = number(value)?,
+                "color" => brush.color = to_color(&value.clone().try_cast::<Array>().ok_or("color is an array")?)?,
+                "hue_jitter" => brush.hue_jitter = number(value)?,
+                "spacing" => brush.spacing = number(value)?,
+                "wet" => brush.wet = value.as_bool().map_err(|_| "wet is true or false")?,
+                name => return Err(format!("brushes have no {name}").into()),
+            }
+        }
+        let brush = brush.validated().map_err(|err| format!("the brush can't paint: {err}"))?;
+        painted.brush = brush;
+        painted.publish(Command::SetBrush(brush));
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn("set_color", move |color: Array| -> ScriptResult<()> {
+        let mut painted = state.borrow_mut();
+        let brush = Brush {
+            color: to_color(&color)?,
+            ..painted.brush
+        };
+        let color = brush.validated().map_err(|err| format!("the brush can't paint: {err}"))?.color;
+        painted.brush.color = color;
+        painted.publish(Command::SetBrushColor(color));
+        Ok(())
+    });
+    let state = painted.clone();
+    engine.register_fn("clear", move || {
+        let mut painted = state.borrow_mut();
+        painted.dots.clear();
+        painted.publish(Command::Clear);
+    });
+    engine.register_fn("canvas_size", move || context.canvas_size as INT);
+    engine.register_fn("dot_count", move || context.dot_count as INT);
+
+    engine.register_type_with_name::<ScriptRng>("Rng");
+    engine.register_fn("random", |seed: INT| ScriptRng(ChaCha8Rng::seed_from_u64(seed as u64)));
+    engine.register_fn("float", |rng: &mut ScriptRng| rng.0.gen::<FLOAT>());
+    engine.register_fn("range", |rng: &mut ScriptRng, low: Dynamic, high: Dynamic| -> ScriptResult<FLOAT> {
+        let (low, high) = (number(&low)? as FLOAT, number(&high)? as FLOAT);
+        Ok(if low < high { rng.0.gen_range(low..high) } else { low })
+    });
+    engine.register_fn("int", |rng: &mut ScriptRng, low: INT, high: INT| {
+        if low < high {
+            rng.0.gen_range(low..high)
+        } else {
+            low
+        }
+    });
+    engine.register_fn("hsv", |h: Dynamic, s: Dynamic, v: Dynamic| -> ScriptResult<Array> {
+        let hsv = Hsv {
+            h: number(&h)?.rem_euclid(360.0),
+            s: number(&s)?,
+            v: number(&v)?,
+        };
+        let color = Color::from_hsv(hsv, 1.0);
+        Ok([color.r, color.g, color.b, color.a].into_iter().map(|value| Dynamic::from_float(value as FLOAT)).collect())
+    });
+
+    engine.register_fn("poisson_disk", |min_distance: Dynamic, seed: INT| -> ScriptResult<Array> {
+        let min_distance = number(&min_distance)?;
+        Ok(points(PoissonDisk { min_distance, seed: seed as u64 }.positions()))
+    });
+    engine.register_fn("spiral", |count: INT, spread: Dynamic| -> ScriptResult<Array> {
+        let spread = number(&spread)?;
+        let spiral = Spiral {
+            count: count.max(0) as usize,
+            spread,
+            ..Spiral::DEFAULT
+        };
+        Ok(points(spiral.positions()))
+    });
+    engine.register_fn("grid", |columns: INT, rows: INT| {
+        let grid = Grid {
+            columns: columns.max(0) as usize,
+            rows: rows.max(0) as usize,
+            ..Grid::DEFAULT
+        };
+        points(grid.positions())
+    });
+    engine.register_fn("halton", |count: INT| {
+        points(Halton { count: count.max(0) as usize, ..Halton::DEFAULT }.positions())
+    });
+    engine.register_fn(
+        "lsystem",
+        |axiom: &str, rules: Map, angle: Dynamic, iterations: INT, spacing: Dynamic| -> ScriptResult<Array> {
+            let rules = rules
+                .into_iter()
+                .map(|(symbol, replacement)| {
+                    let mut chars = symbol.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(symbol), None) => Ok((symbol, replacement.to_string())),
+                        _ => Err(format!("rules replace single symbols, not {symbol:?}").into()),
+                    }
+                })
+                .collect::<ScriptResult<Vec<_>>>()?;
+            let lsystem = LSystem::new(axiom, rules, number(&angle)?, iterations.max(0) as usize);
+            let stamps = lsystem.stamps(number(&spacing)?).map_err(|err| err.to_string())?;
+            Ok(points(stamps))
+        },
+    );
+    engine
+}
+
+/// `[x, y]` arrays for scripts.
+fn points(positions: Vec<[f32; 2]>) -> Array {
+    positions
+        .into_iter()
+        .map(|[x, y]| Dynamic::from_array(vec![Dynamic::from_float(x as FLOAT), Dynamic::from_float(y as FLOAT)]))
+        .collect()
+}
+
+/// `dot` with its parameters clamped to their ranges, or an error for the script if it can't be
+/// drawn at all.
+fn validated(dot: Dot) -> ScriptResult<Dot> {
+    dot.validated().map_err(|err| format!("the dot can't be drawn: {err}").into())
+}
+
+/// A script's number, integer or not.
+fn number(value: &Dynamic) -> ScriptResult<f32> {
+    value
+        .as_float()
+        .map(|value| value as f32)
+        .or_else(|_| value.as_int().map(|value| value as f32))
+        .map_err(|type_name| format!("expected a number, got {type_name}").into())
+}
+
+fn to_color(values: &Array) -> ScriptResult<Color> {
+    let values = values.iter().map(number).collect::<ScriptResult<Vec<_>>>()?;
+    match values[..] {
+        [r, g, b] => Ok(Color::new(r, g, b, 1.0)),
+        [r, g, b, a] => Ok(Color::new(r, g, b, a)),
+        _ => Err("colors are [r, g, b] or [r, g, b, a] arrays".into()),
+    }
+}
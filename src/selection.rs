@@ -0,0 +1,372 @@
+//! Selections of the canvas, made with the magic wand.
+//!
+//! A selection is a mask texture, 1 where selected and 0 elsewhere. The magic wand grows it on
+//! the GPU from a seed pixel into neighbors of a similar color: every pass selects the matching
+//! pixels next to selected ones. Each render runs a batch of passes, until a readback of whether
+//! the last batch changed anything says the region stopped growing.
+//!
+//! Filters only change the selected part of the canvas, see
+//! [`HpSurface::select`](crate::surface::HpSurface::select). Painting isn't restricted,
+//! as dots are redrawn with every render rather than painted into the canvas once.
+
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::preprocessor::ShaderDefines;
+use crate::shaders::{Shader, ShaderError};
+
+/// The format of selection masks, which compute shaders can write.
+pub(crate) const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Region grow passes per render.
+const PASSES_PER_RENDER: u32 = 64;
+
+/// `@workgroup_size` of magic_wand.wgsl, in both dimensions.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Selects the area of similar color around a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MagicWand {
+    /// The pixel to start from, from the top left of the canvas.
+    pub x: u32,
+    pub y: u32,
+    /// How far, 0..1, a channel of a neighbor may differ from the start pixel for the neighbor to
+    /// be selected too. Compared sRGB encoded and unpremultiplied, alpha included.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
+}
+
+fn default_tolerance() -> f32 {
+    0.1
+}
+
+/// `MagicWand` in magic_wand.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MagicWandUniforms {
+    seed: [u32; 2],
+    tolerance: f32,
+    _padding: f32,
+}
+
+/// Whether `device` can run the magic wand.
+pub(crate) fn is_supported(device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_storage_textures_per_shader_stage > 0
+        && limits.max_storage_buffers_per_shader_stage > 0
+}
+
+/// A mask texture with everything selected, for when there is no selection: reads of it are
+/// clamped to its single pixel.
+pub(crate) fn create_full_mask(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+    wgpu::util::DeviceExt::create_texture_with_data(
+        device,
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("full_selection"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: MASK_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        bytemuck::bytes_of(&1.0f32),
+    )
+    .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+pub(crate) struct MagicWandPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl MagicWandPass {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("magic_wand_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: MASK_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<MagicWandUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(4),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("magic_wand_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, Shader::MagicWand.embedded_source(), &pipeline_layout)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        Self {
+            bind_group_layout,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        self.pipeline = catch_validation_error(device, || create_pipeline(device, source, &self.pipeline_layout))?;
+        Ok(())
+    }
+}
+
+/// Where the check whether the selection still grows is at.
+const CHECK_IDLE: u8 = 0;
+const CHECK_PENDING: u8 = 1;
+const CHECK_MAPPED: u8 = 2;
+const CHECK_FAILED: u8 = 3;
+
+/// The selection of a surface, possibly still growing.
+pub(crate) struct Selection {
+    wand: MagicWand,
+    /// The pass reads one mask and writes the other, `latest` is the one written last.
+    masks: [wgpu::TextureView; 2],
+    latest: usize,
+    size: wgpu::Extent3d,
+    uniforms: wgpu::Buffer,
+    /// Set by the passes when they select another pixel.
+    changed: wgpu::Buffer,
+    changed_readback: wgpu::Buffer,
+    check: Arc<AtomicU8>,
+    growing: bool,
+}
+
+impl Selection {
+    /// Starts growing a selection with `wand` on a canvas of `size`.
+    pub fn new(device: &wgpu::Device, wand: MagicWand, size: wgpu::Extent3d) -> Self {
+        let mask = |_| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("selection_mask"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: MASK_FORMAT,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let uniforms = MagicWandUniforms {
+            seed: [wand.x.min(size.width.saturating_sub(1)), wand.y.min(size.height.saturating_sub(1))],
+            tolerance: wand.tolerance,
+            _padding: 0.0,
+        };
+        let uniforms = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("magic_wand_uniforms"),
+                contents: bytemuck::bytes_of(&uniforms),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+        let changed = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("magic_wand_changed"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let changed_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("magic_wand_changed_readback"),
+            size: 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            wand,
+            // New textures start out zeroed, nothing selected
+            masks: [0, 1].map(mask),
+            latest: 0,
+            size,
+            uniforms,
+            changed,
+            changed_readback,
+            check: Arc::new(AtomicU8::new(CHECK_IDLE)),
+            growing: true,
+        }
+    }
+
+    /// What the selection was made with.
+    pub fn wand(&self) -> MagicWand {
+        self.wand
+    }
+
+    /// The mask as far as it has grown.
+    pub fn mask(&self) -> &wgpu::TextureView {
+        &self.masks[self.latest]
+    }
+
+    /// Whether the selection may still grow, so more renders are needed.
+    pub fn is_growing(&self) -> bool {
+        self.growing
+    }
+
+    /// Runs a batch of region grow passes over `canvas_view`, unless the selection stopped growing.
+    pub fn grow(&mut self, pass: &MagicWandPass, device: &wgpu::Device, queue: &wgpu::Queue, canvas_view: &wgpu::TextureView) {
+        if !self.growing {
+            return;
+        }
+        // Native backends only run the map callback when polled
+        device.poll(wgpu::Maintain::Poll);
+        let will_check = match self.check.load(Ordering::Acquire) {
+            CHECK_MAPPED => {
+                let changed: u32 = bytemuck::pod_read_unaligned(&self.changed_readback.slice(..).get_mapped_range());
+                self.changed_readback.unmap();
+                self.check.store(CHECK_IDLE, Ordering::Release);
+                // Passes only ever add pixels, a batch without any means none will
+                if changed == 0 {
+                    self.growing = false;
+                    return;
+                }
+                true
+            }
+            CHECK_IDLE => true,
+            CHECK_FAILED => {
+                tracing::error!("Couldn't check on the magic wand, stopping it");
+                self.growing = false;
+                return;
+            }
+            // The readback buffer can't be written while it's being mapped
+            _ => false,
+        };
+
+        if will_check {
+            queue.write_buffer(&self.changed, 0, bytemuck::bytes_of(&0u32));
+        }
+        let bind_groups = [0, 1].map(|read| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("magic_wand_bind_group"),
+                layout: &pass.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(canvas_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&self.masks[read]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&self.masks[1 - read]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.uniforms.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.changed.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("magic_wand"),
+        });
+        encoder.push_debug_group("magic wand");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("magic_wand"),
+            });
+            compute_pass.set_pipeline(&pass.pipeline);
+            for _ in 0..PASSES_PER_RENDER {
+                compute_pass.set_bind_group(0, &bind_groups[self.latest], &[]);
+                compute_pass.dispatch_workgroups(
+                    (self.size.width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    (self.size.height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                    1,
+                );
+                self.latest = 1 - self.latest;
+            }
+        }
+        if will_check {
+            encoder.copy_buffer_to_buffer(&self.changed, 0, &self.changed_readback, 0, 4);
+        }
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+
+        if will_check {
+            let state = self.check.clone();
+            state.store(CHECK_PENDING, Ordering::Release);
+            self.changed_readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                state.store(if result.is_ok() { CHECK_MAPPED } else { CHECK_FAILED }, Ordering::Release);
+            });
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, ShaderError> {
+    let shader = Shader::MagicWand.create_module(device, source, &ShaderDefines::new())?;
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("magic_wand"),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: "cs_main",
+    }))
+}
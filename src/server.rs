@@ -0,0 +1,157 @@
+//! Rendering as a service, e.g. for thumbnails: a [`RenderServer`] draws documents or batches of
+//! dots sent to it over HTTP on a headless canvas and answers with the PNG. The
+//! `hellopaint-server` binary runs one.
+//!
+//! `POST /render` takes a saved [`Document`] or a JSON array of dots as its body. `GET /health`
+//! answers `ok` once the canvas is set up.
+
+use std::fmt;
+use std::io::Read;
+use std::net::ToSocketAddrs;
+
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::document::Document;
+use crate::error::Error;
+use crate::export::{self, ExportError, ExportOptions};
+use crate::surface::{Dot, HpSurface};
+
+/// Requests with larger bodies are turned away, about a million dots.
+pub const MAX_BODY_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ServerError {
+    /// The headless canvas couldn't be set up.
+    Canvas(Error),
+    /// The server couldn't listen on the address.
+    Listen(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Canvas(err) => write!(f, "failed to set up the canvas: {err}"),
+            ServerError::Listen(err) => write!(f, "failed to listen: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// Why a render failed.
+#[derive(Debug)]
+pub enum RenderError {
+    /// The body isn't a document or dots, the client's fault.
+    Parse(serde_json::Error),
+    Export(ExportError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Parse(err) => write!(f, "invalid document: {err}"),
+            RenderError::Export(err) => write!(f, "failed to export the render: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Renders on a canvas of its own, one request after another.
+pub struct RenderServer {
+    canvas: HpSurface,
+    export: ExportOptions,
+}
+
+impl RenderServer {
+    /// Renders at `canvas_size` pixels, the size of every PNG it answers with.
+    pub fn new(canvas_size: u32, export: ExportOptions) -> Result<Self, ServerError> {
+        let canvas = HpSurface::headless(canvas_size).map_err(ServerError::Canvas)?;
+        Ok(Self { canvas, export })
+    }
+
+    /// Renders `json`, a saved [`Document`] or an array of dots, into a PNG.
+    pub fn render(&mut self, json: &str) -> Result<Vec<u8>, RenderError> {
+        let dots = if json.trim_start().starts_with('[') {
+            parse_dots(json)?
+        } else {
+            Document::from_json(json).map_err(RenderError::Parse)?.dots()
+        };
+        let _span = tracing::info_span!("render_request", dots = dots.len()).entered();
+        self.canvas.set_dots(dots);
+        self.canvas.render().map_err(|err| RenderError::Export(ExportError::Render(err)))?;
+        pollster::block_on(export::export_png(self.canvas.readback(), self.export)).map_err(RenderError::Export)
+    }
+
+    /// Answers requests to `address` until the process is stopped.
+    pub fn serve(mut self, address: impl ToSocketAddrs) -> Result<(), ServerError> {
+        let server = tiny_http::Server::http(address).map_err(ServerError::Listen)?;
+        if let Some(address) = server.server_addr().to_ip() {
+            tracing::info!("Rendering on http://{address}/render");
+        }
+        for request in server.incoming_requests() {
+            self.respond(request);
+        }
+        Ok(())
+    }
+
+    fn respond(&mut self, mut request: Request) {
+        let response = match (request.method(), request.url()) {
+            (Method::Get, "/health") => text(200, "ok"),
+            (Method::Post, "/render") if request.body_length().is_some_and(|length| length as u64 > MAX_BODY_BYTES) => {
+                text(413, "the document is too large")
+            }
+            (Method::Post, "/render") => {
+                let mut body = Vec::new();
+                // Chunked bodies don't say how long they are up front, reading one byte past the
+                // limit tells those that are too large apart
+                let read = request.as_reader().take(MAX_BODY_BYTES + 1).read_to_end(&mut body);
+                let body = match read {
+                    Ok(_) if body.len() as u64 > MAX_BODY_BYTES => Err(text(413, "the document is too large")),
+                    Ok(_) => String::from_utf8(body).map_err(|err| text(400, &format!("the body isn't UTF-8: {err}"))),
+                    Err(err) => Err(text(400, &format!("failed to read the body: {err}"))),
+                };
+                match body {
+                    Ok(body) => match self.render(&body) {
+                        Ok(png) => Response::from_data(png).with_header(header("Content-Type", "image/png")),
+                        Err(err @ RenderError::Parse(_)) => text(400, &err.to_string()),
+                        Err(err) => {
+                            tracing::error!("Render failed: {err}");
+                            text(500, &err.to_string())
+                        }
+                    },
+                    Err(response) => response,
+                }
+            }
+            (_, "/health" | "/render") => text(405, "method not allowed"),
+            _ => text(404, "not found"),
+        };
+        if let Err(err) = request.respond(response) {
+            tracing::warn!("Couldn't answer a request: {err}");
+        }
+    }
+}
+
+/// Dots sent as a JSON array, [validated](Dot::validated) like a document's.
+fn parse_dots(json: &str) -> Result<Vec<Dot>, RenderError> {
+    use serde::de::Error as _;
+
+    let dots: Vec<Dot> = serde_json::from_str(json).map_err(RenderError::Parse)?;
+    dots.into_iter()
+        .enumerate()
+        .map(|(index, dot)| {
+            dot.validated()
+                .map_err(|err| RenderError::Parse(serde_json::Error::custom(format!("dot {index}: {err}"))))
+        })
+        .collect()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("valid header")
+}
+
+fn text(status: u16, body: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
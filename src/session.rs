@@ -0,0 +1,166 @@
+//! Picking up where the last session left off: the [`Session`] is saved when the app quits, or
+//! the page is hidden on the web, and restored when it starts again, unless `--no-restore` is
+//! passed. Natively it's kept in a file in the platform's config directory, in local storage on
+//! the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::MonitorHandle;
+use winit::window::Window;
+
+use crate::guides::Guides;
+use crate::persist;
+use crate::post_process::ColorBlindness;
+use crate::stroke::Brush;
+use crate::surface_view::ViewFlip;
+
+/// Saved as `hellopaint-session.json` natively.
+const SESSION: &str = "hellopaint-session";
+
+/// What the app was doing when it quit. Fields missing from the saved session are the defaults'.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// The document opened or last saved with Ctrl+D, `None` if it never was.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<PathBuf>,
+    pub brush: Brush,
+    pub view: ViewState,
+    /// Natively, the browser decides on the web.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<WindowGeometry>,
+}
+
+/// How the canvas was shown.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    pub flip: ViewFlip,
+    pub guides: Option<Guides>,
+    /// Strokes snapped to the guides.
+    pub snap: bool,
+    pub color_blindness: Option<ColorBlindness>,
+    pub onion_skin: bool,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self {
+            flip: ViewFlip::default(),
+            guides: None,
+            snap: false,
+            color_blindness: None,
+            onion_skin: true,
+        }
+    }
+}
+
+/// Where the window was on the desktop and how large, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Of the window's outer top left corner.
+    pub position: [i32; 2],
+    /// Of the window's client area.
+    pub size: [u32; 2],
+    pub maximized: bool,
+}
+
+impl WindowGeometry {
+    /// `None` where windows can't tell their position, like on Wayland, and while minimized,
+    /// when they have no size and Windows parks them far off-screen.
+    pub fn of(window: &Window) -> Option<Self> {
+        if window.is_minimized() == Some(true) {
+            return None;
+        }
+        let position = window.outer_position().ok()?;
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+        Some(Self {
+            position: [position.x, position.y],
+            size: [size.width, size.height],
+            maximized: window.is_maximized(),
+        })
+    }
+
+    /// Moves and resizes `window` as far as the platform lets it, onto the monitor it overlaps
+    /// most or the primary one if the monitors changed since, and no larger than that monitor.
+    pub fn apply(&self, window: &Window) {
+        let monitors: Vec<_> = window.available_monitors().collect();
+        let monitor = monitors
+            .iter()
+            .max_by_key(|monitor| self.overlap(monitor))
+            .filter(|monitor| self.overlap(monitor) > 0)
+            .cloned()
+            .or_else(|| window.primary_monitor())
+            .or_else(|| monitors.first().cloned());
+        let Some(monitor) = monitor else {
+            // Nothing to keep the window on, e.g. on Wayland
+            let [width, height] = self.size;
+            window.set_inner_size(PhysicalSize::new(width.max(1), height.max(1)));
+            window.set_maximized(self.maximized);
+            return;
+        };
+        let (origin, extent) = (monitor.position(), monitor.size());
+        let width = self.size[0].clamp(1, extent.width.max(1));
+        let height = self.size[1].clamp(1, extent.height.max(1));
+        let x = self.position[0].clamp(origin.x, origin.x + extent.width.saturating_sub(width) as i32);
+        let y = self.position[1].clamp(origin.y, origin.y + extent.height.saturating_sub(height) as i32);
+        window.set_outer_position(PhysicalPosition::new(x, y));
+        window.set_inner_size(PhysicalSize::new(width, height));
+        window.set_maximized(self.maximized);
+    }
+
+    /// How many pixels of the window lie on `monitor`.
+    fn overlap(&self, monitor: &MonitorHandle) -> u64 {
+        let (origin, extent) = (monitor.position(), monitor.size());
+        let span = |start: i32, length: u32, monitor_start: i32, monitor_length: u32| {
+            let end = i64::from(start) + i64::from(length);
+            let monitor_end = i64::from(monitor_start) + i64::from(monitor_length);
+            (end.min(monitor_end) - i64::from(start.max(monitor_start))).max(0) as u64
+        };
+        span(self.position[0], self.size[0], origin.x, extent.width) * span(self.position[1], self.size[1], origin.y, extent.height)
+    }
+}
+
+impl Session {
+    /// The session saved last, `None` if there is none or it can't be read. A brush that can't
+    /// paint is replaced with the default one.
+    pub fn load() -> Option<Self> {
+        match persist::read(SESSION) {
+            Ok(Some(json)) => match serde_json::from_str::<Self>(&json) {
+                Ok(session) => Some(Self {
+                    brush: session.brush.validated().unwrap_or_else(|err| {
+                        tracing::warn!("Painting with the default brush instead of the saved one: {err}");
+                        Brush::default()
+                    }),
+                    ..session
+                }),
+                Err(err) => {
+                    tracing::warn!("Ignoring the saved session: {err}");
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("Couldn't read the last session: {err}");
+                None
+            }
+        }
+    }
+
+    /// Persists the session for the next start.
+    pub fn save(&self) {
+        let result = serde_json::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|json| persist::write(SESSION, &json));
+        if let Err(err) = result {
+            tracing::error!("Couldn't save the session: {err}");
+        }
+    }
+}
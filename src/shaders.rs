@@ -0,0 +1,333 @@
+//! The WGSL sources of the render pipelines.
+//!
+//! They are compiled into the binary. Debug builds can additionally pick up edits to the files
+//! in `src/` while running, see [`ShaderWatcher`].
+//!
+//! Sources are checked with naga before they reach the device, so mistakes are reported with
+//! their file and line instead of as a device validation panic.
+//!
+//! See [`crate::preprocessor`] for the directives shaders can use, like `#include`.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::preprocessor::{preprocess, ShaderDefines};
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The source doesn't parse or validate.
+    Invalid {
+        file: &'static str,
+        /// 1-based line and column of the first problem, if naga knows it.
+        location: Option<(u32, u32)>,
+        message: String,
+        /// The message, quoting the offending line.
+        report: String,
+    },
+    /// The shader is valid WGSL, but the device rejected the pipeline built from it.
+    Device(wgpu::Error),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Invalid { report, .. } => f.write_str(report.trim_end()),
+            ShaderError::Device(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// A shader file in `src/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shader {
+    /// Draws dots onto the canvas texture.
+    Dot,
+    /// Draws the canvas texture into the window.
+    SurfaceView,
+    /// Declarations shared by the shaders drawing dots, `#include`d rather than compiled on its own.
+    DotCommon,
+    /// The effects run after the canvas has been composited, see [`crate::post_process`].
+    PostProcess,
+    /// The passes of the bloom effect.
+    Bloom,
+    /// Grades the image with a 3D lookup table.
+    Lut,
+    /// Declarations shared by the post-processing shaders, `#include`d rather than compiled on its own.
+    PostProcessCommon,
+    /// Writes filter results back into the canvas, see [`crate::filter`].
+    Filter,
+    /// The compute passes of the gaussian blur filter.
+    Blur,
+    /// The compute pass of convolution filters.
+    Convolution,
+    /// The compute pass of color adjustment filters.
+    Adjustment,
+    /// The compute pass of gradient map filters.
+    GradientMap,
+    /// Counts the canvas into histograms, see [`crate::histogram`].
+    Histogram,
+    /// The region grow passes of the magic wand, see [`crate::selection`].
+    MagicWand,
+    /// Moves dots as particles, see [`crate::particles`].
+    Particles,
+    /// Wet paint flowing until it dries, see [`crate::watercolor`].
+    Watercolor,
+    /// Crops, borders and watermarks exports, see [`crate::framing`].
+    Framing,
+}
+
+impl Shader {
+    pub const ALL: [Shader; 17] = [
+        Shader::Dot,
+        Shader::SurfaceView,
+        Shader::DotCommon,
+        Shader::PostProcess,
+        Shader::Bloom,
+        Shader::Lut,
+        Shader::PostProcessCommon,
+        Shader::Filter,
+        Shader::Blur,
+        Shader::Convolution,
+        Shader::Adjustment,
+        Shader::GradientMap,
+        Shader::Histogram,
+        Shader::MagicWand,
+        Shader::Particles,
+        Shader::Watercolor,
+        Shader::Framing,
+    ];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Shader::Dot => "dot_shader.wgsl",
+            Shader::SurfaceView => "surface_view_shader.wgsl",
+            Shader::DotCommon => "dot_common.wgsl",
+            Shader::PostProcess => "post_process.wgsl",
+            Shader::Bloom => "bloom.wgsl",
+            Shader::Lut => "lut.wgsl",
+            Shader::PostProcessCommon => "post_process_common.wgsl",
+            Shader::Filter => "filter.wgsl",
+            Shader::Blur => "blur.wgsl",
+            Shader::Convolution => "convolution.wgsl",
+            Shader::Adjustment => "adjustment.wgsl",
+            Shader::GradientMap => "gradient_map.wgsl",
+            Shader::Histogram => "histogram.wgsl",
+            Shader::MagicWand => "magic_wand.wgsl",
+            Shader::Particles => "particles.wgsl",
+            Shader::Watercolor => "watercolor.wgsl",
+            Shader::Framing => "framing.wgsl",
+        }
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Shader> {
+        Shader::ALL.into_iter().find(|shader| shader.file_name() == file_name)
+    }
+
+    /// The source as it was when the binary was built.
+    pub fn embedded_source(self) -> &'static str {
+        match self {
+            Shader::Dot => include_str!("dot_shader.wgsl"),
+            Shader::SurfaceView => include_str!("surface_view_shader.wgsl"),
+            Shader::DotCommon => include_str!("dot_common.wgsl"),
+            Shader::PostProcess => include_str!("post_process.wgsl"),
+            Shader::Bloom => include_str!("bloom.wgsl"),
+            Shader::Lut => include_str!("lut.wgsl"),
+            Shader::PostProcessCommon => include_str!("post_process_common.wgsl"),
+            Shader::Filter => include_str!("filter.wgsl"),
+            Shader::Blur => include_str!("blur.wgsl"),
+            Shader::Convolution => include_str!("convolution.wgsl"),
+            Shader::Adjustment => include_str!("adjustment.wgsl"),
+            Shader::GradientMap => include_str!("gradient_map.wgsl"),
+            Shader::Histogram => include_str!("histogram.wgsl"),
+            Shader::MagicWand => include_str!("magic_wand.wgsl"),
+            Shader::Particles => include_str!("particles.wgsl"),
+            Shader::Watercolor => include_str!("watercolor.wgsl"),
+            Shader::Framing => include_str!("framing.wgsl"),
+        }
+    }
+
+    /// The current source from `src/`, including edits made since the build.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn read_from_disk(self) -> std::io::Result<String> {
+        std::fs::read_to_string(std::path::Path::new(SHADER_DIR).join(self.file_name()))
+    }
+
+    /// The source used for `#include`s: the file on disk in debug builds, so edits to it are picked
+    /// up by hot reload, and the embedded source otherwise.
+    pub fn include_source(self) -> Cow<'static, str> {
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        if let Ok(source) = self.read_from_disk() {
+            return Cow::Owned(source);
+        }
+        Cow::Borrowed(self.embedded_source())
+    }
+
+    /// The shaders whose pipelines need rebuilding when this file changes.
+    pub fn dependents(self) -> &'static [Shader] {
+        match self {
+            Shader::Dot | Shader::DotCommon => &[Shader::Dot],
+            Shader::SurfaceView => &[Shader::SurfaceView],
+            Shader::PostProcess => &[Shader::PostProcess],
+            Shader::Bloom => &[Shader::Bloom],
+            Shader::Lut => &[Shader::Lut],
+            Shader::PostProcessCommon => &[Shader::PostProcess, Shader::Bloom, Shader::Lut, Shader::Framing],
+            Shader::Filter => &[Shader::Filter],
+            Shader::Blur => &[Shader::Blur],
+            Shader::Convolution => &[Shader::Convolution],
+            Shader::Adjustment => &[Shader::Adjustment],
+            Shader::GradientMap => &[Shader::GradientMap],
+            Shader::Histogram => &[Shader::Histogram],
+            Shader::MagicWand => &[Shader::MagicWand],
+            Shader::Particles => &[Shader::Particles],
+            Shader::Watercolor => &[Shader::Watercolor],
+            Shader::Framing => &[Shader::Framing],
+        }
+    }
+
+    /// Preprocesses `source` with `defines` and checks the result with naga, returning the WGSL
+    /// to compile. Only the shader capabilities `features` enable are allowed, as a device with
+    /// those features would reject the rest anyway.
+    pub fn validate(self, source: &str, defines: &ShaderDefines, features: wgpu::Features) -> Result<String, ShaderError> {
+        let preprocessed = preprocess(self, source, defines)?;
+
+        let module = naga::front::wgsl::parse_str(&preprocessed.source).map_err(|err| {
+            let label = err.labels().next().map_or("", |(_, label)| label);
+            preprocessed.error(err.location(&preprocessed.source), err.message().to_owned(), label, &[])
+        })?;
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), capabilities(features))
+            .validate(&module)
+            .map_err(|err| {
+                let label = err.spans().next().map_or("", |(_, label)| label.as_str());
+                let mut notes = Vec::new();
+                let mut source: &dyn std::error::Error = err.as_inner();
+                while let Some(next) = source.source() {
+                    notes.push(next.to_string());
+                    source = next;
+                }
+                preprocessed.error(err.location(&preprocessed.source), err.as_inner().to_string(), label, &notes)
+            })?;
+
+        Ok(preprocessed.source)
+    }
+
+    /// Preprocesses `source` with `defines`, validates the result and compiles it.
+    pub fn create_module(
+        self,
+        device: &wgpu::Device,
+        source: &str,
+        defines: &ShaderDefines,
+    ) -> Result<wgpu::ShaderModule, ShaderError> {
+        let source = self.validate(source, defines, device.features())?;
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(self.file_name()),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        }))
+    }
+}
+
+/// The shader capabilities a device with `features` supports, as wgpu derives them itself.
+fn capabilities(features: wgpu::Features) -> naga::valid::Capabilities {
+    use naga::valid::Capabilities;
+
+    let non_uniform_indexing = features.contains(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+    let mut capabilities = Capabilities::empty();
+    capabilities.set(Capabilities::PUSH_CONSTANT, features.contains(wgpu::Features::PUSH_CONSTANTS));
+    capabilities.set(Capabilities::FLOAT64, features.contains(wgpu::Features::SHADER_FLOAT64));
+    capabilities.set(Capabilities::PRIMITIVE_INDEX, features.contains(wgpu::Features::SHADER_PRIMITIVE_INDEX));
+    capabilities.set(Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING, non_uniform_indexing);
+    capabilities.set(
+        Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        features.contains(wgpu::Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING),
+    );
+    capabilities.set(Capabilities::SAMPLER_NON_UNIFORM_INDEXING, non_uniform_indexing);
+    capabilities.set(Capabilities::STORAGE_TEXTURE_16BIT_NORM_FORMATS, features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM));
+    capabilities
+}
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+/// Runs `create`, returning the first device validation error it caused instead of panicking on it.
+///
+/// Used when rebuilding pipelines from edited shaders, so a mistake doesn't end the session.
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+pub fn catch_validation_error<T>(
+    device: &wgpu::Device,
+    create: impl FnOnce() -> Result<T, ShaderError>,
+) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(err) => Err(ShaderError::Device(err)),
+        None => created,
+    }
+}
+
+/// Watches the shader files in `src/` for changes.
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+pub struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    changes: std::sync::mpsc::Receiver<Shader>,
+}
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<Self> {
+        use notify::Watcher;
+
+        let (sender, changes) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            // Editors often save by writing a new file and renaming it over the old one
+            if !(event.kind.is_modify() || event.kind.is_create()) {
+                return;
+            }
+            for shader in Shader::ALL {
+                let touched = event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == Some(shader.file_name().as_ref()));
+                if touched {
+                    sender.send(shader).ok();
+                }
+            }
+        })?;
+        watcher.watch(std::path::Path::new(SHADER_DIR), notify::RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// The shaders to rebuild for the changes since the last call, each listed once.
+    ///
+    /// Changes to included files are reported as the shaders including them.
+    pub fn changed(&self) -> Vec<Shader> {
+        let mut changed = Vec::new();
+        for shader in self.changes.try_iter().flat_map(Shader::dependents) {
+            if !changed.contains(shader) {
+                changed.push(*shader);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_constants_need_the_feature() {
+        let source = Shader::SurfaceView.embedded_source();
+        let defines = ShaderDefines::new().with("PUSH_CONSTANTS");
+        assert!(Shader::SurfaceView.validate(source, &defines, wgpu::Features::empty()).is_err());
+        assert!(Shader::SurfaceView.validate(source, &defines, wgpu::Features::PUSH_CONSTANTS).is_ok());
+        assert!(Shader::SurfaceView.validate(source, &ShaderDefines::new(), wgpu::Features::empty()).is_ok());
+    }
+}
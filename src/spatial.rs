@@ -0,0 +1,265 @@
+//! Finding dots by where they are without going through all of them: a [`DotIndex`] sorts the
+//! dots of a canvas into a grid of cells over it, so picking the dot under the pointer, finding
+//! the dots in a rectangle and working out what a change touched only look at the cells there.
+//!
+//! [`HpSurface`](crate::surface::HpSurface) keeps one for its dots as they're added, replaced and
+//! removed, and leaves the dots beyond the canvas out when drawing and decimating them. Dots moved
+//! by particles on the GPU stay where they were added as far as it knows, so with particles all
+//! dots are drawn.
+
+use crate::filter::Region;
+use crate::surface::Dot;
+
+/// A rectangle in canvas coordinates, -1..1 with y up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl CanvasRect {
+    /// All of the canvas.
+    pub const CANVAS: Self = Self {
+        min: [-1.0, -1.0],
+        max: [1.0, 1.0],
+    };
+
+    /// The rectangle between two corners, in any order.
+    pub fn from_corners([x0, y0]: [f32; 2], [x1, y1]: [f32; 2]) -> Self {
+        Self {
+            min: [x0.min(x1), y0.min(y1)],
+            max: [x0.max(x1), y0.max(y1)],
+        }
+    }
+
+    /// What `dot` can cover, pulsing included.
+    pub fn around(dot: &Dot) -> Self {
+        let [x, y] = dot.position();
+        let radius = dot.radius() * (1.0 + dot.animation().pulse.abs());
+        Self {
+            min: [x - radius, y - radius],
+            max: [x + radius, y + radius],
+        }
+    }
+
+    /// What all of `dots` can cover, `None` without any.
+    pub fn around_all(dots: &[Dot]) -> Option<Self> {
+        dots.iter().map(Self::around).reduce(Self::union)
+    }
+
+    /// The smallest rectangle holding both.
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        }
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min[0] <= other.max[0] && other.min[0] <= self.max[0] && self.min[1] <= other.max[1] && other.min[1] <= self.max[1]
+    }
+
+    pub fn contains(&self, [x, y]: [f32; 2]) -> bool {
+        (self.min[0]..=self.max[0]).contains(&x) && (self.min[1]..=self.max[1]).contains(&y)
+    }
+
+    /// The pixels of a canvas of `canvas_size` the rectangle touches, from the top left, `None` if
+    /// it's outside of the canvas.
+    pub fn to_region(&self, canvas_size: u32) -> Option<Region> {
+        let size = canvas_size as f32;
+        let to_pixels = |value: f32| ((value + 1.0) / 2.0 * size).clamp(0.0, size);
+        let [left, right] = [to_pixels(self.min[0]).floor(), to_pixels(self.max[0]).ceil()];
+        // Rows count down from the top
+        let [top, bottom] = [size - to_pixels(self.max[1]).ceil(), size - to_pixels(self.min[1]).floor()];
+        (left < right && top < bottom).then_some(Region {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// The dots of a canvas by index, in the cells of a grid they cover. Dots beyond the canvas are
+/// in the cells at its edges.
+#[derive(Debug, Clone)]
+pub struct DotIndex {
+    /// Indices of the dots in each cell, row by row from the bottom, in drawing order.
+    cells: Vec<Vec<u32>>,
+    len: usize,
+}
+
+impl DotIndex {
+    /// Cells along each side of the canvas, about as large as a big brush.
+    pub const CELLS: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            cells: vec![Vec::new(); Self::CELLS * Self::CELLS],
+            len: 0,
+        }
+    }
+
+    /// Indexes all of `dots`.
+    pub fn from_dots(dots: &[Dot]) -> Self {
+        let mut index = Self::new();
+        for (i, dot) in dots.iter().enumerate() {
+            index.insert(i, dot);
+        }
+        index
+    }
+
+    /// Dots indexed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(Vec::clear);
+        self.len = 0;
+    }
+
+    /// Adds the dot at `index`, as it is.
+    pub fn insert(&mut self, index: usize, dot: &Dot) {
+        let index = index as u32;
+        for cell in Self::cells_of(CanvasRect::around(dot)) {
+            let cell = &mut self.cells[cell];
+            // Dots are mostly added at the end
+            match cell.last() {
+                Some(&last) if last > index => {
+                    let at = cell.binary_search(&index).unwrap_or_else(|at| at);
+                    cell.insert(at, index);
+                }
+                _ => cell.push(index),
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Removes the dot at `index`, which has to be as it was when it was inserted.
+    pub fn remove(&mut self, index: usize, dot: &Dot) {
+        let index = index as u32;
+        for cell in Self::cells_of(CanvasRect::around(dot)) {
+            let cell = &mut self.cells[cell];
+            if let Ok(at) = cell.binary_search(&index) {
+                cell.remove(at);
+            }
+        }
+        self.len -= 1;
+    }
+
+    /// Indices of the dots that may touch `rect`, in drawing order. Some may only come close.
+    pub fn candidates(&self, rect: CanvasRect) -> Vec<usize> {
+        let mut indices: Vec<usize> = Self::cells_of(rect)
+            .flat_map(|cell| &self.cells[cell])
+            .map(|&index| index as usize)
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// Indices of `dots` touching `rect`, in drawing order.
+    pub fn query(&self, rect: CanvasRect, dots: &[Dot]) -> Vec<usize> {
+        let mut indices = self.candidates(rect);
+        indices.retain(|&index| CanvasRect::around(&dots[index]).intersects(&rect));
+        indices
+    }
+
+    /// Index of the topmost of `dots` covering `position`, the one drawn last.
+    pub fn dot_at(&self, position: [f32; 2], dots: &[Dot]) -> Option<usize> {
+        let [x, y] = position;
+        let cell = Self::cells_of(CanvasRect::from_corners(position, position)).next()?;
+        self.cells[cell].iter().rev().map(|&index| index as usize).find(|&index| {
+            let dot = &dots[index];
+            let [dot_x, dot_y] = dot.position();
+            (x - dot_x).powi(2) + (y - dot_y).powi(2) <= dot.radius().powi(2)
+        })
+    }
+
+    /// Indices of `dots` entirely beyond the canvas, in drawing order, which don't have to be
+    /// drawn. Only the cells at its edges hold them.
+    pub fn beyond_canvas(&self, dots: &[Dot]) -> Vec<usize> {
+        let last = Self::CELLS - 1;
+        let edges = (0..Self::CELLS).flat_map(|cell| [cell, last * Self::CELLS + cell, cell * Self::CELLS, cell * Self::CELLS + last]);
+        let mut indices: Vec<usize> = edges
+            .flat_map(|cell| &self.cells[cell])
+            .map(|&index| index as usize)
+            .filter(|&index| !CanvasRect::around(&dots[index]).intersects(&CanvasRect::CANVAS))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// The cells `rect` covers, clamped to the grid.
+    fn cells_of(rect: CanvasRect) -> impl Iterator<Item = usize> {
+        let cell = |value: f32| (((value + 1.0) / 2.0 * Self::CELLS as f32).max(0.0) as usize).min(Self::CELLS - 1);
+        let [columns, rows] = [0, 1].map(|axis| cell(rect.min[axis])..=cell(rect.max[axis]));
+        rows.flat_map(move |row| columns.clone().map(move |column| row * Self::CELLS + column))
+    }
+}
+
+impl Default for DotIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn dot(position: [f32; 2], radius: f32) -> Dot {
+        Dot::new(position, radius, 0.5, Color::RED)
+    }
+
+    #[test]
+    fn queries_return_dots_in_drawing_order() {
+        // Inserted out of order, spanning several cells
+        let dots = [dot([0.0, 0.0], 0.2), dot([0.1, 0.1], 0.01), dot([-0.5, 0.5], 0.01), dot([0.05, 0.0], 0.3)];
+        let mut index = DotIndex::new();
+        for i in [3, 1, 0, 2] {
+            index.insert(i, &dots[i]);
+        }
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.query(CanvasRect::from_corners([-0.1, -0.1], [0.2, 0.2]), &dots), [0, 1, 3]);
+        assert_eq!(index.query(CanvasRect::CANVAS, &dots), [0, 1, 2, 3]);
+        assert_eq!(DotIndex::from_dots(&dots).query(CanvasRect::CANVAS, &dots), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn dot_at_finds_the_topmost_dot() {
+        let dots = [dot([0.0, 0.0], 0.2), dot([0.1, 0.0], 0.2), dot([0.9, 0.9], 0.05)];
+        let index = DotIndex::from_dots(&dots);
+        assert_eq!(index.dot_at([0.05, 0.0], &dots), Some(1));
+        assert_eq!(index.dot_at([-0.15, 0.0], &dots), Some(0));
+        assert_eq!(index.dot_at([-0.9, -0.9], &dots), None);
+    }
+
+    #[test]
+    fn removed_dots_are_no_longer_found() {
+        let dots = [dot([0.0, 0.0], 0.2), dot([0.1, 0.0], 0.2)];
+        let mut index = DotIndex::from_dots(&dots);
+        index.remove(1, &dots[1]);
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.dot_at([0.05, 0.0], &dots), Some(0));
+        assert_eq!(index.query(CanvasRect::CANVAS, &dots), [0]);
+        index.remove(0, &dots[0]);
+        assert!(index.is_empty());
+        assert!(index.candidates(CanvasRect::CANVAS).is_empty());
+    }
+
+    #[test]
+    fn dots_beyond_the_canvas_are_culled() {
+        let dots = [dot([1.5, 0.0], 0.1), dot([0.95, 0.0], 0.1), dot([0.0, -3.0], 0.5), dot([0.0, 0.0], 0.1)];
+        let index = DotIndex::from_dots(&dots);
+        assert_eq!(index.beyond_canvas(&dots), [0, 2]);
+        assert_eq!(index.query(CanvasRect::CANVAS, &dots), [1, 3]);
+    }
+}
@@ -0,0 +1,237 @@
+//! Stipple art from images: [`stipple`] turns a [`StippleImage`], e.g. a photo decoded with
+//! [`StippleImage::from_png`], into dots that are denser and larger where it's darker. They're
+//! scattered by a Poisson disk whose spacing follows the lightness, or laid out as a halftone
+//! screen.
+//!
+//! The image is fit into the canvas keeping its proportions. Lightness is Oklab's, so the tones
+//! come out as they look. Transparent parts get no dots.
+
+use std::f32::consts::PI;
+use std::fmt;
+
+use crate::color::Color;
+use crate::export;
+use crate::generators::PoissonDisk;
+use crate::surface::Dot;
+
+#[derive(Debug)]
+pub enum StippleError {
+    Decode(png::DecodingError),
+    /// The pixels don't fill the size given.
+    Size { width: u32, height: u32, bytes: usize },
+    /// A [`StippleOptions`] field that isn't finite or out of its range.
+    Option { name: &'static str, value: f32 },
+}
+
+impl fmt::Display for StippleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StippleError::Decode(err) => write!(f, "failed to decode the image: {err}"),
+            StippleError::Size { width, height, bytes } => {
+                write!(f, "{bytes} bytes aren't {width}x{height} RGBA pixels")
+            }
+            StippleError::Option { name, value } => write!(f, "the stipple option {name} can't be {value}"),
+        }
+    }
+}
+
+impl std::error::Error for StippleError {}
+
+/// The image dots are placed by.
+#[derive(Debug, Clone)]
+pub struct StippleImage {
+    width: u32,
+    height: u32,
+    /// Rows from the top.
+    pixels: Vec<Color>,
+}
+
+impl StippleImage {
+    /// From sRGB encoded RGBA pixels with straight alpha, rows from the top.
+    pub fn from_rgba8(width: u32, height: u32, rgba: &[u8]) -> Result<Self, StippleError> {
+        if width == 0 || height == 0 || rgba.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+            return Err(StippleError::Size {
+                width,
+                height,
+                bytes: rgba.len(),
+            });
+        }
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|pixel| Color::from_srgb8([pixel[0], pixel[1], pixel[2], pixel[3]]))
+            .collect();
+        Ok(Self { width, height, pixels })
+    }
+
+    /// Decodes a PNG of any color type and bit depth.
+    pub fn from_png(bytes: &[u8]) -> Result<Self, StippleError> {
+        let (width, height, rgba) = export::decode_png(bytes).map_err(StippleError::Decode)?;
+        Self::from_rgba8(width, height, &rgba)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Half the width and height the image covers of the canvas, fit into it.
+    fn extent(&self) -> [f32; 2] {
+        let longest = self.width.max(self.height) as f32;
+        [self.width as f32 / longest, self.height as f32 / longest]
+    }
+
+    /// The pixel at canvas `position`, `None` outside of the image.
+    fn sample(&self, [x, y]: [f32; 2]) -> Option<Color> {
+        let [half_width, half_height] = self.extent();
+        let u = (x + half_width) / (2.0 * half_width);
+        let v = (half_height - y) / (2.0 * half_height);
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        let column = (u * self.width as f32) as u32;
+        let row = (v * self.height as f32) as u32;
+        Some(self.pixels[(row * self.width + column) as usize])
+    }
+
+    /// How much ink the pixel at canvas `position` takes, 0 for white or transparent and 1 for
+    /// opaque black.
+    fn darkness(&self, position: [f32; 2]) -> f32 {
+        self.sample(position)
+            .map_or(0.0, |color| (1.0 - color.to_oklab().l).clamp(0.0, 1.0) * color.a)
+    }
+}
+
+/// How the dots are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StippleMode {
+    /// Scattered as many per area as the image is dark, the darkest parts packed tightest.
+    #[default]
+    PoissonDisk,
+    /// A screen of evenly spaced dots growing with the darkness, like print.
+    Halftone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StippleOptions {
+    pub mode: StippleMode,
+    /// Between dots in the darkest parts, in canvas units. The halftone screen's spacing.
+    pub min_distance: f32,
+    /// Between dots in the lightest parts that still get some.
+    pub max_distance: f32,
+    /// Of the dots in the lightest and the darkest parts.
+    pub min_radius: f32,
+    pub max_radius: f32,
+    pub hardness: f32,
+    /// The color of all dots, the image's colors if not set.
+    pub color: Option<Color>,
+    /// Parts lighter than this get no dots, 0..1 of darkness.
+    pub threshold: f32,
+    /// Turns the halftone screen, in degrees. Print turns it by 45 to hide the grid.
+    pub screen_angle: f32,
+    pub seed: u64,
+}
+
+impl Default for StippleOptions {
+    fn default() -> Self {
+        Self {
+            mode: StippleMode::default(),
+            min_distance: 0.008,
+            max_distance: 0.05,
+            min_radius: 0.002,
+            max_radius: 0.005,
+            hardness: 0.9,
+            color: Some(Color::BLACK),
+            threshold: 0.05,
+            screen_angle: 45.0,
+            seed: 0,
+        }
+    }
+}
+
+impl StippleOptions {
+    /// These options, or the first field that can't draw: distances and radii have to be finite
+    /// and above 0, the hardness in 0..=1 and the rest finite.
+    pub fn validated(self) -> Result<Self, StippleError> {
+        let positive = [
+            ("min_distance", self.min_distance),
+            ("max_distance", self.max_distance),
+            ("min_radius", self.min_radius),
+            ("max_radius", self.max_radius),
+        ];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(StippleError::Option { name, value });
+            }
+        }
+        if !(0.0..=1.0).contains(&self.hardness) {
+            return Err(StippleError::Option {
+                name: "hardness",
+                value: self.hardness,
+            });
+        }
+        for (name, value) in [("threshold", self.threshold), ("screen_angle", self.screen_angle)] {
+            if !value.is_finite() {
+                return Err(StippleError::Option { name, value });
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Dots drawing `image` as stipples, or why `options` can't draw it.
+pub fn stipple(image: &StippleImage, options: &StippleOptions) -> Result<Vec<Dot>, StippleError> {
+    let options = &options.validated()?;
+    let positions = match options.mode {
+        StippleMode::PoissonDisk => weighted_poisson_disk(image, options),
+        StippleMode::Halftone => halftone(image, options),
+    };
+    let dots = positions
+        .into_iter()
+        .filter_map(|position| {
+            let darkness = image.darkness(position);
+            if darkness <= options.threshold {
+                return None;
+            }
+            let radius = match options.mode {
+                StippleMode::PoissonDisk => options.min_radius + (options.max_radius - options.min_radius) * darkness,
+                // Covers as much of its cell as the pixel is dark
+                StippleMode::Halftone => options.min_distance.max(PoissonDisk::MIN_DISTANCE) * (darkness / PI).sqrt(),
+            };
+            let color = options.color.or_else(|| image.sample(position))?;
+            Some(Dot::new(position, radius, options.hardness, Color { a: 1.0, ..color }))
+        })
+        .collect();
+    Ok(dots)
+}
+
+/// A Poisson disk with the spacing of every point from the darkness under it.
+fn weighted_poisson_disk(image: &StippleImage, options: &StippleOptions) -> Vec<[f32; 2]> {
+    let min_distance = options.min_distance.max(PoissonDisk::MIN_DISTANCE);
+    let max_distance = options.max_distance.max(min_distance);
+    let disk = PoissonDisk {
+        min_distance,
+        seed: options.seed,
+    };
+    // As many dots per area as the image is dark
+    disk.positions_spaced(image.extent(), |position| (min_distance / image.darkness(position).sqrt()).min(max_distance))
+}
+
+/// Centers of a screen of `min_distance` cells turned by the screen angle, over the image.
+fn halftone(image: &StippleImage, options: &StippleOptions) -> Vec<[f32; 2]> {
+    let spacing = options.min_distance.max(PoissonDisk::MIN_DISTANCE);
+    let [half_width, half_height] = image.extent();
+    let (sin, cos) = options.screen_angle.to_radians().sin_cos();
+    // The turned screen has to reach the corners
+    let reach = (half_width.hypot(half_height) / spacing).ceil() as i32;
+    (-reach..=reach)
+        .flat_map(|row| (-reach..=reach).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let [x, y] = [column as f32 * spacing, row as f32 * spacing];
+            [x * cos - y * sin, x * sin + y * cos]
+        })
+        .filter(|&[x, y]| x.abs() < half_width && y.abs() < half_height)
+        .collect()
+}
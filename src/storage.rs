@@ -0,0 +1,143 @@
+//! IndexedDB persistence of documents for the web build.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use futures_channel::oneshot;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{IdbDatabase, IdbObjectStore, IdbOpenDbRequest, IdbRequest, IdbTransactionMode};
+
+use crate::document::Document;
+
+const DATABASE_NAME: &str = "hellopaint";
+const DATABASE_VERSION: u32 = 1;
+const STORE_NAME: &str = "documents";
+
+#[derive(Debug)]
+pub enum StorageError {
+    Js(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Js(err) => write!(f, "IndexedDB error: {err}"),
+            StorageError::Json(err) => write!(f, "invalid document: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<JsValue> for StorageError {
+    fn from(value: JsValue) -> Self {
+        StorageError::Js(format!("{value:?}"))
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(err: serde_json::Error) -> Self {
+        StorageError::Json(err)
+    }
+}
+
+/// Documents stored as JSON strings in an IndexedDB object store, keyed by name.
+pub struct IndexedDbStorage {
+    database: IdbDatabase,
+}
+
+impl IndexedDbStorage {
+    pub async fn open() -> Result<Self, StorageError> {
+        let factory = web_sys::window()
+            .ok_or_else(|| StorageError::Js("no window".to_owned()))?
+            .indexed_db()?
+            .ok_or_else(|| StorageError::Js("IndexedDB is not available".to_owned()))?;
+
+        let request = factory.open_with_u32(DATABASE_NAME, DATABASE_VERSION)?;
+        let on_upgrade_needed = Closure::<dyn FnMut(web_sys::Event)>::new(|event: web_sys::Event| {
+            let database = event
+                .target()
+                .and_then(|target| target.dyn_into::<IdbOpenDbRequest>().ok())
+                .and_then(|request| request.result().ok())
+                .and_then(|result| result.dyn_into::<IdbDatabase>().ok());
+            if let Some(database) = database {
+                if !database.object_store_names().contains(STORE_NAME) {
+                    database.create_object_store(STORE_NAME).ok();
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+
+        let database = request_result(&request).await?.dyn_into()?;
+        Ok(Self { database })
+    }
+
+    pub async fn save(&self, name: &str, document: &Document) -> Result<(), StorageError> {
+        let json = document.to_json()?;
+        let store = self.store(IdbTransactionMode::Readwrite)?;
+        request_result(&store.put_with_key(&JsValue::from_str(&json), &JsValue::from_str(name))?).await?;
+        Ok(())
+    }
+
+    pub async fn load(&self, name: &str) -> Result<Option<Document>, StorageError> {
+        let store = self.store(IdbTransactionMode::Readonly)?;
+        let value = request_result(&store.get(&JsValue::from_str(name))?).await?;
+        Ok(value.as_string().map(|json| Document::from_json(&json)).transpose()?)
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), StorageError> {
+        let store = self.store(IdbTransactionMode::Readwrite)?;
+        request_result(&store.delete(&JsValue::from_str(name))?).await?;
+        Ok(())
+    }
+
+    /// Names of all stored documents.
+    pub async fn list(&self) -> Result<Vec<String>, StorageError> {
+        let store = self.store(IdbTransactionMode::Readonly)?;
+        let keys: js_sys::Array = request_result(&store.get_all_keys()?).await?.dyn_into()?;
+        Ok(keys.iter().filter_map(|key| key.as_string()).collect())
+    }
+
+    fn store(&self, mode: IdbTransactionMode) -> Result<IdbObjectStore, StorageError> {
+        let transaction = self.database.transaction_with_str_and_mode(STORE_NAME, mode)?;
+        Ok(transaction.object_store(STORE_NAME)?)
+    }
+}
+
+/// Waits for an IndexedDB request to succeed or fail.
+async fn request_result(request: &IdbRequest) -> Result<JsValue, StorageError> {
+    let (sender, receiver) = oneshot::channel();
+    let sender = Rc::new(RefCell::new(Some(sender)));
+
+    let on_success = {
+        let sender = sender.clone();
+        Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+            if let Some(sender) = sender.borrow_mut().take() {
+                sender.send(true).ok();
+            }
+        })
+    };
+    let on_error = Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+        if let Some(sender) = sender.borrow_mut().take() {
+            sender.send(false).ok();
+        }
+    });
+    request.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+    request.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+    let succeeded = receiver.await.unwrap_or(false);
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+
+    if succeeded {
+        Ok(request.result()?)
+    } else {
+        let message = request
+            .error()?
+            .map_or_else(|| "request failed".to_owned(), |err| err.message());
+        Err(StorageError::Js(message))
+    }
+}
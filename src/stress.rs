@@ -0,0 +1,107 @@
+//! A stress test of the renderer, started with `--stress <dots per frame>`: random dots are added
+//! every frame until frames take longer than the budget, then the number of dots reached and the
+//! frame times are reported as JSON.
+//!
+//! Frame times are the intervals between rendered frames, averaged over [`FRAME_WINDOW`] frames so
+//! a single slow one doesn't end the test. They include waiting for the next swapchain image, and
+//! presenting waits for vsync, so budgets below the display's refresh interval are exceeded right
+//! away.
+
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::clock::Clock;
+use crate::surface::Dot;
+
+/// How many frames the frame time is averaged over.
+pub(crate) const FRAME_WINDOW: usize = 8;
+
+/// A rendered frame and how long it took.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct FrameSample {
+    /// On the canvas.
+    pub dots: usize,
+    pub milliseconds: f64,
+}
+
+/// What a stress test reached, serialized as its JSON output.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StressReport {
+    pub dots_per_frame: usize,
+    pub budget_milliseconds: f64,
+    /// The most dots drawn while the average frame time stayed within the budget.
+    pub dots: usize,
+    /// How long the test ran.
+    pub seconds: f64,
+    /// Every frame, in order.
+    pub frames: Vec<FrameSample>,
+}
+
+impl StressReport {
+    /// Prints the report to stdout, or logs it on the web where there is none.
+    pub fn publish(&self) {
+        let json = serde_json::to_string_pretty(self).expect("stress reports are plain data");
+        #[cfg(not(target_arch = "wasm32"))]
+        println!("{json}");
+        #[cfg(target_arch = "wasm32")]
+        tracing::info!("Stress test finished: {json}");
+    }
+}
+
+/// What to do after a frame of the stress test.
+pub(crate) enum StressStep {
+    /// Add these dots and render the next frame.
+    AddDots(Vec<Dot>),
+    /// The budget was exceeded, the test is over.
+    Done(StressReport),
+}
+
+/// Adds dots frame by frame and times the frames, see the [module docs](self).
+pub(crate) struct StressTest {
+    dots_per_frame: usize,
+    budget_milliseconds: f64,
+    rng: ChaCha8Rng,
+    clock: Clock,
+    last_frame: Option<f64>,
+    frames: Vec<FrameSample>,
+    within_budget: usize,
+}
+
+impl StressTest {
+    pub fn new(dots_per_frame: usize, budget_milliseconds: f64, rng: ChaCha8Rng) -> Self {
+        Self {
+            dots_per_frame,
+            budget_milliseconds,
+            rng,
+            clock: Clock::new(),
+            last_frame: None,
+            frames: Vec::new(),
+            within_budget: 0,
+        }
+    }
+
+    /// Times the frame just rendered with `dots` on the canvas and decides on the next.
+    pub fn frame_rendered(&mut self, dots: usize) -> StressStep {
+        let now = self.clock.elapsed_seconds();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.frames.push(FrameSample {
+                dots,
+                milliseconds: (now - last_frame) * 1000.0,
+            });
+            if let Some(window) = self.frames.len().checked_sub(FRAME_WINDOW).map(|start| &self.frames[start..]) {
+                let average = window.iter().map(|frame| frame.milliseconds).sum::<f64>() / FRAME_WINDOW as f64;
+                if average > self.budget_milliseconds {
+                    return StressStep::Done(StressReport {
+                        dots_per_frame: self.dots_per_frame,
+                        budget_milliseconds: self.budget_milliseconds,
+                        dots: self.within_budget,
+                        seconds: now,
+                        frames: std::mem::take(&mut self.frames),
+                    });
+                }
+                self.within_budget = dots;
+            }
+        }
+        StressStep::AddDots((0..self.dots_per_frame).map(|_| Dot::random(&mut self.rng)).collect())
+    }
+}
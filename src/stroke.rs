@@ -0,0 +1,211 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::surface::{Dot, DotError, DotNoise};
+
+/// Parameters applied to every dot of a stroke. Saved with the strokes of a
+/// [`Document`](crate::document::Document), fields left out are the defaults'.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Brush {
+    /// Radius at full pressure, in canvas units (the canvas spans -1..1).
+    pub radius: f32,
+    pub hardness: f32,
+    pub color: Color,
+    /// Each dot's hue is shifted by up to this many degrees either way, for lively strokes.
+    pub hue_jitter: f32,
+    /// Distance between consecutive dots, relative to their radius.
+    pub spacing: f32,
+    /// Texture of the dots. Each dot offsets the seed, so neighbouring stamps differ.
+    pub noise: DotNoise,
+    /// Paints watercolor that flows until it dries rather than dots, see [`crate::watercolor`].
+    pub wet: bool,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            radius: 0.02,
+            hardness: 0.5,
+            color: Color::RED,
+            hue_jitter: 0.0,
+            spacing: 0.25,
+            noise: DotNoise::default(),
+            wet: false,
+        }
+    }
+}
+
+/// Why a brush can't paint, see [`Brush::validated`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrushError {
+    /// Its dots can't be drawn.
+    Dot(DotError),
+    /// Not a finite hue jitter.
+    HueJitter(f32),
+    /// Not a finite spacing above 0.
+    Spacing(f32),
+}
+
+impl fmt::Display for BrushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrushError::Dot(err) => write!(f, "brushes paint dots, but {err}"),
+            BrushError::HueJitter(jitter) => write!(f, "brushes need a finite hue jitter, not {jitter}"),
+            BrushError::Spacing(spacing) => write!(f, "brushes need a finite spacing above 0, not {spacing}"),
+        }
+    }
+}
+
+impl std::error::Error for BrushError {}
+
+impl Brush {
+    /// This brush with its dots' parameters clamped to their ranges, like [`Dot::validated`], or
+    /// why it can't paint at all, e.g. when it was saved by hand.
+    pub fn validated(self) -> Result<Self, BrushError> {
+        if !self.hue_jitter.is_finite() {
+            return Err(BrushError::HueJitter(self.hue_jitter));
+        }
+        if !(self.spacing.is_finite() && self.spacing > 0.0) {
+            return Err(BrushError::Spacing(self.spacing));
+        }
+        let dot = Dot::new([0.0, 0.0], self.radius, self.hardness, self.color)
+            .with_noise(self.noise)
+            .validated()
+            .map_err(BrushError::Dot)?;
+        Ok(Self {
+            hardness: dot.hardness(),
+            color: dot.color(),
+            noise: dot.noise(),
+            ..self
+        })
+    }
+
+    /// The dot at `index` of a stroke, at `position` with `radius`.
+    pub fn stamp(&self, index: usize, position: [f32; 2], radius: f32) -> Dot {
+        let noise = DotNoise {
+            seed: self.noise.seed + index as f32,
+            ..self.noise
+        };
+        let mut color = self.color;
+        if self.hue_jitter > 0.0 {
+            color = color.shift_hue(self.hue_jitter * jitter(index));
+        }
+        Dot::new(position, radius, self.hardness, color).with_noise(noise)
+    }
+}
+
+/// Pressure below this still leaves a visible dot.
+const MIN_PRESSURE: f32 = 0.1;
+
+/// The closest dots are placed, in canvas units, however small the brush. Also keeps the walk
+/// moving where adding a smaller step wouldn't change the distance travelled.
+const MIN_STEP: f32 = 1e-4;
+
+/// The most dots one pointer sample adds, for samples far off the canvas.
+const MAX_DOTS_PER_SEGMENT: usize = 10_000;
+
+/// A stroke being drawn: turns pointer samples into evenly spaced dots, so fast movements
+/// don't leave gaps.
+#[derive(Debug, Clone)]
+pub struct Stroke {
+    brush: Brush,
+    last: Option<([f32; 2], f32)>,
+    dots: Vec<Dot>,
+}
+
+impl Stroke {
+    pub fn new(brush: Brush) -> Self {
+        Self {
+            brush,
+            last: None,
+            dots: Vec::new(),
+        }
+    }
+
+    pub fn brush(&self) -> &Brush {
+        &self.brush
+    }
+
+    /// Adds a pointer sample in canvas coordinates and returns the dots it produced.
+    ///
+    /// `pressure` is in 0..=1 and scales the dot radius. Samples that aren't finite add nothing.
+    pub fn add_point(&mut self, position: [f32; 2], pressure: f32) -> &[Dot] {
+        let first_new = self.dots.len();
+        if !(position.iter().all(|x| x.is_finite()) && pressure.is_finite()) {
+            return &self.dots[first_new..];
+        }
+        let pressure = pressure.clamp(MIN_PRESSURE, 1.0);
+
+        let Some((last_position, last_pressure)) = self.last else {
+            self.push_dot(position, pressure);
+            self.last = Some((position, pressure));
+            return &self.dots[first_new..];
+        };
+
+        // Walk from the last dot towards the new sample, placing a dot every `spacing` radii
+        let total = distance(last_position, position);
+        let mut travelled = 0.0;
+        for _ in 0..MAX_DOTS_PER_SEGMENT {
+            let step_pressure = lerp(last_pressure, pressure, fraction(travelled, total));
+            let step = (self.brush.radius * step_pressure * self.brush.spacing).max(MIN_STEP);
+            if travelled + step > total {
+                break;
+            }
+            travelled += step;
+
+            let t = fraction(travelled, total);
+            let dot_position = [
+                lerp(last_position[0], position[0], t),
+                lerp(last_position[1], position[1], t),
+            ];
+            let dot_pressure = lerp(last_pressure, pressure, t);
+            self.push_dot(dot_position, dot_pressure);
+            self.last = Some((dot_position, dot_pressure));
+        }
+
+        &self.dots[first_new..]
+    }
+
+    pub fn dots(&self) -> &[Dot] {
+        &self.dots
+    }
+
+    pub fn finish(self) -> Vec<Dot> {
+        self.dots
+    }
+
+    fn push_dot(&mut self, position: [f32; 2], pressure: f32) {
+        let dot = self.brush.stamp(self.dots.len(), position, self.brush.radius * pressure);
+        self.dots.push(dot);
+    }
+}
+
+/// A pseudo random number in -1..1 for the dot at `index`, the same every time so replaying a
+/// stroke gives the same dots.
+fn jitter(index: usize) -> f32 {
+    // SplitMix64's finalizer
+    let mut x = (index as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn fraction(part: f32, total: f32) -> f32 {
+    if total > 0.0 {
+        part / total
+    } else {
+        1.0
+    }
+}
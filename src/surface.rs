@@ -1,10 +1,41 @@
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
 
 use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use wgpu::SamplerDescriptor;
 use wgpu::util::DeviceExt;
 
+use crate::color::Color;
+use crate::diagnostics::{self, Scope};
+use crate::document::{Document, DocumentStroke, StrokeId, StrokeInfo};
+use crate::dump::InstanceReadback;
+use crate::error::Error;
+use crate::export::TextureReadback;
+use crate::filter::{self, Filter, FilterError, FilterKind, FilterPipelines, FilterProgress, FilterTargets, Kernel};
+use crate::framing::{Framing, FramingPass};
+use crate::histogram::{self, HistogramPass, HistogramReadback};
+use crate::lod::{self, LodSettings};
+use crate::new_document::{Background, CanvasSetup};
+use crate::particles::{self, ParticlePass, ParticleSettings, ParticleSystem};
+use crate::plugin::{CanvasInfo, PluginContext, RenderPassPlugin};
+use crate::preprocessor::ShaderDefines;
+use crate::renderer::{PipelineCache, PipelineKey};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::renderer;
+use crate::selection::{self, MagicWand, MagicWandPass, Selection};
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::shaders::{Shader, ShaderError};
+use crate::spatial::{CanvasRect, DotIndex};
+use crate::tween::{Sequence, Tweens};
+use crate::watercolor::{self, WatercolorPass, WetLayer, WetSettings};
+
+/// `VertexInput` in dot_common.wgsl.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 struct Vertex {
@@ -18,41 +49,490 @@ impl Vertex {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &self::Vertex::ATTRIBUTES,
+            attributes: Vertex::ATTRIBUTES,
         }
     }
 }
 
+/// A dot on the canvas, drawn as one instance. Its shader side is `Dot` in dot_common.wgsl.
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
 pub struct Dot {
     position: [f32; 2],
     radius: f32,
     hardness: f32,
-    color: [f32; 4],
+    color: Color,
+    #[serde(default)]
+    noise: DotNoise,
+    #[serde(default)]
+    animation: DotAnimation,
+    /// Where the dot is drawn among the others on canvases ordered by [`DotOrder::Depth`].
+    #[serde(default)]
+    depth: f32,
+}
+
+/// Why a dot can't be drawn, see [`Dot::validated`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DotError {
+    /// Not a finite position.
+    Position([f32; 2]),
+    /// Not a finite radius above 0.
+    Radius(f32),
+    /// A NaN hardness.
+    Hardness,
+    Color(Color),
+    Noise(DotNoise),
+    Animation(DotAnimation),
+    /// Not a finite depth.
+    Depth(f32),
+}
+
+impl fmt::Display for DotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DotError::Position(position) => write!(f, "dots need a finite position, not {position:?}"),
+            DotError::Radius(radius) => write!(f, "dots need a finite radius above 0, not {radius}"),
+            DotError::Hardness => f.write_str("dots need a hardness, not NaN"),
+            DotError::Color(color) => write!(f, "dots need finite color components, not {color:?}"),
+            DotError::Noise(noise) => write!(f, "dots need finite noise parameters, not {noise:?}"),
+            DotError::Animation(animation) => write!(f, "dots need finite animation parameters, not {animation:?}"),
+            DotError::Depth(depth) => write!(f, "dots need a finite depth, not {depth}"),
+        }
+    }
+}
+
+impl std::error::Error for DotError {}
+
+/// Builds a [`Dot`] from parameters in whichever units are at hand, checked by [`Self::build`].
+/// Starts out like a dot of the default [`Brush`](crate::stroke::Brush) in the canvas center.
+#[derive(Debug, Clone, Copy)]
+pub struct DotBuilder {
+    dot: Dot,
+}
+
+impl Default for DotBuilder {
+    fn default() -> Self {
+        let brush = crate::stroke::Brush::default();
+        Self {
+            dot: Dot::new([0.0, 0.0], brush.radius, brush.hardness, brush.color).with_noise(brush.noise),
+        }
+    }
+}
+
+impl DotBuilder {
+    /// In canvas coordinates, -1..1 with y up.
+    pub fn position(mut self, position: [f32; 2]) -> Self {
+        self.dot.position = position;
+        self
+    }
+
+    /// In pixels of a canvas `canvas_size` pixels wide, from its top left with y down.
+    pub fn pixel_position(self, [x, y]: [f32; 2], canvas_size: u32) -> Self {
+        let size = canvas_size as f32;
+        self.position([x / size * 2.0 - 1.0, 1.0 - y / size * 2.0])
+    }
+
+    /// In canvas units, where 1 is half the canvas.
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.dot.radius = radius;
+        self
+    }
+
+    /// In pixels of a canvas `canvas_size` pixels wide.
+    pub fn pixel_radius(self, radius: f32, canvas_size: u32) -> Self {
+        self.radius(radius / canvas_size as f32 * 2.0)
+    }
+
+    /// 0 for a soft falloff to 1 for a hard edge.
+    pub fn hardness(mut self, hardness: f32) -> Self {
+        self.dot.hardness = hardness;
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.dot.color = color;
+        self
+    }
+
+    /// sRGB with straight alpha, each 0..255, like CSS colors.
+    pub fn rgba8(self, rgba: [u8; 4]) -> Self {
+        self.color(Color::from_srgb8(rgba))
+    }
+
+    pub fn noise(mut self, noise: DotNoise) -> Self {
+        self.dot.noise = noise;
+        self
+    }
+
+    pub fn animation(mut self, animation: DotAnimation) -> Self {
+        self.dot.animation = animation;
+        self
+    }
+
+    /// 0 at the back to 1 at the front, see [`DotOrder::Depth`].
+    pub fn depth(mut self, depth: f32) -> Self {
+        self.dot.depth = depth;
+        self
+    }
+
+    /// The dot, with out of range values clamped, or why it can't be drawn.
+    pub fn build(self) -> Result<Dot, DotError> {
+        self.dot.validated()
+    }
+}
+
+/// Procedural texture for a dot's stamp, so it doesn't look perfectly smooth.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct DotNoise {
+    /// Number of noise features across the stamp.
+    pub scale: f32,
+    /// Picks a different pattern, dots with the same seed and scale look the same.
+    pub seed: f32,
+    /// How much the noise thins out the stamp, 0 for none and 1 for holes.
+    pub strength: f32,
+}
+
+/// Changes a dot's radius, alpha and color over time, computed by the dot shader from the canvas
+/// clock set with [`GlobalSurface::set_seconds`], so animated dots don't have to be uploaded again
+/// every frame. The default keeps the dot as it is, and the effects combine.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct DotAnimation {
+    /// Canvas seconds the animation starts at. Until then the dot is drawn as it is, or not at all
+    /// when fading in.
+    pub start: f32,
+    /// Seconds a fade takes and a pulse lasts.
+    pub duration: f32,
+    /// How far the radius swells and shrinks with every pulse, relative to it. 0 doesn't pulse.
+    pub pulse: f32,
+    /// How much the dot fades in over `duration`, up to 1, or out below 0. 0 doesn't fade.
+    pub fade: f32,
+    /// Turns the hue is rotated by per second. 0 keeps the color.
+    pub hue_speed: f32,
+}
+
+impl DotAnimation {
+    /// Swells and shrinks the radius by `amount` of it, a pulse every `period` seconds.
+    pub const fn pulse(start: f32, period: f32, amount: f32) -> Self {
+        Self {
+            start,
+            duration: period,
+            pulse: amount,
+            fade: 0.0,
+            hue_speed: 0.0,
+        }
+    }
+
+    /// Fades from transparent to the dot's alpha over `duration` seconds.
+    pub const fn fade_in(start: f32, duration: f32) -> Self {
+        Self {
+            start,
+            duration,
+            pulse: 0.0,
+            fade: 1.0,
+            hue_speed: 0.0,
+        }
+    }
+
+    /// Fades from the dot's alpha to transparent over `duration` seconds.
+    pub const fn fade_out(start: f32, duration: f32) -> Self {
+        Self {
+            start,
+            duration,
+            pulse: 0.0,
+            fade: -1.0,
+            hue_speed: 0.0,
+        }
+    }
+
+    /// Also cycles the hue, `turns` times per second.
+    pub const fn with_hue_speed(mut self, turns: f32) -> Self {
+        self.hue_speed = turns;
+        self
+    }
+
+    /// Whether this changes the dot at all.
+    pub fn is_animated(&self) -> bool {
+        self.pulse != 0.0 || self.fade != 0.0 || self.hue_speed != 0.0
+    }
 }
 
 impl Dot {
-    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![1 => Float32x2, 2 => Float32, 3 => Float32, 4 => Float32x4];
+    /// A dot taken as is. Dots from untrusted input should come from [`Self::builder`] or be
+    /// [`Self::validated`] instead.
+    pub const fn new(position: [f32; 2], radius: f32, hardness: f32, color: Color) -> Self {
+        Self {
+            position,
+            radius,
+            hardness,
+            color,
+            noise: DotNoise {
+                scale: 0.0,
+                seed: 0.0,
+                strength: 0.0,
+            },
+            animation: DotAnimation {
+                start: 0.0,
+                duration: 0.0,
+                pulse: 0.0,
+                fade: 0.0,
+                hue_speed: 0.0,
+            },
+            depth: 0.0,
+        }
+    }
 
-    const fn vertex_buffer_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    pub const fn with_noise(mut self, noise: DotNoise) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub const fn with_animation(mut self, animation: DotAnimation) -> Self {
+        self.animation = animation;
+        self
+    }
+
+    /// 0 at the back to 1 at the front, see [`DotOrder::Depth`].
+    pub const fn with_depth(mut self, depth: f32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// The center in canvas coordinates, -1..1 with y up.
+    pub fn position(&self) -> [f32; 2] {
+        self.position
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn hardness(&self) -> f32 {
+        self.hardness
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn noise(&self) -> DotNoise {
+        self.noise
+    }
+
+    pub fn animation(&self) -> DotAnimation {
+        self.animation
+    }
+
+    pub fn depth(&self) -> f32 {
+        self.depth
+    }
+
+    /// Whether the dot changes over time, see [`DotAnimation`].
+    pub fn is_animated(&self) -> bool {
+        self.animation.is_animated()
+    }
+
+    /// This dot as the dot shader draws it at canvas time `seconds`, with its animation applied.
+    /// `animate` in dot_common.wgsl does the same.
+    pub fn animated(self, seconds: f32) -> Self {
+        let animation = self.animation;
+        let elapsed = (seconds - animation.start).max(0.0);
+        let progress = ((seconds - animation.start) / animation.duration.max(1e-6)).clamp(0.0, 1.0);
+
+        let wave = if animation.duration > 0.0 {
+            (elapsed / animation.duration * std::f32::consts::TAU).sin()
+        } else {
+            0.0
+        };
+        let radius = self.radius * (1.0 + animation.pulse * wave).max(0.0);
+
+        let faded = if animation.fade >= 0.0 { progress } else { 1.0 - progress };
+        let alpha = self.color.a * (1.0 + (faded - 1.0) * animation.fade.abs());
+
+        // Rotates the color around the gray axis, which keeps its brightness
+        let angle = elapsed * animation.hue_speed * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        let Color { r, g, b, .. } = self.color;
+        let k = 1.0 / 3.0f32.sqrt();
+        let gray = (r + g + b) * k * (1.0 - cos);
+        let rotate = |value: f32, cross: f32| (value * cos + cross * k * sin + k * gray).clamp(0.0, 1.0);
+        let color = Color::new(rotate(r, b - g), rotate(g, r - b), rotate(b, g - r), alpha);
+
+        Self { radius, color, ..self }
+    }
+
+    /// Builds a dot from checked parameters, in canvas or pixel units.
+    pub fn builder() -> DotBuilder {
+        DotBuilder::default()
+    }
+
+    /// This dot with its hardness, color and noise clamped to their ranges, or why it can't be
+    /// drawn at all.
+    pub fn validated(self) -> Result<Self, DotError> {
+        if !self.position.iter().all(|value| value.is_finite()) {
+            return Err(DotError::Position(self.position));
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            return Err(DotError::Radius(self.radius));
+        }
+        if self.hardness.is_nan() {
+            return Err(DotError::Hardness);
+        }
+        let Color { r, g, b, a } = self.color;
+        if ![r, g, b, a].iter().all(|value| value.is_finite()) {
+            return Err(DotError::Color(self.color));
+        }
+        let noise = self.noise;
+        if ![noise.scale, noise.seed, noise.strength].iter().all(|value| value.is_finite()) {
+            return Err(DotError::Noise(noise));
+        }
+        let animation = self.animation;
+        let DotAnimation {
+            start,
+            duration,
+            pulse,
+            fade,
+            hue_speed,
+        } = animation;
+        if ![start, duration, pulse, fade, hue_speed].iter().all(|value| value.is_finite()) {
+            return Err(DotError::Animation(animation));
+        }
+        if !self.depth.is_finite() {
+            return Err(DotError::Depth(self.depth));
+        }
+        Ok(Self {
+            hardness: self.hardness.clamp(0.0, 1.0),
+            color: Color::new(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), a.clamp(0.0, 1.0)),
+            noise: DotNoise {
+                scale: noise.scale.max(0.0),
+                strength: noise.strength.clamp(0.0, 1.0),
+                ..noise
+            },
+            animation: DotAnimation {
+                duration: duration.max(0.0),
+                fade: fade.clamp(-1.0, 1.0),
+                ..animation
+            },
+            depth: self.depth.clamp(0.0, 1.0),
+            ..self
+        })
+    }
+
+    /// A dot somewhere on the canvas with random size, hardness and opaque color. Seeded
+    /// generators like `ChaCha8Rng` give the same dots on every platform.
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            position: [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)],
+            radius: rng.gen_range(0.005..0.05),
+            hardness: rng.gen(),
+            color: Color::new(rng.gen(), rng.gen(), rng.gen(), 1.0),
+            noise: DotNoise::default(),
+            animation: DotAnimation::default(),
+            depth: 0.0,
+        }
+    }
+
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![1 => Float32x2, 2 => Float32, 3 => Float32, 4 => Float32x4, 5 => Float32x3, 6 => Float32x4, 7 => Float32, 8 => Float32];
+
+    /// Layout of [`HpSurface::instance_buffers`], at vertex locations 1 to 8.
+    pub const fn vertex_buffer_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Dot>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &self::Dot::ATTRIBUTES,
+            attributes: Dot::ATTRIBUTES,
+        }
+    }
+}
+
+/// Vertex attributes the dot pipelines take with [`DotInput::VertexAttributes`].
+const DOT_VERTEX_ATTRIBUTES: u32 = (Vertex::ATTRIBUTES.len() + Dot::ATTRIBUTES.len()) as u32;
+
+/// How the dot pipelines get their dots, picked by [`GlobalSurface`] from the device's limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotInput {
+    /// As instanced vertex attributes, see [`Dot::vertex_buffer_desc`].
+    VertexAttributes,
+    /// Pulled out of a storage buffer by instance index, on devices with too few vertex
+    /// attributes. WebGL has no storage buffers to fall back to.
+    StorageBuffer,
+}
+
+impl DotInput {
+    fn for_limits(limits: &wgpu::Limits) -> Result<Self, Error> {
+        if limits.max_vertex_attributes >= DOT_VERTEX_ATTRIBUTES {
+            Ok(DotInput::VertexAttributes)
+        } else if limits.max_storage_buffers_per_shader_stage > 0
+            && limits.max_storage_buffer_binding_size as usize >= std::mem::size_of::<Dot>()
+        {
+            Ok(DotInput::StorageBuffer)
+        } else {
+            Err(Error::TooFewVertexAttributes {
+                needed: DOT_VERTEX_ATTRIBUTES,
+                max: limits.max_vertex_attributes,
+            })
+        }
+    }
+
+    /// The most dots one buffer, and so one draw, can hold on a device with `limits`.
+    fn max_batch(self, limits: &wgpu::Limits) -> usize {
+        let max_bytes = match self {
+            DotInput::VertexAttributes => limits.max_buffer_size,
+            DotInput::StorageBuffer => limits.max_buffer_size.min(limits.max_storage_buffer_binding_size as u64),
+        };
+        (max_bytes / std::mem::size_of::<Dot>() as u64).max(1) as usize
+    }
+}
+
+/// Values that change every frame, `Uniforms` in the shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(crate) struct Uniforms {
+    pub frame: u32,
+    /// Seconds since rendering started, for animations that shouldn't depend on the frame rate.
+    pub seconds: f32,
+    /// Seconds since the previous frame.
+    pub delta_seconds: f32,
+    /// Width over height of the viewport, to keep the square canvas square in it.
+    pub aspect: f32,
+}
+
+impl Default for Uniforms {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            seconds: 0.0,
+            delta_seconds: 0.0,
+            aspect: 1.0,
         }
     }
 }
 
+impl Uniforms {
+    pub const SIZE: u32 = std::mem::size_of::<Uniforms>() as u32;
+}
+
+/// Parameters shared by every canvas of a [`GlobalSurface`], `Canvas` in dot_common.wgsl.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
-struct Uniforms {
-    frame: u32,
-    _padding1: u32,
-    _padding2: u32,
-    _padding3: u32,
+pub struct CanvasUniforms {
+    /// Width and height of the canvas texture in pixels.
+    pub size: [f32; 2],
+    /// Multiplies the alpha of every dot.
+    pub opacity: f32,
+    /// The canvas clock animated dots follow, see [`DotAnimation`].
+    pub seconds: f32,
 }
 
+impl CanvasUniforms {
+    pub fn new(size: [f32; 2], opacity: f32) -> Self {
+        Self {
+            size,
+            opacity,
+            seconds: 0.0,
+        }
+    }
+}
 
 static VERTICES: [Vertex; 6] = [
     Vertex { position: [0.0, 0.0] },
@@ -63,8 +543,8 @@ static VERTICES: [Vertex; 6] = [
     Vertex { position: [0.0, 0.0] },
 ];
 
-static TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
-
+/// What all canvases share: the device, the dot pipelines and the canvas texture description.
+/// Build one per device and hand it to each [`HpSurface`].
 pub struct GlobalSurface {
     pub device: Arc<wgpu::Device>,
 
@@ -72,185 +552,2387 @@ pub struct GlobalSurface {
 
     pub vertex_buffer: wgpu::Buffer,
 
-    pub render_pipeline: wgpu::RenderPipeline,
-
     pub texture_desc: wgpu::TextureDescriptor<'static>,
-}
 
+    /// What this was built with, to build it again on another device.
+    builder: GlobalSurfaceBuilder,
 
-impl GlobalSurface {
-    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+    /// Last set by [`Self::set_opacity`], kept to restore it on a new device.
+    opacity: RwLock<f32>,
 
+    /// What canvases are cleared to, see [`Self::set_background`].
+    background: RwLock<Background>,
 
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("dot_shader.wgsl"))),
-        });
+    /// Holds the [`CanvasUniforms`].
+    pub canvas_uniform_buffer: wgpu::Buffer,
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Surface Pipeline Layout"),
-            bind_group_layouts: &[],
-            push_constant_ranges: &[],
-        });
+    pub canvas_bind_group_layout: Arc<wgpu::BindGroupLayout>,
 
+    /// Binds `canvas_uniform_buffer` as group 0 of the dot pipelines.
+    pub canvas_bind_group: wgpu::BindGroup,
 
-        let texture_size = 1024u32;
+    /// How the dot pipelines get their dots on this device.
+    dot_input: DotInput,
 
-        let texture_desc = wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: texture_size,
-                height: texture_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::TEXTURE_BINDING
-            ,
-            label: None,
-            view_formats: &[],
-        };
+    /// The most dots drawn at once, see [`HpSurface::instance_buffers`].
+    max_batch: usize,
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::vertex_buffer_desc(), Dot::vertex_buffer_desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: texture_desc.format,
-
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent {
-                                src_factor: wgpu::BlendFactor::SrcAlpha,
-                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                operation: wgpu::BlendOperation::Add,
-                            },
-                            alpha: wgpu::BlendComponent::OVER,
-                        }),
-
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })
-                ],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+    /// Binds a batch of dots as group 1 of the dot pipelines with [`DotInput::StorageBuffer`].
+    dots_bind_group_layout: Option<Arc<wgpu::BindGroupLayout>>,
 
-        Self {
-            device,
+    /// Shared by every dot pipeline variant.
+    dot_pipeline_layout: wgpu::PipelineLayout,
 
-            queue,
+    /// The pipeline variants and bind group layouts built for this device, see
+    /// [`Self::render_pipeline`].
+    pub(crate) pipeline_cache: PipelineCache,
 
-            vertex_buffer,
+    /// The dot shader source, replaced when debug builds reload the shader.
+    dot_source: RwLock<Cow<'static, str>>,
 
-            render_pipeline,
+    /// WGSL replacing `custom_dot` in the dot shader, see [`Self::set_dot_snippet`].
+    dot_snippet: RwLock<Option<Arc<str>>>,
 
-            texture_desc,
+    /// Built when the first filter is added, see [`Self::with_filter_pipelines`].
+    filter_pipelines: RwLock<Option<FilterPipelines>>,
+
+    /// The kernels convolution filters can name, see [`Self::register_kernel`].
+    kernels: RwLock<HashMap<String, Kernel>>,
+
+    /// Built when the first histogram is counted, see [`HpSurface::histogram`].
+    histogram_pass: RwLock<Option<HistogramPass>>,
+
+    /// Built when the first selection is made, see [`HpSurface::select`].
+    magic_wand_pass: RwLock<Option<MagicWandPass>>,
+
+    /// Built when particles first move, see [`HpSurface::set_particles`].
+    particle_pass: RwLock<Option<ParticlePass>>,
+
+    /// Built when the first wet dots are painted, see [`HpSurface::add_stroke_dots`].
+    watercolor_pass: RwLock<Option<WatercolorPass>>,
+
+    /// Built when the first framed export is read back, see [`HpSurface::framed_readback`].
+    framing_pass: RwLock<Option<FramingPass>>,
+}
+
+/// The define, and snippet name, of the user's `custom_dot` in dot_shader.wgsl.
+const DOT_SNIPPET: &str = "CUSTOM_DOT";
+
+/// Which stroke every dot of a canvas belongs to.
+#[derive(Debug, Clone, Default)]
+struct Strokes {
+    /// The stroke of every dot, by index.
+    of_dot: Vec<StrokeId>,
+    /// Every stroke with how many dots it has, none for wet ones.
+    info: BTreeMap<StrokeId, (StrokeInfo, usize)>,
+    /// The dots of the wet strokes, which flow as watercolor rather than being drawn as dots.
+    wet: BTreeMap<StrokeId, Vec<Dot>>,
+    /// The id of the next stroke, never reused so undo can't take back a later stroke.
+    next: u64,
+}
+
+impl Strokes {
+    fn begin(&mut self, info: StrokeInfo) -> StrokeId {
+        let stroke = StrokeId(self.next);
+        self.next += 1;
+        self.info.insert(stroke, (info, 0));
+        stroke
+    }
+
+    fn add_dots(&mut self, stroke: StrokeId, count: usize) {
+        self.info.entry(stroke).or_default().1 += count;
+        self.of_dot.extend(std::iter::repeat(stroke).take(count));
+    }
+
+    /// The dots by stroke, as ranges of dots of the same stroke one after the other.
+    fn runs(&self) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for (index, stroke) in self.of_dot.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if self.of_dot[run.start] == *stroke => run.end = index + 1,
+                _ => runs.push(index..index + 1),
+            }
         }
+        runs
     }
-}
 
+    /// The first of the wet strokes painted one after the other up to `stroke`, which dry into the
+    /// same layer of watercolor, see [`WetLayer::deposit`].
+    fn first_wet(&self, stroke: StrokeId) -> StrokeId {
+        self.info
+            .range(..stroke)
+            .rev()
+            .map(|(&id, _)| id)
+            .take_while(|id| self.wet.contains_key(id))
+            .last()
+            .unwrap_or(stroke)
+    }
 
-pub struct HpSurface {
-    pub global: Arc<GlobalSurface>,
+    /// Forgets the dots from `count` on, and the strokes left without any.
+    fn truncate(&mut self, count: usize) {
+        for stroke in self.of_dot.drain(count.min(self.of_dot.len())..) {
+            if let Some((_, dots)) = self.info.get_mut(&stroke) {
+                *dots -= 1;
+                if *dots == 0 {
+                    self.info.remove(&stroke);
+                }
+            }
+        }
+    }
+}
 
-    pub instances: Vec<Dot>,
+/// The define of the dot pipelines pulling dots from a storage buffer, see [`DotInput`].
+const STORAGE_DOTS: &str = "STORAGE_DOTS";
 
-    pub instance_buffer: wgpu::Buffer,
+/// The define of the dot pipelines testing and writing depth, see [`DotOrder::Depth`].
+const DEPTH_DOTS: &str = "DEPTH_DOTS";
 
-    pub texture: wgpu::Texture,
+/// The define of the dot pipelines drawing into a pass with the depth and stencil attachment.
+const DEPTH_STENCIL: &str = "DEPTH_STENCIL";
 
-    pub texture_view: wgpu::TextureView,
+/// The defines of the dot pipelines painting strokes in three steps with [`StrokeOverlap::Once`]:
+/// marking the pixels dots of a stroke cover by at least half in the stencil, painting those once,
+/// and blending the edges of the dots around them.
+const STROKE_MARK: &str = "STROKE_MARK";
+const STROKE_CORE: &str = "STROKE_CORE";
+const STROKE_EDGE: &str = "STROKE_EDGE";
 
-    pub sampler: wgpu::Sampler,
+/// The define of the dot shaders discarding transparent pixels, which would otherwise write depth
+/// and stencil.
+const DISCARD_TRANSPARENT: &str = "DISCARD_TRANSPARENT";
+
+/// Every set of defines the dot pipelines are built with, before [`GlobalSurface::dot_pipeline_key`]
+/// adds the surface's own.
+fn dot_variants() -> Vec<ShaderDefines> {
+    let with_all = |defines: &ShaderDefines, names: &[&'static str]| {
+        names.iter().fold(defines.clone(), |defines, &name| defines.with(name))
+    };
+    let mut variants = vec![ShaderDefines::new()];
+    for names in [&[DEPTH_STENCIL][..], &[DEPTH_DOTS, DISCARD_TRANSPARENT]] {
+        let more: Vec<_> = variants.iter().map(|defines| with_all(defines, names)).collect();
+        variants.extend(more);
+    }
+    let strokes = [&[STROKE_MARK, STROKE_CORE][..], &[STROKE_CORE], &[STROKE_EDGE, DISCARD_TRANSPARENT]];
+    let stroke_variants: Vec<_> = variants
+        .iter()
+        .flat_map(|defines| strokes.map(|names| with_all(defines, names)))
+        .collect();
+    variants.extend(stroke_variants);
+    variants
 }
 
-impl HpSurface {
-    pub fn new(global: Arc<GlobalSurface>) -> Self {
-        let instances = vec![
-            Dot {
-                position: [0.5, 0.5],
-                radius: 0.1,
-                hardness: 0.5,
-                color: [1.0, 0.0, 0.0, 1.0],
-            },
-        ];
+/// Of the attachment canvases ordered by depth or painting strokes once draw their dots with.
+const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
 
-        let instance_buffer = global.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&instances),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+/// Strokes a pass tells apart in its stencil, each with a value for its marked pixels and one more
+/// for those it painted, above 0 for cleared ones.
+const STENCIL_STROKES: usize = 127;
 
-        let texture = global.device.create_texture(&global.texture_desc);
 
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+pub const DEFAULT_CANVAS_SIZE: u32 = 1024;
 
-        let sampler = global.device.create_sampler(&SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+/// How canvas textures store color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CanvasColorSpace {
+    /// 8 bit sRGB. Compact, but many overlapping soft dots band and lose their color.
+    #[default]
+    Srgb,
+    /// 16 bit float linear color, only encoded to sRGB for display and export.
+    Linear,
+}
 
-        Self {
-            global,
-            instances,
-            instance_buffer,
-            texture,
-            texture_view,
-            sampler,
+impl CanvasColorSpace {
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            CanvasColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            CanvasColorSpace::Linear => wgpu::TextureFormat::Rgba16Float,
         }
     }
 
-    pub fn render(&self) {
-        let mut encoder = self.global.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: None,
-        });
+    /// Whether `adapter` can paint into and display canvases of this color space.
+    pub fn is_supported(self, adapter: &wgpu::Adapter) -> bool {
+        let features = adapter.get_texture_format_features(self.texture_format());
+        features.allowed_usages.contains(CANVAS_TEXTURE_USAGES)
+            && features.flags.contains(
+                wgpu::TextureFormatFeatureFlags::BLENDABLE | wgpu::TextureFormatFeatureFlags::FILTERABLE,
+            )
+    }
+}
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(
-                    wgpu::RenderPassColorAttachment {
-                        view: &self.texture_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                            store: true,
-                        },
-                    }
-                )],
-                depth_stencil_attachment: None,
-            });
+/// The gamut canvas colors are shown and exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayGamut {
+    #[default]
+    Srgb,
+    /// Canvas colors are taken as Display P3, which shares sRGB's transfer function but reaches
+    /// more saturated colors. Needs a swapchain with more than 8 bits per channel.
+    DisplayP3,
+}
+
+impl DisplayGamut {
+    /// Whether colors shown in this gamut need converting to the primaries of swapchains of
+    /// `format`: Display P3 on float swapchains, which platforms present as scRGB, linear with
+    /// sRGB's primaries and reaching beyond them below 0 and above 1.
+    pub(crate) fn converts_for(self, format: wgpu::TextureFormat) -> bool {
+        self == DisplayGamut::DisplayP3 && format == wgpu::TextureFormat::Rgba16Float
+    }
+
+    /// The swapchain format to present this gamut in, out of the `formats` a surface supports.
+    /// `None` if none of them is wide enough.
+    ///
+    /// sRGB picks an sRGB format, which encodes the linear colors the canvas holds on its own, so
+    /// colors look the same whichever of them a platform lists first. Where there is none, e.g.
+    /// on some WebGL and Android surfaces, it falls back to the preferred format and the view
+    /// encodes the colors in its shader, see `ENCODE_SRGB` in surface_view_shader.wgsl.
+    pub fn swapchain_format(self, formats: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+        match self {
+            DisplayGamut::Srgb => formats
+                .iter()
+                .copied()
+                .find(|format| format.describe().srgb)
+                .or_else(|| formats.first().copied()),
+            // 10 bit first: it holds encoded color like 8 bit sRGB swapchains, while how a
+            // platform presents float swapchains varies
+            DisplayGamut::DisplayP3 => [wgpu::TextureFormat::Rgb10a2Unorm, wgpu::TextureFormat::Rgba16Float]
+                .into_iter()
+                .find(|format| formats.contains(format)),
+        }
+    }
+}
+
+/// The format viewing the texels of `format` with or without sRGB encoding, if it has one.
+fn srgb_counterpart(format: wgpu::TextureFormat) -> &'static [wgpu::TextureFormat] {
+    use wgpu::TextureFormat::{Bgra8Unorm, Bgra8UnormSrgb, Rgba8Unorm, Rgba8UnormSrgb};
+    match format {
+        Rgba8Unorm => &[Rgba8UnormSrgb],
+        Rgba8UnormSrgb => &[Rgba8Unorm],
+        Bgra8Unorm => &[Bgra8UnormSrgb],
+        Bgra8UnormSrgb => &[Bgra8Unorm],
+        _ => &[],
+    }
+}
+
+const CANVAS_TEXTURE_USAGES: wgpu::TextureUsages = wgpu::TextureUsages::COPY_SRC
+    .union(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    .union(wgpu::TextureUsages::TEXTURE_BINDING);
+
+/// Which dots are drawn over which.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DotOrder {
+    /// Later dots over earlier ones, in the order they were added.
+    #[default]
+    Buffer,
+    /// Dots with a greater [depth](Dot::depth) over those with a lesser one, and in the order
+    /// they were added among the same depth, with a depth buffer. E.g. to draw a scatter plot's
+    /// smaller points over its larger ones whatever order the data came in.
+    ///
+    /// Pixels of a dot hide what's drawn behind it later even where it's translucent, so soft
+    /// and translucent dots still need adding from back to front to blend with what's behind them.
+    Depth,
+}
+
+/// How the dots of a stroke blend where they overlap each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StrokeOverlap {
+    /// Every dot over the ones before it, so translucent strokes get darker where their dots
+    /// overlap, like dabs of paint.
+    #[default]
+    Blend,
+    /// Each pixel painted once by a stroke, with a stencil buffer, so translucent strokes are as
+    /// even as a single layer of paint. Where a dot covers a pixel by at least half, the first such
+    /// dot paints it, the softer edges around blend like before. Dots set or added at once are a
+    /// stroke too.
+    Once,
+}
+
+/// How dots blend into the canvas. All work on the premultiplied colors the canvas stores, see
+/// `premultiply` in dot_common.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendPreset {
+    /// Dots cover what's below them by their alpha, like paint.
+    #[default]
+    Over,
+    /// Dots add their light, brightening towards white.
+    Additive,
+    /// Dots darken what's below them like filters over a light table, assuming an opaque canvas.
+    Multiply,
+    /// Dots lighten what's below them, the inverse of multiplying.
+    Screen,
+}
 
-            render_pass.set_pipeline(&self.global.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.global.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.draw(0..6, 0..1);
+impl BlendPreset {
+    fn blend_state(self) -> wgpu::BlendState {
+        use wgpu::BlendFactor::{Dst, One, OneMinusSrc, OneMinusSrcAlpha};
+        let component = |src_factor, dst_factor| wgpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: wgpu::BlendOperation::Add,
+        };
+        match self {
+            BlendPreset::Over => wgpu::BlendState {
+                color: wgpu::BlendComponent::OVER,
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendPreset::Additive => wgpu::BlendState {
+                color: component(One, One),
+                alpha: component(One, One),
+            },
+            BlendPreset::Multiply => wgpu::BlendState {
+                color: component(Dst, OneMinusSrcAlpha),
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendPreset::Screen => wgpu::BlendState {
+                color: component(One, OneMinusSrc),
+                alpha: wgpu::BlendComponent::OVER,
+            },
         }
+    }
+}
 
-        self.global.queue.submit(Some(encoder.finish()));
+/// Configures a [`GlobalSurface`], starting from square sRGB canvases of
+/// [`DEFAULT_CANVAS_SIZE`] pixels painted over each other without multisampling.
+#[derive(Debug, Clone)]
+pub struct GlobalSurfaceBuilder {
+    canvas_size: u32,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+    blend: BlendPreset,
+    label_prefix: String,
+    reinterpretable: bool,
+}
+
+impl Default for GlobalSurfaceBuilder {
+    fn default() -> Self {
+        Self {
+            canvas_size: DEFAULT_CANVAS_SIZE,
+            format: CanvasColorSpace::default().texture_format(),
+            sample_count: 1,
+            usage: CANVAS_TEXTURE_USAGES,
+            blend: BlendPreset::default(),
+            label_prefix: String::new(),
+            reinterpretable: false,
+        }
+    }
+}
+
+impl GlobalSurfaceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Square canvases of `canvas_size` pixels.
+    pub fn canvas_size(mut self, canvas_size: u32) -> Self {
+        self.canvas_size = canvas_size;
+        self
+    }
+
+    /// Stores canvases in the format of `color_space`.
+    pub fn color_space(self, color_space: CanvasColorSpace) -> Self {
+        self.format(color_space.texture_format())
+    }
+
+    /// Stores canvases in `format`, which needs to be blendable and filterable like the formats
+    /// of [`CanvasColorSpace`]. Exports only support those.
+    pub fn format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Antialiases the edges of hard dots with `sample_count` samples per pixel, 1, 2, 4 or 8.
+    /// 4 works everywhere, the others depend on the device and format.
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// Lets canvas textures also be used for `usage`, e.g. `COPY_DST` to upload into them.
+    pub fn usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = CANVAS_TEXTURE_USAGES | usage;
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendPreset) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Starts the labels of the GPU objects with `prefix`, to tell them apart from the embedding
+    /// app's in graphics debuggers and errors.
+    pub fn label_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.label_prefix = prefix.into();
+        self
+    }
+
+    /// Lets canvases also be viewed in the sRGB or linear counterpart of their format, see
+    /// [`HpSurface::texture_view_as`], e.g. to blend in sRGB space into an sRGB canvas. Only for
+    /// devices with [`wgpu::DownlevelFlags::VIEW_FORMATS`], which WebGL lacks, and formats that
+    /// have a counterpart.
+    pub fn reinterpretable(mut self, reinterpretable: bool) -> Self {
+        self.reinterpretable = reinterpretable;
+        self
+    }
+
+    /// Fails if the device's textures can't be as large as the canvas, or the sample count is
+    /// none of the supported ones.
+    pub fn build(self, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Result<GlobalSurface, Error> {
+        GlobalSurface::build(device, queue, self)
+    }
+}
+
+impl GlobalSurface {
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Result<Self, Error> {
+        Self::builder().build(device, queue)
+    }
+
+    /// Like `new`, with square canvases of `canvas_size` pixels.
+    pub fn with_canvas_size(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, canvas_size: u32) -> Result<Self, Error> {
+        Self::builder().canvas_size(canvas_size).build(device, queue)
+    }
+
+    /// Like `with_canvas_size`, storing the canvases in `color_space`.
+    pub fn with_color_space(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        canvas_size: u32,
+        color_space: CanvasColorSpace,
+    ) -> Result<Self, Error> {
+        Self::builder()
+            .canvas_size(canvas_size)
+            .color_space(color_space)
+            .build(device, queue)
+    }
+
+    /// Configures the canvases further than the constructors do.
+    pub fn builder() -> GlobalSurfaceBuilder {
+        GlobalSurfaceBuilder::new()
+    }
+
+    fn build(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, builder: GlobalSurfaceBuilder) -> Result<Self, Error> {
+        let canvas_size = builder.canvas_size;
+        let limits = device.limits();
+        let max = limits.max_texture_dimension_2d;
+        if canvas_size == 0 {
+            return Err(Error::EmptyCanvas);
+        }
+        if canvas_size > max {
+            return Err(Error::CanvasTooLarge { size: canvas_size, max });
+        }
+        if ![1, 2, 4, 8].contains(&builder.sample_count) {
+            return Err(Error::UnsupportedSampleCount(builder.sample_count));
+        }
+        let dot_input = DotInput::for_limits(&limits)?;
+        let max_batch = dot_input.max_batch(&limits);
+        tracing::info!(
+            "{}Canvases of {canvas_size} pixels, dots as {dot_input:?} in batches of up to {max_batch}",
+            builder.label_prefix
+        );
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&builder.label("Quad Vertices")),
+            contents: bytemuck::cast_slice(&VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+
+        // Multisampled canvases are drawn into a texture of their own and resolved into this one
+        let texture_desc = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: canvas_size,
+                height: canvas_size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: builder.format,
+            usage: builder.usage,
+            label: None,
+            view_formats: if builder.reinterpretable {
+                srgb_counterpart(builder.format)
+            } else {
+                &[]
+            },
+        };
+
+        let canvas_uniforms = CanvasUniforms::new([canvas_size as f32; 2], 1.0);
+        let canvas_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&builder.label("Canvas Uniforms")),
+            contents: bytemuck::bytes_of(&canvas_uniforms),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+        });
+
+        let pipeline_cache = PipelineCache::default();
+        let canvas_bind_group_layout = pipeline_cache.bind_group_layout(
+            &device,
+            &builder.label("Canvas Bind Group Layout"),
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<CanvasUniforms>() as u64),
+                },
+                count: None,
+            }],
+        );
+
+        let canvas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&builder.label("Canvas Bind Group")),
+            layout: &canvas_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: canvas_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let dots_bind_group_layout = (dot_input == DotInput::StorageBuffer).then(|| {
+            pipeline_cache.bind_group_layout(
+                &device,
+                &builder.label("Dots Bind Group Layout"),
+                &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Dot>() as u64),
+                    },
+                    count: None,
+                }],
+            )
+        });
+
+        let mut bind_group_layouts = vec![&*canvas_bind_group_layout];
+        bind_group_layouts.extend(dots_bind_group_layout.as_deref());
+        let dot_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&builder.label("Surface Pipeline Layout")),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let global = Self {
+            device,
+
+            queue,
+
+            vertex_buffer,
+
+            texture_desc,
+
+            builder,
+
+            opacity: RwLock::new(1.0),
+
+            background: RwLock::new(Background::default()),
+
+            canvas_uniform_buffer,
+
+            canvas_bind_group_layout,
+
+            canvas_bind_group,
+
+            dot_input,
+
+            max_batch,
+
+            dots_bind_group_layout,
+
+            dot_pipeline_layout,
+
+            pipeline_cache,
+
+            dot_source: RwLock::new(Cow::Borrowed(Shader::Dot.embedded_source())),
+
+            dot_snippet: RwLock::default(),
+
+            filter_pipelines: RwLock::default(),
+
+            kernels: RwLock::new(Kernel::builtins()),
+
+            histogram_pass: RwLock::default(),
+            magic_wand_pass: RwLock::default(),
+            particle_pass: RwLock::default(),
+            watercolor_pass: RwLock::default(),
+            framing_pass: RwLock::default(),
+        };
+        // Build the default variant right away, so a broken shader shows up at startup
+        diagnostics::scoped(&global.device, Scope::Other("creating the dot pipeline"), || {
+            global.render_pipeline(&ShaderDefines::default())
+        });
+        Ok(global)
+    }
+
+    /// The dot pipeline with `defines` set in the shader, built on first use.
+    pub fn render_pipeline(&self, defines: &ShaderDefines) -> Arc<wgpu::RenderPipeline> {
+        let defines = match &*self.dot_snippet.read().unwrap() {
+            Some(snippet) => defines.clone().with_snippet(DOT_SNIPPET, snippet.clone()),
+            None => defines.clone(),
+        };
+        let source = self.dot_source.read().unwrap();
+        self.pipeline_cache
+            .pipeline(self.dot_pipeline_key(defines), |key| self.create_render_pipeline(&source, key))
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// The dot pipeline variant with `defines` for the canvases of this surface.
+    fn dot_pipeline_key(&self, defines: ShaderDefines) -> PipelineKey {
+        let defines = match self.dot_input {
+            DotInput::VertexAttributes => defines,
+            DotInput::StorageBuffer => defines.with(STORAGE_DOTS),
+        };
+        PipelineKey {
+            shader: Shader::Dot,
+            format: self.builder.format,
+            blend: Some(self.builder.blend.blend_state()),
+            sample_count: self.builder.sample_count,
+            defines,
+        }
+    }
+
+    /// Changes the look of every dot to `snippet`, or back to the default with `None`.
+    ///
+    /// The snippet is WGSL defining
+    /// `fn custom_dot(position: vec2<f32>, hardness: f32, color: vec4<f32>) -> vec4<f32>`, called
+    /// for every pixel of a dot with its position in the dot's square stamp (0..1) and returning
+    /// its straight alpha color. It's checked with naga in every variant of the dot shader first,
+    /// and the current look stays if it doesn't compile in any of them.
+    pub fn set_dot_snippet(&self, snippet: Option<&str>) -> Result<(), ShaderError> {
+        let snippet: Option<Arc<str>> = snippet.map(Arc::from);
+        let with_snippet = |defines: ShaderDefines| match &snippet {
+            Some(snippet) => defines.with_snippet(DOT_SNIPPET, snippet.clone()),
+            None => defines,
+        };
+        {
+            let source = self.dot_source.read().unwrap();
+            for variant in dot_variants() {
+                let key = self.dot_pipeline_key(with_snippet(variant));
+                Shader::Dot.validate(&source, &key.defines, self.device.features())?;
+            }
+        }
+        let key = self.dot_pipeline_key(with_snippet(ShaderDefines::new()));
+        let render_pipeline = self.create_render_pipeline(&self.dot_source.read().unwrap(), &key)?;
+
+        // Variants built with the previous snippet are never used again
+        self.pipeline_cache.clear(Shader::Dot);
+        self.pipeline_cache.insert(key, Arc::new(render_pipeline));
+        *self.dot_snippet.write().unwrap() = snippet;
+        Ok(())
+    }
+
+    /// Rebuilds every dot pipeline variant from `source`, keeping the current ones if any of them
+    /// doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&self, source: String) -> Result<(), ShaderError> {
+        let rebuilt = catch_validation_error(&self.device, || {
+            self.pipeline_cache
+                .keys(Shader::Dot)
+                .into_iter()
+                .map(|key| {
+                    let render_pipeline = self.create_render_pipeline(&source, &key)?;
+                    Ok((key, Arc::new(render_pipeline)))
+                })
+                .collect::<Result<Vec<_>, ShaderError>>()
+        })?;
+        self.pipeline_cache.clear(Shader::Dot);
+        for (key, render_pipeline) in rebuilt {
+            self.pipeline_cache.insert(key, render_pipeline);
+        }
+        *self.dot_source.write().unwrap() = Cow::Owned(source);
+        Ok(())
+    }
+
+    /// Runs `f` with the filter pipelines, building them on first use. `None` if the device can't
+    /// run filters.
+    pub(crate) fn with_filter_pipelines<T>(&self, f: impl FnOnce(&FilterPipelines) -> T) -> Option<T> {
+        if !filter::is_supported(&self.device) {
+            return None;
+        }
+        if self.filter_pipelines.read().unwrap().is_none() {
+            let pipelines = FilterPipelines::new(&self.device, &self.queue, self.texture_desc.format);
+            self.filter_pipelines.write().unwrap().get_or_insert(pipelines);
+        }
+        self.filter_pipelines.read().unwrap().as_ref().map(f)
+    }
+
+    /// Rebuilds the filter pipelines of `shader` from `source`, keeping the current ones if it
+    /// doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_filter_shader(&self, shader: Shader, source: String) -> Result<(), ShaderError> {
+        match self.filter_pipelines.write().unwrap().as_mut() {
+            Some(pipelines) => pipelines.reload_shader(&self.device, shader, &source),
+            // Nothing to rebuild until a filter is added, still report mistakes right away
+            None => shader.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Rebuilds the histogram pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_histogram_shader(&self, source: String) -> Result<(), ShaderError> {
+        match self.histogram_pass.write().unwrap().as_mut() {
+            Some(pass) => pass.reload_shader(&self.device, &source),
+            None => Shader::Histogram.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Rebuilds the magic wand pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_magic_wand_shader(&self, source: String) -> Result<(), ShaderError> {
+        match self.magic_wand_pass.write().unwrap().as_mut() {
+            Some(pass) => pass.reload_shader(&self.device, &source),
+            None => Shader::MagicWand.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Rebuilds the particle pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_particles_shader(&self, source: String) -> Result<(), ShaderError> {
+        match self.particle_pass.write().unwrap().as_mut() {
+            Some(pass) => pass.reload_shader(&self.device, &source),
+            None => Shader::Particles.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Rebuilds the watercolor pipelines from `source`, keeping the current ones if it doesn't
+    /// compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_watercolor_shader(&self, source: String) -> Result<(), ShaderError> {
+        match self.watercolor_pass.write().unwrap().as_mut() {
+            Some(pass) => pass.reload_shader(&self.device, &source),
+            None => Shader::Watercolor.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Rebuilds the framing pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_framing_shader(&self, source: String) -> Result<(), ShaderError> {
+        match self.framing_pass.write().unwrap().as_mut() {
+            Some(pass) => pass.reload_shader(&self.device, &source),
+            None => Shader::Framing.validate(&source, &ShaderDefines::new(), self.device.features()).map(drop),
+        }
+    }
+
+    /// Makes `kernel` available to convolution filters as `name`, replacing any kernel of that
+    /// name, builtins included. Filters already using the name pick it up on the next render.
+    /// Kernels are [validated](Kernel::validated) first, as deserialized ones may be of any size.
+    pub fn register_kernel(&self, name: impl Into<String>, kernel: Kernel) -> Result<(), FilterError> {
+        let kernel = kernel.validated()?;
+        self.kernels.write().unwrap().insert(name.into(), kernel);
+        Ok(())
+    }
+
+    /// Whether a convolution filter can use `name`.
+    pub fn has_kernel(&self, name: &str) -> bool {
+        self.kernels.read().unwrap().contains_key(name)
+    }
+
+    /// The same setup on another device, e.g. after the previous one was lost. Keeps the canvas
+    /// size and color space, the dot shader and snippet, the kernels, the opacity and the
+    /// background.
+    pub fn recreate(&self, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Result<Self, Error> {
+        self.rebuild(self.builder.clone(), device, queue)
+    }
+
+    /// The same setup with canvases of `canvas_size` pixels, e.g. for a new document. Keeps what
+    /// [`Self::recreate`] does besides the size.
+    pub fn resized(&self, canvas_size: u32) -> Result<Self, Error> {
+        let builder = self.builder.clone().canvas_size(canvas_size);
+        self.rebuild(builder, self.device.clone(), self.queue.clone())
+    }
+
+    fn rebuild(&self, builder: GlobalSurfaceBuilder, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Result<Self, Error> {
+        let global = builder.build(device, queue)?;
+        *global.dot_source.write().unwrap() = self.dot_source.read().unwrap().clone();
+        *global.dot_snippet.write().unwrap() = self.dot_snippet.read().unwrap().clone();
+        *global.kernels.write().unwrap() = self.kernels.read().unwrap().clone();
+        global.set_opacity(*self.opacity.read().unwrap());
+        global.set_background(self.background());
+        Ok(global)
+    }
+
+    /// Changes what all canvases are cleared to before their dots are drawn, from their next
+    /// render on.
+    pub fn set_background(&self, background: Background) {
+        *self.background.write().unwrap() = background;
+    }
+
+    pub fn background(&self) -> Background {
+        *self.background.read().unwrap()
+    }
+
+    /// Changes the opacity all canvases are painted with, from the next render on.
+    pub fn set_opacity(&self, opacity: f32) {
+        *self.opacity.write().unwrap() = opacity;
+        let offset = bytemuck::offset_of!(CanvasUniforms::zeroed(), CanvasUniforms, opacity) as wgpu::BufferAddress;
+        self.queue
+            .write_buffer(&self.canvas_uniform_buffer, offset, bytemuck::bytes_of(&opacity));
+    }
+
+    /// Moves the clock of animated dots to `seconds`, from the next render on. Surface views set
+    /// it every frame.
+    pub fn set_seconds(&self, seconds: f32) {
+        let offset = bytemuck::offset_of!(CanvasUniforms::zeroed(), CanvasUniforms, seconds) as wgpu::BufferAddress;
+        self.queue
+            .write_buffer(&self.canvas_uniform_buffer, offset, bytemuck::bytes_of(&seconds));
+    }
+
+    pub fn device(&self) -> &Arc<wgpu::Device> {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Arc<wgpu::Queue> {
+        &self.queue
+    }
+
+    /// How the dot pipelines get their dots on this device.
+    pub fn dot_input(&self) -> DotInput {
+        self.dot_input
+    }
+
+    /// Samples per pixel the dots are drawn with, see [`GlobalSurfaceBuilder::sample_count`].
+    pub fn sample_count(&self) -> u32 {
+        self.builder.sample_count
+    }
+
+    /// `name` with the prefix of [`GlobalSurfaceBuilder::label_prefix`].
+    pub fn label(&self, name: &str) -> String {
+        self.builder.label(name)
+    }
+
+    fn create_render_pipeline(&self, source: &str, key: &PipelineKey) -> Result<wgpu::RenderPipeline, ShaderError> {
+        create_render_pipeline(&self.device, source, key, &self.dot_pipeline_layout, &self.builder)
+    }
+}
+
+impl GlobalSurfaceBuilder {
+    fn label(&self, name: &str) -> String {
+        format!("{}{name}", self.label_prefix)
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    source: &str,
+    key: &PipelineKey,
+    pipeline_layout: &wgpu::PipelineLayout,
+    builder: &GlobalSurfaceBuilder,
+) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let shader = key.shader.create_module(device, source, &key.defines)?;
+    let layouts = [Vertex::vertex_buffer_desc(), Dot::vertex_buffer_desc()];
+    // Dots from storage buffers aren't vertex attributes
+    let buffers = if key.defines.contains(STORAGE_DOTS) { &layouts[..1] } else { &layouts[..] };
+
+    Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&builder.label("Dot Pipeline")),
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: key.blend,
+                    // Marking pixels only writes the stencil
+                    write_mask: match key.defines.contains(STROKE_MARK) {
+                        true => wgpu::ColorWrites::empty(),
+                        false => wgpu::ColorWrites::ALL,
+                    },
+                })
+            ],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: depth_stencil_state(&key.defines),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    }))
+}
+
+/// How the dot pipeline with `defines` tests depth and stencil, if at all.
+fn depth_stencil_state(defines: &ShaderDefines) -> Option<wgpu::DepthStencilState> {
+    if !defines.contains(DEPTH_STENCIL) {
+        return None;
+    }
+    let depth = defines.contains(DEPTH_DOTS);
+    let mark = defines.contains(STROKE_MARK);
+    // With the stroke's reference marking its pixels, and one more once painted
+    let (compare, pass_op) = if mark {
+        (wgpu::CompareFunction::Always, wgpu::StencilOperation::Replace)
+    } else if defines.contains(STROKE_CORE) {
+        (wgpu::CompareFunction::Equal, wgpu::StencilOperation::IncrementClamp)
+    } else if defines.contains(STROKE_EDGE) {
+        // Not marked by this stroke, only by earlier ones with lesser references
+        (wgpu::CompareFunction::Greater, wgpu::StencilOperation::Keep)
+    } else {
+        (wgpu::CompareFunction::Always, wgpu::StencilOperation::Keep)
+    };
+    let face = wgpu::StencilFaceState {
+        compare,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op,
+    };
+    Some(wgpu::DepthStencilState {
+        format: DEPTH_STENCIL_FORMAT,
+        depth_write_enabled: depth && !mark,
+        // Among the same depth, later dots go over earlier ones like without it
+        depth_compare: if depth { wgpu::CompareFunction::GreaterEqual } else { wgpu::CompareFunction::Always },
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xff,
+            write_mask: 0xff,
+        },
+        bias: wgpu::DepthBiasState::default(),
+    })
+}
+
+/// Draws the dots in `range` of `batches` that are also in one of `drawn`, each batch holding
+/// `max_batch` of them but the last.
+fn draw_dots<'pass>(
+    render_pass: &mut wgpu::RenderPass<'pass>,
+    batches: &'pass [DotBatch],
+    range: Range<usize>,
+    drawn: &[Range<usize>],
+    max_batch: usize,
+) {
+    for (index, batch) in batches.iter().enumerate() {
+        let start = index * max_batch;
+        let batch_range = range.start.max(start)..range.end.min(start + batch.count as usize);
+        let mut bound = false;
+        let first = drawn.partition_point(|drawn| drawn.end <= batch_range.start);
+        for drawn in drawn[first..].iter().take_while(|drawn| drawn.start < batch_range.end) {
+            let instances = batch_range.start.max(drawn.start)..batch_range.end.min(drawn.end);
+            if instances.is_empty() {
+                continue;
+            }
+            if !bound {
+                match &batch.bind_group {
+                    Some(bind_group) => render_pass.set_bind_group(1, bind_group, &[]),
+                    None => render_pass.set_vertex_buffer(1, batch.buffer.slice(..)),
+                }
+                bound = true;
+            }
+            render_pass.draw(0..6, (instances.start - start) as u32..(instances.end - start) as u32);
+        }
+    }
+}
+
+/// A pass drawing dots of the canvas, see [`HpSurface::render`].
+struct CanvasPass<'a> {
+    dots: Range<usize>,
+    /// The dots by stroke instead, with the stencil, see [`StrokeOverlap::Once`].
+    runs: Vec<Range<usize>>,
+    /// The layer of watercolor drawn over the dots.
+    watercolor: Option<&'a wgpu::BindGroup>,
+}
+
+/// Draws a layer of watercolor and, after the last dots, the plugins into the canvas pass.
+fn draw_over_dots<'pass>(
+    render_pass: &mut wgpu::RenderPass<'pass>,
+    watercolor: Option<(&'pass WatercolorPass, &'pass wgpu::BindGroup)>,
+    plugins: Option<&'pass [Arc<dyn RenderPassPlugin>]>,
+    canvas: &CanvasInfo,
+) {
+    if let Some((pass, bind_group)) = watercolor {
+        render_pass.push_debug_group("watercolor");
+        WetLayer::draw(pass, bind_group, render_pass);
+        render_pass.pop_debug_group();
+    }
+
+    if let Some(plugins) = plugins {
+        render_pass.push_debug_group("plugins");
+        for plugin in plugins {
+            plugin.draw(render_pass, canvas);
+        }
+        render_pass.pop_debug_group();
+    }
+}
+
+/// Dots in a buffer of their own, drawn at once.
+struct DotBatch {
+    buffer: wgpu::Buffer,
+    /// Binds `buffer` with [`DotInput::StorageBuffer`].
+    bind_group: Option<wgpu::BindGroup>,
+    count: u32,
+}
+
+impl DotBatch {
+    /// `dots` in batches of at most [`GlobalSurface::max_batch`], none if there are none. The
+    /// particle pass can move the dots of `simulated` batches.
+    fn create_all(global: &GlobalSurface, dots: &[Dot], simulated: bool) -> Vec<Self> {
+        dots.chunks(global.max_batch)
+            .map(|dots| Self::new(global, dots, simulated))
+            .collect()
+    }
+
+    fn new(global: &GlobalSurface, dots: &[Dot], simulated: bool) -> Self {
+        let usage = match global.dot_input {
+            DotInput::VertexAttributes => wgpu::BufferUsages::VERTEX,
+            DotInput::StorageBuffer => wgpu::BufferUsages::STORAGE,
+        };
+        // Read back for instance dumps, and written to by tweens
+        let mut usage = usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        if simulated {
+            // Moved by the particle pass, and carried over when dots are added
+            usage |= wgpu::BufferUsages::STORAGE;
+        }
+        let buffer = global.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&global.label("Dot Instances")),
+            contents: bytemuck::cast_slice(dots),
+            usage,
+        });
+        let bind_group = global.dots_bind_group_layout.as_ref().map(|layout| {
+            global.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&global.label("Dots Bind Group")),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            })
+        });
+        Self {
+            buffer,
+            bind_group,
+            count: dots.len() as u32,
+        }
+    }
+}
+
+/// What [`HpSurface::clear`] removed from a canvas, to put back with [`HpSurface::restore`].
+pub struct ClearedCanvas {
+    document: Document,
+    /// The textures themselves rather than copies, nothing paints into them once they're taken.
+    watercolor: Option<WetLayer>,
+}
+
+impl fmt::Debug for ClearedCanvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClearedCanvas")
+            .field("dots", &self.document.dot_count())
+            .field("watercolor", &self.watercolor.is_some())
+            .finish()
+    }
+}
+
+/// A canvas: its dots, rendered into `texture` with the filters on top. Draw it on screen with a
+/// [`SurfaceRenderResources`](crate::surface_view::SurfaceRenderResources).
+pub struct HpSurface {
+    pub global: Arc<GlobalSurface>,
+
+    pub instances: Vec<Dot>,
+
+    /// `instances` in batches the device can draw at once.
+    batches: Vec<DotBatch>,
+
+    /// `instances` with the tiny ones merged, batched by level when first drawn at it and
+    /// dropped when they change, see [`Self::set_lod`].
+    lod_batches: Mutex<BTreeMap<u32, Vec<DotBatch>>>,
+
+    /// Merges tiny dots when the canvas is shown smaller than it is, `None` to draw them all.
+    lod: Option<LodSettings>,
+
+    /// Screen pixels per canvas pixel where the canvas is shown, see [`Self::set_screen_scale`].
+    screen_scale: f32,
+
+    /// `instances` by where they are, see [`Self::dot_at`] and [`Self::dots_in`].
+    index: DotIndex,
+
+    /// Ranges of `instances` drawn, leaving out the dots beyond the canvas, see [`Self::cull`].
+    drawn: Vec<Range<usize>>,
+
+    /// Where dots changed since [`Self::take_dirty_rect`] was last called.
+    dirty: Option<CanvasRect>,
+
+    /// The stroke each of `instances` belongs to, see [`Self::begin_stroke`].
+    strokes: Strokes,
+
+    pub texture: wgpu::Texture,
+
+    pub texture_view: wgpu::TextureView,
+
+    /// Where multisampled dots are drawn before being resolved into `texture`.
+    multisampled_view: Option<wgpu::TextureView>,
+
+    /// Which dots are drawn over which, see [`Self::set_dot_order`].
+    dot_order: DotOrder,
+
+    /// See [`Self::set_stroke_overlap`].
+    stroke_overlap: StrokeOverlap,
+
+    /// The depth and stencil attachment of the dots with [`DotOrder::Depth`] or
+    /// [`StrokeOverlap::Once`].
+    depth_stencil_view: Option<wgpu::TextureView>,
+
+    pub sampler: wgpu::Sampler,
+
+    /// Run over the canvas after its dots, in order.
+    filters: Vec<Filter>,
+
+    /// Created with the first filter.
+    filter_targets: Option<FilterTargets>,
+
+    /// Told how far the next render got with the filters, see [`Self::add_filter`].
+    filter_progress: Mutex<Option<FilterProgress>>,
+
+    /// Run after `filters` until committed, see [`Self::set_preview`].
+    preview: Option<Filter>,
+
+    /// Limits the filters to part of the canvas, see [`Self::select`].
+    selection: Mutex<Option<Selection>>,
+
+    /// Called while rendering, in order, see [`Self::add_plugin`].
+    plugins: Vec<Arc<dyn RenderPassPlugin>>,
+
+    /// Moves the dots in `batches`, see [`Self::set_particles`].
+    particles: Option<ParticleSystem>,
+
+    /// Created with the first wet dots, see [`Self::add_stroke_dots`].
+    watercolor: Option<WetLayer>,
+
+    wet_settings: WetSettings,
+
+    /// Moving dots, see [`Self::tween`].
+    tweens: Tweens,
+}
+
+impl HpSurface {
+    /// An empty canvas.
+    pub fn new(global: Arc<GlobalSurface>) -> Self {
+        let label = global.label("Canvas");
+        let texture = diagnostics::scoped(&global.device, Scope::Other("creating the canvas texture"), || {
+            global.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&label),
+                ..global.texture_desc.clone()
+            })
+        });
+        Self::with_texture(global, texture)
+    }
+
+    /// An empty canvas of `canvas_size` pixels on a device of its own, without a window, e.g. for
+    /// rendering from scripts. The device is traced into `WGPU_TRACE` if it's set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn headless(canvas_size: u32) -> Result<Self, Error> {
+        let instance = wgpu::Instance::default();
+        let trace = renderer::trace_dir(None);
+        let (adapter, device, queue) = pollster::block_on(renderer::request_device(&instance, None, trace.as_deref()))?;
+        let global = GlobalSurface::builder()
+            .canvas_size(canvas_size)
+            .reinterpretable(renderer::supports_view_formats(&adapter))
+            .build(device, queue)?;
+        Ok(Self::new(Arc::new(global)))
+    }
+
+    /// An empty canvas like `builder` describes on a device of its own on `adapter`, e.g. to
+    /// compare how backends draw. Whether the canvas is reinterpretable is up to the adapter.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn headless_on(adapter: wgpu::Adapter, builder: GlobalSurfaceBuilder) -> Result<Self, Error> {
+        let reinterpretable = renderer::supports_view_formats(&adapter);
+        let trace = renderer::trace_dir(None);
+        let (_, device, queue) = pollster::block_on(renderer::open_device(adapter, trace.as_deref()))?;
+        let global = builder.reinterpretable(reinterpretable).build(device, queue)?;
+        Ok(Self::new(Arc::new(global)))
+    }
+
+    /// An empty canvas drawing into `texture`, created elsewhere, e.g. by the render graph of the
+    /// embedding app. It has to be like the canvases of `global`: the same size and format, not
+    /// multisampled, and with at least their usages.
+    pub fn from_texture(global: Arc<GlobalSurface>, texture: wgpu::Texture) -> Result<Self, Error> {
+        let desc = &global.texture_desc;
+        let reason = if texture.format() != desc.format {
+            Some(format!("it's {:?}, the canvases are {:?}", texture.format(), desc.format))
+        } else if texture.size() != desc.size {
+            Some(format!("it's {:?}, the canvases are {:?}", texture.size(), desc.size))
+        } else if texture.sample_count() != 1 {
+            Some(format!("it has {} samples per pixel, the canvases 1", texture.sample_count()))
+        } else if !texture.usage().contains(desc.usage) {
+            Some(format!("it lacks the usages {:?}", desc.usage - texture.usage()))
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => Err(Error::IncompatibleTexture(reason)),
+            None => Ok(Self::with_texture(global, texture)),
+        }
+    }
+
+    fn with_texture(global: Arc<GlobalSurface>, texture: wgpu::Texture) -> Self {
+        let instances = Vec::new();
+
+        let batches = DotBatch::create_all(&global, &instances, false);
+
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let multisampled_view = (global.sample_count() > 1).then(|| {
+            let label = global.label("Multisampled Canvas");
+            global
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&label),
+                    sample_count: global.sample_count(),
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                    ..global.texture_desc.clone()
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        let sampler = global.device.create_sampler(&SamplerDescriptor {
+            label: Some(&global.label("Canvas Sampler")),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            global,
+            instances,
+            batches,
+            lod_batches: Mutex::default(),
+            lod: None,
+            screen_scale: 1.0,
+            index: DotIndex::new(),
+            drawn: Vec::new(),
+            dirty: None,
+            strokes: Strokes::default(),
+            texture,
+            texture_view,
+            multisampled_view,
+            dot_order: DotOrder::Buffer,
+            stroke_overlap: StrokeOverlap::Blend,
+            depth_stencil_view: None,
+            sampler,
+            filters: Vec::new(),
+            filter_targets: None,
+            filter_progress: Mutex::default(),
+            preview: None,
+            selection: Mutex::default(),
+            plugins: Vec::new(),
+            particles: None,
+            watercolor: None,
+            wet_settings: WetSettings::default(),
+            tweens: Tweens::default(),
+        }
+    }
+
+    /// The same canvas on `global`, e.g. after the previous device was lost: its dots, filters and
+    /// preview, and a selection grown again from the same pixel. Watercolor is lost with the
+    /// device.
+    pub fn recreate(&self, global: Arc<GlobalSurface>) -> Self {
+        let mut surface = Self::new(global);
+        surface.replace_all(self.instances.clone(), self.strokes.clone());
+        surface.filters = self.filters.clone();
+        surface.preview = self.preview.clone();
+        let filters: Vec<Filter> = surface.filters.iter().chain(&surface.preview).cloned().collect();
+        // The kernels came along with the global surface
+        surface.prepare_filters(&filters).ok();
+        if let Some(selection) = &*self.selection.lock().unwrap() {
+            surface.select(selection.wand());
+        }
+        if let Some(settings) = self.particle_settings() {
+            surface.set_particles(Some(settings.clone()));
+        }
+        surface.tweens = self.tweens.clone();
+        surface.lod = self.lod;
+        surface.set_dot_order(self.dot_order);
+        surface.set_stroke_overlap(self.stroke_overlap);
+        surface.screen_scale = self.screen_scale;
+        surface
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn texture_view(&self) -> &wgpu::TextureView {
+        &self.texture_view
+    }
+
+    /// A view of the canvas texture in `format`: its own, or the counterpart canvases of a
+    /// [reinterpretable](GlobalSurfaceBuilder::reinterpretable) global surface can also be viewed
+    /// in. Viewing an sRGB canvas as linear reads and blends the encoded values as they are.
+    /// `None` for other formats.
+    pub fn texture_view_as(&self, format: wgpu::TextureFormat) -> Option<wgpu::TextureView> {
+        let desc = &self.global.texture_desc;
+        if format != desc.format && !desc.view_formats.contains(&format) {
+            return None;
+        }
+        Some(self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&self.global.label(&format!("Canvas as {format:?}"))),
+            format: Some(format),
+            ..Default::default()
+        }))
+    }
+
+    /// The dots as instances of the quad vertices, as laid out by [`Dot::vertex_buffer_desc`], in
+    /// as many buffers as the device's limits on their size need, drawn one after the other.
+    pub fn instance_buffers(&self) -> impl Iterator<Item = &wgpu::Buffer> + '_ {
+        self.batches.iter().map(|batch| &batch.buffer)
+    }
+
+    pub fn dots(&self) -> &[Dot] {
+        &self.instances
+    }
+
+    /// Adds `dots` on top, as a stroke of their own.
+    pub fn add_dots(&mut self, dots: &[Dot]) {
+        if dots.is_empty() {
+            return;
+        }
+        let stroke = self.begin_stroke(StrokeInfo::default());
+        self.add_stroke_dots(stroke, dots);
+    }
+
+    /// Starts a stroke without dots yet, which [`Self::add_stroke_dots`] paints.
+    pub fn begin_stroke(&mut self, info: StrokeInfo) -> StrokeId {
+        self.strokes.begin(info)
+    }
+
+    /// Adds `dots` on top as part of `stroke`. Strokes of a [wet](crate::stroke::Brush::wet) brush
+    /// flow as watercolor until they dry, see [`crate::watercolor`].
+    ///
+    /// Devices without compute shaders, like WebGL2, paint wet strokes as dots instead.
+    pub fn add_stroke_dots(&mut self, stroke: StrokeId, dots: &[Dot]) {
+        if dots.is_empty() {
+            return;
+        }
+        if self.paints_wet(stroke) {
+            self.strokes.wet.entry(stroke).or_default().extend_from_slice(dots);
+            self.mark_dirty(CanvasRect::around_all(dots));
+            let first = self.strokes.first_wet(stroke);
+            let global = self.global.clone();
+            self.watercolor_layer().deposit(&global.device, dots, first);
+            return;
+        }
+        self.strokes.add_dots(stroke, dots.len());
+        for (offset, dot) in dots.iter().enumerate() {
+            self.index.insert(self.instances.len() + offset, dot);
+        }
+        self.mark_dirty(CanvasRect::around_all(dots));
+        self.instances.extend_from_slice(dots);
+        self.rebuild_batches(true);
+    }
+
+    /// Replaces all dots and watercolor on the surface, e.g. when loading a document. Tweens of the
+    /// dots before stop.
+    /// The dots are one stroke then.
+    pub fn set_dots(&mut self, dots: Vec<Dot>) {
+        let mut strokes = Strokes {
+            next: self.strokes.next,
+            ..Strokes::default()
+        };
+        if !dots.is_empty() {
+            let stroke = strokes.begin(StrokeInfo::default());
+            strokes.add_dots(stroke, dots.len());
+        }
+        self.replace_all(dots, strokes);
+        self.watercolor = None;
+    }
+
+    /// Replaces all dots on the surface with those of `document`'s strokes, like
+    /// [`Self::set_dots`]. Its wet strokes are painted again, drying at once.
+    pub fn set_document(&mut self, document: Document) {
+        self.replace_strokes(document);
+        self.repaint_watercolor();
+    }
+
+    /// Replaces all strokes with `document`'s, leaving the watercolor as it was.
+    fn replace_strokes(&mut self, document: Document) {
+        let mut strokes = Strokes {
+            next: self.strokes.next,
+            ..Strokes::default()
+        };
+        let wet = watercolor::is_supported(&self.global.device);
+        let mut dots = Vec::with_capacity(document.dot_count());
+        for stroke in document.strokes {
+            strokes.next = strokes.next.max(stroke.id.0 + 1);
+            if wet && stroke.info.is_wet() {
+                strokes.info.insert(stroke.id, (stroke.info, 0));
+                strokes.wet.insert(stroke.id, stroke.dots);
+                continue;
+            }
+            strokes.info.insert(stroke.id, (stroke.info, 0));
+            strokes.add_dots(stroke.id, stroke.dots.len());
+            dots.extend(stroke.dots);
+        }
+        self.replace_all(dots, strokes);
+    }
+
+    /// The dots as a document, grouped into their strokes in the order the strokes were started.
+    pub fn document(&self) -> Document {
+        let mut strokes: Vec<DocumentStroke> = Vec::new();
+        let mut positions = HashMap::new();
+        for (&dot, &id) in self.instances.iter().zip(&self.strokes.of_dot) {
+            let position = *positions.entry(id).or_insert_with(|| {
+                let info = self.strokes.info.get(&id).map(|(info, _)| info.clone()).unwrap_or_default();
+                strokes.push(DocumentStroke {
+                    id,
+                    info,
+                    dots: Vec::new(),
+                });
+                strokes.len() - 1
+            });
+            strokes[position].dots.push(dot);
+        }
+        for (&id, dots) in &self.strokes.wet {
+            let info = self.strokes.info.get(&id).map(|(info, _)| info.clone()).unwrap_or_default();
+            strokes.push(DocumentStroke {
+                id,
+                info,
+                dots: dots.clone(),
+            });
+        }
+        strokes.sort_by_key(|stroke| stroke.id);
+        Document {
+            strokes,
+            canvas: Some(CanvasSetup {
+                size: self.global.texture_desc.size.width,
+                background: self.global.background(),
+            }),
+        }
+    }
+
+    fn replace_all(&mut self, dots: Vec<Dot>, strokes: Strokes) {
+        self.mark_dirty(CanvasRect::around_all(&self.instances));
+        self.mark_dirty(CanvasRect::around_all(&dots));
+        self.index = DotIndex::from_dots(&dots);
+        self.instances = dots;
+        self.strokes = strokes;
+        self.tweens.stop_all();
+        self.rebuild_batches(false);
+    }
+
+    /// Removes the dots from `count` on, like [`Self::set_dots`] with the ones before, but only
+    /// reindexing the ones removed, e.g. to undo the latest strokes.
+    pub fn truncate_dots(&mut self, count: usize) {
+        if count >= self.instances.len() {
+            return;
+        }
+        for index in (count..self.instances.len()).rev() {
+            self.index.remove(index, &self.instances[index]);
+        }
+        self.strokes.truncate(count);
+        self.mark_dirty(CanvasRect::around_all(&self.instances[count..]));
+        self.instances.truncate(count);
+        self.tweens.stop_all();
+        self.rebuild_batches(false);
+    }
+
+    /// Replaces the dots from `start` on with `dots`, as many as there are, in place on the GPU.
+    /// Dots beyond the end are ignored.
+    pub fn replace_dots(&mut self, start: usize, dots: &[Dot]) {
+        let end = (start + dots.len()).min(self.instances.len());
+        if start >= end {
+            return;
+        }
+        for (index, dot) in (start..end).zip(dots) {
+            self.index.remove(index, &self.instances[index]);
+            self.index.insert(index, dot);
+        }
+        self.mark_dirty(CanvasRect::around_all(&self.instances[start..end]));
+        self.mark_dirty(CanvasRect::around_all(&dots[..end - start]));
+        let beyond = |dot: &Dot| !CanvasRect::around(dot).intersects(&CanvasRect::CANVAS);
+        let culled = self.instances[start..end].iter().chain(&dots[..end - start]).any(beyond);
+        self.instances[start..end].copy_from_slice(&dots[..end - start]);
+        if culled {
+            self.cull();
+        }
+        let max_batch = self.global.max_batch;
+        for index in start..end {
+            let batch = &self.batches[index / max_batch];
+            let offset = (index % max_batch * std::mem::size_of::<Dot>()) as wgpu::BufferAddress;
+            self.global.queue.write_buffer(&batch.buffer, offset, bytemuck::bytes_of(&self.instances[index]));
+        }
+        self.lod_batches.get_mut().unwrap().clear();
+    }
+
+    /// Index of the topmost dot covering `position`, in canvas coordinates, e.g. the one under the
+    /// pointer.
+    pub fn dot_at(&self, position: [f32; 2]) -> Option<usize> {
+        self.index.dot_at(position, &self.instances)
+    }
+
+    /// Indices of the dots touching `rect`, in drawing order, e.g. to select them or to draw only
+    /// the ones in view.
+    pub fn dots_in(&self, rect: CanvasRect) -> Vec<usize> {
+        self.index.query(rect, &self.instances)
+    }
+
+    /// The strokes on the canvas, oldest first.
+    pub fn strokes(&self) -> impl Iterator<Item = (StrokeId, &StrokeInfo)> + '_ {
+        self.strokes.info.iter().map(|(&id, (info, _))| (id, info))
+    }
+
+    pub fn stroke_info(&self, stroke: StrokeId) -> Option<&StrokeInfo> {
+        self.strokes.info.get(&stroke).map(|(info, _)| info)
+    }
+
+    /// E.g. to note when a stroke ended.
+    pub fn stroke_info_mut(&mut self, stroke: StrokeId) -> Option<&mut StrokeInfo> {
+        self.strokes.info.get_mut(&stroke).map(|(info, _)| info)
+    }
+
+    /// The stroke the dot at `index` belongs to.
+    pub fn stroke_of(&self, index: usize) -> Option<StrokeId> {
+        self.strokes.of_dot.get(index).copied()
+    }
+
+    /// The stroke of the topmost dot covering `position`, e.g. to select the one under the pointer.
+    pub fn stroke_at(&self, position: [f32; 2]) -> Option<StrokeId> {
+        self.dot_at(position).and_then(|index| self.stroke_of(index))
+    }
+
+    /// Indices of the dots of `stroke`, in drawing order.
+    pub fn stroke_dots(&self, stroke: StrokeId) -> Vec<usize> {
+        let count = self.strokes.info.get(&stroke).map_or(0, |(_, count)| *count);
+        // From the top, where the strokes picked and undone mostly are, until all are found
+        let mut indices: Vec<usize> = (0..self.strokes.of_dot.len())
+            .rev()
+            .filter(|&index| self.strokes.of_dot[index] == stroke)
+            .take(count)
+            .collect();
+        indices.reverse();
+        indices
+    }
+
+    /// Removes `stroke` and all of its dots, false if there's no such stroke.
+    pub fn remove_stroke(&mut self, stroke: StrokeId) -> bool {
+        if self.strokes.wet.remove(&stroke).is_some() {
+            self.strokes.info.remove(&stroke);
+            self.repaint_watercolor();
+            return true;
+        }
+        let Some((_, count)) = self.strokes.info.get(&stroke) else {
+            return false;
+        };
+        let first = self.instances.len() - count;
+        if self.strokes.of_dot[first..].iter().all(|&id| id == stroke) {
+            // Undo mostly takes back the latest stroke, with its dots on top
+            self.truncate_dots(first);
+        } else {
+            let (dots, of_dot): (Vec<Dot>, Vec<StrokeId>) = self
+                .instances
+                .iter()
+                .zip(&self.strokes.of_dot)
+                .filter(|&(_, &id)| id != stroke)
+                .unzip();
+            let mut strokes = self.strokes.clone();
+            strokes.of_dot = of_dot;
+            self.replace_all(dots, strokes);
+        }
+        self.strokes.info.remove(&stroke);
+        true
+    }
+
+    /// Where dots were added, replaced or removed since the last call, `None` if nowhere, e.g. to
+    /// redraw only when dots changed. Wet dots count where they were painted, but filters, the
+    /// flowing watercolor and animations aren't tracked.
+    pub fn take_dirty_rect(&mut self) -> Option<CanvasRect> {
+        self.dirty.take()
+    }
+
+    fn mark_dirty(&mut self, changed: Option<CanvasRect>) {
+        self.dirty = match (self.dirty, changed) {
+            (Some(dirty), Some(changed)) => Some(dirty.union(changed)),
+            (dirty, changed) => dirty.or(changed),
+        };
+    }
+
+    /// Plays `sequence` on the dot at `index`, from how it is now, instead of what played on it.
+    /// It moves on with [`Self::simulate`]. False if there's no such dot.
+    pub fn tween(&mut self, index: usize, sequence: Sequence) -> bool {
+        let Some(&dot) = self.instances.get(index) else {
+            return false;
+        };
+        self.tweens.play(index, dot, sequence);
+        true
+    }
+
+    /// Stops the sequence playing on the dot at `index`, leaving it as it is, see [`Self::tween`].
+    pub fn stop_tween(&mut self, index: usize) {
+        self.tweens.stop(index);
+    }
+
+    /// Batches `instances` again. With particles, the dots already moved keep moving on from where
+    /// they are if `keep_particles`, otherwise all start out at rest.
+    fn rebuild_batches(&mut self, keep_particles: bool) {
+        let batches = DotBatch::create_all(&self.global, &self.instances, self.particles.is_some());
+        if let Some(particles) = &mut self.particles {
+            let previous: Vec<&wgpu::Buffer> = if keep_particles {
+                self.batches.iter().map(|batch| &batch.buffer).collect()
+            } else {
+                Vec::new()
+            };
+            let buffers: Vec<&wgpu::Buffer> = batches.iter().map(|batch| &batch.buffer).collect();
+            let counts: Vec<u32> = batches.iter().map(|batch| batch.count).collect();
+            particles.resize(&self.global.device, &self.global.queue, &previous, &buffers, &counts);
+        }
+        self.batches = batches;
+        self.lod_batches.get_mut().unwrap().clear();
+        self.cull();
+    }
+
+    /// Leaves the dots beyond the canvas out of [`Self::drawn`], unless particles may move them
+    /// onto it.
+    fn cull(&mut self) {
+        let beyond = match self.particles {
+            Some(_) => Vec::new(),
+            None => self.index.beyond_canvas(&self.instances),
+        };
+        self.drawn.clear();
+        let mut start = 0;
+        for index in beyond.into_iter().chain([self.instances.len()]) {
+            if start < index {
+                self.drawn.push(start..index);
+            }
+            start = index + 1;
+        }
+    }
+
+    /// Draws the dots in `order` from the next render on.
+    pub fn set_dot_order(&mut self, order: DotOrder) {
+        self.dot_order = order;
+        self.update_depth_stencil();
+    }
+
+    pub fn dot_order(&self) -> DotOrder {
+        self.dot_order
+    }
+
+    /// Blends the dots of each stroke with `overlap` from the next render on. Tiny dots merged
+    /// for the [level of detail](Self::set_lod) always blend.
+    pub fn set_stroke_overlap(&mut self, overlap: StrokeOverlap) {
+        self.stroke_overlap = overlap;
+        self.update_depth_stencil();
+    }
+
+    pub fn stroke_overlap(&self) -> StrokeOverlap {
+        self.stroke_overlap
+    }
+
+    /// Creates the depth and stencil attachment if the dot order or stroke overlap need it.
+    fn update_depth_stencil(&mut self) {
+        let needed = self.dot_order == DotOrder::Depth || self.stroke_overlap == StrokeOverlap::Once;
+        if needed == self.depth_stencil_view.is_some() {
+            return;
+        }
+        self.depth_stencil_view = needed.then(|| {
+            let label = self.global.label("Canvas Depth Stencil");
+            self.global
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&label),
+                    format: DEPTH_STENCIL_FORMAT,
+                    sample_count: self.global.sample_count(),
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                    ..self.global.texture_desc.clone()
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+    }
+
+    /// Draws dots smaller than `settings` allow on screen merged from the next render on, see
+    /// [`crate::lod`], or all of them with `None`. Particles and playing tweens are always drawn
+    /// in full, and so is the canvas while a selection grows.
+    pub fn set_lod(&mut self, settings: Option<LodSettings>) {
+        self.lod = settings;
+        self.lod_batches.get_mut().unwrap().clear();
+    }
+
+    pub fn lod(&self) -> Option<&LodSettings> {
+        self.lod.as_ref()
+    }
+
+    /// How many screen pixels a canvas pixel covers where the canvas is shown, below 1 when it's
+    /// zoomed out. Surface views set it before every frame.
+    pub fn set_screen_scale(&mut self, screen_scale: f32) {
+        self.screen_scale = screen_scale;
+    }
+
+    /// The [level](lod::level) dots are drawn at by [`Self::render`]. The magic wand's selection
+    /// grows over all of them, not their merged stand-ins.
+    pub fn lod_level(&self) -> u32 {
+        match self.lod {
+            Some(_) if self.particles.is_none() && !self.tweens.is_playing() && !self.is_selecting() => {
+                lod::level(self.screen_scale, self.global.texture_desc.size.width)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Moves the dots as particles with `settings` from the next [`Self::simulate`] on, starting
+    /// at rest where [`Self::dots`] has them, or puts them back there with `None`, see
+    /// [`crate::particles`].
+    ///
+    /// Like filters, this needs compute shaders and only logs a warning without them.
+    pub fn set_particles(&mut self, settings: Option<ParticleSettings>) {
+        if settings.is_some() && !particles::is_supported(&self.global.device) {
+            tracing::warn!("Particles need compute shaders, which this device doesn't have");
+            return;
+        }
+        self.particles = settings.map(|settings| ParticleSystem::new(&self.global.device, settings));
+        self.rebuild_batches(false);
+    }
+
+    pub fn particle_settings(&self) -> Option<&ParticleSettings> {
+        self.particles.as_ref().map(ParticleSystem::settings)
+    }
+
+    /// Moves the tweens and particles on and lets wet paint flow for `delta_seconds`, if there
+    /// are any. Surface views call this before every frame.
+    pub fn simulate(&mut self, delta_seconds: f32) {
+        if self.tweens.is_playing() {
+            for (index, dot) in self.tweens.step(delta_seconds) {
+                self.replace_dots(index, &[dot]);
+            }
+        }
+        let device = &self.global.device;
+        if let (Some(particles), false) = (&self.particles, self.batches.is_empty()) {
+            let mut pass = self.global.particle_pass.write().unwrap();
+            let pass = pass.get_or_insert_with(|| ParticlePass::new(device));
+            let _span = tracing::info_span!("particles", dots = self.instances.len()).entered();
+            diagnostics::scoped(device, Scope::Other("moving the particles"), || {
+                particles.step(pass, device, &self.global.queue, self.instance_buffers(), delta_seconds)
+            });
+        }
+        if let Some(watercolor) = self.watercolor.as_mut().filter(|watercolor| watercolor.is_wet()) {
+            let pass = self.global.watercolor_pass.read().unwrap();
+            let pass = pass.as_ref().expect("built with the wet layer");
+            let _span = tracing::info_span!("watercolor").entered();
+            diagnostics::scoped(device, Scope::Other("letting the paint flow"), || {
+                watercolor.step(pass, device, &self.global.queue, &self.wet_settings, delta_seconds)
+            });
+        }
+    }
+
+    /// Whether the dots of `stroke` flow as watercolor.
+    fn paints_wet(&self, stroke: StrokeId) -> bool {
+        let wet = self.strokes.info.get(&stroke).is_some_and(|(info, _)| info.is_wet());
+        wet && watercolor::is_supported(&self.global.device)
+    }
+
+    /// The watercolor, created with its pipelines for the first wet dots.
+    fn watercolor_layer(&mut self) -> &mut WetLayer {
+        let global = &self.global;
+        self.watercolor.get_or_insert_with(|| {
+            global
+                .watercolor_pass
+                .write()
+                .unwrap()
+                .get_or_insert_with(|| WatercolorPass::new(&global.device, global.texture_desc.format, global.sample_count()));
+            WetLayer::new(&global.device, global.texture_desc.size)
+        })
+    }
+
+    /// Paints the wet strokes again, letting them dry at once, e.g. after undoing one.
+    fn repaint_watercolor(&mut self) {
+        self.watercolor = None;
+        if self.strokes.wet.is_empty() {
+            return;
+        }
+        let _span = tracing::info_span!("repaint_watercolor", strokes = self.strokes.wet.len()).entered();
+        self.watercolor_layer();
+        let (global, strokes) = (&self.global, &self.strokes);
+        let layer = self.watercolor.as_mut().expect("created above");
+        let pass = global.watercolor_pass.read().unwrap();
+        let pass = pass.as_ref().expect("built with the wet layer");
+        for (&stroke, dots) in &strokes.wet {
+            let first = strokes.first_wet(stroke);
+            // Layers dry one after the other
+            if layer.wet_stroke().is_some_and(|wet| wet != first) {
+                layer.settle(pass, &global.device, &global.queue, &self.wet_settings);
+            }
+            layer.deposit(&global.device, dots, first);
+        }
+        layer.settle(pass, &global.device, &global.queue, &self.wet_settings);
+    }
+
+    pub fn wet_settings(&self) -> &WetSettings {
+        &self.wet_settings
+    }
+
+    /// Changes how wet paint flows and dries, including the paint that is already wet.
+    pub fn set_wet_settings(&mut self, settings: WetSettings) {
+        self.wet_settings = settings;
+    }
+
+    /// Whether wet paint is still flowing, so the surface should keep rendering.
+    pub fn is_wet(&self) -> bool {
+        self.watercolor.as_ref().is_some_and(WetLayer::is_wet)
+    }
+
+    /// Removes the wet and the dried paint, with the wet strokes.
+    pub fn clear_watercolor(&mut self) {
+        for stroke in std::mem::take(&mut self.strokes.wet).into_keys() {
+            self.strokes.info.remove(&stroke);
+        }
+        self.watercolor = None;
+    }
+
+    /// Removes all dots and watercolor, keeping them to put back with [`Self::restore`]. Filters
+    /// and the selection stay.
+    pub fn clear(&mut self) -> ClearedCanvas {
+        let cleared = ClearedCanvas {
+            document: self.document(),
+            watercolor: self.watercolor.take(),
+        };
+        self.set_dots(Vec::new());
+        cleared
+    }
+
+    /// Puts back what [`Self::clear`] removed, replacing whatever was painted since.
+    pub fn restore(&mut self, cleared: ClearedCanvas) {
+        self.replace_strokes(cleared.document);
+        // As it was rather than painted again
+        self.watercolor = cleared.watercolor;
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Adds `filter` after the existing ones. The next render reports to `progress` as the GPU
+    /// works through the filters, which takes a while for large blurs of large canvases.
+    ///
+    /// Devices without compute shaders, like WebGL2, can't run filters and only log a warning.
+    pub fn add_filter(&mut self, filter: Filter, progress: Option<FilterProgress>) -> Result<(), FilterError> {
+        let mut filters = self.filters.clone();
+        filters.push(filter);
+        self.set_filters(filters)?;
+        *self.filter_progress.lock().unwrap() = progress;
+        Ok(())
+    }
+
+    /// Removes all filters, including the preview.
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+        self.preview = None;
+    }
+
+    /// Replaces all filters. Fails, keeping the current filters, if a convolution names a kernel
+    /// that isn't registered.
+    pub fn set_filters(&mut self, filters: Vec<Filter>) -> Result<(), FilterError> {
+        self.prepare_filters(&filters)?;
+        self.filters = filters;
+        Ok(())
+    }
+
+    /// The filter shown on top of the others until it's committed, if any.
+    pub fn preview(&self) -> Option<&Filter> {
+        self.preview.as_ref()
+    }
+
+    /// Shows `filter` on top of the others without adding it yet, e.g. while its settings are being
+    /// dragged. `None` cancels the preview.
+    pub fn set_preview(&mut self, filter: Option<Filter>) -> Result<(), FilterError> {
+        self.prepare_filters(filter.as_slice())?;
+        self.preview = filter;
+        Ok(())
+    }
+
+    /// Adds the previewed filter after the others, returning whether there was one.
+    pub fn commit_preview(&mut self) -> bool {
+        match self.preview.take() {
+            Some(filter) => {
+                self.filters.push(filter);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the area around `wand`'s pixel with a similar color, replacing the current
+    /// selection. The area grows over the next renders, see [`Self::is_selecting`]. Only filters
+    /// keep to it: dots are drawn everywhere, as they're redrawn with every render rather than
+    /// painted into the canvas once.
+    ///
+    /// Like filters, this needs compute shaders and only logs a warning without them.
+    pub fn select(&mut self, wand: MagicWand) {
+        if !selection::is_supported(&self.global.device) {
+            tracing::warn!("The magic wand needs compute shaders, which this device doesn't have");
+            return;
+        }
+        let selection = Selection::new(&self.global.device, wand, self.global.texture_desc.size);
+        *self.selection.get_mut().unwrap() = Some(selection);
+    }
+
+    /// Selects the whole canvas again.
+    pub fn clear_selection(&mut self) {
+        *self.selection.get_mut().unwrap() = None;
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selection.lock().unwrap().is_some()
+    }
+
+    /// Whether any dot is animated, tweened or moving as a particle, so the surface should keep
+    /// rendering.
+    pub fn is_animated(&self) -> bool {
+        self.particles.is_some() || self.tweens.is_playing() || self.instances.iter().any(Dot::is_animated)
+    }
+
+    /// Whether the selection is still growing, so the surface should keep rendering.
+    pub fn is_selecting(&self) -> bool {
+        self.selection.lock().unwrap().as_ref().is_some_and(Selection::is_growing)
+    }
+
+    /// Calls `plugin` whenever the canvas renders, after the plugins added before it.
+    pub fn add_plugin(&mut self, plugin: Arc<dyn RenderPassPlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    /// Removes `plugin`, returning whether it was added.
+    pub fn remove_plugin(&mut self, plugin: &Arc<dyn RenderPassPlugin>) -> bool {
+        let count = self.plugins.len();
+        self.plugins.retain(|added| !Arc::ptr_eq(added, plugin));
+        self.plugins.len() != count
+    }
+
+    fn canvas_info(&self) -> CanvasInfo {
+        CanvasInfo {
+            format: self.texture.format(),
+            size: self.global.texture_desc.size,
+            sample_count: self.global.sample_count(),
+        }
+    }
+
+    /// Checks the kernels of `filters` exist, and creates the targets if they are the first ones.
+    fn prepare_filters(&mut self, filters: &[Filter]) -> Result<(), FilterError> {
+        for filter in filters {
+            if let FilterKind::Convolution { kernel } = &filter.kind {
+                if !self.global.has_kernel(kernel) {
+                    return Err(FilterError::UnknownKernel(kernel.clone()));
+                }
+            }
+        }
+        if !filters.is_empty() && self.filter_targets.is_none() {
+            match self.global.with_filter_pipelines(|_| ()) {
+                Some(()) => {
+                    self.filter_targets = Some(FilterTargets::new(&self.global.device, self.global.texture_desc.size));
+                }
+                None => tracing::warn!("Filters need compute shaders, which this device doesn't have"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the canvas, with tiny dots merged as far as [`Self::lod_level`] has it. Fails with
+    /// [`Error::Gpu`] if the GPU rejected a pass, natively; on the web those errors are only logged.
+    pub fn render(&self) -> Result<(), Error> {
+        self.render_at(self.lod_level())
+    }
+
+    /// Renders the canvas with every dot however small it's shown, e.g. before exporting it. Fails
+    /// like [`Self::render`].
+    pub fn render_detailed(&self) -> Result<(), Error> {
+        self.render_at(0)
+    }
+
+    fn render_at(&self, level: u32) -> Result<(), Error> {
+        let _span = tracing::info_span!("canvas", dots = self.instances.len(), level).entered();
+        let device = &self.global.device;
+        let scope = Scope::Pass {
+            pipeline: "dot",
+            pipeline_format: self.global.texture_desc.format,
+            target: "canvas",
+            target_format: self.texture.format(),
+        };
+        diagnostics::checked(device, scope, || {
+            let encode_span = tracing::info_span!("encode", batches = self.batches.len()).entered();
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(&self.global.label("Canvas Encoder")),
+            });
+
+            let canvas = self.canvas_info();
+            encoder.push_debug_group("plugins before canvas pass");
+            for plugin in &self.plugins {
+                plugin.before_pass(&mut PluginContext {
+                    device,
+                    queue: &self.global.queue,
+                    encoder: &mut encoder,
+                    canvas_view: &self.texture_view,
+                    canvas,
+                });
+            }
+            encoder.pop_debug_group();
+
+            let mut lod_batches = self.lod_batches.lock().unwrap();
+            let all_dots = 0..usize::MAX;
+            let (batches, stroke_runs, drawn) = match (level, &self.lod) {
+                (1.., Some(settings)) => {
+                    let batches = lod_batches.entry(level).or_insert_with(|| {
+                        let _span = tracing::info_span!("decimate", level).entered();
+                        let dots: Vec<Dot> = self.drawn.iter().flat_map(|range| &self.instances[range.clone()]).copied().collect();
+                        let dots = lod::decimate(&dots, self.global.texture_desc.size.width, level, settings);
+                        DotBatch::create_all(&self.global, &dots, false)
+                    });
+                    (&*batches, None, std::slice::from_ref(&all_dots))
+                }
+                _ => {
+                    let runs = (self.stroke_overlap == StrokeOverlap::Once).then(|| self.strokes.runs());
+                    (&self.batches, runs, &self.drawn[..])
+                }
+            };
+
+            let mut defines = ShaderDefines::default();
+            if self.depth_stencil_view.is_some() {
+                defines = defines.with(DEPTH_STENCIL);
+            }
+            if self.dot_order == DotOrder::Depth {
+                defines = defines.with(DEPTH_DOTS).with(DISCARD_TRANSPARENT);
+            }
+            let render_pipeline = self.global.render_pipeline(&defines);
+            // Marking, painting and edge pipelines
+            let stroke_pipelines = stroke_runs.as_ref().map(|_| {
+                [
+                    defines.clone().with(STROKE_MARK).with(STROKE_CORE),
+                    defines.clone().with(STROKE_CORE),
+                    defines.clone().with(STROKE_EDGE).with(DISCARD_TRANSPARENT),
+                ]
+                .map(|defines| self.global.render_pipeline(&defines))
+            });
+            let watercolor_pass = self.global.watercolor_pass.read().unwrap();
+            let watercolor = self
+                .watercolor
+                .as_ref()
+                .zip(watercolor_pass.as_ref())
+                .map(|(layer, pass)| (pass, layer.composite_bind_groups(pass, device)));
+            // The dried layers of watercolor, with how many dots they go over
+            let layers: Vec<(usize, &wgpu::BindGroup)> = match &watercolor {
+                Some((_, bind_groups)) => bind_groups
+                    .iter()
+                    .map(|(first, bind_group)| {
+                        let below = match (level, &self.lod, self.dot_order) {
+                            (1.., Some(_), _) | (_, _, DotOrder::Depth) => usize::MAX,
+                            _ => self.strokes.of_dot.iter().position(|id| id > first).unwrap_or(usize::MAX),
+                        };
+                        (below, bind_group)
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            let pass_label = self.global.label("Canvas Pass");
+            let background = self.global.background().clear_color();
+            let color_attachment = |load| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.multisampled_view.as_ref().unwrap_or(&self.texture_view),
+                    resolve_target: self.multisampled_view.as_ref().map(|_| &self.texture_view),
+                    ops: wgpu::Operations { load, store: true },
+                })
+            };
+            // The dots between the layers, each drawn by passes ending with the layer over them.
+            // Strokes drawn with the stencil get references of their own, the stencil is cleared with
+            // a new pass when they run out
+            let mut passes: Vec<CanvasPass<'_>> = Vec::new();
+            let mut start = 0;
+            for (end, layer) in layers.iter().map(|&(below, layer)| (below, Some(layer))).chain([(usize::MAX, None)]) {
+                let dots = start..end.max(start);
+                start = dots.end;
+                match &stroke_runs {
+                    Some(runs) => {
+                        let runs: Vec<Range<usize>> = runs
+                            .iter()
+                            .map(|run| run.start.max(dots.start)..run.end.min(dots.end))
+                            .filter(|run| !run.is_empty())
+                            .collect();
+                        let mut chunks: Vec<CanvasPass<'_>> = runs
+                            .chunks(STENCIL_STROKES)
+                            .map(|runs| CanvasPass {
+                                dots: dots.clone(),
+                                runs: runs.to_vec(),
+                                watercolor: None,
+                            })
+                            .collect();
+                        if chunks.is_empty() {
+                            chunks.push(CanvasPass {
+                                dots: dots.clone(),
+                                runs: Vec::new(),
+                                watercolor: None,
+                            });
+                        }
+                        chunks.last_mut().expect("pushed above").watercolor = layer;
+                        passes.extend(chunks);
+                    }
+                    None => passes.push(CanvasPass {
+                        dots,
+                        runs: Vec::new(),
+                        watercolor: layer,
+                    }),
+                }
+            }
+            let wet_pass = watercolor.as_ref().map(|(pass, _)| *pass);
+            encoder.push_debug_group("canvas pass");
+            for (index, pass) in passes.iter().enumerate() {
+                let [first, last] = [index == 0, index == passes.len() - 1];
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&pass_label),
+                    color_attachments: &[color_attachment(match first {
+                        true => wgpu::LoadOp::Clear(background),
+                        false => wgpu::LoadOp::Load,
+                    })],
+                    depth_stencil_attachment: self.depth_stencil_view.as_ref().map(|view| {
+                        wgpu::RenderPassDepthStencilAttachment {
+                            view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: if first { wgpu::LoadOp::Clear(0.0) } else { wgpu::LoadOp::Load },
+                                store: !last,
+                            }),
+                            stencil_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(0),
+                                store: false,
+                            }),
+                        }
+                    }),
+                });
+
+                if !self.instances.is_empty() {
+                    render_pass.push_debug_group("dots");
+                    render_pass.set_bind_group(0, &self.global.canvas_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, self.global.vertex_buffer.slice(..));
+                    match &stroke_pipelines {
+                        Some(pipelines) => {
+                            for (stroke, run) in pass.runs.iter().enumerate() {
+                                render_pass.set_stencil_reference(stroke as u32 * 2 + 1);
+                                for pipeline in pipelines {
+                                    render_pass.set_pipeline(pipeline);
+                                    draw_dots(&mut render_pass, batches, run.clone(), drawn, self.global.max_batch);
+                                }
+                            }
+                        }
+                        None => {
+                            render_pass.set_pipeline(&render_pipeline);
+                            draw_dots(&mut render_pass, batches, pass.dots.clone(), drawn, self.global.max_batch);
+                        }
+                    }
+                    render_pass.pop_debug_group();
+                }
+
+                if self.depth_stencil_view.is_none() {
+                    draw_over_dots(&mut render_pass, wet_pass.zip(pass.watercolor), last.then_some(&self.plugins[..]), &canvas);
+                } else if pass.watercolor.is_some() || last {
+                    drop(render_pass);
+                    // The watercolor and plugin pipelines don't take the dots' depth and stencil
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass_label),
+                        color_attachments: &[color_attachment(wgpu::LoadOp::Load)],
+                        depth_stencil_attachment: None,
+                    });
+                    draw_over_dots(&mut render_pass, wet_pass.zip(pass.watercolor), last.then_some(&self.plugins[..]), &canvas);
+                }
+            }
+            encoder.pop_debug_group();
+
+            encoder.push_debug_group("plugins after canvas pass");
+            for plugin in &self.plugins {
+                plugin.after_pass(&mut PluginContext {
+                    device,
+                    queue: &self.global.queue,
+                    encoder: &mut encoder,
+                    canvas_view: &self.texture_view,
+                    canvas,
+                });
+            }
+            encoder.pop_debug_group();
+            let commands = encoder.finish();
+            encode_span.exit();
+
+            tracing::info_span!("submit").in_scope(|| self.global.queue.submit(Some(commands)));
+        })?;
+
+        let mut selection = self.selection.lock().unwrap();
+        if let Some(selection) = selection.as_mut().filter(|selection| selection.is_growing()) {
+            let mut pass = self.global.magic_wand_pass.write().unwrap();
+            let pass = pass.get_or_insert_with(|| MagicWandPass::new(device));
+            let _span = tracing::info_span!("grow_selection").entered();
+            diagnostics::checked(device, Scope::Other("growing the selection"), || {
+                selection.grow(pass, device, &self.global.queue, &self.texture_view)
+            })?;
+        }
+
+        let filters: Vec<&Filter> = self.filters.iter().chain(&self.preview).collect();
+        if let (false, Some(targets)) = (filters.is_empty(), &self.filter_targets) {
+            let _span = tracing::info_span!("filters", count = filters.len()).entered();
+            let progress = self.filter_progress.lock().unwrap().take();
+            self.global
+                .with_filter_pipelines(|pipelines| {
+                    diagnostics::checked(device, Scope::Other("running the filters"), || {
+                        pipelines.apply(
+                            device,
+                            &self.global.queue,
+                            &self.texture_view,
+                            targets,
+                            &filters,
+                            &self.global.kernels.read().unwrap(),
+                            selection.as_ref().map(Selection::mask),
+                            progress,
+                        )
+                    })
+                })
+            .transpose()?;
+        }
+        Ok(())
+    }
+
+    /// Starts counting the canvas, as last rendered, into histograms. `None` if the device can't,
+    /// like WebGL2 without compute shaders.
+    pub fn histogram(&self) -> Option<HistogramReadback> {
+        if !histogram::is_supported(&self.global.device) {
+            return None;
+        }
+        let mut pass = self.global.histogram_pass.write().unwrap();
+        let pass = pass.get_or_insert_with(|| HistogramPass::new(&self.global.device));
+        Some(diagnostics::scoped(&self.global.device, Scope::Other("counting the histogram"), || {
+            pass.count(
+                self.global.device.clone(),
+                &self.global.queue,
+                &self.texture_view,
+                self.global.texture_desc.size,
+            )
+        }))
+    }
+
+    /// Starts copying the instance buffers back to the CPU, to compare them with the dots.
+    pub fn instance_readback(&self) -> InstanceReadback {
+        diagnostics::scoped(&self.global.device, Scope::Other("reading back the instance buffers"), || {
+            InstanceReadback::new(
+                self.global.device.clone(),
+                &self.global.queue,
+                self.instance_buffers(),
+                self.instances.clone(),
+            )
+        })
+    }
+
+    /// Starts copying the canvas texture back to the CPU, e.g. for exporting.
+    pub fn readback(&self) -> TextureReadback {
+        diagnostics::scoped(&self.global.device, Scope::Other("reading back the canvas"), || {
+            TextureReadback::new(
+                self.global.device.clone(),
+                &self.global.queue,
+                &self.texture,
+                self.global.texture_desc.size,
+                self.global.texture_desc.format,
+            )
+        })
+    }
+
+    /// Reads back the canvas framed as `framing` asks: cropped, inside a border and with a
+    /// watermark. Just [`Self::readback`] if it doesn't ask for anything.
+    pub fn framed_readback(&self, framing: &Framing) -> TextureReadback {
+        if framing.is_none() {
+            return self.readback();
+        }
+        let device = &self.global.device;
+        let layout = framing.layout(
+            self.global.texture_desc.size.width,
+            device.limits().max_texture_dimension_2d,
+        );
+        diagnostics::scoped(device, Scope::Other("framing the export"), || {
+            let mut pass = self.global.framing_pass.write().unwrap();
+            let pass = pass.get_or_insert_with(|| FramingPass::new(device, self.global.texture_desc.format));
+            let texture = pass.frame(device, &self.global.queue, &self.texture_view, framing, &layout);
+            TextureReadback::new(
+                device.clone(),
+                &self.global.queue,
+                &texture,
+                texture.size(),
+                self.global.texture_desc.format,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The location, component count and element type of every vertex input of the dot shader's
+    /// vertex stage, as naga parses it.
+    fn shader_vertex_inputs() -> Vec<(u32, u32, naga::ScalarKind, naga::Bytes)> {
+        let source = Shader::Dot.validate(Shader::Dot.embedded_source(), &ShaderDefines::new(), wgpu::Features::empty()).unwrap();
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        let entry_point = module.entry_points.iter().find(|entry| entry.stage == naga::ShaderStage::Vertex).unwrap();
+        let input = |binding: &Option<naga::Binding>, ty: naga::Handle<naga::Type>| {
+            let Some(naga::Binding::Location { location, .. }) = binding else {
+                return None;
+            };
+            let (components, kind, width) = match module.types[ty].inner {
+                naga::TypeInner::Scalar { kind, width } => (1, kind, width),
+                naga::TypeInner::Vector { size, kind, width } => (size as u32, kind, width),
+                ref other => panic!("location {location} is a {other:?}"),
+            };
+            Some((*location, components, kind, width))
+        };
+        let mut inputs = Vec::new();
+        for argument in &entry_point.function.arguments {
+            match &module.types[argument.ty].inner {
+                naga::TypeInner::Struct { members, .. } => {
+                    inputs.extend(members.iter().filter_map(|member| input(&member.binding, member.ty)));
+                }
+                _ => inputs.extend(input(&argument.binding, argument.ty)),
+            }
+        }
+        inputs.sort_by_key(|&(location, ..)| location);
+        inputs
+    }
+
+    #[test]
+    fn vertex_attributes_match_the_dot_shader() {
+        let attributes: Vec<_> = Vertex::ATTRIBUTES
+            .iter()
+            .chain(Dot::ATTRIBUTES)
+            .map(|attribute| {
+                let components = match attribute.format {
+                    wgpu::VertexFormat::Float32 => 1,
+                    wgpu::VertexFormat::Float32x2 => 2,
+                    wgpu::VertexFormat::Float32x3 => 3,
+                    wgpu::VertexFormat::Float32x4 => 4,
+                    other => panic!("dot_common.wgsl only takes floats, not {other:?}"),
+                };
+                (attribute.shader_location, components, naga::ScalarKind::Float, 4)
+            })
+            .collect();
+        assert_eq!(shader_vertex_inputs(), attributes);
+    }
+
+    #[test]
+    fn dot_attributes_match_its_fields() {
+        let dot = Dot::random(&mut rand::thread_rng());
+        let base = std::ptr::addr_of!(dot) as usize;
+        let offset = |field: *const f32| (field as usize - base) as wgpu::BufferAddress;
+        // The first field of each attribute, which the following ones of its components follow
+        let fields = [
+            (1, offset(dot.position.as_ptr())),
+            (2, offset(&dot.radius)),
+            (3, offset(&dot.hardness)),
+            (4, offset(&dot.color.r)),
+            (5, offset(&dot.noise.scale)),
+            (6, offset(&dot.animation.start)),
+            (7, offset(&dot.animation.hue_speed)),
+            (8, offset(&dot.depth)),
+        ];
+        let attributes: Vec<_> = Dot::ATTRIBUTES
+            .iter()
+            .map(|attribute| (attribute.shader_location, attribute.offset))
+            .collect();
+        assert_eq!(attributes, fields);
+        let last = Dot::ATTRIBUTES.last().unwrap();
+        assert_eq!(last.offset + last.format.size(), std::mem::size_of::<Dot>() as wgpu::BufferAddress);
+        // `load_dot` in dot_common.wgsl reads them as 17 floats
+        assert_eq!(std::mem::size_of::<Dot>(), 17 * 4);
     }
 }
@@ -1,49 +1,208 @@
 use std::num::NonZeroU64;
 use std::sync::Arc;
-
-use eframe::egui_wgpu;
-use eframe::egui_wgpu::RenderState;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 use wgpu::TextureFormat;
 use wgpu::util::DeviceExt;
 
-use crate::surface::HpSurface;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::clock::Clock;
+use crate::diagnostics::{self, Scope};
+use crate::error::Error;
+use crate::guides::Guides;
+use crate::new_document::CanvasSetup;
+use crate::post_process::PostProcess;
+use crate::preprocessor::ShaderDefines;
+use crate::renderer::{self, PipelineKey};
+use crate::shaders::{Shader, ShaderError};
+use crate::surface::{HpSurface, Uniforms};
+use crate::timeline::Timeline;
+
+/// Called with every frame, see [`SurfaceRenderResources::on_pre_frame`].
+pub type FrameHook = Arc<dyn Fn(&wgpu::Device, &wgpu::Queue, &FrameStats) + Send + Sync>;
 
+/// The frame a [`FrameHook`] is called for.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameStats {
+    /// Counts up from 1 with every prepared frame, wrapping around.
+    pub frame: u32,
+    /// Since the resources were created, or the ones they were recreated from.
+    pub seconds: f64,
+    /// Since the previous frame.
+    pub delta_seconds: f64,
+    /// On the canvas.
+    pub dots: usize,
+}
+
+/// How [`SurfaceRenderResources`] shows the current frame next to its snapshot, see
+/// [`SurfaceRenderResources::take_snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    /// The snapshot on the left half of the canvas' square and the current frame on the right,
+    /// each half as large.
+    SideBySide,
+    /// The snapshot left of a line this far across the canvas, 0..1, and the current frame right
+    /// of it.
+    Split(f32),
+}
+
+impl Comparison {
+    /// `Frame.comparison` and `Frame.split` in surface_view_shader.wgsl.
+    fn uniforms(comparison: Option<Self>) -> (u32, f32) {
+        match comparison {
+            None => (0, 0.0),
+            Some(Comparison::SideBySide) => (1, 0.0),
+            Some(Comparison::Split(split)) => (2, split.clamp(0.0, 1.0)),
+        }
+    }
+}
 
+/// Mirrors the canvas on screen, to check its proportions with fresh eyes. Only the view changes,
+/// never the painting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewFlip {
+    /// Left and right swapped.
+    pub horizontal: bool,
+    /// Top and bottom swapped.
+    pub vertical: bool,
+}
+
+impl ViewFlip {
+    /// `Frame.flip` in surface_view_shader.wgsl.
+    fn bits(self) -> u32 {
+        u32::from(self.horizontal) | u32::from(self.vertical) << 1
+    }
+}
+
+/// Draws an [`HpSurface`] into a render target: with its post-processing effects by
+/// [`Self::render_to_texture`], or into a render pass of the embedding app by [`Self::prepare`]
+/// and [`Self::paint`]. The surface is the current frame of a [`Timeline`], the frames around it
+/// are drawn faded over it as onion skins.
 pub struct SurfaceRenderResources {
-    pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
-    texture_bind_group: wgpu::BindGroup,
-    uniform_buffer: wgpu::Buffer,
-    surface: HpSurface,
+    /// Shared with other views of the same format through the global surface's cache.
+    pipeline: Arc<wgpu::RenderPipeline>,
+    /// Kept to rebuild the pipeline when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pipeline_layout: wgpu::PipelineLayout,
+    /// What `pipeline` draws into.
+    format: TextureFormat,
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    defines: ShaderDefines,
+    texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Of the frames drawn, the current frame first and the onion skins over it, rebuilt by
+    /// [`Self::prepare`].
+    frame_bind_groups: Vec<wgpu::BindGroup>,
+    /// A frame's [`FrameUniforms`] each, grown with the frames drawn.
+    frame_buffers: Vec<wgpu::Buffer>,
+    /// A copy of the canvas as it was, see [`Self::take_snapshot`].
+    snapshot: Option<wgpu::TextureView>,
+    comparison: Option<Comparison>,
+    guides: Option<Guides>,
+    flip: ViewFlip,
+    uniform_binding: UniformBinding,
+    uniforms: Uniforms,
+    /// In pixels, see [`Self::set_viewport_size`].
+    viewport_size: Option<[f32; 2]>,
+    clock: Clock,
+    /// When the previous frame was prepared, in seconds on `clock`.
+    last_frame_seconds: f64,
+    post_process: PostProcess,
+    timeline: Timeline,
+    /// Of the frame last prepared.
+    frame_stats: FrameStats,
+    pre_frame_hooks: Vec<FrameHook>,
+    post_frame_hooks: Vec<FrameHook>,
+}
+
+/// `Frame` in surface_view_shader.wgsl, how a frame of the timeline is drawn.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct FrameUniforms {
+    opacity: f32,
+    /// With the snapshot, see [`Comparison::uniforms`], only for the current frame.
+    comparison: u32,
+    split: f32,
+    /// See [`guide_uniforms`], also only for the current frame.
+    guides: u32,
+    vanishing_points: [[f32; 2]; 3],
+    spacing: f32,
+    /// See [`ViewFlip::bits`].
+    flip: u32,
+}
+
+/// Parallel perspective guides are this far apart, in canvas units.
+const PARALLEL_SPACING: f32 = 0.25;
+
+/// `Frame.guides`, `Frame.vanishing_point0` to 2 and `Frame.spacing` in surface_view_shader.wgsl.
+fn guide_uniforms(guides: Option<Guides>) -> (u32, [[f32; 2]; 3], f32) {
+    let mut vanishing_points = [[0.0; 2]; 3];
+    let Some(guides) = guides else {
+        return (0, vanishing_points, 0.0);
+    };
+    let points = guides.vanishing_points();
+    vanishing_points[..points.len()].copy_from_slice(points);
+    match guides {
+        Guides::Isometric { spacing } => (4, vanishing_points, spacing),
+        _ => (points.len() as u32, vanishing_points, PARALLEL_SPACING),
+    }
+}
+
+/// How the per-frame [`Uniforms`] reach the shader.
+enum UniformBinding {
+    /// Set directly on the render pass, where the device supports push constants.
+    PushConstants,
+    /// Written to a uniform buffer bound as group 1, e.g. on WebGPU.
+    Buffer {
+        buffer: wgpu::Buffer,
+        bind_group: wgpu::BindGroup,
+    },
+}
+
+/// The shader variant for drawing into `format` with `uniform_binding`.
+fn shader_defines(uniform_binding: &UniformBinding, format: TextureFormat) -> ShaderDefines {
+    let mut defines = ShaderDefines::new();
+    if let UniformBinding::PushConstants = uniform_binding {
+        defines = defines.with("PUSH_CONSTANTS");
+    }
+    // The canvas is sampled as linear color, which sRGB targets encode on their own and float
+    // targets keep
+    if renderer::stores_encoded(format) {
+        defines = defines.with("ENCODE_SRGB");
+    }
+    defines
 }
 
+/// The pipeline variant drawing into `format` with `defines`.
+fn pipeline_key(format: TextureFormat, defines: ShaderDefines) -> PipelineKey {
+    PipelineKey {
+        shader: Shader::SurfaceView,
+        format,
+        // The canvas is premultiplied, composite it over what's already in the target
+        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        sample_count: 1,
+        defines,
+    }
+}
+
+const UNIFORM_STAGES: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
 impl SurfaceRenderResources {
 
+    /// Draws `surface` into targets of `format`.
     pub fn new(device: &wgpu::Device, surface: HpSurface, format: TextureFormat) -> Self {
+        Self::with_timeline(device, Timeline::new(surface), format)
+    }
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("custom3d"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("./surface_view_shader.wgsl").into()),
-        });
-
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("custom3d"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: NonZeroU64::new(16),
-                },
-                count: None,
-            }],
-        });
+    /// Draws the current frame of `timeline` into targets of `format`.
+    pub fn with_timeline(device: &wgpu::Device, timeline: Timeline, format: TextureFormat) -> Self {
+        let cache = &timeline.current().global.pipeline_cache;
 
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
+        let texture_bind_group_layout = cache.bind_group_layout(
+            device,
+            "texture_bind_group_layout",
+            &[
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
                         visibility: wgpu::ShaderStages::FRAGMENT,
@@ -62,96 +221,514 @@ impl SurfaceRenderResources {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(std::mem::size_of::<FrameUniforms>() as u64),
+                        },
+                        count: None,
+                    },
+                    // The snapshot compared with, sampled like the frame
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
                 ],
-                label: Some("texture_bind_group_layout"),
+            );
+
+        let uses_push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= Uniforms::SIZE;
+
+        let (pipeline_layout, uniform_binding) = if uses_push_constants {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("custom3d"),
+                bind_group_layouts: &[&texture_bind_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: UNIFORM_STAGES,
+                    range: 0..Uniforms::SIZE,
+                }],
+            });
+            (pipeline_layout, UniformBinding::PushConstants)
+        } else {
+            let bind_group_layout = cache.bind_group_layout(
+                device,
+                "custom3d",
+                &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: UNIFORM_STAGES,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(Uniforms::SIZE.into()),
+                    },
+                    count: None,
+                }],
+            );
+
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("custom3d"),
+                bind_group_layouts: &[&texture_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
             });
 
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("custom3d"),
+                contents: bytemuck::bytes_of(&Uniforms::default()),
+                // Mapping at creation (as done by the create_buffer_init utility) doesn't require us to to add the MAP_WRITE usage
+                // (this *happens* to workaround this bug )
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("custom3d"),
-            bind_group_layouts: &[&bind_group_layout, &texture_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("custom3d"),
+                layout: &bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("custom3d"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(format.into())],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+            (pipeline_layout, UniformBinding::Buffer { buffer, bind_group })
+        };
 
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("custom3d"),
-            contents: bytemuck::cast_slice(&[0.0_f32; 4]), // 16 bytes aligned!
-            // Mapping at creation (as done by the create_buffer_init utility) doesn't require us to to add the MAP_WRITE usage
-            // (this *happens* to workaround this bug )
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
-        });
+        let defines = shader_defines(&uniform_binding, format);
+        let pipeline = cache
+            .pipeline(pipeline_key(format, defines.clone()), |key| {
+                create_pipeline(device, &pipeline_layout, Shader::SurfaceView.embedded_source(), key)
+            })
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        let mut resources = Self {
+            pipeline,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            pipeline_layout,
+            format,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            defines,
+            texture_bind_group_layout,
+            frame_bind_groups: Vec::new(),
+            frame_buffers: Vec::new(),
+            snapshot: None,
+            comparison: None,
+            guides: None,
+            flip: ViewFlip::default(),
+            uniform_binding,
+            uniforms: Uniforms::default(),
+            viewport_size: None,
+            clock: Clock::new(),
+            last_frame_seconds: 0.0,
+            post_process: PostProcess::new(device, format),
+            timeline,
+            frame_stats: FrameStats::default(),
+            pre_frame_hooks: Vec::new(),
+            post_frame_hooks: Vec::new(),
+        };
+        resources.bind_frames(device);
+        resources
+    }
+
+    /// Rebuilds the pipeline from `source`, keeping the current one if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        let key = pipeline_key(self.format, self.defines.clone());
+        let pipeline = catch_validation_error(device, || create_pipeline(device, &self.pipeline_layout, source, &key))?;
+        self.pipeline = Arc::new(pipeline);
+        self.surface().global.pipeline_cache.insert(key, self.pipeline.clone());
+        Ok(())
+    }
+
+    /// The same frames and effects drawn with another device into targets of `format`, e.g. after
+    /// the previous device was lost. Only fails if the new device can't hold the canvas. The
+    /// snapshot was on the previous device and is gone.
+    pub fn recreate(
+        &self,
+        device: &Arc<wgpu::Device>,
+        queue: &Arc<wgpu::Queue>,
+        format: TextureFormat,
+    ) -> Result<Self, Error> {
+        let global = self.surface().global.recreate(device.clone(), queue.clone())?;
+        let timeline = self.timeline.recreate(Arc::new(global))?;
+        let mut resources = Self::with_timeline(device, timeline, format);
+        resources.post_process = self.post_process.recreate(device, queue, format);
+        // Keep the time running for animated dots and effects
+        resources.clock = self.clock.clone();
+        resources.viewport_size = self.viewport_size;
+        resources.comparison = self.comparison;
+        resources.guides = self.guides;
+        resources.flip = self.flip;
+        resources.last_frame_seconds = self.last_frame_seconds;
+        resources.pre_frame_hooks = self.pre_frame_hooks.clone();
+        resources.post_frame_hooks = self.post_frame_hooks.clone();
+        Ok(resources)
+    }
+
+    /// A new document on a canvas of `canvas`'s size and background, drawn the same way: keeps the
+    /// effects, view and hooks, and the canvas merges tiny dots, orders them, blends strokes and
+    /// moves them as particles like the current one. The other frames and the snapshot are gone.
+    pub fn with_new_canvas(
+        &self,
+        device: &Arc<wgpu::Device>,
+        queue: &Arc<wgpu::Queue>,
+        canvas: CanvasSetup,
+    ) -> Result<Self, Error> {
+        let current = self.surface();
+        let global = current.global.resized(canvas.size)?;
+        global.set_background(canvas.background);
+        let mut surface = HpSurface::new(Arc::new(global));
+        surface.set_lod(current.lod().copied());
+        surface.set_dot_order(current.dot_order());
+        surface.set_stroke_overlap(current.stroke_overlap());
+        if let Some(settings) = current.particle_settings() {
+            surface.set_particles(Some(settings.clone()));
+        }
+        let mut timeline = Timeline::new(surface);
+        timeline.set_onion_skin(self.timeline.onion_skin());
+        let mut resources = Self::with_timeline(device, timeline, self.format);
+        resources.post_process = self.post_process.recreate(device, queue, self.format);
+        resources.clock = self.clock.clone();
+        resources.viewport_size = self.viewport_size;
+        resources.guides = self.guides;
+        resources.flip = self.flip;
+        resources.last_frame_seconds = self.last_frame_seconds;
+        resources.pre_frame_hooks = self.pre_frame_hooks.clone();
+        resources.post_frame_hooks = self.post_frame_hooks.clone();
+        Ok(resources)
+    }
+
+    /// The current frame of [`Self::timeline`].
+    pub fn surface(&self) -> &HpSurface {
+        self.timeline.current()
+    }
+
+    pub fn surface_mut(&mut self) -> &mut HpSurface {
+        self.timeline.current_mut()
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Frames switched or added here are drawn from the next [`Self::prepare`] on.
+    pub fn timeline_mut(&mut self) -> &mut Timeline {
+        &mut self.timeline
+    }
+
+    /// The effects applied by [`Self::render_to_texture`].
+    pub fn post_process(&self) -> &PostProcess {
+        &self.post_process
+    }
+
+    pub fn post_process_mut(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    /// Calls `hook` at the start of every [`Self::prepare`], before the canvas is encoded, e.g. to
+    /// upload data the frame's plugins or dots depend on.
+    pub fn on_pre_frame(&mut self, hook: FrameHook) {
+        self.pre_frame_hooks.push(hook);
+    }
+
+    /// Calls `hook` after [`Self::render_to_texture`] submitted a frame. When the canvas is drawn
+    /// with [`Self::paint`], the embedding app submits the frame and nothing calls `hook`.
+    pub fn on_post_frame(&mut self, hook: FrameHook) {
+        self.post_frame_hooks.push(hook);
+    }
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("custom3d"),
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
+    /// Copies the current frame as it was last rendered, to compare it with later.
+    pub fn take_snapshot(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let surface = self.surface();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("snapshot"),
+            format: surface.texture().format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+            ..surface.global.texture_desc.clone()
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("snapshot"),
         });
+        encoder.copy_texture_to_texture(
+            surface.texture().as_image_copy(),
+            texture.as_image_copy(),
+            surface.global.texture_desc.size,
+        );
+        queue.submit(Some(encoder.finish()));
+        self.snapshot = Some(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        self.bind_frames(device);
+    }
 
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&surface.texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&surface.sampler),
-                },
-            ],
-            label: Some("texture_bind_group"),
+    pub fn has_snapshot(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Shows the current frame next to its snapshot from the next [`Self::prepare`] on, or alone
+    /// with `None`. Without a snapshot, both sides show the current frame.
+    pub fn set_comparison(&mut self, comparison: Option<Comparison>) {
+        self.comparison = comparison;
+    }
+
+    pub fn comparison(&self) -> Option<Comparison> {
+        self.comparison
+    }
+
+    /// Shows `guides` over the current frame from the next [`Self::prepare`] on, or none with
+    /// `None`. They're only drawn on screen, never into the canvas.
+    pub fn set_guides(&mut self, guides: Option<Guides>) {
+        self.guides = guides;
+    }
+
+    pub fn guides(&self) -> Option<&Guides> {
+        self.guides.as_ref()
+    }
+
+    /// Mirrors the frames drawn from the next [`Self::prepare`] on, with what's shown over them.
+    /// Side by side, the snapshot and the current frame swap places when flipped horizontally.
+    pub fn set_flip(&mut self, flip: ViewFlip) {
+        self.flip = flip;
+    }
+
+    pub fn flip(&self) -> ViewFlip {
+        self.flip
+    }
+
+    /// Maps a position in the viewport (in pixels, y down) to canvas coordinates (-1..1, y up).
+    ///
+    /// Mirrors `vs_main` in surface_view_shader.wgsl, which places the canvas in the upper-right
+    /// quarter of the viewport with its rows flipped, as large as it fits while staying square.
+    /// Side by side, either half maps to the canvas shown in it. Flipped, positions are mirrored
+    /// like the canvas.
+    pub fn viewport_to_canvas(&self, position: [f32; 2], viewport_size: [f32; 2]) -> [f32; 2] {
+        let clip_x = position[0] / viewport_size[0] * 2.0 - 1.0;
+        let clip_y = 1.0 - position[1] / viewport_size[1] * 2.0;
+        let [scale_x, scale_y] = canvas_scale(viewport_size[0] / viewport_size[1]);
+        let [x, y] = [clip_x / scale_x, clip_y / scale_y];
+        // Like `fs_main`
+        let x = if self.flip.horizontal { 1.0 - x } else { x };
+        let y = if self.flip.vertical { 1.0 - y } else { y };
+        let [x, y] = match self.comparison {
+            Some(Comparison::SideBySide) => [(x * 2.0).rem_euclid(1.0), y * 2.0 - 0.5],
+            _ => [x, y],
+        };
+        [x * 2.0 - 1.0, 1.0 - y * 2.0]
+    }
+
+    /// The size in pixels of what [`Self::paint`] draws into, so the canvas stays square when its
+    /// aspect ratio changes. [`Self::render_to_texture`] sets it to its target's.
+    pub fn set_viewport_size(&mut self, size: [f32; 2]) {
+        if size[0] > 0.0 && size[1] > 0.0 {
+            self.uniforms.aspect = size[0] / size[1];
+            self.viewport_size = Some(size);
+        }
+    }
+
+    /// Draws the canvas into `target`, e.g. the current swapchain texture, followed by the
+    /// post-processing effects. Fails if the GPU rejected rendering the canvas or drawing it, like
+    /// [`HpSurface::render`]; what could be drawn still is.
+    pub fn render_to_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::Texture,
+    ) -> Result<(), Error> {
+        let _span = tracing::info_span!("render_to_texture").entered();
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let size = target.size();
+        self.set_viewport_size([size.width as f32, size.height as f32]);
+        let prepared = self.prepare(device, queue);
+        self.post_process
+            .prepare(device, queue, target.size(), self.uniforms.seconds);
+
+        let scope = Scope::Pass {
+            pipeline: "surface view",
+            pipeline_format: self.format,
+            target: "target texture",
+            target_format: target.format(),
+        };
+        let drawn = diagnostics::checked(device, scope, || {
+            let encode_span = tracing::info_span!("encode").entered();
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("surface_view"),
+            });
+            encoder.push_debug_group("surface view");
+            {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("surface_view"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: self.post_process.input_view().unwrap_or(&target_view),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+
+                self.paint(&mut rpass);
+            }
+            encoder.pop_debug_group();
+            self.post_process.encode(device, &mut encoder, &target_view);
+            let commands = encoder.finish();
+            encode_span.exit();
+
+            tracing::info_span!("submit").in_scope(|| queue.submit(Some(commands)));
         });
 
-        Self {
-            pipeline,
-            bind_group,
-            texture_bind_group,
-            uniform_buffer,
-            surface,
+        for hook in &self.post_frame_hooks {
+            let _span = tracing::info_span!("post_frame_hook").entered();
+            hook(device, queue, &self.frame_stats);
         }
+        prepared.and(drawn)
     }
 
-    pub fn prepare(&self, _device: &wgpu::Device, queue: &wgpu::Queue) {
+    /// Renders the canvas and updates the per-frame uniforms. Call before [`Self::paint`], outside
+    /// of the render pass, and after [`Self::set_viewport_size`] when the viewport changed. Fails
+    /// if the canvas couldn't be rendered, see [`HpSurface::render`]; the uniforms are updated
+    /// anyway, so the canvas can still be painted as it was.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), Error> {
         info!("Preparing surface");
-        self.surface.render();
-        // Update our uniform buffer with the angle from the UI
-        queue.write_buffer(
-            &self.uniform_buffer,
-            0,
-            bytemuck::cast_slice(&[0.0f32, 0.0, 0.0, 0.0]),
-        );
+        let seconds = self.clock.elapsed_seconds();
+        self.uniforms.frame = self.uniforms.frame.wrapping_add(1);
+        self.uniforms.seconds = seconds as f32;
+        self.uniforms.delta_seconds = (seconds - self.last_frame_seconds) as f32;
+        self.frame_stats = FrameStats {
+            frame: self.uniforms.frame,
+            seconds,
+            delta_seconds: seconds - self.last_frame_seconds,
+            dots: self.surface().dots().len(),
+        };
+        self.last_frame_seconds = seconds;
+        for hook in &self.pre_frame_hooks {
+            let _span = tracing::info_span!("pre_frame_hook").entered();
+            hook(device, queue, &self.frame_stats);
+        }
+        let screen_scale = self.viewport_size.map(|size| {
+            let canvas_size = self.surface().global.texture_desc.size.width as f32;
+            let shown = match self.comparison {
+                Some(Comparison::SideBySide) => 0.5,
+                _ => 1.0,
+            };
+            size[0] * canvas_scale(self.uniforms.aspect)[0] / 2.0 / canvas_size * shown
+        });
+        let surface = self.timeline.current_mut();
+        if let Some(screen_scale) = screen_scale {
+            // As wide on screen as the quarter of the viewport `vs_main` puts it in
+            surface.set_screen_scale(screen_scale);
+        }
+        surface.global.set_seconds(self.uniforms.seconds);
+        surface.simulate(self.uniforms.delta_seconds);
+        let rendered = surface.render();
+        self.bind_frames(device);
+        for (index, (buffer, opacity)) in self.frame_buffers.iter().zip(self.frame_opacities()).enumerate() {
+            let current = index == 0;
+            let (comparison, split) = Comparison::uniforms(self.comparison.filter(|_| current));
+            let (guides, vanishing_points, spacing) = guide_uniforms(self.guides.filter(|_| current));
+            let frame = FrameUniforms {
+                opacity,
+                comparison,
+                split,
+                guides,
+                vanishing_points,
+                spacing,
+                flip: self.flip.bits(),
+            };
+            queue.write_buffer(buffer, 0, bytemuck::bytes_of(&frame));
+        }
+        if let UniformBinding::Buffer { buffer, .. } = &self.uniform_binding {
+            queue.write_buffer(buffer, 0, bytemuck::bytes_of(&self.uniforms));
+        }
+        rendered
     }
 
-    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+    /// The opacity of each frame drawn, in the order of `frame_bind_groups`.
+    fn frame_opacities(&self) -> impl Iterator<Item = f32> + '_ {
+        let onion_frames = self.timeline.onion_frames().into_iter();
+        Some(1.0).into_iter().chain(onion_frames.map(|(_, opacity)| opacity))
+    }
 
+    /// Binds the textures of the frames drawn, which change as the timeline does.
+    fn bind_frames(&mut self, device: &wgpu::Device) {
+        let mut frames = vec![(self.timeline.current(), 1.0)];
+        frames.extend(self.timeline.onion_frames());
+        while self.frame_buffers.len() < frames.len() {
+            self.frame_buffers.push(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("frame_uniforms"),
+                size: std::mem::size_of::<FrameUniforms>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        self.frame_bind_groups = frames
+            .iter()
+            .zip(&self.frame_buffers)
+            .map(|((surface, _), buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&surface.texture_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&surface.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(
+                                self.snapshot.as_ref().unwrap_or(&surface.texture_view),
+                            ),
+                        },
+                    ],
+                    label: Some("texture_bind_group"),
+                })
+            })
+            .collect();
+    }
 
-        // Draw our triangle!
+    /// Draws the canvas into `render_pass` with its onion skins, without the post-processing
+    /// effects, which need their own passes.
+    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
         render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        match &self.uniform_binding {
+            UniformBinding::PushConstants => {
+                render_pass.set_push_constants(UNIFORM_STAGES, 0, bytemuck::bytes_of(&self.uniforms));
+            }
+            UniformBinding::Buffer { bind_group, .. } => render_pass.set_bind_group(1, bind_group, &[]),
+        }
 
-        render_pass.draw(0..6, 0..1);
+        for bind_group in &self.frame_bind_groups {
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
     }
 }
+
+/// How much of the viewport's upper-right quarter the canvas covers in x and y for a viewport
+/// `aspect` wide per unit of height, like `vs_main` in surface_view_shader.wgsl.
+fn canvas_scale(aspect: f32) -> [f32; 2] {
+    [(1.0 / aspect).min(1.0), aspect.min(1.0)]
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    source: &str,
+    key: &PipelineKey,
+) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let shader = key.shader.create_module(device, source, &key.defines)?;
+    Ok(renderer::vertexless_pipeline(device, "custom3d", pipeline_layout, &shader, "fs_main", key.format, key.blend))
+}
@@ -4,11 +4,19 @@ struct VertexOut {
 };
 
 struct Uniforms {
-    @size(16) angle: f32, // pad to 16 bytes
+    frame: u32,
+    seconds: f32,
+    delta_seconds: f32,
+    // Width over height of the viewport
+    aspect: f32,
 };
 
-@group(0) @binding(0)
+#ifdef PUSH_CONSTANTS
+var<push_constant> uniforms: Uniforms;
+#else
+@group(1) @binding(0)
 var<uniform> uniforms: Uniforms;
+#endif
 
 var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
     vec2<f32>(0.0, 0.0),
@@ -23,19 +31,127 @@ var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
 fn vs_main(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
 
-    out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
+    // Shrink the quad along the longer side, so canvas pixels and the dots on them stay square
+    let scale = vec2<f32>(min(1.0, 1.0 / uniforms.aspect), min(1.0, uniforms.aspect));
+    out.position = vec4<f32>(v_positions[v_idx] * scale, 0.0, 1.0);
     out.position.x = out.position.x * cos(0.0);
     out.tex_coords = v_positions[v_idx];
 
     return out;
 }
 
-@group(1) @binding(0)
+@group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
-@group(1) @binding(1)
+@group(0) @binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(3)
+var t_snapshot: texture_2d<f32>;
+
+// How a frame of the timeline is drawn, `FrameUniforms` in surface_view.rs
+struct Frame {
+    // Below 1 for onion skins
+    opacity: f32,
+    // Shows `t_snapshot` too, 1 side by side and 2 left of `split`, see `Comparison`
+    comparison: u32,
+    split: f32,
+    // 1 to 3 for as many vanishing points, 4 for isometric, see `guide_uniforms`
+    guides: u32,
+    vanishing_point0: vec2<f32>,
+    vanishing_point1: vec2<f32>,
+    vanishing_point2: vec2<f32>,
+    // Between parallel guides
+    spacing: f32,
+    // 1 mirrors left and right, 2 top and bottom, see `ViewFlip`
+    flip: u32,
+};
+
+@group(0) @binding(2)
+var<uniform> frame: Frame;
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+fn vanishing_point(index: u32) -> vec2<f32> {
+    if index == 0u {
+        return frame.vanishing_point0;
+    }
+    return select(frame.vanishing_point2, frame.vanishing_point1, index == 1u);
+}
+
+// Distance from `position` to the parallel lines across `normal`, `frame.spacing` apart
+fn parallel_distance(position: vec2<f32>, normal: vec2<f32>) -> f32 {
+    let steps = dot(position, normal) / frame.spacing;
+    return abs(steps - round(steps)) * frame.spacing;
+}
+
+// Distance from `position` on the canvas to the nearest guide line, in canvas units
+fn guide_distance(position: vec2<f32>) -> f32 {
+    var distance = 1e9;
+    if frame.guides == 4u {
+        distance = parallel_distance(position, vec2<f32>(1.0, 0.0));
+        distance = min(distance, parallel_distance(position, vec2<f32>(-0.5, 0.8660254)));
+        distance = min(distance, parallel_distance(position, vec2<f32>(0.5, 0.8660254)));
+        return distance;
+    }
+    // Rays fanning out of each vanishing point, 7.5° apart
+    let step = 6.2831853 / 48.0;
+    for (var index = 0u; index < frame.guides; index++) {
+        let offset = position - vanishing_point(index);
+        let angle = atan2(offset.y, offset.x);
+        distance = min(distance, abs(sin(angle - round(angle / step) * step)) * length(offset));
+    }
+    if frame.guides <= 2u {
+        distance = min(distance, parallel_distance(position, vec2<f32>(1.0, 0.0)));
+    }
+    if frame.guides == 1u {
+        distance = min(distance, parallel_distance(position, vec2<f32>(0.0, 1.0)));
+    }
+    return distance;
+}
 
 @fragment
 fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    var coords = in.tex_coords;
+    if (frame.flip & 1u) != 0u {
+        coords.x = 1.0 - coords.x;
+    }
+    if (frame.flip & 2u) != 0u {
+        coords.y = 1.0 - coords.y;
+    }
+    var snapshot = false;
+    if frame.comparison == 1u {
+        // A whole canvas in each half of the square, half as large and centered
+        snapshot = coords.x < 0.5;
+        coords = vec2<f32>(fract(coords.x * 2.0), coords.y * 2.0 - 0.5);
+    } else if frame.comparison == 2u {
+        snapshot = coords.x < frame.split;
+    }
+    // Sampled in uniform control flow, both regions with the same coordinates
+    let current = textureSample(t_diffuse, s_diffuse, coords);
+    let before = textureSample(t_snapshot, s_diffuse, coords);
+    let inside = all(coords >= vec2<f32>(0.0)) && all(coords <= vec2<f32>(1.0));
+    var color = select(vec4<f32>(0.0), select(current, before, snapshot), inside);
+    // A pixel wide line to drag the split by
+    let line_width = fwidth(in.tex_coords.x);
+    if frame.comparison == 2u && abs(coords.x - frame.split) < line_width {
+        color = vec4<f32>(1.0);
+    }
+    // Pixel wide guide lines over the canvas, in canvas coordinates like `Guides`
+    let position = vec2<f32>(coords.x * 2.0 - 1.0, 1.0 - coords.y * 2.0);
+    let pixel = fwidth(position.x);
+    if frame.guides > 0u && inside {
+        let coverage = 1.0 - smoothstep(0.5 * pixel, pixel, guide_distance(position));
+        color = mix(color, vec4<f32>(0.1, 0.4, 1.0, 1.0), 0.6 * coverage);
+    }
+    color = color * frame.opacity;
+#ifdef ENCODE_SRGB
+    // The target stores what we return as is, so encode the straight color and premultiply again
+    if color.a > 0.0 {
+        return vec4<f32>(linear_to_srgb(color.rgb / color.a) * color.a, color.a);
+    }
+#endif
+    return color;
 }
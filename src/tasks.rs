@@ -0,0 +1,112 @@
+//! Slow work like exports and readbacks, run off the render thread so painting doesn't freeze
+//! while a large PNG is encoded and written.
+//!
+//! Natively the app runs them on a few background threads. The web has no threads to spare
+//! here, so there they run on the browser's event loop in between frames. Either way a task can
+//! report back to the app's event loop with a [`TaskEvent`].
+
+use std::future::Future;
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::app::UserEvent;
+use crate::color::Color;
+use crate::dump::{DumpError, InstanceDump};
+use crate::export::ExportError;
+use crate::histogram::Histogram;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::video::VideoError;
+
+/// `Send` where tasks run on other threads, i.e. natively.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+/// `Send` where tasks run on other threads, i.e. natively.
+#[cfg(target_arch = "wasm32")]
+pub(crate) trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// A task finished, sent to the event loop as [`UserEvent::Task`].
+#[derive(Debug)]
+pub enum TaskEvent {
+    /// The canvas was exported to the named file.
+    Exported(Result<&'static str, ExportError>),
+    /// The canvas was counted into histograms.
+    Histogram(Result<Histogram, wgpu::BufferAsyncError>),
+    /// The eyedropper read the color of a canvas pixel.
+    PickedColor(Result<Color, ExportError>),
+    /// The dots and instance buffers were read back, and written to disk natively.
+    DumpedInstances(Result<InstanceDump, DumpError>),
+    /// The timeline or a replay was exported as a video to this path, with this many frames.
+    #[cfg(not(target_arch = "wasm32"))]
+    ExportedVideo(Result<(std::path::PathBuf, u32), VideoError>),
+}
+
+/// Background threads natively, so exports don't block the next frames.
+#[cfg(not(target_arch = "wasm32"))]
+const THREADS: usize = 2;
+
+/// Runs tasks in the background, see the [module docs](self).
+pub(crate) struct Tasks {
+    proxy: EventLoopProxy<UserEvent>,
+    #[cfg(not(target_arch = "wasm32"))]
+    sender: std::sync::mpsc::Sender<std::pin::Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl Tasks {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use std::sync::{mpsc, Arc, Mutex};
+
+            let (sender, receiver) = mpsc::channel();
+            let receiver = Arc::new(Mutex::new(receiver));
+            for index in 0..THREADS {
+                let receiver = Arc::clone(&receiver);
+                std::thread::Builder::new()
+                    .name(format!("task-{index}"))
+                    .spawn(move || loop {
+                        // Let go of the lock before running, so the other threads can take tasks
+                        let task = receiver.lock().unwrap().recv();
+                        match task {
+                            Ok(task) => pollster::block_on(task),
+                            // The app has quit
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("couldn't start a task thread");
+            }
+            Self { proxy, sender }
+        }
+        #[cfg(target_arch = "wasm32")]
+        Self { proxy }
+    }
+
+    /// Runs `task` in the background.
+    pub fn spawn(&self, task: impl Future<Output = ()> + MaybeSend + 'static) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.sender.send(Box::pin(task)).ok();
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(task);
+    }
+
+    /// Runs `task` in the background and sends the event loop what it returns.
+    pub fn spawn_reporting(&self, task: impl Future<Output = TaskEvent> + MaybeSend + 'static) {
+        let proxy = self.proxy.clone();
+        self.spawn(async move {
+            proxy.send_event(UserEvent::Task(task.await)).ok();
+        });
+    }
+}
+
+/// Runs `task` on this thread: blocking until it's done natively, on the browser's event loop on
+/// the web. For work holding on to things that can't leave the thread, like the window's surface.
+pub(crate) fn spawn_local(task: impl Future<Output = ()> + 'static) {
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(task);
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(task);
+}
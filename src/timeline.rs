@@ -0,0 +1,221 @@
+//! Animation frames: every frame is a canvas of its own, with its dots, filters and selection. A
+//! [`Timeline`] keeps them in order and knows the one being painted, and
+//! [`SurfaceRenderResources`](crate::surface_view::SurfaceRenderResources) draws the frames around
+//! it faded over it as onion skins. Frames are opaque, so they're laid on top like on a light
+//! table.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::surface::{GlobalSurface, HpSurface};
+
+#[derive(Debug)]
+pub enum TimelineError {
+    NoFrame { index: usize, frames: usize },
+    /// A timeline always has a frame.
+    LastFrame,
+    /// The frame being left, or the one added, couldn't be rendered for the onion skins.
+    Render(Error),
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::NoFrame { index, frames } => {
+                write!(f, "there is no frame {index}, the timeline has {frames}")
+            }
+            TimelineError::LastFrame => write!(f, "the last frame can't be removed"),
+            TimelineError::Render(err) => write!(f, "failed to render the frame: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+/// Which frames show faded over the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnionSkin {
+    /// Frames shown before the current one.
+    pub before: usize,
+    /// Frames shown after the current one.
+    pub after: usize,
+    /// Of the frames next to the current one, every frame further away is faded by it again.
+    pub opacity: f32,
+}
+
+impl OnionSkin {
+    /// Only the current frame.
+    pub const OFF: Self = Self {
+        before: 0,
+        after: 0,
+        opacity: 0.0,
+    };
+
+    pub fn is_enabled(&self) -> bool {
+        (self.before > 0 || self.after > 0) && self.opacity > 0.0
+    }
+}
+
+impl Default for OnionSkin {
+    /// The frames right before and after the current one.
+    fn default() -> Self {
+        Self {
+            before: 1,
+            after: 1,
+            opacity: 0.3,
+        }
+    }
+}
+
+/// The frames of an animation, one of them current.
+pub struct Timeline {
+    /// Never empty.
+    frames: Vec<HpSurface>,
+    current: usize,
+    onion_skin: OnionSkin,
+}
+
+impl Timeline {
+    /// An animation of just `first`.
+    pub fn new(first: HpSurface) -> Self {
+        Self {
+            frames: vec![first],
+            current: 0,
+            onion_skin: OnionSkin::default(),
+        }
+    }
+
+    /// The same frames on `global`, e.g. after the previous device was lost, see
+    /// [`HpSurface::recreate`]. Fails if one of them couldn't be rendered again.
+    pub fn recreate(&self, global: Arc<GlobalSurface>) -> Result<Self, Error> {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let frame = frame.recreate(global.clone());
+                frame.render()?;
+                Ok(frame)
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            frames,
+            current: self.current,
+            onion_skin: self.onion_skin,
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frames(&self) -> &[HpSurface] {
+        &self.frames
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// The frame being painted.
+    pub fn current(&self) -> &HpSurface {
+        &self.frames[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut HpSurface {
+        &mut self.frames[self.current]
+    }
+
+    pub fn switch_to(&mut self, index: usize) -> Result<(), TimelineError> {
+        if index >= self.frames.len() {
+            return Err(TimelineError::NoFrame {
+                index,
+                frames: self.frames.len(),
+            });
+        }
+        self.leave()?;
+        self.current = index;
+        Ok(())
+    }
+
+    /// Renders the current frame before another one becomes current. Frames are only rendered
+    /// while current, onion skins show them as they were left. Stays on it if that fails.
+    fn leave(&self) -> Result<(), TimelineError> {
+        self.current().render().map_err(TimelineError::Render)
+    }
+
+    /// Switches to the frame after the current one, from the last to the first.
+    pub fn next_frame(&mut self) -> Result<(), TimelineError> {
+        self.leave()?;
+        self.current = (self.current + 1) % self.frames.len();
+        Ok(())
+    }
+
+    /// Switches to the frame before the current one, from the first to the last.
+    pub fn previous_frame(&mut self) -> Result<(), TimelineError> {
+        self.leave()?;
+        self.current = (self.current + self.frames.len() - 1) % self.frames.len();
+        Ok(())
+    }
+
+    /// Inserts an empty frame after the current one and switches to it, returning its index. It
+    /// merges tiny dots, orders them and blends strokes like the current one.
+    pub fn add_frame(&mut self) -> Result<usize, TimelineError> {
+        let mut frame = HpSurface::new(self.current().global.clone());
+        frame.set_lod(self.current().lod().copied());
+        frame.set_dot_order(self.current().dot_order());
+        frame.set_stroke_overlap(self.current().stroke_overlap());
+        self.insert(frame)
+    }
+
+    /// Inserts a copy of the current frame after it and switches to the copy, returning its index.
+    pub fn duplicate_frame(&mut self) -> Result<usize, TimelineError> {
+        let current = self.current();
+        let frame = current.recreate(current.global.clone());
+        self.insert(frame)
+    }
+
+    fn insert(&mut self, frame: HpSurface) -> Result<usize, TimelineError> {
+        frame.render().map_err(TimelineError::Render)?;
+        self.leave()?;
+        self.current += 1;
+        self.frames.insert(self.current, frame);
+        Ok(self.current)
+    }
+
+    /// Removes the current frame, switching to the one before it, or after it if it was the first.
+    pub fn remove_frame(&mut self) -> Result<HpSurface, TimelineError> {
+        if self.frames.len() == 1 {
+            return Err(TimelineError::LastFrame);
+        }
+        let frame = self.frames.remove(self.current);
+        self.current = self.current.saturating_sub(1);
+        Ok(frame)
+    }
+
+    pub fn onion_skin(&self) -> OnionSkin {
+        self.onion_skin
+    }
+
+    pub fn set_onion_skin(&mut self, onion_skin: OnionSkin) {
+        self.onion_skin = onion_skin;
+    }
+
+    /// The frames shown over the current one with their opacity, furthest first so nearer ones are
+    /// drawn over them. The timeline doesn't wrap around for them.
+    pub fn onion_frames(&self) -> Vec<(&HpSurface, f32)> {
+        let skin = self.onion_skin;
+        if !skin.is_enabled() {
+            return Vec::new();
+        }
+        let distance = skin.before.max(skin.after);
+        let mut frames = Vec::new();
+        for distance in (1..=distance).rev() {
+            let opacity = skin.opacity.powi(distance as i32);
+            let before = self.current.checked_sub(distance).filter(|_| distance <= skin.before);
+            let after = Some(self.current + distance).filter(|&index| distance <= skin.after && index < self.frames.len());
+            frames.extend(before.into_iter().chain(after).map(|index| (&self.frames[index], opacity)));
+        }
+        frames
+    }
+}
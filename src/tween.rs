@@ -0,0 +1,165 @@
+//! Dots moved from code, e.g. for generative animations: a [`Sequence`] eases a dot from how it
+//! is to other [`Dot`]s, one after another. [`HpSurface::tween`](crate::surface::HpSurface::tween)
+//! starts one, and [`HpSurface::simulate`](crate::surface::HpSurface::simulate) moves it on with
+//! every frame, the surface counting as animated until it's done.
+
+use crate::color::Color;
+use crate::surface::Dot;
+
+/// How a tween speeds up and slows down between its dots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    /// Starts slowly.
+    EaseIn,
+    /// Ends slowly.
+    EaseOut,
+    /// Starts and ends slowly.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// How far along the way from the first dot to the second a tween is at `progress` of its
+    /// time, both 0..1. Eases cubically.
+    pub fn apply(self, progress: f32) -> f32 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+        }
+    }
+}
+
+/// A step of a [`Sequence`].
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    /// `None` waits, keeping the dot as it is.
+    to: Option<Dot>,
+    seconds: f32,
+    easing: Easing,
+}
+
+/// Steps played one after another, starting from the dot as it is.
+#[derive(Debug, Default, Clone)]
+pub struct Sequence {
+    steps: Vec<Step>,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Eases to `to` in `seconds` after the steps before. Its position, radius, hardness and color
+    /// are eased, the noise and animation are `to`'s from the start.
+    pub fn then(mut self, to: Dot, seconds: f32, easing: Easing) -> Self {
+        self.steps.push(Step {
+            to: Some(to),
+            seconds: seconds.max(0.0),
+            easing,
+        });
+        self
+    }
+
+    /// Keeps the dot as it is for `seconds` after the steps before.
+    pub fn wait(mut self, seconds: f32) -> Self {
+        self.steps.push(Step {
+            to: None,
+            seconds: seconds.max(0.0),
+            easing: Easing::Linear,
+        });
+        self
+    }
+
+    /// Of all steps.
+    pub fn seconds(&self) -> f32 {
+        self.steps.iter().map(|step| step.seconds).sum()
+    }
+
+    /// The dot `seconds` into the sequence, starting out as `from`.
+    pub fn sample(&self, from: Dot, seconds: f32) -> Dot {
+        let mut dot = from;
+        let mut start = 0.0;
+        for step in &self.steps {
+            let Some(to) = step.to else {
+                start += step.seconds;
+                continue;
+            };
+            if seconds < start + step.seconds {
+                let progress = (seconds - start) / step.seconds;
+                return between(dot, to, step.easing.apply(progress));
+            }
+            dot = to;
+            start += step.seconds;
+        }
+        dot
+    }
+}
+
+/// `t` of the way from `from` to `to`, with `to`'s noise and animation.
+fn between(from: Dot, to: Dot, t: f32) -> Dot {
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    let [x, y] = from.position();
+    let [to_x, to_y] = to.position();
+    let (a, b) = (from.color(), to.color());
+    let color = Color::new(lerp(a.r, b.r), lerp(a.g, b.g), lerp(a.b, b.b), lerp(a.a, b.a));
+    Dot::new([lerp(x, to_x), lerp(y, to_y)], lerp(from.radius(), to.radius()), lerp(from.hardness(), to.hardness()), color)
+        .with_noise(to.noise())
+        .with_animation(to.animation())
+}
+
+/// A sequence playing on the dot at `index`.
+#[derive(Debug, Clone)]
+struct Tween {
+    index: usize,
+    from: Dot,
+    sequence: Sequence,
+    elapsed: f32,
+}
+
+/// The sequences playing on a surface's dots.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Tweens {
+    playing: Vec<Tween>,
+}
+
+impl Tweens {
+    /// Plays `sequence` on the dot at `index` starting out as `from`, instead of what played on it.
+    pub fn play(&mut self, index: usize, from: Dot, sequence: Sequence) {
+        self.stop(index);
+        self.playing.push(Tween {
+            index,
+            from,
+            sequence,
+            elapsed: 0.0,
+        });
+    }
+
+    pub fn stop(&mut self, index: usize) {
+        self.playing.retain(|tween| tween.index != index);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.playing.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.playing.is_empty()
+    }
+
+    /// Moves the sequences on by `delta_seconds`, returning the indices of their dots with how
+    /// they are now. Finished ones return their last dot once more and stop.
+    pub fn step(&mut self, delta_seconds: f32) -> Vec<(usize, Dot)> {
+        let mut dots = Vec::with_capacity(self.playing.len());
+        self.playing.retain_mut(|tween| {
+            tween.elapsed += delta_seconds.max(0.0);
+            dots.push((tween.index, tween.sequence.sample(tween.from, tween.elapsed)));
+            tween.elapsed < tween.sequence.seconds()
+        });
+        dots
+    }
+}
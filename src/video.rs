@@ -0,0 +1,276 @@
+//! Videos of animations, e.g. the frames of a [`Timeline`](crate::timeline::Timeline): every
+//! [`VideoFrame`] is rendered on a headless canvas of its own device at the video's size, then
+//! written as a numbered PNG or piped into `ffmpeg`, which encodes GIFs and mp4s. Painting
+//! sessions become timelapses with [`VideoExporter::add_replay`].
+
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::error::Error;
+use crate::export::{self, ExportError, ExportOptions};
+use crate::filter::Filter;
+use crate::replay::{Recording, Replay};
+use crate::surface::{Dot, HpSurface};
+
+#[derive(Debug)]
+pub enum VideoError {
+    /// The headless canvas couldn't be set up.
+    Canvas(Error),
+    Export(ExportError),
+    Io(std::io::Error),
+    /// The encoder isn't installed where the options say.
+    NoEncoder(PathBuf),
+    /// The encoder quit with an error, which it printed.
+    Encoder(std::process::ExitStatus),
+    /// Not a finite frame rate above 0 and up to [`MAX_FPS`].
+    Fps(f32),
+}
+
+impl fmt::Display for VideoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoError::Canvas(err) => write!(f, "failed to set up the canvas: {err}"),
+            VideoError::Export(err) => write!(f, "failed to export a frame: {err}"),
+            VideoError::Io(err) => write!(f, "failed to write the video: {err}"),
+            VideoError::NoEncoder(path) => write!(f, "{} isn't installed, it encodes GIFs and mp4s", path.display()),
+            VideoError::Encoder(status) => write!(f, "the encoder failed with {status}"),
+            VideoError::Fps(fps) => write!(f, "videos need a frame rate above 0 and up to {MAX_FPS}, not {fps}"),
+        }
+    }
+}
+
+impl std::error::Error for VideoError {}
+
+/// What a video is written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoFormat {
+    /// PNGs named by frame number in a directory.
+    ImageSequence,
+    /// A looping GIF, with a palette picked from the frames.
+    Gif,
+    /// H.264 in an mp4.
+    #[default]
+    Mp4,
+}
+
+impl VideoFormat {
+    /// The format the extension of `path` names, `.gif` or `.mp4`, or an image sequence in the
+    /// directory `path` otherwise.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => VideoFormat::Gif,
+            Some(extension) if extension.eq_ignore_ascii_case("mp4") => VideoFormat::Mp4,
+            _ => VideoFormat::ImageSequence,
+        }
+    }
+}
+
+/// The highest frame rate videos are rendered at.
+pub const MAX_FPS: f32 = 240.0;
+
+/// How videos are rendered and encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoOptions {
+    pub format: VideoFormat,
+    /// Width and height in pixels, the canvas is rendered at this size.
+    pub size: u32,
+    /// Frames per second, above 0 and up to [`MAX_FPS`].
+    pub fps: f32,
+    /// How each frame is exported, like PNG exports.
+    pub export: ExportOptions,
+    /// The `ffmpeg` encoding GIFs and mp4s, found on `PATH` by default.
+    pub ffmpeg: PathBuf,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        Self {
+            format: VideoFormat::default(),
+            size: 512,
+            fps: 12.0,
+            export: ExportOptions::default(),
+            ffmpeg: PathBuf::from("ffmpeg"),
+        }
+    }
+}
+
+/// What a frame of a video shows. Animated dots are drawn as they are at the frame's time.
+#[derive(Debug, Clone, Default)]
+pub struct VideoFrame {
+    pub dots: Vec<Dot>,
+    pub filters: Vec<Filter>,
+}
+
+impl VideoFrame {
+    /// What `surface` shows, without its particles, wet paint and plugins.
+    pub fn of(surface: &HpSurface) -> Self {
+        Self {
+            dots: surface.dots().to_vec(),
+            filters: surface.filters().to_vec(),
+        }
+    }
+}
+
+/// Where the frames go.
+enum Sink {
+    Images(PathBuf),
+    /// `stdin` is `None` once the encoder was told there are no more frames.
+    Encoder { child: Child, stdin: Option<ChildStdin> },
+}
+
+/// Renders frames into a video one after another, see [`export_video`] for all at once.
+pub struct VideoExporter {
+    canvas: HpSurface,
+    sink: Sink,
+    options: VideoOptions,
+    frames: u32,
+}
+
+impl VideoExporter {
+    /// Starts a video at `path`, a directory for image sequences. Files already there are
+    /// overwritten.
+    pub fn new(path: impl AsRef<Path>, options: VideoOptions) -> Result<Self, VideoError> {
+        let path = path.as_ref();
+        if !(options.fps > 0.0 && options.fps <= MAX_FPS) {
+            return Err(VideoError::Fps(options.fps));
+        }
+        let canvas = HpSurface::headless(options.size).map_err(VideoError::Canvas)?;
+        let sink = match options.format {
+            VideoFormat::ImageSequence => {
+                std::fs::create_dir_all(path).map_err(VideoError::Io)?;
+                Sink::Images(path.to_owned())
+            }
+            format => {
+                let mut child = encoder_command(&options.ffmpeg, format, options.fps, path)
+                    .spawn()
+                    .map_err(|err| match err.kind() {
+                        std::io::ErrorKind::NotFound => VideoError::NoEncoder(options.ffmpeg.clone()),
+                        _ => VideoError::Io(err),
+                    })?;
+                let stdin = child.stdin.take();
+                Sink::Encoder { child, stdin }
+            }
+        };
+        Ok(Self {
+            canvas,
+            sink,
+            options,
+            frames: 0,
+        })
+    }
+
+    /// Renders `frame` as the next frame of the video.
+    pub fn add_frame(&mut self, frame: &VideoFrame) -> Result<(), VideoError> {
+        self.canvas.set_dots(frame.dots.clone());
+        if let Err(err) = self.canvas.set_filters(frame.filters.clone()) {
+            tracing::warn!("Exporting frame {} without its filters: {err}", self.frames);
+        }
+        self.write_frame()
+    }
+
+    /// Replays `recording` `speed` times as fast as it was painted on an empty canvas, a frame
+    /// at a time, ending with the finished painting.
+    pub fn add_replay(&mut self, recording: &Recording, speed: f64) -> Result<(), VideoError> {
+        let mut replay = Replay::new(recording, speed);
+        self.canvas.set_dots(Vec::new());
+        self.canvas.clear_filters();
+        self.canvas.clear_watercolor();
+        let frame_seconds = 1.0 / self.options.fps as f64;
+        let mut seconds = 0.0;
+        loop {
+            replay.advance_to(seconds, &mut self.canvas);
+            // Lets wet paint flow like it did on screen
+            self.canvas.simulate(frame_seconds as f32);
+            self.write_frame()?;
+            if replay.is_finished() {
+                return Ok(());
+            }
+            seconds += frame_seconds;
+        }
+    }
+
+    /// Renders the canvas as it is as the next frame.
+    fn write_frame(&mut self) -> Result<(), VideoError> {
+        let _span = tracing::info_span!("video_frame", frame = self.frames).entered();
+        self.canvas.global.set_seconds(self.frames as f32 / self.options.fps);
+        self.canvas.render().map_err(|err| VideoError::Export(ExportError::Render(err)))?;
+        let png = pollster::block_on(export::export_png(self.canvas.readback(), self.options.export))
+            .map_err(VideoError::Export)?;
+        match &mut self.sink {
+            Sink::Images(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frames));
+                std::fs::write(path, png).map_err(VideoError::Io)?;
+            }
+            Sink::Encoder { stdin: Some(stdin), .. } => stdin.write_all(&png).map_err(VideoError::Io)?,
+            Sink::Encoder { stdin: None, .. } => unreachable!("frames are only added before finishing"),
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Waits for the encoder to write the video, returning how many frames it has.
+    pub fn finish(mut self) -> Result<u32, VideoError> {
+        if let Sink::Encoder { child, stdin } = &mut self.sink {
+            // Closing its input tells the encoder there are no more frames
+            drop(stdin.take());
+            let status = child.wait().map_err(VideoError::Io)?;
+            if !status.success() {
+                return Err(VideoError::Encoder(status));
+            }
+        }
+        Ok(self.frames)
+    }
+}
+
+impl Drop for VideoExporter {
+    /// Stops an encoder that wasn't [finished](Self::finish), e.g. after a frame failed, so it
+    /// doesn't linger writing half a video.
+    fn drop(&mut self) {
+        if let Sink::Encoder { child, stdin: Some(_) } = &mut self.sink {
+            if let Err(err) = child.kill() {
+                tracing::warn!("Couldn't stop the video encoder: {err}");
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Renders `frames` into a video at `path`, returning how many frames it has.
+pub fn export_video<'a>(
+    frames: impl IntoIterator<Item = &'a VideoFrame>,
+    path: impl AsRef<Path>,
+    options: VideoOptions,
+) -> Result<u32, VideoError> {
+    let mut exporter = VideoExporter::new(path, options)?;
+    for frame in frames {
+        exporter.add_frame(frame)?;
+    }
+    exporter.finish()
+}
+
+/// `ffmpeg` reading PNGs from stdin and writing them to `path` as `format`.
+fn encoder_command(ffmpeg: &Path, format: VideoFormat, fps: f32, path: &Path) -> Command {
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-y", "-loglevel", "error", "-f", "image2pipe", "-c:v", "png", "-framerate"])
+        .arg(fps.to_string())
+        .args(["-i", "-"]);
+    match format {
+        // One palette for the whole GIF, picked from all frames, dithers less than the default
+        VideoFormat::Gif => command.args(["-vf", "split[a][b];[a]palettegen[p];[b][p]paletteuse", "-loop", "0"]),
+        // Most players only take 4:2:0 H.264, which needs even sizes
+        VideoFormat::Mp4 => command.args([
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            "-c:v",
+            "libx264",
+            "-pix_fmt",
+            "yuv420p",
+        ]),
+        VideoFormat::ImageSequence => unreachable!("image sequences aren't encoded"),
+    };
+    command.arg(path).stdin(Stdio::piped());
+    command
+}
@@ -0,0 +1,102 @@
+//! Noticing when the GPU stops finishing work, so driver hangs show up in the log instead of as a
+//! frozen window.
+//!
+//! The [`Watchdog`] remembers each submission it's told about until the queue reports it done. One
+//! that's still pending after [`STALL_SECONDS`] is logged along with everything else waiting, once
+//! per stall, and reported to the caller, which may recreate the device. wgpu 0.15 doesn't report
+//! finished work on WebGPU, so there the watchdog is off.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::Clock;
+
+/// How long a submission may take before it's considered stalled.
+pub(crate) const STALL_SECONDS: f64 = 5.0;
+
+/// Work handed to the queue that hasn't finished yet.
+#[derive(Debug)]
+struct Submission {
+    id: u64,
+    label: String,
+    submitted_at: f64,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    submissions: VecDeque<Submission>,
+    /// The label of the submission finished last, to tell where the GPU got stuck.
+    last_done: Option<String>,
+}
+
+/// Watches submissions for ones that don't finish, see the [module docs](self).
+#[derive(Debug, Default)]
+pub(crate) struct Watchdog {
+    clock: Clock,
+    pending: Arc<Mutex<Pending>>,
+    next_id: u64,
+    /// The submission already reported as stalled, so it's logged once.
+    reported: Option<u64>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watches the work submitted to `queue` so far, naming it `label` in the log if it stalls.
+    /// Does nothing on WebGPU, where wgpu 0.15 can't tell when work is done.
+    pub fn submitted(&mut self, queue: &wgpu::Queue, label: String) {
+        if cfg!(all(target_arch = "wasm32", not(feature = "webgl"))) {
+            return;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.lock().unwrap().submissions.push_back(Submission {
+            id,
+            label,
+            submitted_at: self.clock.elapsed_seconds(),
+        });
+        let pending = self.pending.clone();
+        queue.on_submitted_work_done(move || {
+            let mut pending = pending.lock().unwrap();
+            if let Some(index) = pending.submissions.iter().position(|submission| submission.id == id) {
+                let done = pending.submissions.remove(index);
+                pending.last_done = done.map(|submission| format!("#{id} {}", submission.label));
+            }
+        });
+    }
+
+    /// Whether submitted work is stalled. Polls `device` first, natively that's what runs the
+    /// callbacks of finished work. Logs the pending submissions the first time a stall is noticed.
+    pub fn check(&mut self, device: &wgpu::Device) -> bool {
+        device.poll(wgpu::Maintain::Poll);
+        let now = self.clock.elapsed_seconds();
+        let pending = self.pending.lock().unwrap();
+        let Some(oldest) = pending.submissions.front() else {
+            return false;
+        };
+        if now - oldest.submitted_at < STALL_SECONDS {
+            return false;
+        }
+        if self.reported != Some(oldest.id) {
+            self.reported = Some(oldest.id);
+            let waiting: Vec<String> = pending
+                .submissions
+                .iter()
+                .map(|submission| {
+                    let age = now - submission.submitted_at;
+                    format!("#{} {} ({age:.1}s ago)", submission.id, submission.label)
+                })
+                .collect();
+            tracing::error!(
+                "The GPU hasn't finished work for {:.1}s, {} submissions pending: {}. Last finished: {}",
+                now - oldest.submitted_at,
+                waiting.len(),
+                waiting.join(", "),
+                pending.last_done.as_deref().unwrap_or("nothing"),
+            );
+        }
+        true
+    }
+}
@@ -0,0 +1,535 @@
+//! Watercolor: strokes painted with a [wet](crate::stroke::Brush::wet) brush flow over the canvas
+//! until they dry, see [`HpSurface::add_stroke_dots`](crate::surface::HpSurface::add_stroke_dots).
+//!
+//! Wet dots aren't drawn like the canvas's other dots but put into textures: one with the pigment
+//! and one with how wet it is. Every frame a compute pass spreads and evaporates the water, drifts
+//! the pigment with it from wetter to drier areas and piles it up at the edges of wet areas, which
+//! darkens them like real watercolor. Once [`WetSettings::drying_seconds`] went by without new wet
+//! dots, the pigment is committed to a texture holding the dried paint.
+//!
+//! The dried paint is layered between the dots: each layer is drawn over the dots of the strokes
+//! started before its first wet stroke and under the later ones, except with
+//! [`DotOrder::Depth`](crate::surface::DotOrder::Depth) or zoomed out far enough for
+//! [decimation](crate::lod), where the layers go over all dots. Wet strokes keep their dots, so
+//! they're saved in documents and undone like other strokes. Loading them or undoing one paints the
+//! watercolor again from the dots, letting it dry at once, which can leave it a little different
+//! from how it dried on screen.
+
+use std::num::NonZeroU64;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::shaders::catch_validation_error;
+use crate::document::StrokeId;
+use crate::preprocessor::ShaderDefines;
+use crate::shaders::{Shader, ShaderError};
+use crate::surface::Dot;
+
+/// The format of the pigment textures, which compute shaders can write.
+const PIGMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The format of the water textures.
+const WATER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// `@workgroup_size` of watercolor.wgsl, in both dimensions.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The longest step simulated at once, so a stalled frame doesn't wash the paint away.
+const MAX_STEP_SECONDS: f32 = 1.0 / 20.0;
+
+/// The most steps [`WetLayer::settle`] takes, so slowly drying paint doesn't stall it.
+const MAX_SETTLE_STEPS: usize = 200;
+
+/// How wet paint flows and dries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WetSettings {
+    /// How fast pigment drifts towards drier areas, in pixels per second for a change in wetness
+    /// of 1 per pixel.
+    pub flow: f32,
+    /// How quickly pigment piles up at the edges of wet areas.
+    pub edge_darkening: f32,
+    /// Seconds after the last wet dot until the paint is dry and committed. Water evaporates at
+    /// the rate that dries freshly painted areas in this time.
+    pub drying_seconds: f32,
+}
+
+impl Default for WetSettings {
+    fn default() -> Self {
+        Self {
+            flow: 300.0,
+            edge_darkening: 4.0,
+            drying_seconds: 3.0,
+        }
+    }
+}
+
+/// `Wet` in watercolor.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct WetUniforms {
+    size: [u32; 2],
+    dot_count: u32,
+    delta_seconds: f32,
+    flow: f32,
+    edge_darkening: f32,
+    evaporation: f32,
+    _padding: f32,
+}
+
+/// Whether `device` can paint watercolor.
+pub(crate) fn is_supported(device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_storage_textures_per_shader_stage >= 3
+        && limits.max_storage_buffers_per_shader_stage > 0
+}
+
+/// The pipelines of watercolor.wgsl, for canvases of one format.
+pub(crate) struct WatercolorPass {
+    compute_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Kept to rebuild the pipelines when the shader is edited.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    layouts: (wgpu::PipelineLayout, wgpu::PipelineLayout),
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    target: (wgpu::TextureFormat, u32),
+    pipelines: Pipelines,
+}
+
+struct Pipelines {
+    flow: wgpu::ComputePipeline,
+    commit: wgpu::ComputePipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+impl WatercolorPass {
+    /// The pipelines for canvases of `format` with `sample_count` samples.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let texture_entry = |binding, visibility, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+            },
+            count: None,
+        };
+        let storage_entry = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let sampler_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 8,
+            visibility,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let compute = wgpu::ShaderStages::COMPUTE;
+        let compute_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("watercolor_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: compute,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<WetUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                texture_entry(1, compute, true),
+                texture_entry(2, compute, false),
+                texture_entry(3, compute, true),
+                storage_entry(4, PIGMENT_FORMAT),
+                storage_entry(5, WATER_FORMAT),
+                storage_entry(6, PIGMENT_FORMAT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: compute,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<Dot>() as u64),
+                    },
+                    count: None,
+                },
+                sampler_entry(compute),
+            ],
+        });
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("watercolor_composite_bind_group_layout"),
+            entries: &[texture_entry(1, fragment, true), texture_entry(3, fragment, true), sampler_entry(fragment)],
+        });
+        let pipeline_layout = |label, layout| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            })
+        };
+        let layouts = (
+            pipeline_layout("watercolor_pipeline_layout", &compute_layout),
+            pipeline_layout("watercolor_composite_pipeline_layout", &composite_layout),
+        );
+        let pipelines = create_pipelines(device, Shader::Watercolor.embedded_source(), &layouts, format, sample_count)
+            .unwrap_or_else(|err| panic!("{err}"));
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("watercolor_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            compute_layout,
+            composite_layout,
+            sampler,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            layouts,
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            target: (format, sample_count),
+            pipelines,
+        }
+    }
+
+    /// Rebuilds the pipelines from `source`, keeping the current ones if it doesn't compile.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        let (format, sample_count) = self.target;
+        self.pipelines = catch_validation_error(device, || {
+            create_pipelines(device, source, &self.layouts, format, sample_count)
+        })?;
+        Ok(())
+    }
+}
+
+/// The wet and dried paint of a surface.
+pub(crate) struct WetLayer {
+    size: wgpu::Extent3d,
+    /// The passes read one texture of each pair and write the other, `latest` is the one written
+    /// last.
+    pigment: [wgpu::TextureView; 2],
+    water: [wgpu::TextureView; 2],
+    latest: usize,
+    /// Oldest first, the wet paint dries into the last one.
+    dried: Vec<DriedLayer>,
+    /// Stands in for the pigment of the layers the wet paint doesn't dry into.
+    blank: wgpu::TextureView,
+    uniforms: wgpu::Buffer,
+    /// Painted since the last step.
+    pending: Vec<Dot>,
+    /// Since the last wet dot, `None` while everything is dry.
+    wet_seconds: Option<f32>,
+}
+
+/// Paint that dried between the same dots.
+struct DriedLayer {
+    /// The first wet stroke drying into the layer. It's drawn over the dots of earlier strokes.
+    first: StrokeId,
+    /// Read and written like the pigment, see [`WetLayer::latest`].
+    textures: [wgpu::TextureView; 2],
+    latest: usize,
+}
+
+impl WetLayer {
+    /// Dry, empty paint for a canvas of `size`.
+    pub fn new(device: &wgpu::Device, size: wgpu::Extent3d) -> Self {
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("watercolor_uniforms"),
+            size: std::mem::size_of::<WetUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let blank = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+
+        // New textures start out zeroed, without paint and dry
+        Self {
+            size,
+            pigment: [0, 1].map(|_| create_texture(device, "watercolor_pigment", size, PIGMENT_FORMAT)),
+            water: [0, 1].map(|_| create_texture(device, "watercolor_water", size, WATER_FORMAT)),
+            latest: 0,
+            dried: Vec::new(),
+            blank: create_texture(device, "watercolor_blank", blank, PIGMENT_FORMAT),
+            uniforms,
+            pending: Vec::new(),
+            wet_seconds: None,
+        }
+    }
+
+    /// Puts `dots` down wet with the next step. Once dry, they're drawn over the dots of the
+    /// strokes started before `first`, the first of the wet strokes painted one after the other
+    /// that they belong to.
+    pub fn deposit(&mut self, device: &wgpu::Device, dots: &[Dot], first: StrokeId) {
+        // Paint that's still wet dries together with the new dots
+        if self.wet_seconds.is_none() && self.dried.last().map_or(true, |layer| layer.first != first) {
+            self.dried.push(DriedLayer {
+                first,
+                textures: [0, 1].map(|_| create_texture(device, "watercolor_dried", self.size, PIGMENT_FORMAT)),
+                latest: 0,
+            });
+        }
+        self.pending.extend_from_slice(dots);
+        self.wet_seconds = Some(0.0);
+    }
+
+    /// Whether paint is still flowing, so more steps are needed.
+    pub fn is_wet(&self) -> bool {
+        self.wet_seconds.is_some()
+    }
+
+    /// The first wet stroke of the paint that's wet, see [`Self::deposit`].
+    pub fn wet_stroke(&self) -> Option<StrokeId> {
+        self.dried.last().filter(|_| self.is_wet()).map(|layer| layer.first)
+    }
+
+    /// Lets the paint flow for `delta_seconds`, at most [`MAX_STEP_SECONDS`], and commits it once
+    /// it dried.
+    pub fn step(
+        &mut self,
+        pass: &WatercolorPass,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &WetSettings,
+        delta_seconds: f32,
+    ) {
+        let Some(wet_seconds) = self.wet_seconds else {
+            return;
+        };
+        let delta_seconds = delta_seconds.clamp(0.0, MAX_STEP_SECONDS);
+        let wet_seconds = wet_seconds + delta_seconds;
+        self.advance(pass, device, queue, settings, delta_seconds, wet_seconds >= settings.drying_seconds);
+        if self.is_wet() {
+            self.wet_seconds = Some(wet_seconds);
+        }
+    }
+
+    /// Lets the paint flow until it dried, at most [`MAX_SETTLE_STEPS`] steps, e.g. when painting
+    /// watercolor strokes again.
+    pub fn settle(&mut self, pass: &WatercolorPass, device: &wgpu::Device, queue: &wgpu::Queue, settings: &WetSettings) {
+        let steps = (settings.drying_seconds / MAX_STEP_SECONDS).ceil().clamp(1.0, MAX_SETTLE_STEPS as f32) as usize;
+        for _ in 1..steps {
+            self.step(pass, device, queue, settings, MAX_STEP_SECONDS);
+        }
+        if self.is_wet() {
+            self.advance(pass, device, queue, settings, MAX_STEP_SECONDS, true);
+        }
+    }
+
+    /// One step of the flow, committing the paint to the last dried layer if `dry`.
+    fn advance(
+        &mut self,
+        pass: &WatercolorPass,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &WetSettings,
+        delta_seconds: f32,
+        dry: bool,
+    ) {
+        let uniforms = WetUniforms {
+            size: [self.size.width, self.size.height],
+            dot_count: self.pending.len() as u32,
+            delta_seconds,
+            flow: settings.flow,
+            edge_darkening: settings.edge_darkening,
+            evaporation: 1.0 / settings.drying_seconds.max(f32::EPSILON),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+        // Bindings can't be empty, without dots a zeroed one stands in, unread
+        let dots = if self.pending.is_empty() { vec![Dot::zeroed()] } else { std::mem::take(&mut self.pending) };
+        let dots = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("watercolor_dots"),
+            contents: bytemuck::cast_slice(&dots),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let flow = self.bind_group(pass, device, &dots);
+        self.latest = 1 - self.latest;
+        let commit = dry.then(|| self.bind_group(pass, device, &dots));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("watercolor"),
+        });
+        encoder.push_debug_group("watercolor");
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("watercolor"),
+            });
+            let workgroups = [self.size.width, self.size.height].map(|size| (size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE);
+            compute_pass.set_pipeline(&pass.pipelines.flow);
+            compute_pass.set_bind_group(0, &flow, &[]);
+            compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+            if let Some(commit) = &commit {
+                compute_pass.set_pipeline(&pass.pipelines.commit);
+                compute_pass.set_bind_group(0, commit, &[]);
+                compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+            }
+        }
+        encoder.pop_debug_group();
+        queue.submit(Some(encoder.finish()));
+
+        if dry {
+            self.latest = 1 - self.latest;
+            let layer = self.dried.last_mut().expect("deposited into");
+            layer.latest = 1 - layer.latest;
+            self.wet_seconds = None;
+        }
+    }
+
+    /// Reads the latest textures and writes the others.
+    fn bind_group(&self, pass: &WatercolorPass, device: &wgpu::Device, dots: &wgpu::Buffer) -> wgpu::BindGroup {
+        let (read, write) = (self.latest, 1 - self.latest);
+        let dried = self.dried.last().expect("deposited into");
+        let (dried_read, dried_write) = (dried.latest, 1 - dried.latest);
+        let view = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("watercolor_bind_group"),
+            layout: &pass.compute_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+                view(1, &self.pigment[read]),
+                view(2, &self.water[read]),
+                view(3, &dried.textures[dried_read]),
+                view(4, &self.pigment[write]),
+                view(5, &self.water[write]),
+                view(6, &dried.textures[dried_write]),
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: dots.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Sampler(&pass.sampler),
+                },
+            ],
+        })
+    }
+
+    /// What [`Self::draw`] binds for each dried layer, oldest first with the first wet stroke
+    /// drying into it. Created before the canvas pass begins so they outlive it.
+    pub fn composite_bind_groups(&self, pass: &WatercolorPass, device: &wgpu::Device) -> Vec<(StrokeId, wgpu::BindGroup)> {
+        self.dried
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| {
+                // Only the last layer has wet paint on top
+                let pigment = match index == self.dried.len() - 1 {
+                    true => &self.pigment[self.latest],
+                    false => &self.blank,
+                };
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("watercolor_composite_bind_group"),
+                    layout: &pass.composite_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(pigment),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(&layer.textures[layer.latest]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 8,
+                            resource: wgpu::BindingResource::Sampler(&pass.sampler),
+                        },
+                    ],
+                });
+                (layer.first, bind_group)
+            })
+            .collect()
+    }
+
+    /// Draws a layer of dried paint, with the wet paint over the last one, into the canvas pass.
+    pub fn draw<'rp>(pass: &'rp WatercolorPass, bind_group: &'rp wgpu::BindGroup, render_pass: &mut wgpu::RenderPass<'rp>) {
+        render_pass.set_pipeline(&pass.pipelines.composite);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// A zeroed texture for the passes to read and write.
+fn create_texture(device: &wgpu::Device, label: &str, size: wgpu::Extent3d, format: wgpu::TextureFormat) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_pipelines(
+    device: &wgpu::Device,
+    source: &str,
+    (compute_layout, composite_layout): &(wgpu::PipelineLayout, wgpu::PipelineLayout),
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<Pipelines, ShaderError> {
+    let shader = Shader::Watercolor.create_module(device, source, &ShaderDefines::new())?;
+    let compute = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("watercolor"),
+            layout: Some(compute_layout),
+            module: &shader,
+            entry_point,
+        })
+    };
+    let composite = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("watercolor_composite"),
+        layout: Some(composite_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_composite",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_composite",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    });
+    Ok(Pipelines {
+        flow: compute("flow"),
+        commit: compute("commit"),
+        composite,
+    })
+}
@@ -0,0 +1,139 @@
+// Wet paint flowing over the canvas until it dries, see watercolor.rs.
+//
+// `Wet` mirrors `WetUniforms` in watercolor.rs, keep them in sync when changing either side.
+
+struct Wet {
+    size: vec2<u32>,
+    dotCount: u32,
+    deltaSeconds: f32,
+    flow: f32,
+    edgeDarkening: f32,
+    // Water lost per second
+    evaporation: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> wet: Wet;
+// Premultiplied linear color of the wet paint
+@group(0) @binding(1)
+var pigment: texture_2d<f32>;
+// How wet the paint is, 0..1
+@group(0) @binding(2)
+var water: texture_2d<f32>;
+// Premultiplied linear color of the paint that dried
+@group(0) @binding(3)
+var dried: texture_2d<f32>;
+@group(0) @binding(4)
+var next_pigment: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(5)
+var next_water: texture_storage_2d<r32float, write>;
+@group(0) @binding(6)
+var next_dried: texture_storage_2d<rgba16float, write>;
+// The dots painted wet since the last step, `Dot`s as laid out in Rust, 17 floats each
+@group(0) @binding(7)
+var<storage, read> dots: array<f32>;
+@group(0) @binding(8)
+var linear_sampler: sampler;
+
+fn water_at(pixel: vec2<i32>) -> f32 {
+    let clamped = clamp(pixel, vec2<i32>(0), vec2<i32>(wet.size) - 1);
+    return textureLoad(water, clamped, 0).r;
+}
+
+// Puts the wet dots down at `position`, in canvas coordinates, like dot_shader.wgsl
+// draws them but without their noise.
+fn deposit(position: vec2<f32>, paint: ptr<function, vec4<f32>>, moisture: ptr<function, f32>) {
+    for (var index = 0u; index < wet.dotCount; index = index + 1u) {
+        let base = index * 17u;
+        let center = vec2<f32>(dots[base], dots[base + 1u]);
+        let radius = dots[base + 2u];
+        let hardness = dots[base + 3u];
+        let color = vec4<f32>(dots[base + 4u], dots[base + 5u], dots[base + 6u], dots[base + 7u]);
+
+        let offset = (position - center) / (2.0 * radius);
+        let distance = dot(offset, offset) * 2.0;
+        // What `fwidth(distance)` is in dot_shader.wgsl, `offset` moves this much per pixel
+        let step = 1.0 / (f32(wet.size.x) * radius);
+        let edge = 4.0 * (abs(offset.x) + abs(offset.y)) * step;
+        let coverage = (1.0 - smoothstep(min(hardness / 2.0, 0.5 - edge), 0.5, distance)) * color.a;
+        *paint = vec4<f32>(color.rgb * coverage, coverage) + *paint * (1.0 - coverage);
+        *moisture = max(*moisture, min(coverage * 2.0, 1.0));
+    }
+}
+
+// One step of the paint flowing: water spreads and evaporates, pigment drifts with it from
+// wetter to drier areas and piles up at the edges of wet areas.
+@compute @workgroup_size(8, 8)
+fn flow(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= wet.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    let dt = wet.deltaSeconds;
+
+    let here = water_at(pixel);
+    let left = water_at(pixel - vec2<i32>(1, 0));
+    let right = water_at(pixel + vec2<i32>(1, 0));
+    let up = water_at(pixel - vec2<i32>(0, 1));
+    let down = water_at(pixel + vec2<i32>(0, 1));
+    let average = (left + right + up + down) / 4.0;
+    let gradient = vec2<f32>(right - left, down - up) * 0.5;
+
+    var paint = textureLoad(pigment, pixel, 0);
+    if (max(here, average) > 0.0) {
+        let velocity = -gradient * wet.flow;
+        let origin = (vec2<f32>(pixel) + 0.5 - velocity * dt) / vec2<f32>(wet.size);
+        paint = textureSampleLevel(pigment, linear_sampler, origin, 0.0);
+        paint = paint * (1.0 + wet.edgeDarkening * length(gradient) * dt);
+        if (paint.a > 1.0) {
+            paint = paint / paint.a;
+        }
+    }
+    var moisture = mix(here, average, min(dt * 8.0, 1.0));
+    moisture = max(moisture - wet.evaporation * dt, 0.0);
+
+    let size = vec2<f32>(wet.size);
+    let position = vec2<f32>((f32(pixel.x) + 0.5) / size.x * 2.0 - 1.0, 1.0 - (f32(pixel.y) + 0.5) / size.y * 2.0);
+    deposit(position, &paint, &moisture);
+
+    textureStore(next_pigment, pixel, paint);
+    textureStore(next_water, pixel, vec4<f32>(moisture, 0.0, 0.0, 0.0));
+}
+
+// Puts the wet paint onto the dried paint, leaving nothing wet.
+@compute @workgroup_size(8, 8)
+fn commit(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= wet.size)) {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    let paint = textureLoad(pigment, pixel, 0);
+    let below = textureLoad(dried, pixel, 0);
+    textureStore(next_dried, pixel, paint + below * (1.0 - paint.a));
+    textureStore(next_pigment, pixel, vec4<f32>(0.0));
+    textureStore(next_water, pixel, vec4<f32>(0.0));
+}
+
+struct CompositeOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the canvas
+@vertex
+fn vs_composite(@builtin(vertex_index) index: u32) -> CompositeOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: CompositeOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// The wet paint over the dried paint, premultiplied, blended over the canvas.
+@fragment
+fn fs_composite(input: CompositeOutput) -> @location(0) vec4<f32> {
+    let paint = textureSample(pigment, linear_sampler, input.uv);
+    let below = textureSample(dried, linear_sampler, input.uv);
+    return paint + below * (1.0 - paint.a);
+}
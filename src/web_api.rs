@@ -0,0 +1,334 @@
+//! JavaScript API for embedding the painter in a web page.
+//!
+//! ```js
+//! import init_wasm, { init, addDots, clear, exportPng, onStroke, setLut, importPalette } from "./hellopaint_wgpu.js";
+//!
+//! await init_wasm();
+//! init("canvas");
+//! onStroke((dotsJson) => console.log(JSON.parse(dotsJson)));
+//! addDots(JSON.stringify([{ position: [0, 0], radius: 0.1, hardness: 0.5, color: [0, 0, 1, 1] }]));
+//! const png = await exportPng();
+//! setLut(await (await fetch("film.cube")).text());
+//! const palette = JSON.parse(importPalette("swatches.ase", new Uint8Array(await (await fetch("swatches.ase")).arrayBuffer())));
+//! ```
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures_channel::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use winit::event_loop::{EventLoopBuilder, EventLoopProxy};
+use winit::platform::web::WindowBuilderExtWebSys;
+use winit::window::WindowBuilder;
+
+use crate::adjustment_layer::AdjustmentLayer;
+use crate::app::{self, UserEvent};
+use crate::color::Color;
+use crate::command::Command;
+use crate::config::Config;
+use crate::filter::{Filter, Kernel};
+use crate::guides::Guides;
+use crate::lut::Lut;
+use crate::palette::Palette;
+use crate::post_process::ColorBlindness;
+use crate::selection::MagicWand;
+use crate::spatial::CanvasRect;
+use crate::stroke::Brush;
+use crate::surface::Dot;
+
+thread_local! {
+    static APP: RefCell<Option<EventLoopProxy<UserEvent>>> = RefCell::new(None);
+}
+
+/// Starts the painter on the `<canvas>` element with the given id.
+#[wasm_bindgen]
+pub fn init(canvas_id: &str) -> Result<(), JsValue> {
+    console_error_panic_hook::set_once();
+
+    if APP.with(|app| app.borrow().is_some()) {
+        return Err("the painter is already running".into());
+    }
+
+    let canvas = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(canvas_id))
+        .ok_or_else(|| format!("no element with id {canvas_id:?}"))?
+        .dyn_into::<web_sys::HtmlCanvasElement>()?;
+
+    let event_loop = EventLoopBuilder::with_user_event().build();
+    let window = WindowBuilder::new()
+        .with_canvas(Some(canvas))
+        .build(&event_loop)
+        .map_err(|err| err.to_string())?;
+
+    APP.with(|app| *app.borrow_mut() = Some(event_loop.create_proxy()));
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(err) = app::run(event_loop, Rc::new(window), Config::default()).await {
+            tracing::error!("{err}");
+        }
+    });
+    Ok(())
+}
+
+/// Adds dots given as a JSON array of `{ position, radius, hardness, color }` objects, optionally
+/// with `noise: { scale, seed, strength }`.
+#[wasm_bindgen(js_name = addDots)]
+pub fn add_dots(json: &str) -> Result<(), JsValue> {
+    let dots: Vec<Dot> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let dots = dots
+        .into_iter()
+        .map(Dot::validated)
+        .collect::<Result<_, _>>()
+        .map_err(|err| err.to_string())?;
+    send(UserEvent::Command(Command::AddDots(dots)))
+}
+
+#[wasm_bindgen]
+pub fn clear() -> Result<(), JsValue> {
+    send(UserEvent::Command(Command::Clear))
+}
+
+/// Takes back the last stroke, added dots or clearing.
+#[wasm_bindgen]
+pub fn undo() -> Result<(), JsValue> {
+    send(UserEvent::Command(Command::Undo))
+}
+
+/// Asks over the canvas which size and background to start a new document with, like Ctrl+N,
+/// which browsers keep for opening a window.
+#[wasm_bindgen(js_name = newDocument)]
+pub fn new_document() -> Result<(), JsValue> {
+    send(UserEvent::NewDocument)
+}
+
+/// Changes the look of dots to a WGSL snippet defining
+/// `fn custom_dot(position: vec2<f32>, hardness: f32, color: vec4<f32>) -> vec4<f32>`, or back to
+/// the default with `null`. Resolves once it's in use, or rejects with the shader error.
+#[wasm_bindgen(js_name = setDotShader)]
+pub fn set_dot_shader(wgsl: Option<String>) -> js_sys::Promise {
+    let (sender, receiver) = oneshot::channel();
+    let sent = send(UserEvent::SetDotSnippet(wgsl, sender));
+    wasm_bindgen_futures::future_to_promise(async move {
+        sent?;
+        receiver
+            .await
+            .map_err(|_| "the painter has stopped")?
+            .map_err(|err| err.to_string())?;
+        Ok(JsValue::UNDEFINED)
+    })
+}
+
+/// Grades the image with a LUT given as the text of a `.cube` file.
+#[wasm_bindgen(js_name = setLut)]
+pub fn set_lut(cube: &str) -> Result<(), JsValue> {
+    let lut = Lut::from_cube(cube).map_err(|err| err.to_string())?;
+    send(UserEvent::SetLut(Some(lut)))
+}
+
+#[wasm_bindgen(js_name = clearLut)]
+pub fn clear_lut() -> Result<(), JsValue> {
+    send(UserEvent::SetLut(None))
+}
+
+/// Skips the LUT while `bypass` is set, to compare against the ungraded image.
+#[wasm_bindgen(js_name = setLutBypass)]
+pub fn set_lut_bypass(bypass: bool) -> Result<(), JsValue> {
+    send(UserEvent::SetLutBypass(bypass))
+}
+
+/// Simulates `protanopia`, `deuteranopia` or `tritanopia` on the display, or normal color vision
+/// again with `null`, to check that a palette reads for everyone.
+#[wasm_bindgen(js_name = setColorBlindness)]
+pub fn set_color_blindness(kind: Option<String>) -> Result<(), JsValue> {
+    let color_blindness = match kind {
+        Some(kind) => Some(ColorBlindness::from_name(&kind).ok_or_else(|| format!("unknown color blindness {kind:?}"))?),
+        None => None,
+    };
+    send(UserEvent::SetColorBlindness(color_blindness))
+}
+
+/// Replaces the adjustment layers shown on top of the canvas, given as a JSON array bottom to top
+/// like `[{ "kind": "curves", "rgb": [[0, 0], [0.5, 0.6], [1, 1]] }, { "kind": "hsl", "saturation": -0.3, "opacity": 0.5 }]`.
+/// They change only what's displayed, never the painting, and `[]` removes them.
+#[wasm_bindgen(js_name = setAdjustmentLayers)]
+pub fn set_adjustment_layers(json: &str) -> Result<(), JsValue> {
+    let layers: Vec<AdjustmentLayer> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    send(UserEvent::SetAdjustmentLayers(layers))
+}
+
+/// Loads the swatches of a `.gpl` or `.ase` file, picked with the keys 1 to 9. Returns the
+/// palette as JSON, with its groups and named colors, for the page to show.
+#[wasm_bindgen(js_name = importPalette)]
+pub fn import_palette(file_name: &str, data: &[u8]) -> Result<String, JsValue> {
+    let palette = Palette::load(file_name, data).map_err(|err| err.to_string())?;
+    let json = serde_json::to_string(&palette).map_err(|err| err.to_string())?;
+    send(UserEvent::SetPalette(palette))?;
+    Ok(json)
+}
+
+/// Runs a filter over the canvas after the existing ones, given as JSON like
+/// `{ "kind": "gaussian_blur", "radius": 8, "region": { "x": 0, "y": 0, "width": 256, "height": 256 } }`
+/// or `{ "kind": "convolution", "kernel": "sharpen" }`, where `region` is optional. Needs WebGPU,
+/// WebGL2 has no compute shaders.
+#[wasm_bindgen(js_name = addFilter)]
+pub fn add_filter(json: &str) -> Result<(), JsValue> {
+    let filter: Filter = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    send(UserEvent::AddFilter(filter))
+}
+
+#[wasm_bindgen(js_name = clearFilters)]
+pub fn clear_filters() -> Result<(), JsValue> {
+    send(UserEvent::ClearFilters)
+}
+
+/// Shows a filter, given as JSON like for `addFilter`, on top of the others without adding it yet.
+/// Call again as its settings change, e.g. `{ "kind": "adjustment", "brightness": 0.1 }` while a
+/// slider is dragged, then `commitPreview` or `cancelPreview`.
+#[wasm_bindgen(js_name = previewFilter)]
+pub fn preview_filter(json: &str) -> Result<(), JsValue> {
+    let filter: Filter = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    send(UserEvent::PreviewFilter(Some(filter)))
+}
+
+/// Adds the previewed filter after the others.
+#[wasm_bindgen(js_name = commitPreview)]
+pub fn commit_preview() -> Result<(), JsValue> {
+    send(UserEvent::CommitPreview)
+}
+
+#[wasm_bindgen(js_name = cancelPreview)]
+pub fn cancel_preview() -> Result<(), JsValue> {
+    send(UserEvent::PreviewFilter(None))
+}
+
+/// Makes a kernel available to convolution filters as `name`, given as JSON like
+/// `{ "size": 3, "weights": [0, 0, 0, 0, 1, 0, 0, 0, 0], "bias": 0 }` with the weights row by
+/// row, where `bias` is optional. `sharpen`, `emboss` and `edge_detect` are built in.
+#[wasm_bindgen(js_name = registerKernel)]
+pub fn register_kernel(name: &str, json: &str) -> Result<(), JsValue> {
+    let kernel: Kernel = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let kernel = kernel.validated().map_err(|err| err.to_string())?;
+    send(UserEvent::RegisterKernel(name.to_owned(), kernel))
+}
+
+/// Selects the area of similar color around the canvas pixel `x`, `y` (from the top left), so
+/// filters only change it. Strokes still paint everywhere. `tolerance` 0..1 is how far colors may
+/// differ, 0.1 if left out. Needs WebGPU like filters.
+#[wasm_bindgen(js_name = magicWand)]
+pub fn magic_wand(x: u32, y: u32, tolerance: Option<f32>) -> Result<(), JsValue> {
+    let tolerance = tolerance.unwrap_or(0.1);
+    send(UserEvent::Select(Some(MagicWand { x, y, tolerance })))
+}
+
+#[wasm_bindgen(js_name = clearSelection)]
+pub fn clear_selection() -> Result<(), JsValue> {
+    send(UserEvent::Select(None))
+}
+
+/// Shows guides written like the `guides` option, e.g. `2-point:-1.5,0,1.5,0` for vanishing points
+/// in canvas coordinates or `isometric:0.2`, or none with `null`. Strokes snapping to the previous
+/// guides snap to these.
+#[wasm_bindgen(js_name = setGuides)]
+pub fn set_guides(guides: Option<String>) -> Result<(), JsValue> {
+    let guides = match guides {
+        Some(guides) => Some(Guides::parse(&guides).ok_or_else(|| format!("invalid guides {guides:?}"))?),
+        None => None,
+    };
+    send(UserEvent::SetGuides(guides))
+}
+
+/// Paints the next strokes in a color given as JSON `[r, g, b, a]`, linear like dot colors and
+/// clamped to 0..=1 like theirs.
+#[wasm_bindgen(js_name = setBrushColor)]
+pub fn set_brush_color(json: &str) -> Result<(), JsValue> {
+    let color: Color = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let brush = Brush {
+        color,
+        ..Brush::default()
+    };
+    let color = brush.validated().map_err(|err| format!("the brush can't paint: {err}"))?.color;
+    send(UserEvent::Command(Command::SetBrushColor(color)))
+}
+
+/// Resolves to the recently painted colors as a JSON array, most recent first, in the format
+/// `setBrushColor` accepts. They are kept in local storage between visits.
+#[wasm_bindgen(js_name = recentColors)]
+pub fn recent_colors() -> js_sys::Promise {
+    let (sender, receiver) = oneshot::channel();
+    let sent = send(UserEvent::RecentColors(sender));
+    wasm_bindgen_futures::future_to_promise(async move {
+        sent?;
+        let colors = receiver.await.map_err(|_| "the app has stopped")?;
+        let json = serde_json::to_string(&colors).map_err(|err| err.to_string())?;
+        Ok(JsValue::from_str(&json))
+    })
+}
+
+/// Resolves to the indices of the dots touching the rectangle between two corners, in canvas
+/// coordinates (-1..1 with y up), as a JSON array in drawing order, e.g. to select them.
+#[wasm_bindgen(js_name = dotsIn)]
+pub fn dots_in(x0: f32, y0: f32, x1: f32, y1: f32) -> js_sys::Promise {
+    let (sender, receiver) = oneshot::channel();
+    let sent = send(UserEvent::DotsIn(CanvasRect::from_corners([x0, y0], [x1, y1]), sender));
+    wasm_bindgen_futures::future_to_promise(async move {
+        sent?;
+        let indices = receiver.await.map_err(|_| "the app has stopped")?;
+        let json = serde_json::to_string(&indices).map_err(|err| err.to_string())?;
+        Ok(JsValue::from_str(&json))
+    })
+}
+
+/// Resolves to histograms of the canvas as JSON, `{ "red": [...], "green": [...], "blue": [...],
+/// "luminance": [...] }` with 256 pixel counts each, by sRGB encoded value. Resolves to `null`
+/// on WebGL2, which has no compute shaders.
+#[wasm_bindgen]
+pub fn histogram() -> js_sys::Promise {
+    let (sender, receiver) = oneshot::channel();
+    let sent = send(UserEvent::Histogram(sender));
+    wasm_bindgen_futures::future_to_promise(async move {
+        sent?;
+        let histogram = receiver.await.map_err(|_| "the app has stopped")?;
+        let json = serde_json::to_string(&histogram).map_err(|err| err.to_string())?;
+        Ok(JsValue::from_str(&json))
+    })
+}
+
+/// Resolves to the canvas encoded as PNG, as a `Uint8Array`.
+#[wasm_bindgen(js_name = exportPng)]
+pub fn export_png() -> js_sys::Promise {
+    let (sender, receiver) = oneshot::channel();
+    let sent = send(UserEvent::ExportPng(sender));
+    wasm_bindgen_futures::future_to_promise(async move {
+        sent?;
+        let png = receiver
+            .await
+            .map_err(|_| "the export was cancelled")?
+            .map_err(|err| err.to_string())?;
+        Ok(js_sys::Uint8Array::from(png.as_slice()).into())
+    })
+}
+
+/// Calls `callback` with the dots of every finished stroke, as a JSON string in the format
+/// `addDots` accepts.
+#[wasm_bindgen(js_name = onStroke)]
+pub fn on_stroke(callback: js_sys::Function) -> Result<(), JsValue> {
+    send(UserEvent::SetStrokeListener(Box::new(move |dots| {
+        match serde_json::to_string(dots) {
+            Ok(json) => {
+                callback.call1(&JsValue::NULL, &JsValue::from_str(&json)).ok();
+            }
+            Err(err) => tracing::error!("Couldn't serialize stroke: {err}"),
+        }
+    })))
+}
+
+fn send(event: UserEvent) -> Result<(), JsValue> {
+    APP.with(|app| {
+        app.borrow()
+            .as_ref()
+            .ok_or("call init first")?
+            .send_event(event)
+            .map_err(|_| "the painter has stopped".into())
+    })
+}
@@ -0,0 +1,183 @@
+//! Rendering into an `OffscreenCanvas`, so the painter can run in a Web Worker and heavy
+//! dot rendering doesn't block the page's main thread.
+//!
+//! The page transfers the canvas and forwards pointer events, the worker owns the GPU:
+//!
+//! ```js
+//! // main thread
+//! const offscreen = canvas.transferControlToOffscreen();
+//! worker.postMessage({ type: "init", canvas: offscreen }, [offscreen]);
+//! canvas.addEventListener("pointermove", (e) =>
+//!     worker.postMessage({ type: "move", id: e.pointerId, x: e.offsetX * devicePixelRatio,
+//!                          y: e.offsetY * devicePixelRatio, pressure: e.pressure }));
+//!
+//! // worker
+//! import init_wasm, { OffscreenRenderer } from "./hellopaint_wgpu.js";
+//! let renderer;
+//! onmessage = async ({ data }) => {
+//!     if (data.type === "init") {
+//!         await init_wasm();
+//!         renderer = await OffscreenRenderer.create(data.canvas);
+//!         const frame = () => { renderer.render(); requestAnimationFrame(frame); };
+//!         requestAnimationFrame(frame);
+//!     } else if (data.type === "move") {
+//!         renderer.pointerMove(data.id, data.x, data.y, data.pressure);
+//!     } // ... "down", "up", "resize"
+//! };
+//! ```
+
+use std::sync::Arc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::renderer;
+use crate::command::{Command, CommandBus, Painting, PointerId};
+use crate::surface::{DisplayGamut, Dot, GlobalSurface, HpSurface, DEFAULT_CANVAS_SIZE};
+use crate::surface_view::SurfaceRenderResources;
+
+#[wasm_bindgen]
+pub struct OffscreenRenderer {
+    _instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    render_resources: SurfaceRenderResources,
+    bus: CommandBus,
+    painting: Painting,
+    needs_redraw: bool,
+}
+
+#[wasm_bindgen]
+impl OffscreenRenderer {
+    /// Sets up the GPU for a canvas received from `transferControlToOffscreen()`.
+    pub async fn create(canvas: web_sys::OffscreenCanvas) -> Result<OffscreenRenderer, JsValue> {
+        console_error_panic_hook::set_once();
+
+        let (width, height) = (canvas.width(), canvas.height());
+        let instance = wgpu::Instance::default();
+        let surface = instance
+            .create_surface_from_offscreen_canvas(&canvas)
+            .map_err(|err| err.to_string())?;
+        let (adapter, device, queue) = renderer::request_device(&instance, Some(&surface), None)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let config = renderer::surface_configuration(&surface, &adapter, width, height, DisplayGamut::Srgb)
+            .map_err(|err| err.to_string())?;
+        surface.configure(&device, &config);
+
+        let canvas_size = renderer::fit_canvas_size(DEFAULT_CANVAS_SIZE, &device.limits());
+        let global_surface = Arc::new(
+            GlobalSurface::with_canvas_size(device.clone(), queue.clone(), canvas_size).map_err(|err| err.to_string())?,
+        );
+        let render_resources =
+            SurfaceRenderResources::new(&device, HpSurface::new(global_surface), config.format);
+
+        Ok(Self {
+            _instance: instance,
+            adapter,
+            device,
+            queue,
+            surface,
+            config,
+            render_resources,
+            bus: CommandBus::new(),
+            painting: Painting::default(),
+            needs_redraw: true,
+        })
+    }
+
+    /// Resizes the drawing buffer, in physical pixels. Empty sizes, e.g. of a hidden canvas, are
+    /// ignored.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        self.config = renderer::surface_configuration(&self.surface, &self.adapter, width, height, DisplayGamut::Srgb)
+            .map_err(|err| err.to_string())?;
+        self.surface.configure(&self.device, &self.config);
+        self.needs_redraw = true;
+        Ok(())
+    }
+
+    /// Draws a frame if anything changed since the last one. Returns whether it drew, and fails
+    /// if the GPU ran out of memory or rejected drawing the frame.
+    pub fn render(&mut self) -> Result<bool, JsValue> {
+        if !self.needs_redraw {
+            return Ok(false);
+        }
+        let frame = match renderer::acquire_frame(&self.surface, &self.device, &self.config) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(false),
+            Err(err) => return Err(err.to_string().into()),
+        };
+        let rendered = self
+            .render_resources
+            .render_to_texture(&self.device, &self.queue, &frame.texture);
+        frame.present();
+        self.needs_redraw = false;
+        rendered.map_err(|err| err.to_string())?;
+        Ok(true)
+    }
+
+    /// Starts a stroke. Positions are in physical pixels relative to the canvas.
+    #[wasm_bindgen(js_name = pointerDown)]
+    pub fn pointer_down(&mut self, pointer_id: i32, x: f32, y: f32, pressure: f32) {
+        self.publish(Command::BeginStroke {
+            pointer: PointerId::Pointer(pointer_id),
+            position: self.to_canvas(x, y),
+            pressure,
+        });
+    }
+
+    #[wasm_bindgen(js_name = pointerMove)]
+    pub fn pointer_move(&mut self, pointer_id: i32, x: f32, y: f32, pressure: f32) {
+        self.publish(Command::ContinueStroke {
+            pointer: PointerId::Pointer(pointer_id),
+            position: self.to_canvas(x, y),
+            pressure,
+        });
+    }
+
+    #[wasm_bindgen(js_name = pointerUp)]
+    pub fn pointer_up(&mut self, pointer_id: i32) {
+        self.publish(Command::EndStroke(PointerId::Pointer(pointer_id)));
+    }
+
+    /// Adds dots given as JSON, in the same format as the main-thread `addDots`.
+    #[wasm_bindgen(js_name = addDots)]
+    pub fn add_dots(&mut self, json: &str) -> Result<(), JsValue> {
+        let dots: Vec<Dot> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let dots = dots
+            .into_iter()
+            .map(Dot::validated)
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string())?;
+        self.publish(Command::AddDots(dots));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.publish(Command::Clear);
+    }
+
+    /// Takes back the last stroke, added dots or clearing.
+    pub fn undo(&mut self) {
+        self.publish(Command::Undo);
+    }
+
+    /// Maps a position in physical pixels relative to the canvas element to canvas coordinates.
+    fn to_canvas(&self, x: f32, y: f32) -> [f32; 2] {
+        let viewport = [self.config.width as f32, self.config.height as f32];
+        self.render_resources.viewport_to_canvas([x, y], viewport)
+    }
+
+    /// Applies `command` right away, drawing the change with the next frame.
+    fn publish(&mut self, command: Command) {
+        self.bus.publish(command);
+        let applied = self.bus.apply(&mut self.painting, self.render_resources.surface_mut());
+        self.needs_redraw |= applied.redraw;
+    }
+}
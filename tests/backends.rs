@@ -0,0 +1,113 @@
+//! Renders the same seeded dots on every adapter wgpu finds, e.g. Vulkan, GL and a software
+//! renderer on Linux or DX12 and DX11 on Windows, and compares them pixel by pixel against the
+//! first. Catches blending and format bugs that only show on some backends.
+//!
+//! Run with `cargo test --test backends -- --nocapture` to see the differences of every adapter.
+//! When one is off beyond the tolerances, its image and a diff are written next to the test
+//! binaries. With fewer than two adapters there's nothing to compare and the tests pass.
+
+mod common;
+
+use std::path::PathBuf;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use hellopaint_wgpu::color::Color;
+use hellopaint_wgpu::export::Dither;
+use hellopaint_wgpu::surface::CanvasColorSpace;
+use hellopaint_wgpu::{Dot, GlobalSurface, HpSurface};
+
+use common::{write_png, Difference, CHANNEL_TOLERANCE};
+
+const CANVAS_SIZE: u32 = 256;
+
+const SEED: u64 = 1467;
+
+/// Translucent dots of random sizes and hardness, overlapping enough to show how they blend.
+fn seeded_dots() -> Vec<Dot> {
+    let mut rng = ChaCha8Rng::seed_from_u64(SEED);
+    (0..300)
+        .map(|_| {
+            let dot = Dot::random(&mut rng);
+            let Color { r, g, b, .. } = dot.color();
+            let alpha = rng.gen_range(0.2..1.0);
+            Dot::new(dot.position(), dot.radius() * 3.0, dot.hardness(), Color::new(r, g, b, alpha))
+        })
+        .collect()
+}
+
+/// An adapter's rendering, named for the report.
+struct Rendering {
+    adapter: String,
+    rgba: Vec<u8>,
+}
+
+/// Renders the seeded dots on every adapter, one after the other.
+fn render_everywhere(color_space: CanvasColorSpace) -> Vec<Rendering> {
+    let instance = wgpu::Instance::default();
+    let mut renderings = Vec::new();
+    for adapter in instance.enumerate_adapters(wgpu::Backends::all()) {
+        let info = adapter.get_info();
+        let name = format!("{} ({:?})", info.name, info.backend);
+        let builder = GlobalSurface::builder().canvas_size(CANVAS_SIZE).color_space(color_space);
+        let mut surface = match HpSurface::headless_on(adapter, builder) {
+            Ok(surface) => surface,
+            Err(err) => {
+                eprintln!("skipping {name}: {err}");
+                continue;
+            }
+        };
+        surface.set_dots(seeded_dots());
+        surface.render().unwrap();
+        let rgba = pollster::block_on(surface.readback().into_rgba8(Dither::None)).unwrap();
+        renderings.push(Rendering { adapter: name, rgba });
+    }
+    renderings
+}
+
+/// Compares every rendering with the first, reporting all of them before failing on any beyond
+/// the tolerances.
+fn check(name: &str, color_space: CanvasColorSpace) {
+    let renderings = render_everywhere(color_space);
+    let Some((reference, others)) = renderings.split_first() else {
+        eprintln!("{name}: skipped, there's no adapter");
+        return;
+    };
+    eprintln!("{name}: comparing against {}", reference.adapter);
+    let mut failed = Vec::new();
+    for other in others {
+        let difference = Difference::between(&other.rgba, &reference.rgba);
+        eprintln!(
+            "  {}: {} pixels ({:.2}%) off by more than {CHANNEL_TOLERANCE}, at most by {}",
+            other.adapter,
+            difference.mismatches,
+            difference.share() * 100.0,
+            difference.max_distance,
+        );
+        if difference.is_beyond_tolerance() {
+            let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+            let file_name = format!("{name}_{}", other.adapter).replace(|c: char| !c.is_alphanumeric(), "_");
+            write_png(&out.join(format!("{file_name}.rendered.png")), CANVAS_SIZE, &other.rgba);
+            write_png(&out.join(format!("{file_name}.diff.png")), CANVAS_SIZE, &difference.diff);
+            failed.push(other.adapter.as_str());
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "{name} differs from {} on {}, see {}",
+        reference.adapter,
+        failed.join(", "),
+        env!("CARGO_TARGET_TMPDIR"),
+    );
+}
+
+#[test]
+fn srgb_canvas() {
+    check("srgb", CanvasColorSpace::Srgb);
+}
+
+#[test]
+fn linear_canvas() {
+    check("linear", CanvasColorSpace::Linear);
+}
@@ -0,0 +1,60 @@
+//! What the rendering tests share: comparing images pixel by pixel within the tolerances GPUs
+//! need, and writing them out when they differ.
+
+use std::path::Path;
+
+/// How far a channel may be off, GPUs round and interpolate a little differently.
+pub const CHANNEL_TOLERANCE: u8 = 3;
+
+/// The share of pixels that may be further off, for edges rasterized differently.
+pub const PIXEL_TOLERANCE: f64 = 0.005;
+
+/// How two RGBA8 images of the same size differ.
+pub struct Difference {
+    /// Pixels with a channel off by more than [`CHANNEL_TOLERANCE`].
+    pub mismatches: usize,
+    /// The most any channel is off.
+    pub max_distance: u8,
+    /// How far each pixel is off, in red, to be written next to the images.
+    pub diff: Vec<u8>,
+}
+
+impl Difference {
+    pub fn between(rendered: &[u8], expected: &[u8]) -> Self {
+        let mut diff = vec![0; rendered.len()];
+        let (mut mismatches, mut max_distance) = (0, 0);
+        for (index, (rendered, expected)) in rendered.chunks(4).zip(expected.chunks(4)).enumerate() {
+            let distance = rendered.iter().zip(expected).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+            max_distance = max_distance.max(distance);
+            if distance > CHANNEL_TOLERANCE {
+                mismatches += 1;
+            }
+            diff[index * 4..index * 4 + 4].copy_from_slice(&[distance.saturating_mul(8), 0, 0, 255]);
+        }
+        Self {
+            mismatches,
+            max_distance,
+            diff,
+        }
+    }
+
+    /// The share of pixels off by more than [`CHANNEL_TOLERANCE`].
+    pub fn share(&self) -> f64 {
+        self.mismatches as f64 / (self.diff.len() / 4) as f64
+    }
+
+    /// Whether more pixels are off than [`PIXEL_TOLERANCE`] allows.
+    pub fn is_beyond_tolerance(&self) -> bool {
+        self.share() > PIXEL_TOLERANCE
+    }
+}
+
+/// Writes square RGBA8 `rgba` of `size` pixels as a PNG at `path`, creating its directory.
+pub fn write_png(path: &Path, size: u32, rgba: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(rgba).unwrap();
+}
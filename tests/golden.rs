@@ -0,0 +1,172 @@
+//! Golden image tests: known dot sets are rendered headlessly and compared against the reference
+//! PNGs in tests/golden, so changes to the shaders or blending can't alter the output unnoticed.
+//!
+//! The references hold the canvas as read back, premultiplied. After an intended change to the
+//! output, regenerate them with `UPDATE_GOLDEN=1 cargo test --test golden` and review the new
+//! images. A failing comparison writes the rendered image and a diff next to the test binaries.
+//!
+//! Each scene is also drawn by the CPU rasterizer, which the GPU has to match as closely as the
+//! references, except for noisy dots whose hash differs between implementations.
+//!
+//! Without an adapter, e.g. on CI machines without even a software renderer, the tests are
+//! skipped.
+
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hellopaint_wgpu::color::Color;
+use hellopaint_wgpu::export::Dither;
+use hellopaint_wgpu::raster::CpuCanvas;
+use hellopaint_wgpu::surface::{CanvasColorSpace, DotNoise};
+use hellopaint_wgpu::{Dot, GlobalSurface, HpSurface};
+
+use common::{write_png, Difference};
+
+const CANVAS_SIZE: u32 = 128;
+
+/// The dots of a scene, on an sRGB or linear canvas.
+struct Scene {
+    name: &'static str,
+    color_space: CanvasColorSpace,
+    dots: Vec<Dot>,
+    /// Whether the CPU rasterizer draws it like the GPU.
+    matches_cpu: bool,
+}
+
+/// Renders `scene` and compares it against its reference, or writes the reference with
+/// `UPDATE_GOLDEN` set.
+fn check(scene: Scene) {
+    let Ok(headless) = HpSurface::headless(CANVAS_SIZE) else {
+        eprintln!("skipping {}, there's no adapter", scene.name);
+        return;
+    };
+    let global = GlobalSurface::builder()
+        .canvas_size(CANVAS_SIZE)
+        .color_space(scene.color_space)
+        .build(headless.global.device.clone(), headless.global.queue.clone())
+        .unwrap();
+    let mut surface = HpSurface::new(Arc::new(global));
+    surface.set_dots(scene.dots.clone());
+    surface.render().unwrap();
+    let rendered = pollster::block_on(surface.readback().into_rgba8(Dither::None)).unwrap();
+
+    if scene.matches_cpu {
+        let mut canvas = CpuCanvas::new(CANVAS_SIZE, scene.color_space);
+        canvas.set_dots(scene.dots);
+        canvas.render();
+        compare(&format!("{} on the CPU", scene.name), &rendered, &canvas.rgba8());
+    }
+
+    let reference_path = golden_dir().join(format!("{}.png", scene.name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&reference_path, CANVAS_SIZE, &rendered);
+        return;
+    }
+    compare(scene.name, &rendered, &read_png(&reference_path));
+}
+
+/// Panics if `rendered` differs from `expected` beyond the tolerances, writing both and a diff.
+fn compare(name: &str, rendered: &[u8], expected: &[u8]) {
+    let difference = Difference::between(rendered, expected);
+    if difference.is_beyond_tolerance() {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+        let file_name = name.replace(' ', "_");
+        write_png(&out.join(format!("{file_name}.rendered.png")), CANVAS_SIZE, rendered);
+        write_png(&out.join(format!("{file_name}.expected.png")), CANVAS_SIZE, expected);
+        write_png(&out.join(format!("{file_name}.diff.png")), CANVAS_SIZE, &difference.diff);
+        panic!(
+            "{name} differs in {} pixels, by up to {}, see {}",
+            difference.mismatches,
+            difference.max_distance,
+            out.display()
+        );
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn read_png(path: &PathBuf) -> Vec<u8> {
+    let file = std::fs::File::open(path)
+        .unwrap_or_else(|err| panic!("no reference at {}, create it with UPDATE_GOLDEN=1: {err}", path.display()));
+    let mut reader = png::Decoder::new(file).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (CANVAS_SIZE, CANVAS_SIZE), "{} has the wrong size", path.display());
+    pixels.truncate(info.buffer_size());
+    pixels
+}
+
+/// A row of dots from soft to hard.
+fn hardness_row(y: f32, color: Color) -> Vec<Dot> {
+    (0..5)
+        .map(|index| Dot::new([-0.8 + index as f32 * 0.4, y], 0.15, index as f32 / 4.0, color))
+        .collect()
+}
+
+#[test]
+fn hardness() {
+    check(Scene {
+        name: "hardness",
+        color_space: CanvasColorSpace::Srgb,
+        dots: [
+            hardness_row(0.5, Color::RED),
+            hardness_row(0.0, Color::from_srgb8([30, 90, 220, 255])),
+            hardness_row(-0.5, Color::WHITE),
+        ]
+        .concat(),
+        matches_cpu: true,
+    });
+}
+
+/// Translucent dots over each other, where blending and the sRGB encoding show.
+fn overlapping() -> Vec<Dot> {
+    vec![
+        Dot::new([-0.2, 0.1], 0.35, 0.8, Color::new(1.0, 0.0, 0.0, 0.5)),
+        Dot::new([0.2, 0.1], 0.35, 0.8, Color::new(0.0, 1.0, 0.0, 0.5)),
+        Dot::new([0.0, -0.25], 0.35, 0.8, Color::new(0.0, 0.0, 1.0, 0.5)),
+        Dot::new([0.0, 0.0], 0.1, 1.0, Color::new(0.0, 0.0, 0.0, 0.25)),
+    ]
+}
+
+#[test]
+fn overlapping_srgb() {
+    check(Scene {
+        name: "overlapping_srgb",
+        color_space: CanvasColorSpace::Srgb,
+        dots: overlapping(),
+        matches_cpu: true,
+    });
+}
+
+#[test]
+fn overlapping_linear() {
+    check(Scene {
+        name: "overlapping_linear",
+        color_space: CanvasColorSpace::Linear,
+        dots: overlapping(),
+        matches_cpu: true,
+    });
+}
+
+#[test]
+fn noise() {
+    let noise = |seed: f32, strength: f32| DotNoise {
+        scale: 6.0,
+        seed,
+        strength,
+    };
+    check(Scene {
+        name: "noise",
+        color_space: CanvasColorSpace::Srgb,
+        dots: vec![
+            Dot::new([-0.45, 0.45], 0.4, 0.5, Color::BLACK).with_noise(noise(1.0, 0.5)),
+            Dot::new([0.45, 0.45], 0.4, 0.5, Color::BLACK).with_noise(noise(2.0, 1.0)),
+            Dot::new([0.0, -0.4], 0.5, 0.9, Color::from_srgb8([200, 120, 40, 255])).with_noise(noise(3.0, 0.8)),
+        ],
+        matches_cpu: false,
+    });
+}